@@ -0,0 +1,6 @@
+pub mod dsp;
+pub mod resampler;
+pub mod scheduler;
+pub mod spc700;
+pub mod spc_file;
+pub mod wav;
@@ -0,0 +1,7 @@
+pub mod brr;
+pub mod buffer;
+pub mod echo;
+pub mod envelope;
+pub mod gaussian;
+pub mod regs;
+pub mod voice;
@@ -0,0 +1,81 @@
+use crate::apu::dsp::regs::clamp16;
+
+// a BRR block is a header byte followed by 16 4-bit samples
+pub const BRR_BLOCK_SIZE: u16 = 9;
+
+// header flags
+pub const BRR_END: u8 = 0x1 << 0;
+pub const BRR_LOOP: u8 = 0x1 << 1;
+
+// decode 4 samples from a pair of BRR bytes (first sample in the top nybble), continuing the
+// prediction filter from the previous two decoded samples
+pub fn decode(header: u8, nybbles: u16, older: i32, old: i32) -> [i32; 4] {
+    let shift = header >> 4;
+    let filter = header & 0x0C;
+    let mut p1 = old;
+    let mut p2 = older;
+    let mut out = [0i32; 4];
+
+    for (i, sample) in out.iter_mut().enumerate() {
+        // sign extend the nybble
+        let mut s = ((nybbles << (i * 4)) as i16 >> 12) as i32;
+
+        s = (s << shift) >> 1;
+        // ranges 13-15 are invalid, they only keep the sign
+        if shift >= 0xD {
+            s = match s < 0 {
+                true => -0x800,
+                false => 0,
+            };
+        }
+
+        let half_p2 = p2 >> 1;
+        match filter {
+            // s += p1 * 0.46875
+            0x4 => {
+                s += p1 >> 1;
+                s += (-p1) >> 5;
+            }
+            // s += p1 * 0.953125 - p2 * 0.46875
+            0x8 => {
+                s += p1 - half_p2;
+                s += half_p2 >> 4;
+                s += (p1 * -3) >> 6;
+            }
+            // s += p1 * 0.8984375 - p2 * 0.40625
+            0xC => {
+                s += p1 - half_p2;
+                s += (p1 * -13) >> 7;
+                s += (half_p2 * 3) >> 4;
+            }
+            _ => {}
+        }
+
+        // samples are 15-bit, doubled and wrapped to 16
+        s = (clamp16(s) * 2) as i16 as i32;
+        *sample = s;
+        p2 = p1;
+        p1 = s;
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decode_unfiltered() {
+        // range 12, filter 0
+        let out = decode(0xC0, 0x17F8, 0, 0);
+        assert_eq!(out, [0x1000, 0x7000, -0x1000, -0x8000]);
+    }
+
+    #[test]
+    fn decode_filter_1() {
+        // a silent block after a loud sample decays through the filter
+        let out = decode(0x04, 0x0000, 0, 0x4000);
+        assert_eq!(out[0], 0x3C00);
+        assert!(out[1] < out[0] && out[2] < out[1] && out[3] < out[2]);
+    }
+}
@@ -0,0 +1,46 @@
+use std::collections::VecDeque;
+
+// one second of output at the native rate
+pub const BUFFER_CAPACITY: usize = 32000;
+
+// stereo output of the DSP waiting to be picked up, the oldest frames are dropped when nobody
+// drains it
+pub struct SampleBuffer {
+    frames: VecDeque<(i16, i16)>,
+    capacity: usize,
+}
+
+impl SampleBuffer {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            frames: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    pub fn push(&mut self, left: i16, right: i16) {
+        if self.frames.len() == self.capacity {
+            self.frames.pop_front();
+        }
+        self.frames.push_back((left, right));
+    }
+
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    // take every buffered frame, oldest first
+    pub fn drain(&mut self) -> impl Iterator<Item = (i16, i16)> + '_ {
+        self.frames.drain(..)
+    }
+}
+
+impl Default for SampleBuffer {
+    fn default() -> Self {
+        Self::new(BUFFER_CAPACITY)
+    }
+}
@@ -0,0 +1,95 @@
+use crate::apu::dsp::regs::*;
+
+// the FIR filter runs over the last 8 samples read from the echo buffer
+pub const FIR_TAPS: usize = 8;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Echo {
+    pub offset: u16,
+    // EDL is only picked up when the buffer wraps around
+    pub length: u16,
+    pub history: [[i32; 2]; FIR_TAPS],
+    pub history_pos: usize,
+}
+
+impl Echo {
+    pub fn new() -> Self {
+        Self {
+            offset: 0,
+            length: 0,
+            history: [[0; 2]; FIR_TAPS],
+            history_pos: 0,
+        }
+    }
+}
+
+impl Default for Echo {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Dsp {
+    // mix the echo buffer into the voice output and feed the echo voices back into it
+    pub(crate) fn run_echo(
+        &mut self,
+        main: [i32; 2],
+        echo_in: [i32; 2],
+        aram: &mut [u8],
+    ) -> (i16, i16) {
+        let regs = &self.regs;
+        let echo = &mut self.echo;
+        let addr = ((regs[ESA] as u16) << 8).wrapping_add(echo.offset);
+
+        // read the oldest entry, it becomes the newest FIR input
+        let pos = echo.history_pos;
+        for ch in 0..2 {
+            let sample = read_aram_word(aram, addr.wrapping_add(ch as u16 * 2)) as i16 as i32;
+            echo.history[pos][ch] = sample >> 1;
+        }
+        echo.history_pos = (pos + 1) % FIR_TAPS;
+
+        let mut filtered = [0i32; 2];
+        for (ch, out) in filtered.iter_mut().enumerate() {
+            // C0 applies to the oldest sample, C7 to the newest
+            let tap = |i: usize| {
+                let coefficient = regs[FIR | (i << 4)] as i8 as i32;
+                (echo.history[(pos + 1 + i) % FIR_TAPS][ch] * coefficient) >> 6
+            };
+            let sum: i32 = (0..FIR_TAPS - 1).map(tap).sum();
+            *out = clamp16(sum as i16 as i32 + tap(FIR_TAPS - 1));
+        }
+
+        let mut output = [0i32; 2];
+        for ch in 0..2 {
+            let mvol = regs[MVOLL | (ch << 4)] as i8 as i32;
+            let evol = regs[EVOLL | (ch << 4)] as i8 as i32;
+            output[ch] = clamp16(((main[ch] * mvol) >> 7) + ((filtered[ch] * evol) >> 7));
+        }
+        if regs[FLG] & FLG_MUTE > 0 {
+            output = [0, 0];
+        }
+
+        if regs[FLG] & FLG_ECHO_DISABLE == 0 {
+            let efb = regs[EFB] as i8 as i32;
+            for ch in 0..2 {
+                let sample = clamp16(echo_in[ch] + ((filtered[ch] * efb) >> 7)) & !1;
+                let [lo, hi] = (sample as i16).to_le_bytes();
+                let addr = addr.wrapping_add(ch as u16 * 2);
+                aram[addr as usize] = lo;
+                aram[addr.wrapping_add(1) as usize] = hi;
+            }
+        }
+
+        // each entry is 4 bytes, EDL counts 2K blocks and 0 means a single entry
+        if echo.offset == 0 {
+            echo.length = (regs[EDL] & 0xF) as u16 * 0x800;
+        }
+        echo.offset += 4;
+        if echo.offset >= echo.length {
+            echo.offset = 0;
+        }
+
+        (output[0] as i16, output[1] as i16)
+    }
+}
@@ -0,0 +1,192 @@
+// the global counter counts down from here, every rate period divides it evenly
+pub const COUNTER_RANGE: u32 = 2048 * 5 * 3;
+
+// period in samples of each of the 32 rates, rate 0 never fires
+#[rustfmt::skip]
+const COUNTER_RATES: [u32; 32] = [
+    COUNTER_RANGE + 1,
+    2048, 1536, 1280, 1024, 768, 640, 512, 384, 320, 256, 192, 160, 128, 96, 80, 64, 48, 40, 32,
+    24, 20, 16, 12, 10, 8, 6, 5, 4, 3, 2, 1,
+];
+
+// phase of each rate relative to the counter
+#[rustfmt::skip]
+const COUNTER_OFFSETS: [u32; 32] = [
+    1, 0, 1040, 536, 0, 1040, 536, 0, 1040, 536, 0, 1040, 536, 0, 1040, 536, 0, 1040, 536, 0, 1040,
+    536, 0, 1040, 536, 0, 1040, 536, 0, 1040, 0, 0,
+];
+
+// whether an event running at `rate` happens on the current sample
+pub fn counter_fires(counter: u32, rate: usize) -> bool {
+    (counter + COUNTER_OFFSETS[rate]).is_multiple_of(COUNTER_RATES[rate])
+}
+
+// ADSR1 - bit 7 selects ADSR over GAIN
+pub const ADSR_ENABLE: u8 = 0x1 << 7;
+
+pub const ENVELOPE_MAX: i32 = 0x7FF;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EnvelopeMode {
+    Attack,
+    Decay,
+    Sustain,
+    Release,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Envelope {
+    pub mode: EnvelopeMode,
+    pub level: i32,
+    // the last computed level, even when the counter did not allow it to be applied
+    pub hidden: i32,
+}
+
+impl Envelope {
+    pub fn new() -> Self {
+        Self {
+            mode: EnvelopeMode::Release,
+            level: 0,
+            hidden: 0,
+        }
+    }
+
+    pub fn silence(&mut self) {
+        self.mode = EnvelopeMode::Release;
+        self.level = 0;
+    }
+
+    // advance the envelope by one sample
+    pub fn run(&mut self, adsr1: u8, adsr2: u8, gain: u8, counter: u32) {
+        let mut env = self.level;
+
+        // release ignores the counter, it always drops 8 per sample
+        if self.mode == EnvelopeMode::Release {
+            self.level = (env - 0x8).max(0);
+            return;
+        }
+
+        let rate;
+        let mut env_data = adsr2;
+        if adsr1 & ADSR_ENABLE > 0 {
+            match self.mode {
+                EnvelopeMode::Attack => {
+                    rate = ((adsr1 & 0x0F) * 2 + 1) as usize;
+                    env += match rate < 31 {
+                        true => 0x20,
+                        false => 0x400,
+                    };
+                }
+                _ => {
+                    env -= 1;
+                    env -= env >> 8;
+                    rate = match self.mode {
+                        EnvelopeMode::Decay => (((adsr1 >> 3) & 0x0E) + 0x10) as usize,
+                        _ => (adsr2 & 0x1F) as usize,
+                    };
+                }
+            }
+        } else {
+            env_data = gain;
+            let mode = gain >> 5;
+            match mode {
+                // direct
+                0..=3 => {
+                    env = gain as i32 * 0x10;
+                    rate = 31;
+                }
+                _ => {
+                    rate = (gain & 0x1F) as usize;
+                    match mode {
+                        // linear decrease
+                        4 => env -= 0x20,
+                        // exponential decrease
+                        5 => {
+                            env -= 1;
+                            env -= env >> 8;
+                        }
+                        // linear increase, mode 7 slows down past 3/4
+                        _ => {
+                            env += 0x20;
+                            if mode == 7 && self.hidden >= 0x600 {
+                                env += 0x8 - 0x20;
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        // decay ends when the level reaches the sustain level
+        if env >> 8 == (env_data >> 5) as i32 && self.mode == EnvelopeMode::Decay {
+            self.mode = EnvelopeMode::Sustain;
+        }
+        self.hidden = env;
+
+        // linear decrease going negative also ends up here
+        if !(0..=ENVELOPE_MAX).contains(&env) {
+            env = env.clamp(0, ENVELOPE_MAX);
+            if self.mode == EnvelopeMode::Attack {
+                self.mode = EnvelopeMode::Decay;
+            }
+        }
+
+        if counter_fires(counter, rate) {
+            self.level = env;
+        }
+    }
+}
+
+impl Default for Envelope {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn adsr_attack_decay_sustain() {
+        let mut env = Envelope::new();
+        env.mode = EnvelopeMode::Attack;
+
+        // fastest attack and decay, sustain level 4/8, sustain rate off
+        let adsr1 = ADSR_ENABLE | 0x7F;
+        let adsr2 = 0x80;
+        env.run(adsr1, adsr2, 0, 0);
+        assert_eq!(env.level, 0x400);
+        env.run(adsr1, adsr2, 0, 0);
+        assert_eq!(env.level, 0x7FF);
+        assert_eq!(env.mode, EnvelopeMode::Decay);
+
+        let mut counter = COUNTER_RANGE;
+        while env.mode == EnvelopeMode::Decay {
+            counter -= 1;
+            env.run(adsr1, adsr2, 0, counter);
+        }
+        // the level itself only follows on the next tick of the decay rate
+        assert_eq!(env.hidden >> 8, 4);
+        assert!(env.level >> 8 <= 5);
+
+        // rate 0 never changes the level
+        let level = env.level;
+        for counter in 0..100 {
+            env.run(adsr1, adsr2, 0, counter);
+        }
+        assert_eq!(env.level, level);
+    }
+
+    #[test]
+    fn gain_direct_and_release() {
+        let mut env = Envelope::new();
+        env.mode = EnvelopeMode::Attack;
+        env.run(0, 0, 0x40, 0);
+        assert_eq!(env.level, 0x400);
+
+        env.mode = EnvelopeMode::Release;
+        env.run(0, 0, 0x40, 0);
+        assert_eq!(env.level, 0x3F8);
+    }
+}
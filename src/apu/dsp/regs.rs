@@ -0,0 +1,302 @@
+use crate::apu::dsp::buffer::SampleBuffer;
+use crate::apu::dsp::echo::{Echo, FIR_TAPS};
+use crate::apu::dsp::envelope::{COUNTER_RANGE, EnvelopeMode, counter_fires};
+use crate::apu::dsp::voice::{BRR_BUF_SIZE, Voice};
+use crate::movie::state::{SaveState, StateReader, StateWriter};
+use std::error::Error;
+
+// SPC700 cycles per output sample (1.024MHz / 32kHz)
+pub const CYCLES_PER_SAMPLE: u64 = 32;
+pub const SAMPLE_RATE: u32 = 32000;
+
+pub const VOICES: usize = 8;
+
+// per voice registers, at $x0-$x9 for voice x
+pub const V_VOLL: usize = 0x0;
+pub const V_VOLR: usize = 0x1;
+pub const V_PITCHL: usize = 0x2;
+pub const V_PITCHH: usize = 0x3;
+pub const V_SRCN: usize = 0x4;
+pub const V_ADSR1: usize = 0x5;
+pub const V_ADSR2: usize = 0x6;
+pub const V_GAIN: usize = 0x7;
+pub const V_ENVX: usize = 0x8;
+pub const V_OUTX: usize = 0x9;
+
+// global registers
+pub const MVOLL: usize = 0x0C;
+pub const MVOLR: usize = 0x1C;
+pub const EVOLL: usize = 0x2C;
+pub const EVOLR: usize = 0x3C;
+pub const KON: usize = 0x4C;
+pub const KOFF: usize = 0x5C;
+pub const FLG: usize = 0x6C;
+pub const ENDX: usize = 0x7C;
+pub const EFB: usize = 0x0D;
+pub const PMON: usize = 0x2D;
+pub const NON: usize = 0x3D;
+pub const EON: usize = 0x4D;
+pub const DIR: usize = 0x5D;
+pub const ESA: usize = 0x6D;
+pub const EDL: usize = 0x7D;
+// FIR coefficients C0-C7 at $0F-$7F
+pub const FIR: usize = 0x0F;
+
+// FLG bits, the low 5 bits are the noise rate
+pub const FLG_RESET: u8 = 0x1 << 7;
+pub const FLG_MUTE: u8 = 0x1 << 6;
+pub const FLG_ECHO_DISABLE: u8 = 0x1 << 5;
+
+pub fn clamp16(val: i32) -> i32 {
+    val.clamp(i16::MIN as i32, i16::MAX as i32)
+}
+
+pub(crate) fn read_aram_word(aram: &[u8], addr: u16) -> u16 {
+    u16::from_le_bytes([aram[addr as usize], aram[addr.wrapping_add(1) as usize]])
+}
+
+pub struct Dsp {
+    pub regs: [u8; 128],
+    pub voices: [Voice; VOICES],
+    pub echo: Echo,
+    pub output: SampleBuffer,
+    pub(crate) counter: u32,
+    pub(crate) noise: i32,
+    // KON writes are only seen every other sample
+    pub(crate) new_kon: u8,
+    every_other_sample: bool,
+    cycles: u64,
+}
+
+impl Dsp {
+    pub fn new() -> Self {
+        let mut regs = [0u8; 128];
+        regs[FLG] = FLG_RESET | FLG_MUTE | FLG_ECHO_DISABLE;
+
+        Self {
+            regs,
+            voices: [Voice::new(); VOICES],
+            echo: Echo::new(),
+            output: SampleBuffer::default(),
+            counter: 0,
+            noise: 0x4000,
+            new_kon: 0,
+            every_other_sample: true,
+            cycles: 0,
+        }
+    }
+
+    pub fn read_register(&self, addr: u8) -> u8 {
+        self.regs[(addr & 0x7F) as usize]
+    }
+
+    pub fn write_register(&mut self, addr: u8, val: u8) {
+        let addr = addr as usize;
+        self.regs[addr] = val;
+        match addr {
+            KON => self.new_kon = val,
+            // writing any value acknowledges every voice
+            ENDX => self.regs[ENDX] = 0,
+            _ => {}
+        }
+    }
+
+    // the SPC700 has spent some cycles, produce the samples that are due
+    pub fn clock(&mut self, cycles: u64, aram: &mut [u8]) {
+        self.cycles += cycles;
+        while self.cycles >= CYCLES_PER_SAMPLE {
+            self.cycles -= CYCLES_PER_SAMPLE;
+            self.run_sample(aram);
+        }
+    }
+
+    pub fn run_sample(&mut self, aram: &mut [u8]) {
+        self.counter = match self.counter {
+            0 => COUNTER_RANGE - 1,
+            counter => counter - 1,
+        };
+
+        if counter_fires(self.counter, (self.regs[FLG] & 0x1F) as usize) {
+            let feedback = (self.noise << 13) ^ (self.noise << 14);
+            self.noise = (feedback & 0x4000) ^ (self.noise >> 1);
+        }
+
+        self.every_other_sample = !self.every_other_sample;
+        let (kon, koff) = match self.every_other_sample {
+            true => (std::mem::take(&mut self.new_kon), self.regs[KOFF]),
+            false => (0, 0),
+        };
+
+        let mut main = [0i32; 2];
+        let mut echo = [0i32; 2];
+        let mut previous = 0;
+        for v in 0..VOICES {
+            let output = self.run_voice(v, previous, kon, koff, aram);
+            previous = output;
+
+            for ch in 0..2 {
+                let volume = self.regs[(v << 4) | (V_VOLL + ch)] as i8 as i32;
+                let amplitude = (output * volume) >> 7;
+                main[ch] = clamp16(main[ch] + amplitude);
+                if self.regs[EON] & (0x1 << v) > 0 {
+                    echo[ch] = clamp16(echo[ch] + amplitude);
+                }
+            }
+        }
+
+        let (left, right) = self.run_echo(main, echo, aram);
+        self.output.push(left, right);
+    }
+}
+
+impl Default for Dsp {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// the samples waiting in the output buffer aren't part of a save state
+impl SaveState for Dsp {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.bytes(&self.regs);
+        for voice in &self.voices {
+            for sample in voice.buf {
+                w.i32(sample);
+            }
+            w.u8(voice.buf_pos as u8);
+            w.u32(voice.interp_pos);
+            w.u16(voice.brr_addr);
+            w.u16(voice.brr_offset);
+            w.u8(voice.kon_delay);
+            w.u8(voice.envelope.mode as u8);
+            w.i32(voice.envelope.level);
+            w.i32(voice.envelope.hidden);
+        }
+        w.u16(self.echo.offset);
+        w.u16(self.echo.length);
+        for [left, right] in self.echo.history {
+            w.i32(left);
+            w.i32(right);
+        }
+        w.u8(self.echo.history_pos as u8);
+        w.u32(self.counter);
+        w.i32(self.noise);
+        w.u8(self.new_kon);
+        w.bool(self.every_other_sample);
+        w.u64(self.cycles);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), Box<dyn Error>> {
+        r.bytes(&mut self.regs)?;
+        for voice in &mut self.voices {
+            for sample in &mut voice.buf {
+                *sample = r.i32()?;
+            }
+            voice.buf_pos = r.u8()? as usize % BRR_BUF_SIZE;
+            voice.interp_pos = r.u32()?;
+            voice.brr_addr = r.u16()?;
+            voice.brr_offset = r.u16()?;
+            voice.kon_delay = r.u8()?;
+            voice.envelope.mode = match r.u8()? {
+                0 => EnvelopeMode::Attack,
+                1 => EnvelopeMode::Decay,
+                2 => EnvelopeMode::Sustain,
+                _ => EnvelopeMode::Release,
+            };
+            voice.envelope.level = r.i32()?;
+            voice.envelope.hidden = r.i32()?;
+        }
+        self.echo.offset = r.u16()?;
+        self.echo.length = r.u16()?;
+        for [left, right] in &mut self.echo.history {
+            *left = r.i32()?;
+            *right = r.i32()?;
+        }
+        self.echo.history_pos = r.u8()? as usize % FIR_TAPS;
+        self.counter = r.u32()?;
+        self.noise = r.i32()?;
+        self.new_kon = r.u8()?;
+        self.every_other_sample = r.bool()?;
+        self.cycles = r.u64()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::apu::dsp::envelope::EnvelopeMode;
+
+    // a square wave in a single looping block at $1000, directory at $0200
+    fn setup() -> (Dsp, Vec<u8>) {
+        let mut aram = vec![0u8; 0x10000];
+        aram[0x200..0x204].copy_from_slice(&[0x00, 0x10, 0x00, 0x10]);
+        aram[0x1000] = 0xB3;
+        aram[0x1001..0x1005].fill(0x77);
+        aram[0x1005..0x1009].fill(0x99);
+
+        let mut dsp = Dsp::new();
+        dsp.write_register(FLG as u8, FLG_ECHO_DISABLE);
+        dsp.write_register(MVOLL as u8, 0x7F);
+        dsp.write_register(MVOLR as u8, 0x7F);
+        dsp.write_register(DIR as u8, 0x02);
+        dsp.write_register(V_VOLL as u8, 0x7F);
+        dsp.write_register(V_VOLR as u8, 0x40);
+        dsp.write_register(V_PITCHH as u8, 0x10);
+        dsp.write_register(V_ADSR1 as u8, 0x8F);
+        dsp.write_register(V_ADSR2 as u8, 0xE0);
+        (dsp, aram)
+    }
+
+    #[test]
+    fn key_on_plays_and_loops() {
+        let (mut dsp, mut aram) = setup();
+        dsp.write_register(KON as u8, 0x01);
+        dsp.clock(CYCLES_PER_SAMPLE * 64, &mut aram);
+
+        // sustain level 7/8 is reached as soon as the attack ends
+        assert_eq!(dsp.voices[0].envelope.mode, EnvelopeMode::Sustain);
+        assert_eq!(dsp.regs[ENDX], 0x01);
+        let frames: Vec<(i16, i16)> = dsp.output.drain().collect();
+        assert_eq!(frames.len(), 64);
+        assert!(frames.iter().any(|&(l, _)| l > 0x1000));
+        assert!(frames.iter().any(|&(l, _)| l < -0x1000));
+        // right is at half volume
+        assert!(
+            frames
+                .iter()
+                .all(|&(l, r)| (r as i32).abs() * 3 <= (l as i32).abs() * 2 + 3)
+        );
+
+        // key off releases the voice
+        dsp.write_register(KOFF as u8, 0x01);
+        dsp.clock(CYCLES_PER_SAMPLE * 400, &mut aram);
+        assert_eq!(dsp.voices[0].envelope.level, 0);
+    }
+
+    #[test]
+    fn echo_writes_buffer() {
+        let (mut dsp, mut aram) = setup();
+        dsp.write_register(FLG as u8, 0);
+        dsp.write_register(EON as u8, 0x01);
+        dsp.write_register(ESA as u8, 0x80);
+        dsp.write_register(EDL as u8, 0x01);
+        dsp.write_register(FIR as u8 + 0x70, 0x7F);
+        dsp.write_register(EVOLL as u8, 0x7F);
+        dsp.write_register(KON as u8, 0x01);
+        dsp.clock(CYCLES_PER_SAMPLE * 64, &mut aram);
+
+        assert!(aram[0x8000..0x8800].iter().any(|&b| b != 0));
+        assert_eq!(dsp.echo.length, 0x800);
+    }
+
+    #[test]
+    fn noise_lfsr() {
+        let mut dsp = Dsp::new();
+        let mut aram = vec![0u8; 0x10000];
+        dsp.write_register(FLG as u8, 0x1F);
+        let noise = dsp.noise;
+        dsp.run_sample(&mut aram);
+        assert_ne!(dsp.noise, noise);
+    }
+}
@@ -0,0 +1,159 @@
+use crate::apu::dsp::brr::{self, BRR_BLOCK_SIZE, BRR_END, BRR_LOOP};
+use crate::apu::dsp::envelope::{Envelope, EnvelopeMode};
+use crate::apu::dsp::gaussian;
+use crate::apu::dsp::regs::*;
+
+// decoded samples kept around for the interpolator, three groups of four
+pub const BRR_BUF_SIZE: usize = 12;
+
+// samples between KON and the voice being audible
+const KON_DELAY: u8 = 5;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Voice {
+    pub buf: [i32; BRR_BUF_SIZE],
+    pub buf_pos: usize,
+    // 4.12 fixed point position inside the decoded samples
+    pub interp_pos: u32,
+    pub brr_addr: u16,
+    pub brr_offset: u16,
+    pub kon_delay: u8,
+    pub envelope: Envelope,
+}
+
+impl Voice {
+    pub fn new() -> Self {
+        Self {
+            buf: [0; BRR_BUF_SIZE],
+            buf_pos: 0,
+            interp_pos: 0,
+            brr_addr: 0,
+            brr_offset: 1,
+            kon_delay: 0,
+            envelope: Envelope::new(),
+        }
+    }
+
+    fn decode_brr(&mut self, aram: &[u8]) {
+        let header = aram[self.brr_addr as usize];
+        let addr = self.brr_addr.wrapping_add(self.brr_offset);
+        let nybbles =
+            u16::from_be_bytes([aram[addr as usize], aram[addr.wrapping_add(1) as usize]]);
+
+        let older = self.buf[(self.buf_pos + BRR_BUF_SIZE - 2) % BRR_BUF_SIZE];
+        let old = self.buf[(self.buf_pos + BRR_BUF_SIZE - 1) % BRR_BUF_SIZE];
+        let samples = brr::decode(header, nybbles, older, old);
+        self.buf[self.buf_pos..self.buf_pos + 4].copy_from_slice(&samples);
+        self.buf_pos = (self.buf_pos + 4) % BRR_BUF_SIZE;
+    }
+
+    fn interpolate(&self) -> i32 {
+        let first = (self.interp_pos >> 12) as usize + self.buf_pos;
+        let samples = [0, 1, 2, 3].map(|i| self.buf[(first + i) % BRR_BUF_SIZE]);
+        gaussian::interpolate(samples, self.interp_pos)
+    }
+}
+
+impl Default for Voice {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Dsp {
+    // produce one sample for voice `v`, `pmon_input` is the output of the voice before it
+    pub(crate) fn run_voice(
+        &mut self,
+        v: usize,
+        pmon_input: i32,
+        kon: u8,
+        koff: u8,
+        aram: &[u8],
+    ) -> i32 {
+        let bit = 0x1 << v;
+        let base = v << 4;
+        let regs = &mut self.regs;
+
+        let mut pitch =
+            (u16::from_le_bytes([regs[base | V_PITCHL], regs[base | V_PITCHH]]) & 0x3FFF) as i32;
+        // voice 0 has nothing to be modulated by
+        if v > 0 && regs[PMON] & bit > 0 {
+            pitch += ((pmon_input >> 5) * pitch) >> 10;
+        }
+
+        // sample directory entries are a start address followed by a loop address
+        let entry = ((regs[DIR] as u16) << 8).wrapping_add(regs[base | V_SRCN] as u16 * 4);
+        let voice = &mut self.voices[v];
+
+        if voice.kon_delay > 0 {
+            if voice.kon_delay == KON_DELAY {
+                voice.brr_addr = read_aram_word(aram, entry);
+                voice.brr_offset = 1;
+                voice.buf_pos = 0;
+                regs[ENDX] &= !bit;
+            }
+            // no envelope and no pitch while starting up, only the first blocks are decoded
+            voice.envelope.level = 0;
+            voice.envelope.hidden = 0;
+            voice.kon_delay -= 1;
+            voice.interp_pos = match voice.kon_delay & 0x3 {
+                0 => 0,
+                _ => 0x4000,
+            };
+            pitch = 0;
+        }
+
+        let mut output = voice.interpolate();
+        if regs[NON] & bit > 0 {
+            output = (self.noise * 2) as i16 as i32;
+        }
+        let output = ((output * voice.envelope.level) >> 11) & !1;
+        regs[base | V_ENVX] = (voice.envelope.level >> 4) as u8;
+        regs[base | V_OUTX] = (output >> 8) as u8;
+
+        // a soft reset or an end block without the loop flag silences the voice immediately
+        let header = aram[voice.brr_addr as usize];
+        if regs[FLG] & FLG_RESET > 0
+            || (voice.kon_delay == 0 && header & (BRR_END | BRR_LOOP) == BRR_END)
+        {
+            voice.envelope.silence();
+        }
+
+        if koff & bit > 0 {
+            voice.envelope.mode = EnvelopeMode::Release;
+        }
+        if kon & bit > 0 {
+            voice.kon_delay = KON_DELAY;
+            voice.envelope.mode = EnvelopeMode::Attack;
+        }
+
+        if voice.kon_delay == 0 {
+            voice.envelope.run(
+                regs[base | V_ADSR1],
+                regs[base | V_ADSR2],
+                regs[base | V_GAIN],
+                self.counter,
+            );
+        }
+
+        if voice.interp_pos >= 0x4000 {
+            voice.decode_brr(aram);
+            voice.brr_offset += 2;
+            if voice.brr_offset >= BRR_BLOCK_SIZE {
+                voice.brr_offset = 1;
+                match header & BRR_END > 0 {
+                    true => {
+                        voice.brr_addr = read_aram_word(aram, entry.wrapping_add(2));
+                        regs[ENDX] |= bit;
+                    }
+                    false => voice.brr_addr = voice.brr_addr.wrapping_add(BRR_BLOCK_SIZE),
+                }
+            }
+        }
+
+        // pitch modulation can push the position too far ahead
+        voice.interp_pos = ((voice.interp_pos & 0x3FFF) + pitch as u32).min(0x7FFF);
+
+        output
+    }
+}
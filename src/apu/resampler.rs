@@ -0,0 +1,74 @@
+// linear interpolation between two sample rates, in exact integer steps so recordings do not
+// drift from the emulated output
+pub struct Resampler {
+    input_rate: u64,
+    output_rate: u64,
+    previous: (i16, i16),
+    // time of the next output frame after `previous`, one input frame is `output_rate` long
+    position: u64,
+}
+
+impl Resampler {
+    pub fn new(input_rate: u32, output_rate: u32) -> Self {
+        Self {
+            input_rate: input_rate as u64,
+            output_rate: output_rate as u64,
+            previous: (0, 0),
+            position: 0,
+        }
+    }
+
+    // feed one input frame, `emit` is called for every output frame that is now complete
+    pub fn push(&mut self, frame: (i16, i16), mut emit: impl FnMut((i16, i16))) {
+        while self.position < self.output_rate {
+            let lerp = |a: i16, b: i16| {
+                let a = a as i64;
+                let b = b as i64;
+                (a + (b - a) * self.position as i64 / self.output_rate as i64) as i16
+            };
+            emit((
+                lerp(self.previous.0, frame.0),
+                lerp(self.previous.1, frame.1),
+            ));
+            self.position += self.input_rate;
+        }
+        self.position -= self.output_rate;
+        self.previous = frame;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn resample(input_rate: u32, output_rate: u32, frames: usize) -> Vec<(i16, i16)> {
+        let mut resampler = Resampler::new(input_rate, output_rate);
+        let mut out = Vec::new();
+        for i in 0..frames {
+            let s = (i % 300) as i16 * 100;
+            resampler.push((s, -s), |f| out.push(f));
+        }
+        out
+    }
+
+    #[test]
+    fn upsample_ratio() {
+        assert_eq!(resample(32000, 48000, 32000).len(), 48000);
+        assert_eq!(resample(32000, 44100, 32000).len(), 44100);
+    }
+
+    #[test]
+    fn upsample_interpolates() {
+        let out = resample(32000, 48000, 4);
+        assert_eq!(
+            out[..5],
+            [(0, 0), (0, 0), (33, -33), (100, -100), (166, -166)]
+        );
+    }
+
+    #[test]
+    fn same_rate_is_delayed_copy() {
+        let out = resample(32000, 32000, 4);
+        assert_eq!(out, [(0, 0), (0, 0), (100, -100), (200, -200)]);
+    }
+}
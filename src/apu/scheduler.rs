@@ -0,0 +1,133 @@
+use crate::apu::dsp::buffer::SampleBuffer;
+use crate::apu::spc700::alu::Spc700;
+use crate::movie::state::{SaveState, StateReader, StateWriter};
+use std::error::Error;
+
+// both cores are derived from their own crystals (21.477MHz and 24.576MHz / 24)
+pub const MASTER_CLOCK_HZ: u64 = 21_477_272;
+pub const SPC_CLOCK_HZ: u64 = 1_024_000;
+
+// Runs the SPC700 in lockstep with the main CPU. Time is kept as a balance where one master
+// cycle is worth SPC_CLOCK_HZ and one SPC700 cycle is worth MASTER_CLOCK_HZ, so the ratio is
+// exact and the SPC700 never drifts. The SPC700 catches up whenever the CPU touches the
+// ports, which makes the mailbox handshakes deterministic.
+pub struct Scheduler {
+    pub spc: Spc700,
+    pub master_clock_hz: u64,
+    balance: i64,
+}
+
+impl Scheduler {
+    pub fn new() -> Self {
+        Self {
+            spc: Spc700::new(Box::default()),
+            master_clock_hz: MASTER_CLOCK_HZ,
+            balance: 0,
+        }
+    }
+
+    // the main CPU side has spent some master cycles
+    pub fn advance(&mut self, master_cycles: u64) {
+        self.balance += (master_cycles * SPC_CLOCK_HZ) as i64;
+    }
+
+    // run the SPC700 until it is level with the main CPU
+    pub fn catch_up(&mut self) {
+        while self.balance > 0 {
+            let cycles = self.spc.step();
+            self.balance -= (cycles * self.master_clock_hz) as i64;
+        }
+    }
+
+    // APUIO0-3 as read by the main CPU ($2140-$2143)
+    pub fn read_port(&mut self, port: usize) -> u8 {
+        self.catch_up();
+        self.spc.bus.ports_out[port]
+    }
+
+    // APUIO0-3 as written by the main CPU ($2140-$2143)
+    pub fn write_port(&mut self, port: usize, val: u8) {
+        self.catch_up();
+        self.spc.bus.ports_in[port] = val;
+    }
+
+    // the sound output so far, up to the current main CPU cycle
+    pub fn samples(&mut self) -> &mut SampleBuffer {
+        self.catch_up();
+        &mut self.spc.bus.dsp.output
+    }
+}
+
+impl Default for Scheduler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SaveState for Scheduler {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.i64(self.balance);
+        self.spc.save_state(w);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), Box<dyn Error>> {
+        self.balance = r.i64()?;
+        self.spc.load_state(r)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cpu::bus::Bus;
+
+    fn wait_for(b: &mut Bus, port: u32, value: u8) {
+        for _ in 0..100_000 {
+            if b.read_byte(port) == value {
+                return;
+            }
+        }
+        panic!("timeout waiting for 0x{:X} on 0x{:X}", value, port);
+    }
+
+    #[test]
+    fn ipl_handshake_and_upload() {
+        let mut b = Bus::new();
+        wait_for(&mut b, 0x2140, 0xAA);
+        wait_for(&mut b, 0x2141, 0xBB);
+
+        // MOV $F5, #$5A ; BRA -2
+        let program = [0x8F, 0x5A, 0xF5, 0x2F, 0xFE];
+
+        // transfer to $0200
+        b.write_byte(0x2141, 0x01);
+        b.write_byte(0x2142, 0x00);
+        b.write_byte(0x2143, 0x02);
+        b.write_byte(0x2140, 0xCC);
+        wait_for(&mut b, 0x2140, 0xCC);
+
+        for (i, byte) in program.iter().enumerate() {
+            b.write_byte(0x2141, *byte);
+            b.write_byte(0x2140, i as u8);
+            wait_for(&mut b, 0x2140, i as u8);
+        }
+
+        // jump to $0200
+        b.write_byte(0x2141, 0x00);
+        b.write_byte(0x2142, 0x00);
+        b.write_byte(0x2143, 0x02);
+        b.write_byte(0x2140, program.len() as u8 + 1);
+        wait_for(&mut b, 0x2141, 0x5A);
+
+        assert_eq!(&b.apu.spc.bus.aram[0x200..0x205], &program);
+    }
+
+    #[test]
+    fn clock_ratio() {
+        let mut b = Bus::new();
+        // one NTSC frame worth of master cycles
+        b.tick(1364 * 262);
+        b.apu.catch_up();
+        let expected = 1364 * 262 * super::SPC_CLOCK_HZ / super::MASTER_CLOCK_HZ;
+        assert!(b.apu.spc.cycles >= expected && b.apu.spc.cycles < expected + 16);
+    }
+}
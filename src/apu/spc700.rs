@@ -0,0 +1,29 @@
+pub mod alu;
+pub mod bus;
+mod op_adc;
+mod op_addw;
+mod op_and;
+mod op_asl;
+mod op_bit;
+mod op_branch;
+mod op_call;
+mod op_cmp;
+mod op_daa;
+mod op_dec;
+mod op_div;
+mod op_eor;
+mod op_flags;
+mod op_inc;
+mod op_incw;
+mod op_jmp;
+mod op_lsr;
+mod op_mov;
+mod op_movw;
+mod op_mul;
+mod op_nop;
+mod op_or;
+mod op_push;
+mod op_rol;
+mod op_ror;
+mod op_sbc;
+mod op_xcn;
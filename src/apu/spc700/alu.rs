@@ -0,0 +1,572 @@
+use crate::apu::spc700::bus::SpcBus;
+use crate::movie::state::{SaveState, StateReader, StateWriter};
+use log::debug;
+use std::error::Error;
+
+pub const P_CARRY: u8 = 0x1;
+pub const P_ZERO: u8 = 0x1 << 1;
+pub const P_IRQ_ENABLE: u8 = 0x1 << 2;
+pub const P_HALF_CARRY: u8 = 0x1 << 3;
+pub const P_BREAK: u8 = 0x1 << 4;
+pub const P_DIRECT_PAGE: u8 = 0x1 << 5;
+pub const P_OVERFLOW: u8 = 0x1 << 6;
+pub const P_NEGATIVE: u8 = 0x1 << 7;
+
+pub const RESET_VECTOR: u16 = 0xFFFE;
+pub const STACK_PAGE: u16 = 0x100;
+
+// base cycles per opcode, taken branches add 2 more
+pub const CYCLES: [u8; 256] = [
+    2, 8, 4, 5, 3, 4, 3, 6, 2, 6, 5, 4, 5, 4, 6, 8, // 0x
+    2, 8, 4, 5, 4, 5, 5, 6, 5, 5, 6, 5, 2, 2, 4, 6, // 1x
+    2, 8, 4, 5, 3, 4, 3, 6, 2, 6, 5, 4, 5, 4, 5, 4, // 2x
+    2, 8, 4, 5, 4, 5, 5, 6, 5, 5, 6, 5, 2, 2, 3, 8, // 3x
+    2, 8, 4, 5, 3, 4, 3, 6, 2, 6, 4, 4, 5, 4, 6, 6, // 4x
+    2, 8, 4, 5, 4, 5, 5, 6, 5, 5, 4, 5, 2, 2, 4, 3, // 5x
+    2, 8, 4, 5, 3, 4, 3, 6, 2, 6, 4, 4, 5, 4, 5, 5, // 6x
+    2, 8, 4, 5, 4, 5, 5, 6, 5, 5, 5, 5, 2, 2, 3, 6, // 7x
+    2, 8, 4, 5, 3, 4, 3, 6, 2, 6, 5, 4, 5, 2, 4, 5, // 8x
+    2, 8, 4, 5, 4, 5, 5, 6, 5, 5, 5, 5, 2, 2, 12, 5, // 9x
+    3, 8, 4, 5, 3, 4, 3, 6, 2, 6, 4, 4, 5, 2, 4, 4, // Ax
+    2, 8, 4, 5, 4, 5, 5, 6, 5, 5, 5, 5, 2, 2, 3, 4, // Bx
+    3, 8, 4, 5, 4, 5, 4, 7, 2, 5, 6, 4, 5, 2, 4, 9, // Cx
+    2, 8, 4, 5, 5, 6, 6, 7, 4, 5, 5, 5, 2, 2, 6, 3, // Dx
+    2, 8, 4, 5, 3, 4, 3, 6, 2, 4, 5, 3, 4, 3, 4, 3, // Ex
+    2, 8, 4, 5, 4, 5, 5, 6, 3, 4, 5, 4, 2, 2, 4, 3, // Fx
+];
+
+pub struct Spc700 {
+    pub bus: Box<SpcBus>,
+    pub reg_a: u8,
+    pub reg_x: u8,
+    pub reg_y: u8,
+    pub sp: u8,
+    pub pc: u16,
+    pub psw: u8,
+    pub cycles: u64,
+    pub halted: bool,
+    // extra cycles spent by the current instruction (taken branches)
+    pub(crate) extra_cycles: u8,
+}
+
+// where the result of a two-operand ALU instruction goes
+#[derive(Debug, Clone, Copy)]
+pub enum Operand {
+    A,
+    X,
+    Y,
+    Memory(u16),
+}
+
+impl Spc700 {
+    pub fn new(bus: Box<SpcBus>) -> Self {
+        let mut spc = Self {
+            bus,
+            reg_a: 0x0,
+            reg_x: 0x0,
+            reg_y: 0x0,
+            sp: 0xEF,
+            pc: 0x0,
+            psw: 0x2,
+            cycles: 0,
+            halted: false,
+            extra_cycles: 0,
+        };
+        spc.reset();
+        spc
+    }
+
+    pub fn reset(&mut self) {
+        self.bus.ipl_enabled = true;
+        self.pc = self.read_word(RESET_VECTOR);
+        self.sp = 0xEF;
+        self.psw = 0x2;
+        self.halted = false;
+    }
+
+    // run one instruction, returning the SPC700 cycles it took
+    pub fn step(&mut self) -> u64 {
+        if self.halted {
+            // SLEEP and STOP only end with a reset, keep the timers running
+            self.bus.clock(2);
+            self.cycles += 2;
+            return 2;
+        }
+
+        let opcode = self.fetch_byte();
+        self.extra_cycles = 0;
+        self.decode_and_execute(opcode);
+
+        let cycles = (CYCLES[opcode as usize] + self.extra_cycles) as u64;
+        self.bus.clock(cycles);
+        self.cycles += cycles;
+        cycles
+    }
+
+    fn decode_and_execute(&mut self, opcode: u8) {
+        match opcode {
+            // MOV Load/store/transfer
+            0xE8 | 0xE4 | 0xF4 | 0xE5 | 0xF5 | 0xF6 | 0xE6 | 0xBF | 0xE7 | 0xF7 | 0xCD | 0xF8
+            | 0xF9 | 0xE9 | 0x8D | 0xEB | 0xFB | 0xEC | 0xC4 | 0xD4 | 0xC5 | 0xD5 | 0xD6 | 0xC6
+            | 0xAF | 0xC7 | 0xD7 | 0xD8 | 0xD9 | 0xC9 | 0xCB | 0xDB | 0xCC | 0x7D | 0xDD | 0x5D
+            | 0xFD | 0x9D | 0xBD | 0xFA | 0x8F => self.op_mov(opcode),
+
+            // MOVW Word load/store
+            0xBA | 0xDA => self.op_movw(opcode),
+
+            // OR Logical OR
+            0x04 | 0x05 | 0x06 | 0x07 | 0x08 | 0x09 | 0x14 | 0x15 | 0x16 | 0x17 | 0x18 | 0x19 => {
+                self.op_or(opcode)
+            }
+
+            // AND Logical AND
+            0x24 | 0x25 | 0x26 | 0x27 | 0x28 | 0x29 | 0x34 | 0x35 | 0x36 | 0x37 | 0x38 | 0x39 => {
+                self.op_and(opcode)
+            }
+
+            // EOR Logical Exclusive-OR
+            0x44 | 0x45 | 0x46 | 0x47 | 0x48 | 0x49 | 0x54 | 0x55 | 0x56 | 0x57 | 0x58 | 0x59 => {
+                self.op_eor(opcode)
+            }
+
+            // CMP Compare
+            0x64 | 0x65 | 0x66 | 0x67 | 0x68 | 0x69 | 0x74 | 0x75 | 0x76 | 0x77 | 0x78 | 0x79
+            | 0xC8 | 0x3E | 0x1E | 0xAD | 0x7E | 0x5E => self.op_cmp(opcode),
+
+            // ADC Add with carry
+            0x84 | 0x85 | 0x86 | 0x87 | 0x88 | 0x89 | 0x94 | 0x95 | 0x96 | 0x97 | 0x98 | 0x99 => {
+                self.op_adc(opcode)
+            }
+
+            // SBC Subtract with carry
+            0xA4 | 0xA5 | 0xA6 | 0xA7 | 0xA8 | 0xA9 | 0xB4 | 0xB5 | 0xB6 | 0xB7 | 0xB8 | 0xB9 => {
+                self.op_sbc(opcode)
+            }
+
+            // INC Increment
+            0xBC | 0xAB | 0xBB | 0xAC | 0x3D | 0xFC => self.op_inc(opcode),
+
+            // DEC Decrement
+            0x9C | 0x8B | 0x9B | 0x8C | 0x1D | 0xDC => self.op_dec(opcode),
+
+            // ASL Arithmetic shift left
+            0x1C | 0x0B | 0x1B | 0x0C => self.op_asl(opcode),
+
+            // LSR Logical shift right
+            0x5C | 0x4B | 0x5B | 0x4C => self.op_lsr(opcode),
+
+            // ROL Rotate left through carry
+            0x3C | 0x2B | 0x3B | 0x2C => self.op_rol(opcode),
+
+            // ROR Rotate right through carry
+            0x7C | 0x6B | 0x7B | 0x6C => self.op_ror(opcode),
+
+            // XCN Exchange nibbles of A
+            0x9F => self.op_xcn(opcode),
+
+            // INCW/DECW Word increment/decrement
+            0x3A | 0x1A => self.op_incw(opcode),
+
+            // ADDW/SUBW/CMPW Word arithmetic with YA
+            0x7A | 0x9A | 0x5A => self.op_addw(opcode),
+
+            // MUL YA = Y * A
+            0xCF => self.op_mul(opcode),
+
+            // DIV Y = YA % X, A = YA / X
+            0x9E => self.op_div(opcode),
+
+            // DAA/DAS Decimal adjust
+            0xDF | 0xBE => self.op_daa(opcode),
+
+            // Branches, including BBS/BBC, CBNE and DBNZ
+            0x2F | 0xF0 | 0xD0 | 0xB0 | 0x90 | 0x70 | 0x50 | 0x30 | 0x10 | 0x03 | 0x23 | 0x43
+            | 0x63 | 0x83 | 0xA3 | 0xC3 | 0xE3 | 0x13 | 0x33 | 0x53 | 0x73 | 0x93 | 0xB3 | 0xD3
+            | 0xF3 | 0x2E | 0xDE | 0x6E | 0xFE => self.op_branch(opcode),
+
+            // JMP Jump
+            0x5F | 0x1F => self.op_jmp(opcode),
+
+            // CALL/PCALL/TCALL/BRK/RET/RETI Subroutines and interrupts
+            0x3F | 0x4F | 0x01 | 0x11 | 0x21 | 0x31 | 0x41 | 0x51 | 0x61 | 0x71 | 0x81 | 0x91
+            | 0xA1 | 0xB1 | 0xC1 | 0xD1 | 0xE1 | 0xF1 | 0x0F | 0x6F | 0x7F => self.op_call(opcode),
+
+            // PUSH/POP Stack
+            0x2D | 0x4D | 0x6D | 0x0D | 0xAE | 0xCE | 0xEE | 0x8E => self.op_push(opcode),
+
+            // SET1/CLR1/TSET1/TCLR1/AND1/OR1/EOR1/NOT1/MOV1 Bit operations
+            0x02 | 0x22 | 0x42 | 0x62 | 0x82 | 0xA2 | 0xC2 | 0xE2 | 0x12 | 0x32 | 0x52 | 0x72
+            | 0x92 | 0xB2 | 0xD2 | 0xF2 | 0x0E | 0x4E | 0x4A | 0x6A | 0x0A | 0x2A | 0x8A | 0xEA
+            | 0xAA | 0xCA => self.op_bit(opcode),
+
+            // CLRC/SETC/NOTC/CLRV/CLRP/SETP/EI/DI Flag operations
+            0x60 | 0x80 | 0xED | 0xE0 | 0x20 | 0x40 | 0xA0 | 0xC0 => self.op_flags(opcode),
+
+            // NOP/SLEEP/STOP
+            0x00 | 0xEF | 0xFF => self.op_nop(opcode),
+        }
+    }
+
+    pub fn read_byte(&mut self, addr: u16) -> u8 {
+        self.bus.read_byte(addr)
+    }
+
+    pub fn write_byte(&mut self, addr: u16, val: u8) {
+        self.bus.write_byte(addr, val)
+    }
+
+    pub fn read_word(&mut self, addr: u16) -> u16 {
+        let lo = self.read_byte(addr);
+        let hi = self.read_byte(addr.wrapping_add(1));
+        Self::make_word(lo, hi)
+    }
+
+    // direct page words wrap inside the page
+    pub fn read_dp_word(&mut self, addr: u8) -> u16 {
+        let lo = self.read_byte(self.dp(addr));
+        let hi = self.read_byte(self.dp(addr.wrapping_add(1)));
+        Self::make_word(lo, hi)
+    }
+
+    pub fn write_dp_word(&mut self, addr: u8, val: u16) {
+        self.write_byte(self.dp(addr), (val & 0xFF) as u8);
+        self.write_byte(self.dp(addr.wrapping_add(1)), (val >> 8) as u8);
+    }
+
+    pub fn make_word(lo: u8, hi: u8) -> u16 {
+        ((hi as u16) << 8) | lo as u16
+    }
+
+    pub fn fetch_byte(&mut self) -> u8 {
+        let value = self.read_byte(self.pc);
+        self.pc = self.pc.wrapping_add(1);
+        value
+    }
+
+    pub fn fetch_word(&mut self) -> u16 {
+        let lo = self.fetch_byte();
+        let hi = self.fetch_byte();
+        Self::make_word(lo, hi)
+    }
+
+    // direct page is $00xx or $01xx depending on the P flag
+    pub fn dp(&self, addr: u8) -> u16 {
+        match self.psw & P_DIRECT_PAGE > 0 {
+            true => 0x100 | addr as u16,
+            false => addr as u16,
+        }
+    }
+
+    pub fn ya(&self) -> u16 {
+        Self::make_word(self.reg_a, self.reg_y)
+    }
+
+    pub fn set_ya(&mut self, value: u16) {
+        self.reg_a = (value & 0xFF) as u8;
+        self.reg_y = (value >> 8) as u8;
+    }
+
+    // d
+    pub fn addr_dp(&mut self) -> u16 {
+        let d = self.fetch_byte();
+        self.dp(d)
+    }
+
+    // d+X
+    pub fn addr_dp_x(&mut self) -> u16 {
+        let d = self.fetch_byte();
+        self.dp(d.wrapping_add(self.reg_x))
+    }
+
+    // d+Y
+    pub fn addr_dp_y(&mut self) -> u16 {
+        let d = self.fetch_byte();
+        self.dp(d.wrapping_add(self.reg_y))
+    }
+
+    // !a
+    pub fn addr_abs(&mut self) -> u16 {
+        self.fetch_word()
+    }
+
+    // !a+X
+    pub fn addr_abs_x(&mut self) -> u16 {
+        self.fetch_word().wrapping_add(self.reg_x as u16)
+    }
+
+    // !a+Y
+    pub fn addr_abs_y(&mut self) -> u16 {
+        self.fetch_word().wrapping_add(self.reg_y as u16)
+    }
+
+    // (X)
+    pub fn addr_ind_x(&mut self) -> u16 {
+        self.dp(self.reg_x)
+    }
+
+    // [d+X]
+    pub fn addr_dp_x_ind(&mut self) -> u16 {
+        let d = self.fetch_byte();
+        self.read_dp_word(d.wrapping_add(self.reg_x))
+    }
+
+    // [d]+Y
+    pub fn addr_dp_ind_y(&mut self) -> u16 {
+        let d = self.fetch_byte();
+        self.read_dp_word(d).wrapping_add(self.reg_y as u16)
+    }
+
+    // decode the operands shared by OR/AND/EOR/CMP/ADC/SBC (low nibble 4-9 of each row pair)
+    pub fn fetch_alu_operands(&mut self, opcode: u8) -> (Operand, u8, u8) {
+        match opcode & 0x1F {
+            // A, d
+            0x04 => {
+                let addr = self.addr_dp();
+                (Operand::A, self.reg_a, self.read_byte(addr))
+            }
+            // A, !a
+            0x05 => {
+                let addr = self.addr_abs();
+                (Operand::A, self.reg_a, self.read_byte(addr))
+            }
+            // A, (X)
+            0x06 => {
+                let addr = self.addr_ind_x();
+                (Operand::A, self.reg_a, self.read_byte(addr))
+            }
+            // A, [d+X]
+            0x07 => {
+                let addr = self.addr_dp_x_ind();
+                (Operand::A, self.reg_a, self.read_byte(addr))
+            }
+            // A, #i
+            0x08 => (Operand::A, self.reg_a, self.fetch_byte()),
+            // dd, ds
+            0x09 => {
+                let src = self.addr_dp();
+                let rhs = self.read_byte(src);
+                let dst = self.addr_dp();
+                (Operand::Memory(dst), self.read_byte(dst), rhs)
+            }
+            // A, d+X
+            0x14 => {
+                let addr = self.addr_dp_x();
+                (Operand::A, self.reg_a, self.read_byte(addr))
+            }
+            // A, !a+X
+            0x15 => {
+                let addr = self.addr_abs_x();
+                (Operand::A, self.reg_a, self.read_byte(addr))
+            }
+            // A, !a+Y
+            0x16 => {
+                let addr = self.addr_abs_y();
+                (Operand::A, self.reg_a, self.read_byte(addr))
+            }
+            // A, [d]+Y
+            0x17 => {
+                let addr = self.addr_dp_ind_y();
+                (Operand::A, self.reg_a, self.read_byte(addr))
+            }
+            // d, #i
+            0x18 => {
+                let rhs = self.fetch_byte();
+                let dst = self.addr_dp();
+                (Operand::Memory(dst), self.read_byte(dst), rhs)
+            }
+            // (X), (Y)
+            0x19 => {
+                let rhs_addr = self.dp(self.reg_y);
+                let rhs = self.read_byte(rhs_addr);
+                let dst = self.dp(self.reg_x);
+                (Operand::Memory(dst), self.read_byte(dst), rhs)
+            }
+            _ => panic!("invalid ALU opcode 0x{:X}", opcode),
+        }
+    }
+
+    pub fn store(&mut self, target: Operand, value: u8) {
+        match target {
+            Operand::A => self.reg_a = value,
+            Operand::X => self.reg_x = value,
+            Operand::Y => self.reg_y = value,
+            Operand::Memory(addr) => self.write_byte(addr, value),
+        }
+    }
+
+    pub fn load(&mut self, target: Operand) -> u8 {
+        match target {
+            Operand::A => self.reg_a,
+            Operand::X => self.reg_x,
+            Operand::Y => self.reg_y,
+            Operand::Memory(addr) => self.read_byte(addr),
+        }
+    }
+
+    // A / d / d+X / !a operand of the shift, rotate, INC and DEC groups
+    pub fn modify_operand(&mut self, opcode: u8) -> Operand {
+        match opcode & 0x1F {
+            0x1C => Operand::A,
+            0x0B => Operand::Memory(self.addr_dp()),
+            0x1B => Operand::Memory(self.addr_dp_x()),
+            0x0C => Operand::Memory(self.addr_abs()),
+            _ => panic!("invalid read-modify-write opcode 0x{:X}", opcode),
+        }
+    }
+
+    pub fn push(&mut self, value: u8) {
+        self.write_byte(STACK_PAGE | self.sp as u16, value);
+        self.sp = self.sp.wrapping_sub(1);
+    }
+
+    pub fn pop(&mut self) -> u8 {
+        self.sp = self.sp.wrapping_add(1);
+        self.read_byte(STACK_PAGE | self.sp as u16)
+    }
+
+    pub fn push_word(&mut self, value: u16) {
+        self.push((value >> 8) as u8);
+        self.push((value & 0xFF) as u8);
+    }
+
+    pub fn pop_word(&mut self) -> u16 {
+        let lo = self.pop();
+        let hi = self.pop();
+        Self::make_word(lo, hi)
+    }
+
+    pub fn adc(&mut self, lhs: u8, rhs: u8) -> u8 {
+        let carry = (self.psw & P_CARRY) as u16;
+        let result = lhs as u16 + rhs as u16 + carry;
+        let value = (result & 0xFF) as u8;
+
+        self.flag(P_CARRY, result > 0xFF);
+        self.flag(
+            P_HALF_CARRY,
+            (lhs & 0xF) as u16 + (rhs & 0xF) as u16 + carry > 0xF,
+        );
+        self.flag(P_OVERFLOW, !(lhs ^ rhs) & (lhs ^ value) & 0x80 > 0);
+        self.flag_nz(value);
+        value
+    }
+
+    pub fn sbc(&mut self, lhs: u8, rhs: u8) -> u8 {
+        self.adc(lhs, !rhs)
+    }
+
+    pub fn compare(&mut self, lhs: u8, rhs: u8) {
+        self.flag(P_CARRY, lhs >= rhs);
+        self.flag_nz(lhs.wrapping_sub(rhs));
+    }
+
+    pub fn flag_nz(&mut self, value: u8) {
+        self.flag(P_ZERO, value == 0);
+        self.flag(P_NEGATIVE, value & 0x80 > 0);
+    }
+
+    pub fn flag_nz16(&mut self, value: u16) {
+        self.flag(P_ZERO, value == 0);
+        self.flag(P_NEGATIVE, value & 0x8000 > 0);
+    }
+
+    pub fn flag(&mut self, flag: u8, set: bool) {
+        if set {
+            self.psw |= flag;
+        } else {
+            self.psw &= !(flag);
+        }
+    }
+
+    pub fn trace(&self, opcode: u8, name: &str) {
+        debug!(
+            "[SPC 0x{:X}:0x{:X}] {} : A=0x{:X} X=0x{:X} Y=0x{:X} SP=0x{:X} PSW={:08b}",
+            self.pc, opcode, name, self.reg_a, self.reg_x, self.reg_y, self.sp, self.psw
+        );
+    }
+}
+
+impl SaveState for Spc700 {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.u8(self.reg_a);
+        w.u8(self.reg_x);
+        w.u8(self.reg_y);
+        w.u8(self.sp);
+        w.u16(self.pc);
+        w.u8(self.psw);
+        w.u64(self.cycles);
+        w.bool(self.halted);
+        w.u8(self.extra_cycles);
+        self.bus.save_state(w);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), Box<dyn Error>> {
+        self.reg_a = r.u8()?;
+        self.reg_x = r.u8()?;
+        self.reg_y = r.u8()?;
+        self.sp = r.u8()?;
+        self.pc = r.u16()?;
+        self.psw = r.u8()?;
+        self.cycles = r.u64()?;
+        self.halted = r.bool()?;
+        self.extra_cycles = r.u8()?;
+        self.bus.load_state(r)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ipl_boot() {
+        let mut s = Spc700::new(Box::default());
+        assert_eq!(s.pc, 0xFFC0);
+
+        // the IPL clears the zero page, then signals $AA/$BB to the CPU
+        for _ in 0..2000 {
+            s.step();
+        }
+        assert_eq!(s.bus.ports_out[0], 0xAA);
+        assert_eq!(s.bus.ports_out[1], 0xBB);
+        assert_eq!(s.sp, 0xEF);
+    }
+
+    #[test]
+    fn every_opcode_decodes() {
+        for opcode in 0..=0xFFu8 {
+            let mut b = SpcBus::new();
+            b.ipl_enabled = false;
+            b.aram[0x200] = opcode;
+            let mut s = Spc700::new(Box::new(b));
+            s.pc = 0x200;
+            s.reg_x = 0x1;
+            s.step();
+        }
+    }
+
+    #[test]
+    fn direct_page_flag() {
+        let mut b = SpcBus::new();
+        b.aram[0x0010] = 0x11;
+        b.aram[0x0110] = 0x22;
+        let mut s = Spc700::new(Box::new(b));
+        assert_eq!(s.read_byte(s.dp(0x10)), 0x11);
+        s.psw |= P_DIRECT_PAGE;
+        assert_eq!(s.read_byte(s.dp(0x10)), 0x22);
+    }
+
+    #[test]
+    fn timers() {
+        let mut b = SpcBus::new();
+        // timer 2 every 4 ticks (64 cycles), timer 0 every 256 ticks
+        b.write_byte(0xFC, 0x4);
+        b.write_byte(0xFA, 0x0);
+        b.write_byte(0xF1, 0x5);
+        b.clock(64 * 3 + 10);
+        assert_eq!(b.read_byte(0xFF), 0x3);
+        assert_eq!(b.read_byte(0xFF), 0x0);
+        assert_eq!(b.read_byte(0xFD), 0x0);
+        b.clock(128 * 256);
+        assert_eq!(b.read_byte(0xFD), 0x1);
+    }
+}
@@ -0,0 +1,225 @@
+use crate::apu::dsp::regs::Dsp;
+use crate::movie::state::{SaveState, StateReader, StateWriter};
+use log::debug;
+use std::error::Error;
+
+pub const ARAM_SIZE: usize = 0x10000;
+pub const IPL_ROM_BASE: u16 = 0xFFC0;
+
+// the 64-byte boot program mapped at $FFC0-$FFFF while CONTROL bit 7 is set
+pub const IPL_ROM: [u8; 64] = [
+    0xCD, 0xEF, 0xBD, 0xE8, 0x00, 0xC6, 0x1D, 0xD0, 0xFC, 0x8F, 0xAA, 0xF4, 0x8F, 0xBB, 0xF5, 0x78,
+    0xCC, 0xF4, 0xD0, 0xFB, 0x2F, 0x19, 0xEB, 0xF4, 0xD0, 0xFC, 0x7E, 0xF4, 0xD0, 0x0B, 0xE4, 0xF5,
+    0xCB, 0xF4, 0xD7, 0x00, 0xFC, 0xD0, 0xF3, 0xAB, 0x01, 0x10, 0xEF, 0x7E, 0xF4, 0x10, 0xEB, 0xBA,
+    0xF6, 0xDA, 0x00, 0xBA, 0xF4, 0xC4, 0xF4, 0xDD, 0x5D, 0xD0, 0xDB, 0x1F, 0x00, 0x00, 0xC0, 0xFF,
+];
+
+pub const CONTROL_IPL_ENABLE: u8 = 0x1 << 7;
+pub const CONTROL_CLEAR_PORTS_23: u8 = 0x1 << 5;
+pub const CONTROL_CLEAR_PORTS_01: u8 = 0x1 << 4;
+
+// timers 0 and 1 tick at 8kHz, timer 2 at 64kHz
+pub const TIMER_PERIODS: [u64; 3] = [128, 128, 16];
+
+#[derive(Debug, Clone, Copy)]
+pub struct Timer {
+    pub enabled: bool,
+    pub target: u8,
+    pub stage: u8,
+    pub output: u8,
+    cycles: u64,
+}
+
+impl Timer {
+    pub fn new() -> Self {
+        Self {
+            enabled: false,
+            target: 0,
+            stage: 0,
+            output: 0,
+            cycles: 0,
+        }
+    }
+
+    pub fn clock(&mut self, cycles: u64, period: u64) {
+        if !self.enabled {
+            return;
+        }
+
+        self.cycles += cycles;
+        while self.cycles >= period {
+            self.cycles -= period;
+            // a target of 0 means 256
+            self.stage = self.stage.wrapping_add(1);
+            if self.stage == self.target {
+                self.stage = 0;
+                self.output = (self.output + 1) & 0xF;
+            }
+        }
+    }
+}
+
+impl Default for Timer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub struct SpcBus {
+    pub aram: Box<[u8]>,
+    pub ipl_enabled: bool,
+    pub timers: [Timer; 3],
+    // $F4-$F7 as seen by the SPC700 (written by the main CPU)
+    pub ports_in: [u8; 4],
+    // $F4-$F7 as seen by the main CPU (written by the SPC700)
+    pub ports_out: [u8; 4],
+    pub dsp_addr: u8,
+    pub dsp: Dsp,
+}
+
+impl SpcBus {
+    pub fn new() -> Self {
+        Self {
+            aram: vec![0u8; ARAM_SIZE].into_boxed_slice(),
+            ipl_enabled: true,
+            timers: [Timer::new(); 3],
+            ports_in: [0; 4],
+            ports_out: [0; 4],
+            dsp_addr: 0,
+            dsp: Dsp::new(),
+        }
+    }
+
+    pub fn clock(&mut self, cycles: u64) {
+        for (timer, period) in self.timers.iter_mut().zip(TIMER_PERIODS) {
+            timer.clock(cycles, period);
+        }
+        self.dsp.clock(cycles, &mut self.aram);
+    }
+
+    pub fn read_byte(&mut self, addr: u16) -> u8 {
+        match addr {
+            // TEST/CONTROL are write-only
+            0xF0 | 0xF1 => 0x0,
+
+            // DSPADDR - DSP Register Index
+            0xF2 => self.dsp_addr,
+
+            // DSPDATA - DSP Register Data (mirrored every 128 bytes)
+            0xF3 => self.dsp.read_register(self.dsp_addr),
+
+            // CPUIO0-3 - CPU Input and Output Registers
+            0xF4..=0xF7 => self.ports_in[(addr - 0xF4) as usize],
+
+            // T0TARGET-T2TARGET are write-only
+            0xFA..=0xFC => 0x0,
+
+            // T0OUT-T2OUT - Timer Outputs, reset to zero when read
+            0xFD..=0xFF => {
+                let timer = &mut self.timers[(addr - 0xFD) as usize];
+                let value = timer.output;
+                timer.output = 0;
+                value
+            }
+
+            IPL_ROM_BASE..=0xFFFF if self.ipl_enabled => IPL_ROM[(addr - IPL_ROM_BASE) as usize],
+
+            _ => self.aram[addr as usize],
+        }
+    }
+
+    pub fn write_byte(&mut self, addr: u16, val: u8) {
+        match addr {
+            // TEST - Testing functions
+            0xF0 => debug!("SPC700 TEST write 0x{:X} (ignored)", val),
+
+            // CONTROL - Timer, I/O and ROM Control
+            0xF1 => self.write_control(val),
+
+            // DSPADDR - DSP Register Index
+            0xF2 => self.dsp_addr = val,
+
+            // DSPDATA - DSP Register Data, $80-$FF are read-only mirrors
+            0xF3 => {
+                if self.dsp_addr < 0x80 {
+                    self.dsp.write_register(self.dsp_addr, val);
+                }
+            }
+
+            // CPUIO0-3 - CPU Input and Output Registers
+            0xF4..=0xF7 => self.ports_out[(addr - 0xF4) as usize] = val,
+
+            // T0TARGET-T2TARGET - Timer Divider
+            0xFA..=0xFC => self.timers[(addr - 0xFA) as usize].target = val,
+
+            // T0OUT-T2OUT are read-only
+            0xFD..=0xFF => {}
+
+            // everything else, including $F8/$F9 and the area under the IPL ROM, is RAM
+            _ => self.aram[addr as usize] = val,
+        }
+    }
+
+    pub(crate) fn write_control(&mut self, val: u8) {
+        for (i, timer) in self.timers.iter_mut().enumerate() {
+            let enable = val & (0x1 << i) > 0;
+            // a 0 to 1 transition restarts the timer
+            if enable && !timer.enabled {
+                timer.stage = 0;
+                timer.output = 0;
+            }
+            timer.enabled = enable;
+        }
+
+        if val & CONTROL_CLEAR_PORTS_01 > 0 {
+            self.ports_in[0] = 0;
+            self.ports_in[1] = 0;
+        }
+        if val & CONTROL_CLEAR_PORTS_23 > 0 {
+            self.ports_in[2] = 0;
+            self.ports_in[3] = 0;
+        }
+
+        self.ipl_enabled = val & CONTROL_IPL_ENABLE > 0;
+    }
+}
+
+impl Default for SpcBus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SaveState for SpcBus {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.bytes(&self.aram);
+        w.bool(self.ipl_enabled);
+        for timer in &self.timers {
+            w.bool(timer.enabled);
+            w.u8(timer.target);
+            w.u8(timer.stage);
+            w.u8(timer.output);
+            w.u64(timer.cycles);
+        }
+        w.bytes(&self.ports_in);
+        w.bytes(&self.ports_out);
+        w.u8(self.dsp_addr);
+        self.dsp.save_state(w);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), Box<dyn Error>> {
+        r.bytes(&mut self.aram)?;
+        self.ipl_enabled = r.bool()?;
+        for timer in &mut self.timers {
+            timer.enabled = r.bool()?;
+            timer.target = r.u8()?;
+            timer.stage = r.u8()?;
+            timer.output = r.u8()?;
+            timer.cycles = r.u64()?;
+        }
+        r.bytes(&mut self.ports_in)?;
+        r.bytes(&mut self.ports_out)?;
+        self.dsp_addr = r.u8()?;
+        self.dsp.load_state(r)
+    }
+}
@@ -0,0 +1,57 @@
+use crate::apu::spc700::alu::Spc700;
+
+impl Spc700 {
+    pub fn op_adc(&mut self, opcode: u8) {
+        let (target, lhs, rhs) = self.fetch_alu_operands(opcode);
+        let result = self.adc(lhs, rhs);
+        self.store(target, result);
+        self.trace(opcode, "ADC");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::apu::spc700::alu::*;
+    use crate::apu::spc700::bus::SpcBus;
+
+    fn spc_with(program: &[u8]) -> Spc700 {
+        let mut b = SpcBus::new();
+        b.ipl_enabled = false;
+        b.aram[0x200..0x200 + program.len()].copy_from_slice(program);
+        let mut s = Spc700::new(Box::new(b));
+        s.pc = 0x200;
+        s
+    }
+
+    #[test]
+    fn op_adc_immediate() {
+        // ADC A, #$46
+        let mut s = spc_with(&[0x88, 0x46]);
+        s.reg_a = 0x58;
+        s.psw = P_CARRY;
+        s.step();
+        assert_eq!(s.reg_a, 0x9F);
+        assert_eq!(s.psw, P_NEGATIVE | P_OVERFLOW);
+    }
+
+    #[test]
+    fn op_adc_carry_out() {
+        // ADC A, #$01
+        let mut s = spc_with(&[0x88, 0x01]);
+        s.reg_a = 0xFF;
+        s.step();
+        assert_eq!(s.reg_a, 0x00);
+        assert_eq!(s.psw, P_CARRY | P_ZERO | P_HALF_CARRY);
+    }
+
+    #[test]
+    fn op_adc_memory_to_memory() {
+        // ADC $10, $11
+        let mut s = spc_with(&[0x89, 0x11, 0x10]);
+        s.bus.aram[0x10] = 0x20;
+        s.bus.aram[0x11] = 0x22;
+        s.step();
+        assert_eq!(s.bus.aram[0x10], 0x42);
+        assert_eq!(s.cycles, 6);
+    }
+}
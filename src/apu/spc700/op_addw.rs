@@ -0,0 +1,41 @@
+use crate::apu::spc700::alu::{P_CARRY, P_HALF_CARRY, P_OVERFLOW, Spc700};
+
+impl Spc700 {
+    pub fn op_addw(&mut self, opcode: u8) {
+        let d = self.fetch_byte();
+        let value = self.read_dp_word(d);
+        let ya = self.ya();
+
+        match opcode {
+            // ADDW YA, d (carry is ignored on input)
+            0x7A => {
+                let result = ya as u32 + value as u32;
+                let word = (result & 0xFFFF) as u16;
+                self.flag(P_CARRY, result > 0xFFFF);
+                self.flag(P_HALF_CARRY, (ya & 0xFFF) + (value & 0xFFF) > 0xFFF);
+                self.flag(P_OVERFLOW, !(ya ^ value) & (ya ^ word) & 0x8000 > 0);
+                self.flag_nz16(word);
+                self.set_ya(word);
+            }
+
+            // SUBW YA, d (carry is ignored on input)
+            0x9A => {
+                let word = ya.wrapping_sub(value);
+                self.flag(P_CARRY, ya >= value);
+                self.flag(P_HALF_CARRY, (ya & 0xFFF) >= (value & 0xFFF));
+                self.flag(P_OVERFLOW, (ya ^ value) & (ya ^ word) & 0x8000 > 0);
+                self.flag_nz16(word);
+                self.set_ya(word);
+            }
+
+            // CMPW YA, d
+            0x5A => {
+                self.flag(P_CARRY, ya >= value);
+                self.flag_nz16(ya.wrapping_sub(value));
+            }
+
+            _ => panic!("invalid opcode {}", opcode),
+        }
+        self.trace(opcode, "ADDW/SUBW/CMPW");
+    }
+}
@@ -0,0 +1,14 @@
+use crate::apu::spc700::alu::{P_CARRY, Spc700};
+
+impl Spc700 {
+    pub fn op_asl(&mut self, opcode: u8) {
+        let target = self.modify_operand(opcode);
+        let value = self.load(target);
+        let result = value << 1;
+
+        self.flag(P_CARRY, value & 0x80 > 0);
+        self.flag_nz(result);
+        self.store(target, result);
+        self.trace(opcode, "ASL");
+    }
+}
@@ -0,0 +1,63 @@
+use crate::apu::spc700::alu::{P_CARRY, Spc700};
+
+impl Spc700 {
+    pub fn op_bit(&mut self, opcode: u8) {
+        match opcode {
+            // TSET1 !a / TCLR1 !a, N and Z come from A - [a]
+            0x0E | 0x4E => {
+                let addr = self.addr_abs();
+                let value = self.read_byte(addr);
+                self.flag_nz(self.reg_a.wrapping_sub(value));
+                let result = match opcode {
+                    0x0E => value | self.reg_a,
+                    _ => value & !self.reg_a,
+                };
+                self.write_byte(addr, result);
+                self.trace(opcode, "TSET1/TCLR1");
+            }
+
+            // AND1/OR1/EOR1/NOT1/MOV1 with a 13-bit address and a 3-bit bit number
+            0x4A | 0x6A | 0x0A | 0x2A | 0x8A | 0xEA | 0xAA | 0xCA => {
+                let operand = self.fetch_word();
+                let addr = operand & 0x1FFF;
+                let bit = (operand >> 13) as u8;
+                let value = self.read_byte(addr);
+                let mem_bit = (value >> bit) & 0x1 > 0;
+                let carry = self.psw & P_CARRY > 0;
+
+                match opcode {
+                    0x4A => self.flag(P_CARRY, carry && mem_bit),
+                    0x6A => self.flag(P_CARRY, carry && !mem_bit),
+                    0x0A => self.flag(P_CARRY, carry || mem_bit),
+                    0x2A => self.flag(P_CARRY, carry || !mem_bit),
+                    0x8A => self.flag(P_CARRY, carry ^ mem_bit),
+                    0xAA => self.flag(P_CARRY, mem_bit),
+                    0xEA => self.write_byte(addr, value ^ (0x1 << bit)),
+                    _ => {
+                        let result = match carry {
+                            true => value | (0x1 << bit),
+                            false => value & !(0x1 << bit),
+                        };
+                        self.write_byte(addr, result);
+                    }
+                }
+                self.trace(opcode, "AND1/OR1/EOR1/NOT1/MOV1");
+            }
+
+            // SET1 d.n / CLR1 d.n
+            _ if opcode & 0xF == 0x2 => {
+                let addr = self.addr_dp();
+                let value = self.read_byte(addr);
+                let mask = 0x1 << (opcode >> 5);
+                let result = match opcode & 0x10 == 0 {
+                    true => value | mask,
+                    false => value & !mask,
+                };
+                self.write_byte(addr, result);
+                self.trace(opcode, "SET1/CLR1");
+            }
+
+            _ => panic!("invalid opcode {}", opcode),
+        }
+    }
+}
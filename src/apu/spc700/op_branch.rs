@@ -0,0 +1,73 @@
+use crate::apu::spc700::alu::*;
+
+impl Spc700 {
+    pub fn op_branch(&mut self, opcode: u8) {
+        let (taken, name) = match opcode {
+            // BRA
+            0x2F => (true, "BRA"),
+
+            // BEQ/BNE
+            0xF0 => (self.psw & P_ZERO > 0, "BEQ"),
+            0xD0 => (self.psw & P_ZERO == 0, "BNE"),
+
+            // BCS/BCC
+            0xB0 => (self.psw & P_CARRY > 0, "BCS"),
+            0x90 => (self.psw & P_CARRY == 0, "BCC"),
+
+            // BVS/BVC
+            0x70 => (self.psw & P_OVERFLOW > 0, "BVS"),
+            0x50 => (self.psw & P_OVERFLOW == 0, "BVC"),
+
+            // BMI/BPL
+            0x30 => (self.psw & P_NEGATIVE > 0, "BMI"),
+            0x10 => (self.psw & P_NEGATIVE == 0, "BPL"),
+
+            // CBNE d, r / CBNE d+X, r
+            0x2E | 0xDE => {
+                let addr = match opcode {
+                    0x2E => self.addr_dp(),
+                    _ => self.addr_dp_x(),
+                };
+                let value = self.read_byte(addr);
+                (self.reg_a != value, "CBNE")
+            }
+
+            // DBNZ d, r
+            0x6E => {
+                let addr = self.addr_dp();
+                let value = self.read_byte(addr).wrapping_sub(1);
+                self.write_byte(addr, value);
+                (value != 0, "DBNZ")
+            }
+
+            // DBNZ Y, r
+            0xFE => {
+                self.reg_y = self.reg_y.wrapping_sub(1);
+                (self.reg_y != 0, "DBNZ")
+            }
+
+            // BBS d.n, r / BBC d.n, r
+            _ if opcode & 0xF == 0x3 => {
+                let addr = self.addr_dp();
+                let bit = (self.read_byte(addr) >> (opcode >> 5)) & 0x1 > 0;
+                match opcode & 0x10 == 0 {
+                    true => (bit, "BBS"),
+                    false => (!bit, "BBC"),
+                }
+            }
+
+            _ => panic!("invalid opcode {}", opcode),
+        };
+
+        let offset = self.fetch_byte() as i8;
+        if taken {
+            self.pc = self.pc.wrapping_add(offset as i16 as u16);
+            // BRA always takes the branch, its cycle count already includes it
+            if opcode != 0x2F {
+                self.extra_cycles += 2;
+            }
+        }
+
+        self.trace(opcode, name);
+    }
+}
@@ -0,0 +1,59 @@
+use crate::apu::spc700::alu::{P_BREAK, P_IRQ_ENABLE, Spc700};
+
+// TCALL 0 vector, TCALL n reads its address from $FFDE - 2n
+pub const TCALL_VECTOR_BASE: u16 = 0xFFDE;
+
+impl Spc700 {
+    pub fn op_call(&mut self, opcode: u8) {
+        match opcode {
+            // CALL !a
+            0x3F => {
+                let addr = self.fetch_word();
+                self.push_word(self.pc);
+                self.pc = addr;
+                self.trace(opcode, "CALL");
+            }
+
+            // PCALL u
+            0x4F => {
+                let page = self.fetch_byte();
+                self.push_word(self.pc);
+                self.pc = 0xFF00 | page as u16;
+                self.trace(opcode, "PCALL");
+            }
+
+            // BRK
+            0x0F => {
+                self.push_word(self.pc);
+                self.push(self.psw);
+                self.flag(P_BREAK, true);
+                self.flag(P_IRQ_ENABLE, false);
+                self.pc = self.read_word(TCALL_VECTOR_BASE);
+                self.trace(opcode, "BRK");
+            }
+
+            // RET
+            0x6F => {
+                self.pc = self.pop_word();
+                self.trace(opcode, "RET");
+            }
+
+            // RETI
+            0x7F => {
+                self.psw = self.pop();
+                self.pc = self.pop_word();
+                self.trace(opcode, "RETI");
+            }
+
+            // TCALL n
+            _ if opcode & 0xF == 0x1 => {
+                let vector = TCALL_VECTOR_BASE - ((opcode >> 4) as u16) * 2;
+                self.push_word(self.pc);
+                self.pc = self.read_word(vector);
+                self.trace(opcode, "TCALL");
+            }
+
+            _ => panic!("invalid opcode {}", opcode),
+        }
+    }
+}
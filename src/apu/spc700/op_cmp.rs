@@ -0,0 +1,41 @@
+use crate::apu::spc700::alu::{Operand, Spc700};
+
+impl Spc700 {
+    pub fn op_cmp(&mut self, opcode: u8) {
+        let (lhs, rhs) = match opcode {
+            // CMP X, #i / d / !a
+            0xC8 => (self.reg_x, self.fetch_byte()),
+            0x3E => {
+                let addr = self.addr_dp();
+                (self.reg_x, self.read_byte(addr))
+            }
+            0x1E => {
+                let addr = self.addr_abs();
+                (self.reg_x, self.read_byte(addr))
+            }
+
+            // CMP Y, #i / d / !a
+            0xAD => (self.reg_y, self.fetch_byte()),
+            0x7E => {
+                let addr = self.addr_dp();
+                (self.reg_y, self.read_byte(addr))
+            }
+            0x5E => {
+                let addr = self.addr_abs();
+                (self.reg_y, self.read_byte(addr))
+            }
+
+            // CMP A, ... / dd, ds / d, #i / (X), (Y)
+            _ => {
+                let (target, lhs, rhs) = self.fetch_alu_operands(opcode);
+                match target {
+                    Operand::A | Operand::Memory(_) => (lhs, rhs),
+                    _ => panic!("invalid opcode {}", opcode),
+                }
+            }
+        };
+
+        self.compare(lhs, rhs);
+        self.trace(opcode, "CMP");
+    }
+}
@@ -0,0 +1,37 @@
+use crate::apu::spc700::alu::{P_CARRY, P_HALF_CARRY, Spc700};
+
+impl Spc700 {
+    pub fn op_daa(&mut self, opcode: u8) {
+        let carry = self.psw & P_CARRY > 0;
+        let half_carry = self.psw & P_HALF_CARRY > 0;
+
+        match opcode {
+            // DAA Decimal adjust after addition
+            0xDF => {
+                if carry || self.reg_a > 0x99 {
+                    self.reg_a = self.reg_a.wrapping_add(0x60);
+                    self.flag(P_CARRY, true);
+                }
+                if half_carry || (self.reg_a & 0xF) > 0x9 {
+                    self.reg_a = self.reg_a.wrapping_add(0x6);
+                }
+            }
+
+            // DAS Decimal adjust after subtraction
+            0xBE => {
+                if !carry || self.reg_a > 0x99 {
+                    self.reg_a = self.reg_a.wrapping_sub(0x60);
+                    self.flag(P_CARRY, false);
+                }
+                if !half_carry || (self.reg_a & 0xF) > 0x9 {
+                    self.reg_a = self.reg_a.wrapping_sub(0x6);
+                }
+            }
+
+            _ => panic!("invalid opcode {}", opcode),
+        }
+
+        self.flag_nz(self.reg_a);
+        self.trace(opcode, "DAA/DAS");
+    }
+}
@@ -0,0 +1,20 @@
+use crate::apu::spc700::alu::{Operand, Spc700};
+
+impl Spc700 {
+    pub fn op_dec(&mut self, opcode: u8) {
+        let target = match opcode {
+            0x9C => Operand::A,
+            0x1D => Operand::X,
+            0xDC => Operand::Y,
+            0x8B => Operand::Memory(self.addr_dp()),
+            0x9B => Operand::Memory(self.addr_dp_x()),
+            0x8C => Operand::Memory(self.addr_abs()),
+            _ => panic!("invalid opcode {}", opcode),
+        };
+
+        let value = self.load(target).wrapping_sub(1);
+        self.flag_nz(value);
+        self.store(target, value);
+        self.trace(opcode, "DEC");
+    }
+}
@@ -0,0 +1,64 @@
+use crate::apu::spc700::alu::{P_HALF_CARRY, P_OVERFLOW, Spc700};
+
+impl Spc700 {
+    pub fn op_div(&mut self, opcode: u8) {
+        let ya = self.ya() as u32;
+        let x = self.reg_x as u32;
+
+        self.flag(P_OVERFLOW, self.reg_y as u32 >= x);
+        self.flag(P_HALF_CARRY, (self.reg_y & 0xF) >= (self.reg_x & 0xF));
+
+        if (self.reg_y as u32) < (x << 1) {
+            // quotient fits in 9 bits
+            self.reg_a = ((ya / x) & 0xFF) as u8;
+            self.reg_y = (ya % x) as u8;
+        } else {
+            // the hardware algorithm produces these values on overflow
+            self.reg_a = (255 - (ya - (x << 9)) / (256 - x)) as u8;
+            self.reg_y = (x + (ya - (x << 9)) % (256 - x)) as u8;
+        }
+
+        self.flag_nz(self.reg_a);
+        self.trace(opcode, "DIV");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::apu::spc700::alu::*;
+    use crate::apu::spc700::bus::SpcBus;
+
+    fn div(ya: u16, x: u8) -> Spc700 {
+        let mut b = SpcBus::new();
+        b.ipl_enabled = false;
+        b.aram[0x200] = 0x9E;
+        let mut s = Spc700::new(Box::new(b));
+        s.pc = 0x200;
+        s.set_ya(ya);
+        s.reg_x = x;
+        s.step();
+        s
+    }
+
+    #[test]
+    fn op_div() {
+        let s = div(1000, 7);
+        assert_eq!(s.reg_a, 142);
+        assert_eq!(s.reg_y, 6);
+        assert_eq!(s.psw & P_OVERFLOW, 0x0);
+    }
+
+    #[test]
+    fn op_div_overflow() {
+        // quotient does not fit in 8 bits
+        let s = div(0x1234, 0x10);
+        assert_eq!(s.psw & P_OVERFLOW, P_OVERFLOW);
+        assert_eq!(s.reg_a, 0x23);
+        assert_eq!(s.reg_y, 0x4);
+
+        // division by zero
+        let s = div(0x1234, 0x0);
+        assert_eq!(s.reg_a, 0xED);
+        assert_eq!(s.reg_y, 0x34);
+    }
+}
@@ -0,0 +1,29 @@
+use crate::apu::spc700::alu::*;
+
+impl Spc700 {
+    pub fn op_flags(&mut self, opcode: u8) {
+        match opcode {
+            // CLRC/SETC/NOTC
+            0x60 => self.flag(P_CARRY, false),
+            0x80 => self.flag(P_CARRY, true),
+            0xED => self.psw ^= P_CARRY,
+
+            // CLRV also clears the half carry
+            0xE0 => {
+                self.flag(P_OVERFLOW, false);
+                self.flag(P_HALF_CARRY, false);
+            }
+
+            // CLRP/SETP
+            0x20 => self.flag(P_DIRECT_PAGE, false),
+            0x40 => self.flag(P_DIRECT_PAGE, true),
+
+            // EI/DI
+            0xA0 => self.flag(P_IRQ_ENABLE, true),
+            0xC0 => self.flag(P_IRQ_ENABLE, false),
+
+            _ => panic!("invalid opcode {}", opcode),
+        }
+        self.trace(opcode, "FLAGS");
+    }
+}
@@ -0,0 +1,20 @@
+use crate::apu::spc700::alu::{Operand, Spc700};
+
+impl Spc700 {
+    pub fn op_inc(&mut self, opcode: u8) {
+        let target = match opcode {
+            0xBC => Operand::A,
+            0x3D => Operand::X,
+            0xFC => Operand::Y,
+            0xAB => Operand::Memory(self.addr_dp()),
+            0xBB => Operand::Memory(self.addr_dp_x()),
+            0xAC => Operand::Memory(self.addr_abs()),
+            _ => panic!("invalid opcode {}", opcode),
+        };
+
+        let value = self.load(target).wrapping_add(1);
+        self.flag_nz(value);
+        self.store(target, value);
+        self.trace(opcode, "INC");
+    }
+}
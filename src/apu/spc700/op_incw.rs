@@ -0,0 +1,19 @@
+use crate::apu::spc700::alu::Spc700;
+
+impl Spc700 {
+    pub fn op_incw(&mut self, opcode: u8) {
+        let d = self.fetch_byte();
+        let value = self.read_dp_word(d);
+        let result = match opcode {
+            // INCW d
+            0x3A => value.wrapping_add(1),
+            // DECW d
+            0x1A => value.wrapping_sub(1),
+            _ => panic!("invalid opcode {}", opcode),
+        };
+
+        self.write_dp_word(d, result);
+        self.flag_nz16(result);
+        self.trace(opcode, "INCW/DECW");
+    }
+}
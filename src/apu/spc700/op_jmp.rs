@@ -0,0 +1,19 @@
+use crate::apu::spc700::alu::Spc700;
+
+impl Spc700 {
+    pub fn op_jmp(&mut self, opcode: u8) {
+        self.pc = match opcode {
+            // JMP !a
+            0x5F => self.fetch_word(),
+
+            // JMP [!a+X]
+            0x1F => {
+                let addr = self.addr_abs_x();
+                self.read_word(addr)
+            }
+
+            _ => panic!("invalid opcode {}", opcode),
+        };
+        self.trace(opcode, "JMP");
+    }
+}
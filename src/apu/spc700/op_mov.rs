@@ -0,0 +1,181 @@
+use crate::apu::spc700::alu::Spc700;
+
+impl Spc700 {
+    pub fn op_mov(&mut self, opcode: u8) {
+        match opcode {
+            // MOV A, #i / d / d+X / !a / !a+X / !a+Y / (X) / (X)+ / [d+X] / [d]+Y
+            0xE8 => self.reg_a = self.fetch_byte(),
+            0xE4 => {
+                let addr = self.addr_dp();
+                self.reg_a = self.read_byte(addr);
+            }
+            0xF4 => {
+                let addr = self.addr_dp_x();
+                self.reg_a = self.read_byte(addr);
+            }
+            0xE5 => {
+                let addr = self.addr_abs();
+                self.reg_a = self.read_byte(addr);
+            }
+            0xF5 => {
+                let addr = self.addr_abs_x();
+                self.reg_a = self.read_byte(addr);
+            }
+            0xF6 => {
+                let addr = self.addr_abs_y();
+                self.reg_a = self.read_byte(addr);
+            }
+            0xE6 => {
+                let addr = self.addr_ind_x();
+                self.reg_a = self.read_byte(addr);
+            }
+            0xBF => {
+                let addr = self.addr_ind_x();
+                self.reg_a = self.read_byte(addr);
+                self.reg_x = self.reg_x.wrapping_add(1);
+            }
+            0xE7 => {
+                let addr = self.addr_dp_x_ind();
+                self.reg_a = self.read_byte(addr);
+            }
+            0xF7 => {
+                let addr = self.addr_dp_ind_y();
+                self.reg_a = self.read_byte(addr);
+            }
+
+            // MOV X, #i / d / d+Y / !a
+            0xCD => self.reg_x = self.fetch_byte(),
+            0xF8 => {
+                let addr = self.addr_dp();
+                self.reg_x = self.read_byte(addr);
+            }
+            0xF9 => {
+                let addr = self.addr_dp_y();
+                self.reg_x = self.read_byte(addr);
+            }
+            0xE9 => {
+                let addr = self.addr_abs();
+                self.reg_x = self.read_byte(addr);
+            }
+
+            // MOV Y, #i / d / d+X / !a
+            0x8D => self.reg_y = self.fetch_byte(),
+            0xEB => {
+                let addr = self.addr_dp();
+                self.reg_y = self.read_byte(addr);
+            }
+            0xFB => {
+                let addr = self.addr_dp_x();
+                self.reg_y = self.read_byte(addr);
+            }
+            0xEC => {
+                let addr = self.addr_abs();
+                self.reg_y = self.read_byte(addr);
+            }
+
+            // MOV d / d+X / !a / !a+X / !a+Y / (X) / (X)+ / [d+X] / [d]+Y, A
+            0xC4 => {
+                let addr = self.addr_dp();
+                self.write_byte(addr, self.reg_a);
+            }
+            0xD4 => {
+                let addr = self.addr_dp_x();
+                self.write_byte(addr, self.reg_a);
+            }
+            0xC5 => {
+                let addr = self.addr_abs();
+                self.write_byte(addr, self.reg_a);
+            }
+            0xD5 => {
+                let addr = self.addr_abs_x();
+                self.write_byte(addr, self.reg_a);
+            }
+            0xD6 => {
+                let addr = self.addr_abs_y();
+                self.write_byte(addr, self.reg_a);
+            }
+            0xC6 => {
+                let addr = self.addr_ind_x();
+                self.write_byte(addr, self.reg_a);
+            }
+            0xAF => {
+                let addr = self.addr_ind_x();
+                self.write_byte(addr, self.reg_a);
+                self.reg_x = self.reg_x.wrapping_add(1);
+            }
+            0xC7 => {
+                let addr = self.addr_dp_x_ind();
+                self.write_byte(addr, self.reg_a);
+            }
+            0xD7 => {
+                let addr = self.addr_dp_ind_y();
+                self.write_byte(addr, self.reg_a);
+            }
+
+            // MOV d / d+Y / !a, X
+            0xD8 => {
+                let addr = self.addr_dp();
+                self.write_byte(addr, self.reg_x);
+            }
+            0xD9 => {
+                let addr = self.addr_dp_y();
+                self.write_byte(addr, self.reg_x);
+            }
+            0xC9 => {
+                let addr = self.addr_abs();
+                self.write_byte(addr, self.reg_x);
+            }
+
+            // MOV d / d+X / !a, Y
+            0xCB => {
+                let addr = self.addr_dp();
+                self.write_byte(addr, self.reg_y);
+            }
+            0xDB => {
+                let addr = self.addr_dp_x();
+                self.write_byte(addr, self.reg_y);
+            }
+            0xCC => {
+                let addr = self.addr_abs();
+                self.write_byte(addr, self.reg_y);
+            }
+
+            // MOV between registers
+            0x7D => self.reg_a = self.reg_x,
+            0xDD => self.reg_a = self.reg_y,
+            0x5D => self.reg_x = self.reg_a,
+            0xFD => self.reg_y = self.reg_a,
+            0x9D => self.reg_x = self.sp,
+            0xBD => self.sp = self.reg_x,
+
+            // MOV dd, ds
+            0xFA => {
+                let src = self.addr_dp();
+                let value = self.read_byte(src);
+                let dst = self.addr_dp();
+                self.write_byte(dst, value);
+            }
+
+            // MOV d, #i
+            0x8F => {
+                let value = self.fetch_byte();
+                let dst = self.addr_dp();
+                self.write_byte(dst, value);
+            }
+
+            _ => panic!("invalid opcode {}", opcode),
+        }
+
+        // loads into A, X and Y set N and Z (MOV SP, X does not)
+        match opcode {
+            0xE8 | 0xE4 | 0xF4 | 0xE5 | 0xF5 | 0xF6 | 0xE6 | 0xBF | 0xE7 | 0xF7 | 0x7D | 0xDD => {
+                self.flag_nz(self.reg_a)
+            }
+            0xCD | 0xF8 | 0xF9 | 0xE9 | 0x5D | 0x9D => self.flag_nz(self.reg_x),
+            0x8D | 0xEB | 0xFB | 0xEC | 0xFD => self.flag_nz(self.reg_y),
+            _ => {}
+        }
+
+        self.trace(opcode, "MOV");
+    }
+}
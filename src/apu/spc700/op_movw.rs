@@ -0,0 +1,21 @@
+use crate::apu::spc700::alu::Spc700;
+
+impl Spc700 {
+    pub fn op_movw(&mut self, opcode: u8) {
+        let d = self.fetch_byte();
+        match opcode {
+            // MOVW YA, d
+            0xBA => {
+                let value = self.read_dp_word(d);
+                self.set_ya(value);
+                self.flag_nz16(value);
+            }
+
+            // MOVW d, YA
+            0xDA => self.write_dp_word(d, self.ya()),
+
+            _ => panic!("invalid opcode {}", opcode),
+        }
+        self.trace(opcode, "MOVW");
+    }
+}
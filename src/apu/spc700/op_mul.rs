@@ -0,0 +1,12 @@
+use crate::apu::spc700::alu::Spc700;
+
+impl Spc700 {
+    pub fn op_mul(&mut self, opcode: u8) {
+        let result = self.reg_y as u16 * self.reg_a as u16;
+        self.set_ya(result);
+
+        // N and Z only look at the high byte
+        self.flag_nz(self.reg_y);
+        self.trace(opcode, "MUL");
+    }
+}
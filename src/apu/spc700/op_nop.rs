@@ -0,0 +1,20 @@
+use crate::apu::spc700::alu::Spc700;
+use log::warn;
+
+impl Spc700 {
+    pub fn op_nop(&mut self, opcode: u8) {
+        match opcode {
+            // NOP
+            0x00 => {}
+
+            // SLEEP/STOP halt the core until the next reset
+            0xEF | 0xFF => {
+                warn!("SPC700 halted by opcode 0x{:X} at 0x{:X}", opcode, self.pc);
+                self.halted = true;
+            }
+
+            _ => panic!("invalid opcode {}", opcode),
+        }
+        self.trace(opcode, "NOP");
+    }
+}
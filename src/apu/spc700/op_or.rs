@@ -0,0 +1,11 @@
+use crate::apu::spc700::alu::Spc700;
+
+impl Spc700 {
+    pub fn op_or(&mut self, opcode: u8) {
+        let (target, lhs, rhs) = self.fetch_alu_operands(opcode);
+        let result = lhs | rhs;
+        self.flag_nz(result);
+        self.store(target, result);
+        self.trace(opcode, "OR");
+    }
+}
@@ -0,0 +1,22 @@
+use crate::apu::spc700::alu::Spc700;
+
+impl Spc700 {
+    pub fn op_push(&mut self, opcode: u8) {
+        match opcode {
+            // PUSH A/X/Y/PSW
+            0x2D => self.push(self.reg_a),
+            0x4D => self.push(self.reg_x),
+            0x6D => self.push(self.reg_y),
+            0x0D => self.push(self.psw),
+
+            // POP A/X/Y/PSW (no flags affected, except by POP PSW itself)
+            0xAE => self.reg_a = self.pop(),
+            0xCE => self.reg_x = self.pop(),
+            0xEE => self.reg_y = self.pop(),
+            0x8E => self.psw = self.pop(),
+
+            _ => panic!("invalid opcode {}", opcode),
+        }
+        self.trace(opcode, "PUSH/POP");
+    }
+}
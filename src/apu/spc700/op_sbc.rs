@@ -0,0 +1,10 @@
+use crate::apu::spc700::alu::Spc700;
+
+impl Spc700 {
+    pub fn op_sbc(&mut self, opcode: u8) {
+        let (target, lhs, rhs) = self.fetch_alu_operands(opcode);
+        let result = self.sbc(lhs, rhs);
+        self.store(target, result);
+        self.trace(opcode, "SBC");
+    }
+}
@@ -0,0 +1,9 @@
+use crate::apu::spc700::alu::Spc700;
+
+impl Spc700 {
+    pub fn op_xcn(&mut self, opcode: u8) {
+        self.reg_a = self.reg_a.rotate_left(4);
+        self.flag_nz(self.reg_a);
+        self.trace(opcode, "XCN");
+    }
+}
@@ -0,0 +1,311 @@
+use crate::apu::dsp::regs::{Dsp, EDL, KON};
+use crate::apu::spc700::alu::Spc700;
+use crate::apu::spc700::bus::{
+    ARAM_SIZE, CONTROL_CLEAR_PORTS_01, CONTROL_CLEAR_PORTS_23, IPL_ROM_BASE,
+};
+use log::info;
+use std::error::Error;
+use std::fmt;
+use std::fs::read;
+
+const SIGNATURE: &[u8] = b"SNES-SPC700 Sound File Data";
+const HAS_ID666: u8 = 26;
+
+const RAM_OFFSET: usize = 0x100;
+const DSP_OFFSET: usize = 0x10100;
+const EXTRA_RAM_OFFSET: usize = 0x101C0;
+const FILE_SIZE: usize = 0x10200;
+
+// ID666 tag, stored either as text or as binary numbers depending on the dumper
+#[derive(Debug, Default)]
+pub struct Id666 {
+    pub song_title: String,
+    pub game_title: String,
+    pub dumper: String,
+    pub comments: String,
+    pub artist: String,
+    pub play_seconds: Option<u32>,
+    pub fade_ms: Option<u32>,
+}
+
+impl fmt::Display for Id666 {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "(song_title: {}, game_title: {}, artist: {}, dumper: {}, play_seconds: {:?}, fade_ms: {:?})",
+            self.song_title,
+            self.game_title,
+            self.artist,
+            self.dumper,
+            self.play_seconds,
+            self.fade_ms
+        )
+    }
+}
+
+// snapshot of the sound module: SPC700 registers, 64K of RAM and the DSP registers
+pub struct SpcFile {
+    pub tag: Option<Id666>,
+    pub pc: u16,
+    pub reg_a: u8,
+    pub reg_x: u8,
+    pub reg_y: u8,
+    pub psw: u8,
+    pub sp: u8,
+    pub ram: Vec<u8>,
+    pub dsp_regs: [u8; 128],
+    // the RAM hidden under the IPL ROM at $FFC0-$FFFF
+    pub extra_ram: [u8; 64],
+}
+
+fn text(data: &[u8]) -> String {
+    let end = data.iter().position(|&b| b == 0).unwrap_or(data.len());
+    String::from_utf8_lossy(&data[..end]).trim().to_string()
+}
+
+fn number(data: &[u8]) -> Option<u32> {
+    text(data).parse().ok().filter(|&n| n > 0)
+}
+
+fn binary_number(data: &[u8]) -> Option<u32> {
+    let mut bytes = [0u8; 4];
+    bytes[..data.len()].copy_from_slice(data);
+    Some(u32::from_le_bytes(bytes)).filter(|&n| n > 0)
+}
+
+impl Id666 {
+    // the dump date is MM/DD/YYYY in the text format, day, month and a 16-bit year in the binary
+    // one, only without a date the lengths have to tell (digits or nothing in the text format)
+    fn is_text(data: &[u8]) -> bool {
+        let date = &data[0x9E..0xA9];
+        let field = match date.iter().any(|&b| b != 0) {
+            true => date,
+            false => &data[0xA9..0xB1],
+        };
+        field
+            .iter()
+            .all(|&b| b == 0 || b.is_ascii_digit() || b == b'/' || b == b'-')
+    }
+
+    fn parse(data: &[u8]) -> Self {
+        let is_text = Self::is_text(data);
+
+        let mut tag = Self {
+            song_title: text(&data[0x2E..0x4E]),
+            game_title: text(&data[0x4E..0x6E]),
+            dumper: text(&data[0x6E..0x7E]),
+            comments: text(&data[0x7E..0x9E]),
+            ..Default::default()
+        };
+
+        match is_text {
+            true => {
+                tag.play_seconds = number(&data[0xA9..0xAC]);
+                tag.fade_ms = number(&data[0xAC..0xB1]);
+                tag.artist = text(&data[0xB1..0xD1]);
+            }
+            false => {
+                tag.play_seconds = binary_number(&data[0xA9..0xAC]);
+                tag.fade_ms = binary_number(&data[0xAC..0xB0]);
+                tag.artist = text(&data[0xB0..0xD0]);
+            }
+        }
+        tag
+    }
+}
+
+impl SpcFile {
+    pub fn parse(data: &[u8]) -> Result<Self, Box<dyn Error>> {
+        if !data.starts_with(SIGNATURE) {
+            return Err("not an SPC file".into());
+        }
+        if data.len() < FILE_SIZE {
+            return Err("truncated SPC file".into());
+        }
+
+        let tag = match data[0x23] == HAS_ID666 {
+            true => Some(Id666::parse(data)),
+            false => None,
+        };
+
+        let mut dsp_regs = [0u8; 128];
+        dsp_regs.copy_from_slice(&data[DSP_OFFSET..DSP_OFFSET + 128]);
+        let mut extra_ram = [0u8; 64];
+        extra_ram.copy_from_slice(&data[EXTRA_RAM_OFFSET..EXTRA_RAM_OFFSET + 64]);
+
+        Ok(Self {
+            tag,
+            pc: u16::from_le_bytes([data[0x25], data[0x26]]),
+            reg_a: data[0x27],
+            reg_x: data[0x28],
+            reg_y: data[0x29],
+            psw: data[0x2A],
+            sp: data[0x2B],
+            ram: data[RAM_OFFSET..RAM_OFFSET + ARAM_SIZE].to_vec(),
+            dsp_regs,
+            extra_ram,
+        })
+    }
+
+    // restore the snapshot, the SPC700 carries on from where the dump was taken
+    pub fn load_into(&self, spc: &mut Spc700) {
+        let bus = &mut spc.bus;
+        bus.aram.copy_from_slice(&self.ram);
+        bus.aram[IPL_ROM_BASE as usize..].copy_from_slice(&self.extra_ram);
+
+        // the I/O registers live in the RAM image too, the port values are what the CPU wrote
+        let io = &self.ram[0xF0..0x100];
+        bus.write_control(io[0x1] & !(CONTROL_CLEAR_PORTS_01 | CONTROL_CLEAR_PORTS_23));
+        bus.dsp_addr = io[0x2];
+        bus.ports_in.copy_from_slice(&io[0x4..0x8]);
+        for (i, timer) in bus.timers.iter_mut().enumerate() {
+            timer.target = io[0xA + i];
+            timer.output = io[0xD + i] & 0xF;
+        }
+
+        bus.dsp.load_registers(&self.dsp_regs);
+
+        spc.pc = self.pc;
+        spc.reg_a = self.reg_a;
+        spc.reg_x = self.reg_x;
+        spc.reg_y = self.reg_y;
+        spc.psw = self.psw;
+        spc.sp = self.sp;
+        spc.halted = false;
+    }
+}
+
+pub fn open(path: &str) -> Result<SpcFile, Box<dyn Error>> {
+    let spc = SpcFile::parse(&read(path)?)?;
+    match &spc.tag {
+        Some(tag) => info!("Loaded SPC: {}", tag),
+        None => info!("Loaded SPC without tag"),
+    }
+    Ok(spc)
+}
+
+impl Dsp {
+    // take over a register dump, keying on the voices in its KON again like a write would, the
+    // snapshot was taken while they were playing
+    pub fn load_registers(&mut self, regs: &[u8; 128]) {
+        self.regs = *regs;
+        self.new_kon = regs[KON];
+        self.echo.offset = 0;
+        self.echo.length = (regs[EDL] & 0xF) as u16 * 0x800;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::apu::spc700::bus::SpcBus;
+
+    fn spc_image() -> Vec<u8> {
+        let mut data = vec![0u8; FILE_SIZE];
+        data[..33].copy_from_slice(b"SNES-SPC700 Sound File Data v0.30");
+        data[0x21] = 26;
+        data[0x22] = 26;
+        data[0x23] = HAS_ID666;
+        data[0x24] = 30;
+        data[0x25..0x2C].copy_from_slice(&[0x00, 0x04, 0x12, 0x34, 0x56, 0x02, 0xCF]);
+        data[0x2E..0x37].copy_from_slice(b"Main Song");
+        data[0x4E..0x55].copy_from_slice(b"My Game");
+        data[0x9E..0xA8].copy_from_slice(b"06/15/1999");
+        data[0xA9..0xAC].copy_from_slice(b"120");
+        data[0xAC..0xB0].copy_from_slice(b"5000");
+        data[0xB1..0xB7].copy_from_slice(b"Artist");
+
+        // MOV $F5, #$77 ; BRA -2 at $0400
+        data[RAM_OFFSET + 0x400..RAM_OFFSET + 0x405]
+            .copy_from_slice(&[0x8F, 0x77, 0xF5, 0x2F, 0xFE]);
+        // timer 0 running with the IPL ROM hidden
+        data[RAM_OFFSET + 0xF1] = 0x01;
+        data[RAM_OFFSET + 0xFA] = 0x10;
+        data[RAM_OFFSET + 0xF4] = 0xAB;
+        data[DSP_OFFSET + 0x0C] = 0x7F;
+        data[DSP_OFFSET + 0x4C] = 0xFF;
+        data[EXTRA_RAM_OFFSET + 0x3E] = 0x42;
+        data
+    }
+
+    #[test]
+    fn parse_text_tag() {
+        let spc = SpcFile::parse(&spc_image()).unwrap();
+        let tag = spc.tag.unwrap();
+        assert_eq!(tag.song_title, "Main Song");
+        assert_eq!(tag.game_title, "My Game");
+        assert_eq!(tag.artist, "Artist");
+        assert_eq!(tag.play_seconds, Some(120));
+        assert_eq!(tag.fade_ms, Some(5000));
+        assert_eq!(spc.pc, 0x400);
+        assert_eq!(spc.sp, 0xCF);
+    }
+
+    #[test]
+    fn parse_binary_tag() {
+        let mut data = spc_image();
+        data[0x9E..0xA9].copy_from_slice(&[1, 12, 0xD0, 0x07, 0, 0, 0, 0, 0, 0, 0]);
+        data[0xA9..0xB1].copy_from_slice(&[0x5A, 0, 0, 0x10, 0x27, 0, 0, 0]);
+        data[0xB0..0xD1].fill(0);
+        data[0xB0..0xB6].copy_from_slice(b"Binary");
+        let tag = SpcFile::parse(&data).unwrap().tag.unwrap();
+        assert_eq!(tag.play_seconds, Some(90));
+        assert_eq!(tag.fade_ms, Some(10000));
+        assert_eq!(tag.artist, "Binary");
+    }
+
+    #[test]
+    fn binary_tag_that_looks_like_text() {
+        let mut data = spc_image();
+        // 53 seconds, no fade and an artist starting with a digit, only the date gives it away
+        data[0x9E..0xA9].fill(0);
+        data[0x9E..0xA2].copy_from_slice(&[15, 6, 0xCF, 0x07]);
+        data[0xA9..0xD1].fill(0);
+        data[0xA9] = b'5';
+        data[0xB0..0xBB].copy_from_slice(b"2 Unlimited");
+        let tag = SpcFile::parse(&data).unwrap().tag.unwrap();
+        assert_eq!(tag.play_seconds, Some(53));
+        assert_eq!(tag.fade_ms, None);
+        assert_eq!(tag.artist, "2 Unlimited");
+    }
+
+    #[test]
+    fn rejects_other_files() {
+        assert!(SpcFile::parse(&[0u8; FILE_SIZE]).is_err());
+        assert!(SpcFile::parse(&spc_image()[..0x1000]).is_err());
+    }
+
+    #[test]
+    fn load_and_run() {
+        let spc_file = SpcFile::parse(&spc_image()).unwrap();
+        let mut spc = Spc700::new(Box::<SpcBus>::default());
+        spc_file.load_into(&mut spc);
+
+        assert!(!spc.bus.ipl_enabled);
+        assert_eq!(spc.bus.read_byte(0xFFFE), 0x42);
+        assert_eq!(spc.bus.read_byte(0xF4), 0xAB);
+        assert!(spc.bus.timers[0].enabled);
+        assert_eq!(spc.bus.dsp.read_register(0x0C), 0x7F);
+        assert_eq!(spc.bus.dsp.read_register(0x4C), 0xFF);
+
+        for _ in 0..4 {
+            spc.step();
+        }
+        assert_eq!(spc.bus.ports_out[1], 0x77);
+        assert_eq!((spc.reg_a, spc.reg_x, spc.reg_y), (0x12, 0x34, 0x56));
+    }
+
+    #[test]
+    fn replays_key_on() {
+        let spc_file = SpcFile::parse(&spc_image()).unwrap();
+        let mut spc = Spc700::new(Box::<SpcBus>::default());
+        spc_file.load_into(&mut spc);
+
+        let bus = &mut spc.bus;
+        assert!(bus.dsp.voices.iter().all(|voice| voice.kon_delay == 0));
+        bus.dsp.run_sample(&mut bus.aram);
+        bus.dsp.run_sample(&mut bus.aram);
+        // the dump keys on all 8 voices
+        assert!(bus.dsp.voices.iter().all(|voice| voice.kon_delay > 0));
+    }
+}
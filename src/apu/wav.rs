@@ -0,0 +1,135 @@
+use crate::apu::dsp::buffer::SampleBuffer;
+use crate::apu::dsp::regs::SAMPLE_RATE;
+use crate::apu::resampler::Resampler;
+use std::fs::File;
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+
+const HEADER_SIZE: u32 = 44;
+const CHANNELS: u16 = 2;
+const BITS_PER_SAMPLE: u16 = 16;
+const FRAME_SIZE: u32 = (CHANNELS * BITS_PER_SAMPLE / 8) as u32;
+
+// 16-bit stereo PCM, the sizes in the header are kept up to date by update_header
+pub struct WavWriter<W: Write + Seek> {
+    out: W,
+    data_size: u32,
+}
+
+impl<W: Write + Seek> WavWriter<W> {
+    pub fn new(mut out: W, sample_rate: u32) -> io::Result<Self> {
+        out.write_all(b"RIFF")?;
+        out.write_all(&(HEADER_SIZE - 8).to_le_bytes())?;
+        out.write_all(b"WAVE")?;
+
+        out.write_all(b"fmt ")?;
+        out.write_all(&16u32.to_le_bytes())?;
+        // PCM
+        out.write_all(&1u16.to_le_bytes())?;
+        out.write_all(&CHANNELS.to_le_bytes())?;
+        out.write_all(&sample_rate.to_le_bytes())?;
+        out.write_all(&(sample_rate * FRAME_SIZE).to_le_bytes())?;
+        out.write_all(&(FRAME_SIZE as u16).to_le_bytes())?;
+        out.write_all(&BITS_PER_SAMPLE.to_le_bytes())?;
+
+        out.write_all(b"data")?;
+        out.write_all(&0u32.to_le_bytes())?;
+
+        Ok(Self { out, data_size: 0 })
+    }
+
+    pub fn write_frame(&mut self, (left, right): (i16, i16)) -> io::Result<()> {
+        self.out.write_all(&left.to_le_bytes())?;
+        self.out.write_all(&right.to_le_bytes())?;
+        self.data_size += FRAME_SIZE;
+        Ok(())
+    }
+
+    // patch the RIFF and data chunk sizes, the file stays playable if we never get to finish
+    pub fn update_header(&mut self) -> io::Result<()> {
+        self.out.seek(SeekFrom::Start(4))?;
+        self.out
+            .write_all(&(HEADER_SIZE - 8 + self.data_size).to_le_bytes())?;
+        self.out.seek(SeekFrom::Start(HEADER_SIZE as u64 - 4))?;
+        self.out.write_all(&self.data_size.to_le_bytes())?;
+        self.out.seek(SeekFrom::End(0))?;
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> io::Result<W> {
+        self.update_header()?;
+        self.out.flush()?;
+        Ok(self.out)
+    }
+}
+
+// streams the DSP output to a WAV file, optionally resampled from the native 32kHz
+pub struct AudioRecorder {
+    writer: WavWriter<BufWriter<File>>,
+    resampler: Option<Resampler>,
+}
+
+impl AudioRecorder {
+    pub fn create(path: &str, sample_rate: u32) -> io::Result<Self> {
+        let file = BufWriter::new(File::create(path)?);
+        let resampler = match sample_rate == SAMPLE_RATE {
+            true => None,
+            false => Some(Resampler::new(SAMPLE_RATE, sample_rate)),
+        };
+
+        Ok(Self {
+            writer: WavWriter::new(file, sample_rate)?,
+            resampler,
+        })
+    }
+
+    // write out everything the DSP produced since the last call
+    pub fn record(&mut self, samples: &mut SampleBuffer) -> io::Result<()> {
+        for frame in samples.drain() {
+            match &mut self.resampler {
+                Some(resampler) => {
+                    let mut result = Ok(());
+                    resampler.push(frame, |f| {
+                        if result.is_ok() {
+                            result = self.writer.write_frame(f);
+                        }
+                    });
+                    result?;
+                }
+                None => self.writer.write_frame(frame)?,
+            }
+        }
+        self.writer.update_header()
+    }
+
+    pub fn finish(self) -> io::Result<()> {
+        self.writer.finish()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn wav_header() {
+        let mut wav = WavWriter::new(Cursor::new(Vec::new()), 48000).unwrap();
+        wav.write_frame((1, -1)).unwrap();
+        wav.write_frame((0x1234, 0)).unwrap();
+        let bytes = wav.finish().unwrap().into_inner();
+
+        assert_eq!(bytes.len(), 44 + 8);
+        assert_eq!(&bytes[0..4], b"RIFF");
+        assert_eq!(&bytes[4..8], &44u32.to_le_bytes());
+        assert_eq!(&bytes[8..16], b"WAVEfmt ");
+        assert_eq!(&bytes[24..28], &48000u32.to_le_bytes());
+        assert_eq!(&bytes[28..32], &192000u32.to_le_bytes());
+        assert_eq!(&bytes[36..40], b"data");
+        assert_eq!(&bytes[40..44], &8u32.to_le_bytes());
+        assert_eq!(
+            &bytes[44..],
+            &[0x01, 0x00, 0xFF, 0xFF, 0x34, 0x12, 0x00, 0x00]
+        );
+    }
+}
@@ -0,0 +1,3 @@
+pub mod gzip;
+pub mod inflate;
+pub mod zip;
@@ -0,0 +1,85 @@
+use crate::archive::inflate::inflate;
+use crate::checksum::crc32;
+use std::error::Error;
+
+pub const GZIP_MAGIC: [u8; 2] = [0x1F, 0x8B];
+
+const METHOD_DEFLATE: u8 = 8;
+
+// header flags
+const FLAG_HCRC: u8 = 0x1 << 1;
+const FLAG_EXTRA: u8 = 0x1 << 2;
+const FLAG_NAME: u8 = 0x1 << 3;
+const FLAG_COMMENT: u8 = 0x1 << 4;
+
+// skip a zero terminated string
+fn skip_string(data: &[u8], pos: usize) -> Result<usize, Box<dyn Error>> {
+    let len = data
+        .get(pos..)
+        .and_then(|rest| rest.iter().position(|&byte| byte == 0))
+        .ok_or("truncated gzip header")?;
+    Ok(pos + len + 1)
+}
+
+// decode the first member of a gzip file (RFC 1952), checking its CRC32 and size
+pub fn gunzip(data: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
+    if data.len() < 18 || data[..2] != GZIP_MAGIC {
+        return Err("not a gzip file".into());
+    }
+    if data[2] != METHOD_DEFLATE {
+        return Err("unsupported gzip compression method".into());
+    }
+
+    let flags = data[3];
+    let mut pos = 10;
+    if flags & FLAG_EXTRA > 0 {
+        let len = data.get(pos..pos + 2).ok_or("truncated gzip header")?;
+        pos += 2 + u16::from_le_bytes([len[0], len[1]]) as usize;
+    }
+    if flags & FLAG_NAME > 0 {
+        pos = skip_string(data, pos)?;
+    }
+    if flags & FLAG_COMMENT > 0 {
+        pos = skip_string(data, pos)?;
+    }
+    if flags & FLAG_HCRC > 0 {
+        pos += 2;
+    }
+
+    let (out, used) = inflate(data.get(pos..).ok_or("truncated gzip file")?)?;
+    let trailer = data
+        .get(pos + used..pos + used + 8)
+        .ok_or("truncated gzip file")?;
+    let crc = u32::from_le_bytes([trailer[0], trailer[1], trailer[2], trailer[3]]);
+    let size = u32::from_le_bytes([trailer[4], trailer[5], trailer[6], trailer[7]]);
+    if crc != crc32(&out) || size != out.len() as u32 {
+        return Err("gzip file is corrupt, CRC32 mismatch".into());
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plain_and_named() {
+        let data = hex::decode("1f8b08000000000002030bf2f7550cc28301bdf7602620000000").unwrap();
+        assert_eq!(gunzip(&data).unwrap(), b"ROM!".repeat(8));
+
+        // with the original file name in the header
+        let data =
+            hex::decode("1f8b08080000000002ff67616d652e73666300cb48cdc9c9070086a6103605000000")
+                .unwrap();
+        assert_eq!(gunzip(&data).unwrap(), b"hello");
+    }
+
+    #[test]
+    fn corrupt() {
+        let mut data = hex::decode("1f8b08000000000002030bf2f7550cc28301bdf7602620000000").unwrap();
+        let last = data.len() - 1;
+        data[last - 4] ^= 0x1;
+        assert!(gunzip(&data).is_err());
+        assert!(gunzip(b"not gzip at all!!!").is_err());
+    }
+}
@@ -0,0 +1,313 @@
+use crate::checksum::adler32;
+use std::error::Error;
+
+// Deflate decoder (RFC 1951), enough for zip, gzip and zlib streams
+
+// the code length code lengths come in this order
+const CODE_LENGTH_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+
+// base length and extra bits of length symbols 257-285
+#[rustfmt::skip]
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+#[rustfmt::skip]
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+
+// base distance and extra bits of distance symbols 0-29
+#[rustfmt::skip]
+const DIST_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+#[rustfmt::skip]
+const DIST_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+
+const MAX_BITS: usize = 15;
+const END_OF_BLOCK: u16 = 256;
+
+struct BitReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+    bit_buf: u32,
+    bit_count: u32,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self {
+            data,
+            pos: 0,
+            bit_buf: 0,
+            bit_count: 0,
+        }
+    }
+
+    // n bits, least significant first
+    fn bits(&mut self, n: u32) -> Result<u32, Box<dyn Error>> {
+        while self.bit_count < n {
+            let byte = *self.data.get(self.pos).ok_or("truncated deflate stream")?;
+            self.pos += 1;
+            self.bit_buf |= (byte as u32) << self.bit_count;
+            self.bit_count += 8;
+        }
+        let value = self.bit_buf & ((1 << n) - 1);
+        self.bit_buf >>= n;
+        self.bit_count -= n;
+        Ok(value)
+    }
+
+    // drop what is left of the current byte
+    fn align(&mut self) {
+        self.bit_buf = 0;
+        self.bit_count = 0;
+    }
+}
+
+// canonical Huffman code, as the number of codes of each length and the symbols sorted by code
+struct Huffman {
+    counts: [u16; MAX_BITS + 1],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Result<Self, Box<dyn Error>> {
+        let mut counts = [0u16; MAX_BITS + 1];
+        for &len in lengths {
+            counts[len as usize] += 1;
+        }
+        counts[0] = 0;
+
+        // reject over-subscribed codes, incomplete ones are allowed
+        let mut left = 1i32;
+        for &count in &counts[1..] {
+            left = (left << 1) - count as i32;
+            if left < 0 {
+                return Err("invalid Huffman code".into());
+            }
+        }
+
+        let mut offsets = [0u16; MAX_BITS + 1];
+        for len in 1..MAX_BITS {
+            offsets[len + 1] = offsets[len] + counts[len];
+        }
+        let mut symbols = vec![0u16; lengths.len()];
+        for (symbol, &len) in lengths.iter().enumerate() {
+            if len > 0 {
+                symbols[offsets[len as usize] as usize] = symbol as u16;
+                offsets[len as usize] += 1;
+            }
+        }
+        Ok(Self { counts, symbols })
+    }
+
+    fn decode(&self, reader: &mut BitReader) -> Result<u16, Box<dyn Error>> {
+        let mut code = 0i32;
+        let mut first = 0i32;
+        let mut index = 0i32;
+        for len in 1..=MAX_BITS {
+            code |= reader.bits(1)? as i32;
+            let count = self.counts[len] as i32;
+            if code - count < first {
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err("invalid Huffman code in deflate stream".into())
+    }
+}
+
+fn fixed_codes() -> Result<(Huffman, Huffman), Box<dyn Error>> {
+    let mut lengths = [0u8; 288];
+    lengths[..144].fill(8);
+    lengths[144..256].fill(9);
+    lengths[256..280].fill(7);
+    lengths[280..].fill(8);
+    Ok((Huffman::new(&lengths)?, Huffman::new(&[5; 30])?))
+}
+
+fn dynamic_codes(reader: &mut BitReader) -> Result<(Huffman, Huffman), Box<dyn Error>> {
+    let literals = reader.bits(5)? as usize + 257;
+    let distances = reader.bits(5)? as usize + 1;
+    let code_lengths = reader.bits(4)? as usize + 4;
+
+    let mut lengths = [0u8; 19];
+    for &index in &CODE_LENGTH_ORDER[..code_lengths] {
+        lengths[index] = reader.bits(3)? as u8;
+    }
+    let length_code = Huffman::new(&lengths)?;
+
+    // literal/length and distance code lengths form one run-length coded sequence
+    let mut lengths = vec![0u8; literals + distances];
+    let mut index = 0;
+    while index < lengths.len() {
+        let symbol = length_code.decode(reader)?;
+        let (value, repeat) = match symbol {
+            0..=15 => (symbol as u8, 1),
+            16 => {
+                let previous = *index
+                    .checked_sub(1)
+                    .and_then(|i| lengths.get(i))
+                    .ok_or("repeat with no previous length")?;
+                (previous, 3 + reader.bits(2)? as usize)
+            }
+            17 => (0, 3 + reader.bits(3)? as usize),
+            _ => (0, 11 + reader.bits(7)? as usize),
+        };
+        if index + repeat > lengths.len() {
+            return Err("too many code lengths".into());
+        }
+        lengths[index..index + repeat].fill(value);
+        index += repeat;
+    }
+
+    if lengths[END_OF_BLOCK as usize] == 0 {
+        return Err("no end of block code".into());
+    }
+    Ok((
+        Huffman::new(&lengths[..literals])?,
+        Huffman::new(&lengths[literals..])?,
+    ))
+}
+
+fn inflate_block(
+    reader: &mut BitReader,
+    out: &mut Vec<u8>,
+    literal: &Huffman,
+    distance: &Huffman,
+) -> Result<(), Box<dyn Error>> {
+    loop {
+        let symbol = literal.decode(reader)?;
+        match symbol {
+            0..=255 => out.push(symbol as u8),
+            END_OF_BLOCK => return Ok(()),
+            _ => {
+                let index = (symbol - 257) as usize;
+                if index >= LENGTH_BASE.len() {
+                    return Err("invalid length symbol".into());
+                }
+                let len =
+                    LENGTH_BASE[index] as usize + reader.bits(LENGTH_EXTRA[index] as u32)? as usize;
+
+                let index = distance.decode(reader)? as usize;
+                if index >= DIST_BASE.len() {
+                    return Err("invalid distance symbol".into());
+                }
+                let dist =
+                    DIST_BASE[index] as usize + reader.bits(DIST_EXTRA[index] as u32)? as usize;
+                if dist > out.len() {
+                    return Err("distance too far back".into());
+                }
+
+                // the copy may overlap what it produces
+                let start = out.len() - dist;
+                for i in 0..len {
+                    out.push(out[start + i]);
+                }
+            }
+        }
+    }
+}
+
+// decode a raw deflate stream, returning the data and the number of bytes it took up
+pub fn inflate(data: &[u8]) -> Result<(Vec<u8>, usize), Box<dyn Error>> {
+    let mut reader = BitReader::new(data);
+    let mut out = Vec::new();
+    loop {
+        let last = reader.bits(1)? == 1;
+        match reader.bits(2)? {
+            // stored
+            0 => {
+                reader.align();
+                let pos = reader.pos;
+                let header = data.get(pos..pos + 4).ok_or("truncated deflate stream")?;
+                let len = u16::from_le_bytes([header[0], header[1]]);
+                if len != !u16::from_le_bytes([header[2], header[3]]) {
+                    return Err("stored block length mismatch".into());
+                }
+                let block = data
+                    .get(pos + 4..pos + 4 + len as usize)
+                    .ok_or("truncated deflate stream")?;
+                out.extend_from_slice(block);
+                reader.pos = pos + 4 + len as usize;
+            }
+            1 => {
+                let (literal, distance) = fixed_codes()?;
+                inflate_block(&mut reader, &mut out, &literal, &distance)?;
+            }
+            2 => {
+                let (literal, distance) = dynamic_codes(&mut reader)?;
+                inflate_block(&mut reader, &mut out, &literal, &distance)?;
+            }
+            _ => return Err("invalid deflate block type".into()),
+        }
+        if last {
+            return Ok((out, reader.pos));
+        }
+    }
+}
+
+// decode a zlib stream (RFC 1950) and check its Adler-32
+pub fn unzlib(data: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
+    if data.len() < 6 || !u16::from_be_bytes([data[0], data[1]]).is_multiple_of(31) {
+        return Err("invalid zlib header".into());
+    }
+    if data[0] & 0x0F != 8 || data[1] & 0x20 > 0 {
+        return Err("unsupported zlib stream".into());
+    }
+
+    let (out, used) = inflate(&data[2..])?;
+    let checksum = data
+        .get(2 + used..2 + used + 4)
+        .ok_or("truncated zlib stream")?;
+    if u32::from_be_bytes([checksum[0], checksum[1], checksum[2], checksum[3]]) != adler32(&out) {
+        return Err("zlib checksum mismatch".into());
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stored_and_fixed_blocks() {
+        // "hi!" in a stored block, then "abcabcabcabc" as a fixed Huffman block with a match
+        let data = hex::decode("000300fcff6869214b4c4a4e842100").unwrap();
+        let (out, used) = inflate(&data).unwrap();
+        assert_eq!(out, b"hi!abcabcabcabc");
+        assert_eq!(used, data.len());
+    }
+
+    #[test]
+    fn dynamic_block() {
+        // zlib at level 9 over twelve numbered lines of the same sentence
+        let data = hex::decode(concat!(
+            "78da9dd2b71180300043d19e293402396d4330603036c9a4e939d800d5ba57e92ba905dc1c5b27305b",
+            "590d2817736834e6446fc76985d9c5f2cdaab82fd4a675d46b3cc2f8840908131226224c4c98843029",
+            "6132e6532a849f253c54eadc8d",
+        ))
+        .unwrap();
+        let expected: String = (0..12)
+            .map(|i| format!("line {}: the quick brown fox jumps over the lazy dog\n", i))
+            .collect();
+        assert_eq!(unzlib(&data).unwrap(), expected.as_bytes());
+    }
+
+    #[test]
+    fn corrupt_streams() {
+        assert!(inflate(&[0x07]).is_err());
+        assert!(inflate(&[0x01, 0x05, 0x00, 0x00, 0x00]).is_err());
+        assert!(unzlib(&[0x78, 0x9C, 0x03, 0x00, 0x00, 0x00, 0x00, 0x02]).is_err());
+    }
+}
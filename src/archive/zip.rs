@@ -0,0 +1,183 @@
+use crate::archive::inflate::inflate;
+use crate::checksum::crc32;
+use std::error::Error;
+use std::fs;
+
+pub const ZIP_MAGIC: [u8; 4] = [b'P', b'K', 0x03, 0x04];
+
+const LOCAL_HEADER_SIGNATURE: u32 = 0x04034B50;
+const CENTRAL_HEADER_SIGNATURE: u32 = 0x02014B50;
+const END_OF_DIRECTORY_SIGNATURE: u32 = 0x06054B50;
+const END_OF_DIRECTORY_SIZE: usize = 22;
+
+const METHOD_STORED: u16 = 0;
+const METHOD_DEFLATE: u16 = 8;
+
+const FLAG_ENCRYPTED: u16 = 0x1;
+
+fn u16_at(data: &[u8], pos: usize) -> Result<u16, Box<dyn Error>> {
+    let bytes = data.get(pos..pos + 2).ok_or("truncated zip file")?;
+    Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+}
+
+fn u32_at(data: &[u8], pos: usize) -> Result<u32, Box<dyn Error>> {
+    let bytes = data.get(pos..pos + 4).ok_or("truncated zip file")?;
+    Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+#[derive(Debug)]
+pub struct ZipEntry {
+    pub name: String,
+    pub size: u32,
+    method: u16,
+    flags: u16,
+    crc32: u32,
+    compressed_size: u32,
+    header_offset: u32,
+}
+
+// a zip archive read through its central directory, members can be stored or deflated
+pub struct ZipArchive {
+    data: Vec<u8>,
+    pub entries: Vec<ZipEntry>,
+}
+
+impl ZipArchive {
+    pub fn parse(data: Vec<u8>) -> Result<Self, Box<dyn Error>> {
+        // the end of directory record sits before a comment of up to 64K
+        let last = data
+            .len()
+            .checked_sub(END_OF_DIRECTORY_SIZE)
+            .ok_or("not a zip file")?;
+        let first = last.saturating_sub(0xFFFF);
+        let end = (first..=last)
+            .rev()
+            .find(|&pos| u32_at(&data, pos).ok() == Some(END_OF_DIRECTORY_SIGNATURE))
+            .ok_or("not a zip file")?;
+
+        let count = u16_at(&data, end + 10)?;
+        let mut pos = u32_at(&data, end + 16)? as usize;
+        if count == 0xFFFF || pos == 0xFFFFFFFF {
+            return Err("zip64 archives are not supported".into());
+        }
+
+        let mut entries = Vec::with_capacity(count as usize);
+        for _ in 0..count {
+            if u32_at(&data, pos)? != CENTRAL_HEADER_SIGNATURE {
+                return Err("invalid zip central directory".into());
+            }
+            let name_len = u16_at(&data, pos + 28)? as usize;
+            let extra_len = u16_at(&data, pos + 30)? as usize;
+            let comment_len = u16_at(&data, pos + 32)? as usize;
+            let name = data
+                .get(pos + 46..pos + 46 + name_len)
+                .ok_or("truncated zip file")?;
+
+            entries.push(ZipEntry {
+                name: String::from_utf8_lossy(name).into_owned(),
+                size: u32_at(&data, pos + 24)?,
+                method: u16_at(&data, pos + 10)?,
+                flags: u16_at(&data, pos + 8)?,
+                crc32: u32_at(&data, pos + 16)?,
+                compressed_size: u32_at(&data, pos + 20)?,
+                header_offset: u32_at(&data, pos + 42)?,
+            });
+            pos += 46 + name_len + extra_len + comment_len;
+        }
+
+        Ok(Self { data, entries })
+    }
+
+    pub fn open(path: &str) -> Result<Self, Box<dyn Error>> {
+        Self::parse(fs::read(path)?)
+    }
+
+    pub fn find(&self, name: &str) -> Option<&ZipEntry> {
+        self.entries.iter().find(|entry| entry.name == name)
+    }
+
+    pub fn read(&self, entry: &ZipEntry) -> Result<Vec<u8>, Box<dyn Error>> {
+        if entry.flags & FLAG_ENCRYPTED > 0 {
+            return Err(format!("{} is encrypted", entry.name).into());
+        }
+
+        // the local header repeats the name, with an extra field of its own length
+        let pos = entry.header_offset as usize;
+        if u32_at(&self.data, pos)? != LOCAL_HEADER_SIGNATURE {
+            return Err(format!("invalid local header for {}", entry.name).into());
+        }
+        let start = pos
+            + 30
+            + u16_at(&self.data, pos + 26)? as usize
+            + u16_at(&self.data, pos + 28)? as usize;
+        let compressed = self
+            .data
+            .get(start..start + entry.compressed_size as usize)
+            .ok_or("truncated zip file")?;
+
+        let data = match entry.method {
+            METHOD_STORED => compressed.to_vec(),
+            METHOD_DEFLATE => inflate(compressed)?.0,
+            method => {
+                return Err(format!(
+                    "{} uses unsupported compression method {}",
+                    entry.name, method
+                )
+                .into());
+            }
+        };
+        if data.len() != entry.size as usize || crc32(&data) != entry.crc32 {
+            return Err(format!("{} is corrupt, CRC32 mismatch", entry.name).into());
+        }
+        Ok(data)
+    }
+
+    pub fn read_file(&self, name: &str) -> Result<Vec<u8>, Box<dyn Error>> {
+        let entry = self
+            .find(name)
+            .ok_or(format!("{} not found in zip file", name))?;
+        self.read(entry)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a stored Header.txt and a deflated Input Log.txt
+    const ARCHIVE: &str = concat!(
+        "504b030414000000000000002150e26604c00e0000000e0000000a0000004865616465722e747874506c",
+        "6174666f726d20534e45530a504b030414000000080000002150e146d8d712000000d00200000d000000",
+        "496e707574204c6f672e747874abd1d3abd14302355c35a322a32243560400504b010214031400000000",
+        "0000002150e26604c00e0000000e0000000a00000000000000000000008001000000004865616465722e",
+        "747874504b0102140314000000080000002150e146d8d712000000d00200000d00000000000000000000",
+        "00800136000000496e707574204c6f672e747874504b0506000000000200020073000000730000000000",
+    );
+
+    #[test]
+    fn read_members() {
+        let zip = ZipArchive::parse(hex::decode(ARCHIVE).unwrap()).unwrap();
+        let names: Vec<&str> = zip
+            .entries
+            .iter()
+            .map(|entry| entry.name.as_str())
+            .collect();
+        assert_eq!(names, ["Header.txt", "Input Log.txt"]);
+        assert_eq!(zip.read_file("Header.txt").unwrap(), b"Platform SNES\n");
+        assert_eq!(
+            zip.read_file("Input Log.txt").unwrap(),
+            b"|..|............|\n".repeat(40)
+        );
+        assert!(zip.read_file("missing").is_err());
+    }
+
+    #[test]
+    fn corrupt_member() {
+        let mut data = hex::decode(ARCHIVE).unwrap();
+        // flip a byte of the stored member
+        data[0x2C] ^= 0xFF;
+        let zip = ZipArchive::parse(data).unwrap();
+        assert!(zip.read_file("Header.txt").is_err());
+        assert!(ZipArchive::parse(b"PK".to_vec()).is_err());
+    }
+}
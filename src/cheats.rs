@@ -0,0 +1,286 @@
+use crate::cpu::bus::Bus;
+use std::error::Error;
+use std::fs;
+
+// Cheat codes: Game Genie (DDDD-DDDD), Pro Action Replay (AAAAAAVV) and raw AAAAAA:VV or
+// AAAAAA:VV:CC with a compare value. Game Genie codes and codes aimed at ROM change what reads
+// of an address return, PAR and raw codes aimed at RAM are written every frame.
+
+// Game Genie letters stand for the hex digits 0-F in this order
+const GAME_GENIE_LETTERS: &[u8; 16] = b"DF4709156BC8A23E";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Effect {
+    // reads of the address return the value, like a Game Genie on the cartridge bus
+    Read,
+    // the value is stored every frame and replaces what the game writes, like a PAR
+    Write,
+}
+
+#[derive(Debug, Clone)]
+pub struct Cheat {
+    pub code: String,
+    pub description: String,
+    pub address: u32,
+    pub value: u8,
+    // only patch when the byte would otherwise be this
+    pub compare: Option<u8>,
+    pub effect: Effect,
+    pub enabled: bool,
+}
+
+impl Cheat {
+    fn applies(&self, address: u32, value: u8) -> bool {
+        self.enabled && self.address == address && self.compare.is_none_or(|c| c == value)
+    }
+}
+
+fn hex(text: &str) -> Result<u32, Box<dyn Error>> {
+    match text.bytes().all(|c| c.is_ascii_hexdigit()) {
+        true => Ok(u32::from_str_radix(text, 16)?),
+        false => Err("not a hex number".into()),
+    }
+}
+
+// work RAM, and the SRAM of LoROM ($70-$7D) and HiROM ($20-$3F:$6000-$7FFF) carts
+fn is_ram(address: u32) -> bool {
+    let bank = address >> 16;
+    let offset = address & 0xFFFF;
+    match bank {
+        0x7E | 0x7F => true,
+        0x70..=0x7D => offset < 0x8000,
+        _ if bank & 0x40 == 0 => {
+            offset < 0x2000 || (bank & 0x3F >= 0x20 && (0x6000..0x8000).contains(&offset))
+        }
+        _ => false,
+    }
+}
+
+// the letters are hex digits in disguise, the first two give the value and the other six a
+// scrambled address: ijkl qrst opab cduv wxef ghmn -> abcd efgh ijkl mnop qrst uvwx
+fn decode_game_genie(code: &str) -> Result<(u32, u8), Box<dyn Error>> {
+    let mut digits = 0u32;
+    for letter in code.bytes().filter(|c| *c != b'-') {
+        let digit = GAME_GENIE_LETTERS
+            .iter()
+            .position(|known| *known == letter.to_ascii_uppercase())
+            .ok_or("invalid Game Genie letter")?;
+        digits = (digits << 4) | digit as u32;
+    }
+    let bits = digits & 0xFFFFFF;
+    let address = ((bits & 0x003C00) << 10)
+        | ((bits & 0x00003C) << 14)
+        | ((bits & 0xF00000) >> 8)
+        | ((bits & 0x000003) << 10)
+        | ((bits & 0x00C000) >> 6)
+        | ((bits & 0x0F0000) >> 12)
+        | ((bits & 0x0003C0) >> 6);
+    Ok((address, (digits >> 24) as u8))
+}
+
+pub fn decode(code: &str) -> Result<Cheat, Box<dyn Error>> {
+    let invalid = |e: Box<dyn Error>| format!("invalid cheat {}: {}", code, e);
+    let (address, value, compare) = match code.len() {
+        9 if code.as_bytes()[4] == b'-' => {
+            let (address, value) = decode_game_genie(code).map_err(invalid)?;
+            (address, value, None)
+        }
+        8 if !code.contains(':') => {
+            let digits = hex(code).map_err(invalid)?;
+            (digits >> 8, digits as u8, None)
+        }
+        _ => {
+            let fields: Vec<&str> = code.split(':').collect();
+            let (address, value, compare) = match fields[..] {
+                [address, value] => (address, value, None),
+                [address, value, compare] => (address, value, Some(compare)),
+                _ => return Err(format!("unknown cheat format {}", code).into()),
+            };
+            let byte = |text: &str| match text.len() {
+                2 => hex(text).map(|value| value as u8),
+                _ => Err("values are 2 hex digits".into()),
+            };
+            if address.len() != 6 {
+                return Err(invalid("addresses are 6 hex digits".into()).into());
+            }
+            (
+                hex(address).map_err(invalid)?,
+                byte(value).map_err(invalid)?,
+                compare.map(byte).transpose().map_err(invalid)?,
+            )
+        }
+    };
+
+    let effect = match code.contains('-') || !is_ram(address) {
+        true => Effect::Read,
+        false => Effect::Write,
+    };
+    Ok(Cheat {
+        code: code.to_string(),
+        description: String::new(),
+        address: Bus::mirror(address),
+        value,
+        compare,
+        effect,
+        enabled: true,
+    })
+}
+
+#[derive(Default)]
+pub struct Cheats {
+    pub cheats: Vec<Cheat>,
+}
+
+impl Cheats {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&mut self, code: &str) -> Result<&mut Cheat, Box<dyn Error>> {
+        self.cheats.push(decode(code)?);
+        Ok(self.cheats.last_mut().unwrap())
+    }
+
+    // one code per line followed by an optional description, a line starting with '-' is
+    // loaded switched off
+    pub fn parse(&mut self, text: &str) -> Result<(), Box<dyn Error>> {
+        for (number, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let (enabled, line) = match line.strip_prefix('-') {
+                Some(line) => (false, line.trim_start()),
+                None => (true, line),
+            };
+            let (code, description) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+            let cheat = self
+                .add(code)
+                .map_err(|e| format!("line {}: {}", number + 1, e))?;
+            cheat.description = description.trim().to_string();
+            cheat.enabled = enabled;
+        }
+        Ok(())
+    }
+
+    pub fn open(&mut self, path: &str) -> Result<(), Box<dyn Error>> {
+        self.parse(&fs::read_to_string(path)?)
+            .map_err(|e| format!("{}: {}", path, e).into())
+    }
+
+    pub fn set_enabled(&mut self, index: usize, enabled: bool) -> Result<(), Box<dyn Error>> {
+        let cheat = self
+            .cheats
+            .get_mut(index)
+            .ok_or(format!("no cheat {}", index))?;
+        cheat.enabled = enabled;
+        Ok(())
+    }
+
+    pub fn toggle(&mut self, index: usize) -> Result<bool, Box<dyn Error>> {
+        let enabled = !self
+            .cheats
+            .get(index)
+            .ok_or(format!("no cheat {}", index))?
+            .enabled;
+        self.set_enabled(index, enabled)?;
+        Ok(enabled)
+    }
+
+    // what a read of `address` returns instead of `value`
+    pub fn read(&self, address: u32, value: u8) -> u8 {
+        self.cheats
+            .iter()
+            .filter(|cheat| cheat.effect == Effect::Read)
+            .find(|cheat| cheat.applies(address, value))
+            .map_or(value, |cheat| cheat.value)
+    }
+
+    // what gets stored when the game writes `value` to `address`
+    pub fn write(&self, address: u32, value: u8) -> u8 {
+        self.cheats
+            .iter()
+            .filter(|cheat| cheat.effect == Effect::Write)
+            .find(|cheat| cheat.applies(address, value))
+            .map_or(value, |cheat| cheat.value)
+    }
+
+    // the bytes the RAM codes store, done once a frame at the start of VBlank. `peek` reads
+    // what is there now.
+    pub fn ram_writes(&self, peek: impl Fn(u32) -> u8) -> Vec<(u32, u8)> {
+        self.cheats
+            .iter()
+            .filter(|cheat| cheat.effect == Effect::Write)
+            .filter(|cheat| cheat.applies(cheat.address, peek(cheat.address)))
+            .map(|cheat| (cheat.address, cheat.value))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decode_formats() {
+        let cheat = decode("C2C1-17A4").unwrap();
+        assert_eq!((cheat.address, cheat.value), (0x80A96F, 0xAD));
+        assert_eq!(cheat.effect, Effect::Read);
+
+        let cheat = decode("7E0DBE63").unwrap();
+        assert_eq!((cheat.address, cheat.value), (0x7E0DBE, 0x63));
+        assert_eq!(cheat.effect, Effect::Write);
+
+        // low RAM mirrors are stored as their bank $7E address
+        let cheat = decode("000019:02").unwrap();
+        assert_eq!((cheat.address, cheat.effect), (0x7E0019, Effect::Write));
+
+        let cheat = decode("008123:EA:A9").unwrap();
+        assert_eq!(cheat.compare, Some(0xA9));
+        assert_eq!(cheat.effect, Effect::Read);
+
+        assert!(decode("C2C1-17AZ").is_err());
+        assert!(
+            decode("7E0DBE:6")
+                .unwrap_err()
+                .to_string()
+                .ends_with("2 hex digits")
+        );
+        assert!(decode("nonsense").is_err());
+    }
+
+    #[test]
+    fn hooks() {
+        let mut cheats = Cheats::new();
+        cheats
+            .parse(
+                "# lives\n\
+                 7E0DBE:63 infinite lives\n\
+                 - 008123:EA:A9 skip intro\n\
+                 7E0100:05:01\n",
+            )
+            .unwrap();
+        assert_eq!(cheats.cheats[0].description, "infinite lives");
+        assert!(!cheats.cheats[1].enabled);
+
+        assert_eq!(cheats.read(0x008123, 0xA9), 0xA9);
+        assert!(cheats.toggle(1).unwrap());
+        assert_eq!(cheats.read(0x008123, 0xA9), 0xEA);
+        assert_eq!(cheats.read(0x008123, 0x00), 0x00);
+
+        assert_eq!(cheats.write(0x7E0DBE, 0x02), 0x63);
+        assert_eq!(cheats.write(0x7E0100, 0x01), 0x05);
+        assert_eq!(cheats.write(0x7E0100, 0x02), 0x02);
+
+        let mut memory = vec![0u8; 0x800000];
+        memory[0x7E0100] = 0x01;
+        for (address, value) in cheats.ram_writes(|address| memory[address as usize]) {
+            memory[address as usize] = value;
+        }
+        assert_eq!(memory[0x7E0DBE], 0x63);
+        assert_eq!(memory[0x7E0100], 0x05);
+
+        assert!(cheats.set_enabled(3, false).is_err());
+        assert!(cheats.parse("7E0000\n").is_err());
+    }
+}
@@ -0,0 +1,3 @@
+pub mod sa1;
+pub mod superfx;
+pub mod upd7725;
@@ -0,0 +1,4 @@
+pub mod bus;
+pub mod chip;
+pub mod dma;
+pub mod math;
@@ -0,0 +1,785 @@
+use crate::coprocessor::sa1::dma::{
+    self, CDMA_END, DCNT_CONVERSION_TYPE_1, DCNT_DEST_BWRAM, DCNT_ENABLE, Dma, Source,
+};
+use crate::coprocessor::sa1::math::Arithmetic;
+use crate::cpu::bus::CpuBus;
+
+// The cartridge side of an SA-1 game: ROM behind the bank switching registers, BW-RAM (the
+// battery backed RAM), the 2K of I-RAM inside the chip and the $2200-$23FF registers. Both the
+// S-CPU (through `main_read`/`main_write`) and the SA-1 (as its `CpuBus`) see it, each with
+// its own memory map.
+
+pub const IRAM_SIZE: usize = 0x800;
+// games with less still get the 2K every SA-1 board has
+const MIN_BWRAM_SIZE: usize = 0x800;
+
+// the SA-1 runs at 10.74MHz, two master cycles for ROM and I-RAM, BW-RAM takes twice as long
+const FAST_CYCLES: u64 = 2;
+const BWRAM_CYCLES: u64 = 4;
+
+// CCNT ($2200), the S-CPU's hold on the SA-1
+pub const CCNT_IRQ: u8 = 0x1 << 7;
+pub const CCNT_WAIT: u8 = 0x1 << 6;
+pub const CCNT_RESET: u8 = 0x1 << 5;
+pub const CCNT_NMI: u8 = 0x1 << 4;
+
+// SCNT ($2209), what the SA-1 asks of the S-CPU
+pub const SCNT_IRQ: u8 = 0x1 << 7;
+pub const SCNT_IRQ_VECTOR: u8 = 0x1 << 6;
+pub const SCNT_NMI_VECTOR: u8 = 0x1 << 4;
+
+// interrupt bits shared by SIE/SIC/SFR on the S-CPU side and CIE/CIC/CFR on the SA-1 side
+pub const INT_IRQ: u8 = 0x1 << 7;
+pub const INT_TIMER: u8 = 0x1 << 6;
+pub const INT_DMA: u8 = 0x1 << 5;
+pub const INT_NMI: u8 = 0x1 << 4;
+
+const SA1_VERSION: u8 = 0x23;
+
+// which side of the chip an access comes from, for write protection and vectors
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Side {
+    Snes,
+    Sa1,
+}
+
+pub struct Sa1Bus {
+    rom: Vec<u8>,
+    pub iram: [u8; IRAM_SIZE],
+    pub bwram: Vec<u8>,
+    pub math: Arithmetic,
+    pub dma: Dma,
+    ccnt: u8,
+    sie: u8,
+    scnt: u8,
+    cie: u8,
+    // raised interrupts, as read from SFR and CFR
+    snes_flags: u8,
+    sa1_flags: u8,
+    nmi_edge: bool,
+    reset_released: bool,
+    // reset, NMI and IRQ vectors of the SA-1, then NMI and IRQ vectors given to the S-CPU
+    crv: u16,
+    cnv: u16,
+    civ: u16,
+    snv: u16,
+    siv: u16,
+    // CXB, DXB, EXB and FXB: the 1M ROM block behind each quarter of the memory map
+    mmc: [u8; 4],
+    bmaps: u8,
+    bmap: u8,
+    sbwe: u8,
+    cbwe: u8,
+    bwpa: u8,
+    siwp: u8,
+    ciwp: u8,
+    bbf: u8,
+    // timer registers, stored but the H/V timer does not count
+    timer: [u8; 6],
+    // variable-length bit reads: VBD, the ROM address and the bit position within it
+    vbd: u8,
+    vda: u32,
+    vbit: u32,
+    // last value on the SA-1's data bus, for open bus reads
+    mdr: u8,
+    pub cycles: u64,
+}
+
+impl Sa1Bus {
+    pub fn new(rom: Vec<u8>, bwram_size: usize) -> Self {
+        Self {
+            rom,
+            iram: [0; IRAM_SIZE],
+            bwram: vec![0; bwram_size.max(MIN_BWRAM_SIZE)],
+            math: Arithmetic::default(),
+            dma: Dma::default(),
+            // the SA-1 is held in reset until the S-CPU lets it go
+            ccnt: CCNT_RESET,
+            sie: 0,
+            scnt: 0,
+            cie: 0,
+            snes_flags: 0,
+            sa1_flags: 0,
+            nmi_edge: false,
+            reset_released: false,
+            crv: 0,
+            cnv: 0,
+            civ: 0,
+            snv: 0,
+            siv: 0,
+            mmc: [0, 1, 2, 3],
+            bmaps: 0,
+            bmap: 0,
+            sbwe: 0,
+            cbwe: 0,
+            bwpa: 0,
+            siwp: 0,
+            ciwp: 0,
+            bbf: 0,
+            timer: [0; 6],
+            vbd: 0,
+            vda: 0,
+            vbit: 0,
+            mdr: 0,
+            cycles: 0,
+        }
+    }
+
+    // the SA-1 is stopped while the S-CPU holds it in reset or makes it wait
+    pub fn halted(&self) -> bool {
+        self.ccnt & (CCNT_RESET | CCNT_WAIT) > 0
+    }
+
+    // true once after the S-CPU lets go of reset, the SA-1 then starts at CRV
+    pub fn take_reset(&mut self) -> bool {
+        std::mem::take(&mut self.reset_released)
+    }
+
+    pub fn reset_vector(&self) -> u16 {
+        self.crv
+    }
+
+    // the S-CPU's IRQ line
+    pub fn snes_irq(&self) -> bool {
+        self.snes_flags & self.sie & (INT_IRQ | INT_DMA) > 0
+    }
+
+    // banks $00-$3F and $80-$BF are LoROM with 32K of a block per bank, $C0-$FF are HiROM with
+    // the whole block. Without bit 7 the LoROM quarters show blocks 0-3 whatever the register.
+    fn rom_offset(&self, bank: u32, offset: u32) -> usize {
+        let (slot, hirom) = match bank {
+            0xC0..=0xFF => ((bank >> 4) & 0x3, true),
+            _ => (((bank >> 5) & 0x1) | ((bank >> 6) & 0x2), false),
+        };
+        let mmc = self.mmc[slot as usize];
+        let block = match hirom || mmc & 0x80 > 0 {
+            true => mmc as u32 & 0x7,
+            false => slot,
+        };
+        let within = match hirom {
+            true => ((bank & 0xF) << 16) | offset,
+            false => ((bank & 0x1F) << 15) | (offset & 0x7FFF),
+        };
+        ((block << 20) | within) as usize % self.rom.len().max(1)
+    }
+
+    fn rom_byte(&self, bank: u32, offset: u32) -> u8 {
+        self.rom
+            .get(self.rom_offset(bank, offset))
+            .copied()
+            .unwrap_or(0)
+    }
+
+    // the vectors the SA-1 supplies in place of the ROM's
+    fn vector(&self, side: Side, offset: u32) -> Option<u8> {
+        let vector = match (side, offset & !0x1) {
+            (Side::Sa1, 0xFFEA | 0xFFFA) => self.cnv,
+            (Side::Sa1, 0xFFEE | 0xFFFE) => self.civ,
+            (Side::Sa1, 0xFFFC) => self.crv,
+            (Side::Snes, 0xFFEA) if self.scnt & SCNT_NMI_VECTOR > 0 => self.snv,
+            (Side::Snes, 0xFFEE) if self.scnt & SCNT_IRQ_VECTOR > 0 => self.siv,
+            _ => return None,
+        };
+        Some(vector.to_le_bytes()[(offset & 0x1) as usize])
+    }
+
+    fn bwram_index(&self, offset: usize) -> usize {
+        offset % self.bwram.len()
+    }
+
+    // BW-RAM writes need SBWE/CBWE, except past the protected area at the start that BWPA sets
+    fn bwram_writable(&self, side: Side, index: usize) -> bool {
+        let enable = match side {
+            Side::Snes => self.sbwe,
+            Side::Sa1 => self.cbwe,
+        };
+        enable & 0x80 > 0 || index >= 0x100 << (self.bwpa & 0xF)
+    }
+
+    // SIWP/CIWP allow writes to I-RAM 256 bytes at a time
+    fn iram_writable(&self, side: Side, index: usize) -> bool {
+        let protect = match side {
+            Side::Snes => self.siwp,
+            Side::Sa1 => self.ciwp,
+        };
+        protect & (0x1 << (index >> 8)) > 0
+    }
+
+    // $60-$6F on the SA-1 side: BW-RAM as one 2bpp or 4bpp pixel per byte
+    fn bitmap_position(&self, index: usize) -> (usize, usize, u8) {
+        let bits = match self.bbf & 0x80 {
+            0 => 4,
+            _ => 2,
+        };
+        let per_byte = 8 / bits;
+        let byte = self.bwram_index(index / per_byte);
+        (byte, (index % per_byte) * bits, ((1u16 << bits) - 1) as u8)
+    }
+
+    // byte `index` of the tiles a type 1 conversion makes from the bitmap at SDA
+    fn converted_byte(&self, index: usize) -> u8 {
+        let dma = &self.dma;
+        let bits = dma.bits_per_pixel();
+        let (tile, within) = (index / dma.bytes_per_char(), index % dma.bytes_per_char());
+        let width = dma.chars_per_line();
+        let (row, plane) = ((within % 16) / 2, (within / 16) * 2 + (within & 0x1));
+
+        // the bitmap holds `width` tiles side by side, 8 pixels each
+        let stride = width * bits;
+        let y = (tile / width) * 8 + row;
+        let start = dma.source as usize + y * stride + (tile % width) * bits;
+        let bytes: Vec<u8> = (0..bits)
+            .map(|i| self.bwram[self.bwram_index(start + i)])
+            .collect();
+        let pixels: Vec<u8> = (0..8).map(|x| dma::packed_pixel(&bytes, x, bits)).collect();
+        dma::plane_bits(&pixels, plane)
+    }
+
+    // memory both sides see the same way, None for what isn't there
+    fn read_memory(&mut self, side: Side, addr: u32) -> Option<u8> {
+        let bank = (addr >> 16) & 0xFF;
+        let offset = addr & 0xFFFF;
+        match bank {
+            0x00..=0x3F | 0x80..=0xBF => match offset {
+                0x0000..=0x07FF if side == Side::Sa1 => Some(self.iram[offset as usize]),
+                0x2200..=0x23FF => Some(self.read_register(offset as u16)),
+                0x3000..=0x37FF => Some(self.iram[offset as usize & 0x7FF]),
+                0x6000..=0x7FFF => {
+                    let block = match side {
+                        Side::Snes => self.bmaps & 0x1F,
+                        Side::Sa1 => self.bmap & 0x7F,
+                    };
+                    let index = (block as usize) << 13 | (offset as usize & 0x1FFF);
+                    match side == Side::Sa1 && self.bmap & 0x80 > 0 {
+                        true => Some(self.read_bitmap(index)),
+                        false => Some(self.read_bwram(side, index)),
+                    }
+                }
+                0x8000..=0xFFFF => Some(match bank {
+                    0x00 => self
+                        .vector(side, offset)
+                        .unwrap_or_else(|| self.rom_byte(bank, offset)),
+                    _ => self.rom_byte(bank, offset),
+                }),
+                _ => None,
+            },
+            0x40..=0x4F => {
+                Some(self.read_bwram(side, ((bank as usize & 0xF) << 16) | offset as usize))
+            }
+            0x60..=0x6F if side == Side::Sa1 => {
+                Some(self.read_bitmap(((bank as usize & 0xF) << 16) | offset as usize))
+            }
+            0xC0..=0xFF => Some(self.rom_byte(bank, offset)),
+            _ => None,
+        }
+    }
+
+    fn read_bwram(&self, side: Side, index: usize) -> u8 {
+        // a type 1 conversion hands the S-CPU tiles in place of the bitmap
+        if side == Side::Snes
+            && self.dma.converting
+            && let Some(converted) = index.checked_sub(self.dma.source as usize)
+        {
+            return self.converted_byte(converted);
+        }
+        self.bwram[self.bwram_index(index)]
+    }
+
+    fn read_bitmap(&self, index: usize) -> u8 {
+        let (byte, shift, mask) = self.bitmap_position(index);
+        (self.bwram[byte] >> shift) & mask
+    }
+
+    fn write_memory(&mut self, side: Side, addr: u32, val: u8) -> bool {
+        let bank = (addr >> 16) & 0xFF;
+        let offset = addr & 0xFFFF;
+        match bank {
+            0x00..=0x3F | 0x80..=0xBF => match offset {
+                0x0000..=0x07FF if side == Side::Sa1 => self.write_iram(side, offset as usize, val),
+                0x2200..=0x23FF => self.write_register(offset as u16, val),
+                0x3000..=0x37FF => self.write_iram(side, offset as usize & 0x7FF, val),
+                0x6000..=0x7FFF => {
+                    let block = match side {
+                        Side::Snes => self.bmaps & 0x1F,
+                        Side::Sa1 => self.bmap & 0x7F,
+                    };
+                    let index = (block as usize) << 13 | (offset as usize & 0x1FFF);
+                    match side == Side::Sa1 && self.bmap & 0x80 > 0 {
+                        true => self.write_bitmap(index, val),
+                        false => self.write_bwram(side, index, val),
+                    }
+                }
+                // ROM
+                0x8000..=0xFFFF => {}
+                _ => return false,
+            },
+            0x40..=0x4F => {
+                self.write_bwram(side, ((bank as usize & 0xF) << 16) | offset as usize, val)
+            }
+            0x60..=0x6F if side == Side::Sa1 => {
+                self.write_bitmap(((bank as usize & 0xF) << 16) | offset as usize, val)
+            }
+            0xC0..=0xFF => {}
+            _ => return false,
+        }
+        true
+    }
+
+    fn write_iram(&mut self, side: Side, index: usize, val: u8) {
+        if self.iram_writable(side, index) {
+            self.iram[index] = val;
+        }
+    }
+
+    fn write_bwram(&mut self, side: Side, index: usize, val: u8) {
+        let index = self.bwram_index(index);
+        if self.bwram_writable(side, index) {
+            self.bwram[index] = val;
+        }
+    }
+
+    fn write_bitmap(&mut self, index: usize, val: u8) {
+        let (byte, shift, mask) = self.bitmap_position(index);
+        self.bwram[byte] = (self.bwram[byte] & !(mask << shift)) | ((val & mask) << shift);
+    }
+
+    // 16 bits from the bit position of a variable-length read
+    fn bit_data(&self) -> u16 {
+        let bytes = (0..3).fold(0u32, |data, i| {
+            let addr = self.vda + i;
+            data | (self.rom_byte((addr >> 16) & 0xFF, addr & 0xFFFF) as u32) << (i * 8)
+        });
+        (bytes >> self.vbit) as u16
+    }
+
+    fn advance_bits(&mut self) {
+        let len = match self.vbd & 0xF {
+            0 => 16,
+            len => len as u32,
+        };
+        self.vbit += len;
+        self.vda = (self.vda + (self.vbit >> 3)) & 0xFFFFFF;
+        self.vbit &= 0x7;
+    }
+
+    fn read_register(&mut self, offset: u16) -> u8 {
+        match offset {
+            // SFR
+            0x2300 => self.snes_flags | (self.scnt & (SCNT_IRQ_VECTOR | SCNT_NMI_VECTOR | 0xF)),
+            // CFR
+            0x2301 => self.sa1_flags | (self.ccnt & 0xF),
+            0x2302..=0x2305 => 0,
+            0x2306..=0x230B => self.math.read_register(offset),
+            0x230C => self.bit_data() as u8,
+            0x230D => {
+                let value = (self.bit_data() >> 8) as u8;
+                if self.vbd & 0x80 > 0 {
+                    self.advance_bits();
+                }
+                value
+            }
+            0x230E => SA1_VERSION,
+            // write-only
+            _ => self.mdr,
+        }
+    }
+
+    fn write_register(&mut self, offset: u16, val: u8) {
+        match offset {
+            0x2200 => {
+                if self.ccnt & CCNT_RESET > 0 && val & CCNT_RESET == 0 {
+                    self.reset_released = true;
+                }
+                if val & CCNT_IRQ > 0 {
+                    self.sa1_flags |= INT_IRQ;
+                }
+                if val & CCNT_NMI > 0 {
+                    self.sa1_flags |= INT_NMI;
+                    self.nmi_edge = true;
+                }
+                self.ccnt = val;
+            }
+            0x2201 => self.sie = val,
+            0x2202 => self.snes_flags &= !(val & (INT_IRQ | INT_DMA)),
+            0x2203 => self.crv = (self.crv & 0xFF00) | val as u16,
+            0x2204 => self.crv = (self.crv & 0x00FF) | (val as u16) << 8,
+            0x2205 => self.cnv = (self.cnv & 0xFF00) | val as u16,
+            0x2206 => self.cnv = (self.cnv & 0x00FF) | (val as u16) << 8,
+            0x2207 => self.civ = (self.civ & 0xFF00) | val as u16,
+            0x2208 => self.civ = (self.civ & 0x00FF) | (val as u16) << 8,
+            0x2209 => {
+                if val & SCNT_IRQ > 0 {
+                    self.snes_flags |= INT_IRQ;
+                }
+                self.scnt = val;
+            }
+            0x220A => self.cie = val,
+            0x220B => self.sa1_flags &= !(val & (INT_IRQ | INT_TIMER | INT_DMA | INT_NMI)),
+            0x220C => self.snv = (self.snv & 0xFF00) | val as u16,
+            0x220D => self.snv = (self.snv & 0x00FF) | (val as u16) << 8,
+            0x220E => self.siv = (self.siv & 0xFF00) | val as u16,
+            0x220F => self.siv = (self.siv & 0x00FF) | (val as u16) << 8,
+            0x2210..=0x2215 => self.timer[(offset - 0x2210) as usize] = val,
+            0x2220..=0x2223 => self.mmc[(offset - 0x2220) as usize] = val,
+            0x2224 => self.bmaps = val,
+            0x2225 => self.bmap = val,
+            0x2226 => self.sbwe = val,
+            0x2227 => self.cbwe = val,
+            0x2228 => self.bwpa = val,
+            0x2229 => self.siwp = val,
+            0x222A => self.ciwp = val,
+            0x2230 => self.dma.control = val,
+            0x2231 => {
+                self.dma.cdma = val;
+                if val & CDMA_END > 0 {
+                    self.dma.converting = false;
+                }
+            }
+            0x2232 => self.dma.source = (self.dma.source & 0xFFFF00) | val as u32,
+            0x2233 => self.dma.source = (self.dma.source & 0xFF00FF) | (val as u32) << 8,
+            0x2234 => self.dma.source = (self.dma.source & 0x00FFFF) | (val as u32) << 16,
+            0x2235 => self.dma.dest = (self.dma.dest & 0xFFFF00) | val as u32,
+            0x2236 => {
+                self.dma.dest = (self.dma.dest & 0xFF00FF) | (val as u32) << 8;
+                if self.dma.control & DCNT_DEST_BWRAM == 0 {
+                    self.start_dma();
+                }
+            }
+            0x2237 => {
+                self.dma.dest = (self.dma.dest & 0x00FFFF) | (val as u32) << 16;
+                if self.dma.control & DCNT_DEST_BWRAM > 0 {
+                    self.start_dma();
+                }
+            }
+            0x2238 => self.dma.count = (self.dma.count & 0xFF00) | val as u16,
+            0x2239 => self.dma.count = (self.dma.count & 0x00FF) | (val as u16) << 8,
+            0x223F => self.bbf = val,
+            0x2240..=0x224F => {
+                self.dma.brf[(offset - 0x2240) as usize] = val;
+                // each full buffer of 8 pixels is one row of a type 2 conversion
+                if offset & 0x7 == 0x7 && self.dma.char_conversion() {
+                    let start = (offset as usize - 0x2240) & 0x8;
+                    self.convert_row(start);
+                }
+            }
+            0x2250..=0x2254 => self.math.write_register(offset, val),
+            0x2258 => {
+                self.vbd = val;
+                // without auto increment, every VBD write moves on
+                if val & 0x80 == 0 {
+                    self.advance_bits();
+                }
+            }
+            0x2259 => self.vda = (self.vda & 0xFFFF00) | val as u32,
+            0x225A => self.vda = (self.vda & 0xFF00FF) | (val as u32) << 8,
+            0x225B => {
+                self.vda = (self.vda & 0x00FFFF) | (val as u32) << 16;
+                self.vbit = 0;
+            }
+            _ => {}
+        }
+    }
+
+    fn start_dma(&mut self) {
+        if self.dma.control & DCNT_ENABLE == 0 {
+            return;
+        }
+        self.dma.row = 0;
+        if self.dma.char_conversion() {
+            // type 1 tells the S-CPU the tiles are ready to be read, type 2 waits for pixels
+            if self.dma.control & DCNT_CONVERSION_TYPE_1 > 0 {
+                self.dma.converting = true;
+                self.snes_flags |= INT_DMA;
+            }
+            return;
+        }
+
+        for i in 0..self.dma.count as u32 {
+            let source = self.dma.source + i;
+            let byte = match self.dma.source_kind() {
+                Source::Rom => self.rom_byte((source >> 16) & 0xFF, source & 0xFFFF),
+                Source::Bwram => self.bwram[self.bwram_index(source as usize)],
+                Source::Iram => self.iram[source as usize % IRAM_SIZE],
+            };
+            let dest = (self.dma.dest + i) as usize;
+            match self.dma.control & DCNT_DEST_BWRAM > 0 {
+                true => {
+                    let index = self.bwram_index(dest);
+                    self.bwram[index] = byte;
+                }
+                false => self.iram[dest % IRAM_SIZE] = byte,
+            }
+        }
+        self.sa1_flags |= INT_DMA;
+    }
+
+    // turn the 8 pixels in a BRF buffer into one row of tile at DDA in I-RAM
+    fn convert_row(&mut self, start: usize) {
+        let pixels = self.dma.brf[start..start + 8].to_vec();
+        let tile = self.dma.row as usize / 8;
+        let row = self.dma.row as usize % 8;
+        let base = self.dma.dest as usize + tile * self.dma.bytes_per_char();
+        for plane in 0..self.dma.bits_per_pixel() {
+            self.iram[(base + dma::planar_offset(row, plane)) % IRAM_SIZE] =
+                dma::plane_bits(&pixels, plane);
+        }
+        self.dma.row += 1;
+    }
+
+    // the S-CPU side: registers, I-RAM, the BW-RAM window and ROM. None is for addresses that
+    // aren't the cartridge's, like WRAM and the PPU.
+    pub fn main_read(&mut self, addr: u32) -> Option<u8> {
+        self.read_memory(Side::Snes, addr)
+    }
+
+    pub fn main_write(&mut self, addr: u32, val: u8) -> bool {
+        self.write_memory(Side::Snes, addr, val)
+    }
+
+    // where I-RAM and BW-RAM are for the S-CPU, as (is I-RAM, index)
+    fn ram_location(&self, addr: u32) -> Option<(bool, usize)> {
+        let bank = (addr >> 16) & 0xFF;
+        let offset = addr as usize & 0xFFFF;
+        match (bank, offset) {
+            (0x00..=0x3F | 0x80..=0xBF, 0x3000..=0x37FF) => Some((true, offset & 0x7FF)),
+            (0x00..=0x3F | 0x80..=0xBF, 0x6000..=0x7FFF) => {
+                let index = ((self.bmaps & 0x1F) as usize) << 13 | (offset & 0x1FFF);
+                Some((false, self.bwram_index(index)))
+            }
+            (0x40..=0x4F, _) => {
+                let index = (bank as usize & 0xF) << 16 | offset;
+                Some((false, self.bwram_index(index)))
+            }
+            _ => None,
+        }
+    }
+
+    // I-RAM and BW-RAM without write protection or DMA getting in the way, for RAM search,
+    // watches and cheats
+    pub fn peek(&self, addr: u32) -> Option<u8> {
+        match self.ram_location(addr)? {
+            (true, index) => Some(self.iram[index]),
+            (false, index) => Some(self.bwram[index]),
+        }
+    }
+
+    pub fn poke(&mut self, addr: u32, val: u8) -> bool {
+        match self.ram_location(addr) {
+            Some((true, index)) => self.iram[index] = val,
+            Some((false, index)) => self.bwram[index] = val,
+            None => return false,
+        }
+        true
+    }
+
+    fn access_cycles(&self, addr: u32) -> u64 {
+        match (addr >> 16) & 0xFF {
+            0x40..=0x4F | 0x60..=0x6F => BWRAM_CYCLES,
+            0x00..=0x3F | 0x80..=0xBF if (0x6000..0x8000).contains(&(addr & 0xFFFF)) => {
+                BWRAM_CYCLES
+            }
+            _ => FAST_CYCLES,
+        }
+    }
+}
+
+impl CpuBus for Sa1Bus {
+    fn read_byte(&mut self, addr: u32) -> u8 {
+        self.cycles += self.access_cycles(addr);
+        if let Some(value) = self.read_memory(Side::Sa1, addr) {
+            self.mdr = value;
+        }
+        self.mdr
+    }
+
+    fn write_byte(&mut self, addr: u32, val: u8) {
+        self.cycles += self.access_cycles(addr);
+        self.mdr = val;
+        self.write_memory(Side::Sa1, addr, val);
+    }
+
+    fn take_nmi(&mut self) -> bool {
+        match self.nmi_edge && self.cie & INT_NMI > 0 {
+            true => std::mem::take(&mut self.nmi_edge),
+            false => false,
+        }
+    }
+
+    fn irq_pending(&self) -> bool {
+        self.sa1_flags & self.cie & (INT_IRQ | INT_TIMER | INT_DMA) > 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // four 1M blocks, each filled with its number
+    fn bus() -> Sa1Bus {
+        let rom = (0..4u8).flat_map(|block| vec![block; 0x100000]).collect();
+        Sa1Bus::new(rom, 0x8000)
+    }
+
+    #[test]
+    fn bank_switching() {
+        let mut bus = bus();
+        assert_eq!(bus.main_read(0x008000), Some(0));
+        assert_eq!(bus.main_read(0x208000), Some(1));
+        assert_eq!(bus.main_read(0xA08000), Some(3));
+        assert_eq!(bus.main_read(0xC00000), Some(0));
+        assert_eq!(bus.main_read(0xF00000), Some(3));
+
+        // the LoROM quarter only follows CXB with bit 7 set, the HiROM banks always do
+        bus.main_write(0x002220, 0x02);
+        assert_eq!(bus.main_read(0x008000), Some(0));
+        assert_eq!(bus.main_read(0xC00000), Some(2));
+        bus.main_write(0x002220, 0x82);
+        assert_eq!(bus.main_read(0x008000), Some(2));
+
+        assert_eq!(bus.main_read(0x7E0000), None);
+        assert_eq!(bus.main_read(0x002100), None);
+        assert_eq!(bus.main_read(0x00230E), Some(SA1_VERSION));
+    }
+
+    #[test]
+    fn ram_protection_and_windows() {
+        let mut bus = bus();
+        // I-RAM needs SIWP, BW-RAM past the 256 byte protected area is always writable
+        bus.main_write(0x003000, 0x11);
+        assert_eq!(bus.main_read(0x003000), Some(0x00));
+        bus.main_write(0x002229, 0x01);
+        bus.main_write(0x003000, 0x11);
+        assert_eq!(bus.main_read(0x003000), Some(0x11));
+        assert_eq!(bus.read_byte(0x000000), 0x11);
+
+        bus.main_write(0x400000, 0x22);
+        assert_eq!(bus.main_read(0x400000), Some(0x00));
+        bus.main_write(0x002226, 0x80);
+        bus.main_write(0x400000, 0x22);
+        bus.main_write(0x401FFF, 0x33);
+        // BMAPS picks the 8K block at $6000
+        assert_eq!(bus.main_read(0x006000), Some(0x22));
+        bus.main_write(0x002224, 0x01);
+        assert_eq!(bus.main_read(0x007FFF), Some(0x00));
+        assert_eq!(bus.main_read(0x406000), Some(0x00));
+
+        // the SA-1's bitmap view, 4bpp: pixels 0 and 1 are the two nibbles of byte 0
+        bus.write_byte(0x600001, 0x0F);
+        assert_eq!(bus.read_byte(0x400000), 0xF2);
+        assert_eq!(bus.read_byte(0x600000), 0x02);
+    }
+
+    #[test]
+    fn interrupts_and_messages() {
+        let mut bus = bus();
+        assert!(bus.halted());
+        bus.main_write(0x002200, 0x00);
+        assert!(!bus.halted());
+        assert!(bus.take_reset());
+        assert!(!bus.take_reset());
+
+        // S-CPU -> SA-1
+        bus.write_byte(0x00220A, INT_IRQ | INT_NMI);
+        bus.main_write(0x002200, CCNT_IRQ | CCNT_NMI | 0x5);
+        assert!(bus.irq_pending());
+        assert!(bus.take_nmi());
+        assert!(!bus.take_nmi());
+        assert_eq!(bus.read_byte(0x002301), INT_IRQ | INT_NMI | 0x5);
+        bus.write_byte(0x00220B, INT_IRQ);
+        assert!(!bus.irq_pending());
+
+        // SA-1 -> S-CPU, with its own IRQ vector
+        bus.main_write(0x002201, INT_IRQ);
+        bus.write_byte(0x00220E, 0x34);
+        bus.write_byte(0x00220F, 0x12);
+        bus.write_byte(0x002209, SCNT_IRQ | SCNT_IRQ_VECTOR | 0xA);
+        assert!(bus.snes_irq());
+        assert_eq!(
+            bus.main_read(0x002300),
+            Some(INT_IRQ | SCNT_IRQ_VECTOR | 0xA)
+        );
+        assert_eq!(bus.main_read(0x00FFEE), Some(0x34));
+        assert_eq!(bus.main_read(0x00FFEF), Some(0x12));
+        bus.main_write(0x002202, INT_IRQ);
+        assert!(!bus.snes_irq());
+    }
+
+    #[test]
+    fn variable_length_reads() {
+        let mut rom = vec![0u8; 0x10000];
+        rom[0..3].copy_from_slice(&[0b1010_0101, 0b1100_0011, 0xFF]);
+        let mut bus = Sa1Bus::new(rom, 0);
+        for (offset, val) in [(0x2259, 0x00), (0x225A, 0x80), (0x225B, 0x00)] {
+            bus.write_byte(offset, val);
+        }
+        assert_eq!(bus.read_byte(0x230C), 0b1010_0101);
+
+        // auto increment by 4 bits on each read of the high byte
+        bus.write_byte(0x2258, 0x84);
+        assert_eq!(bus.read_byte(0x230D), 0b1100_0011);
+        assert_eq!(bus.read_byte(0x230C), 0b0011_1010);
+        bus.read_byte(0x230D);
+        assert_eq!(bus.read_byte(0x230C), 0b1100_0011);
+
+        // fixed mode moves on when VBD is written
+        bus.write_byte(0x2258, 0x03);
+        assert_eq!(bus.read_byte(0x230C), 0b1111_1000);
+    }
+
+    #[test]
+    fn dma_and_character_conversion() {
+        let mut bus = bus();
+        // a plain copy of 4 bytes of ROM block 1 into I-RAM
+        for (offset, val) in [
+            (0x2230, DCNT_ENABLE),
+            (0x2232, 0x00),
+            (0x2233, 0x80),
+            (0x2234, 0x20),
+            (0x2238, 0x04),
+            (0x2239, 0x00),
+            (0x2235, 0x10),
+            (0x2236, 0x00),
+        ] {
+            bus.write_byte(offset, val);
+        }
+        assert_eq!(bus.iram[0x0F..0x15], [0, 1, 1, 1, 1, 0]);
+        assert_eq!(bus.read_byte(0x2301) & INT_DMA, INT_DMA);
+
+        // type 2: one row of 2bpp pixels written through BRF
+        bus.write_byte(0x2230, DCNT_ENABLE | dma::DCNT_CHAR_CONVERSION);
+        bus.write_byte(0x2231, 0x02);
+        bus.write_byte(0x2235, 0x00);
+        bus.write_byte(0x2236, 0x01);
+        for (i, pixel) in [3, 0, 1, 2, 3, 0, 0, 1].iter().enumerate() {
+            bus.write_byte(0x2240 + i as u32, *pixel);
+        }
+        assert_eq!(bus.iram[0x100..0x102], [0b1010_1001, 0b1001_1000]);
+
+        // type 1: the S-CPU reads tiles from the BW-RAM bitmap at SDA, 2bpp one tile wide
+        bus.main_write(0x002226, 0x80);
+        bus.main_write(0x400100, 0x1B);
+        bus.main_write(0x400101, 0x1B);
+        bus.main_write(0x400102, 0xE4);
+        bus.write_byte(
+            0x2230,
+            DCNT_ENABLE | dma::DCNT_CHAR_CONVERSION | DCNT_CONVERSION_TYPE_1,
+        );
+        bus.write_byte(0x2231, 0x02);
+        for (offset, val) in [
+            (0x2232, 0x00),
+            (0x2233, 0x01),
+            (0x2234, 0x00),
+            (0x2236, 0x00),
+        ] {
+            bus.write_byte(offset, val);
+        }
+        assert_eq!(bus.main_read(0x002300), Some(INT_DMA));
+        // pixels 3 2 1 0 3 2 1 0 on row 0, 0 1 2 3 0 0 0 0 on row 1
+        assert_eq!(bus.main_read(0x400100), Some(0b1010_1010));
+        assert_eq!(bus.main_read(0x400101), Some(0b1100_1100));
+        assert_eq!(bus.main_read(0x400102), Some(0b0101_0000));
+        assert_eq!(bus.main_read(0x400103), Some(0b0011_0000));
+        bus.write_byte(0x2231, CDMA_END);
+        assert_eq!(bus.main_read(0x400100), Some(0x1B));
+    }
+}
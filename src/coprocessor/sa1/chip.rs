@@ -0,0 +1,94 @@
+use crate::coprocessor::sa1::bus::Sa1Bus;
+use crate::cpu::alu::{Cpu, S_ACCUMULATOR_MEMORY, S_INDEX_REGISTERS, S_IRQ_DISABLE};
+
+// The SA-1 itself: a second 65816 on its own bus, kept in step with the master clock by the
+// S-CPU's bus.
+
+pub struct Sa1 {
+    pub cpu: Cpu<Sa1Bus>,
+    // master cycles the SA-1 may still run before it catches up with the S-CPU
+    balance: i64,
+}
+
+impl Sa1 {
+    pub fn new(rom: Vec<u8>, bwram_size: usize) -> Self {
+        Self {
+            cpu: Cpu::new(Box::new(Sa1Bus::new(rom, bwram_size))),
+            balance: 0,
+        }
+    }
+
+    // run for as many master cycles as the S-CPU just spent
+    pub fn advance(&mut self, cycles: u64) {
+        if self.cpu.bus.take_reset() {
+            let cpu = &mut self.cpu;
+            cpu.pc = cpu.bus.reset_vector();
+            cpu.reg_pb = 0;
+            cpu.reg_db = 0;
+            cpu.reg_d = 0;
+            cpu.reg_p = S_ACCUMULATOR_MEMORY | S_INDEX_REGISTERS | S_IRQ_DISABLE;
+            cpu.emulation = true;
+            cpu.sp = 0x1FF;
+        }
+        if self.cpu.bus.halted() {
+            self.balance = 0;
+            return;
+        }
+
+        self.balance += cycles as i64;
+        while self.balance > 0 {
+            let before = self.cpu.bus.cycles;
+            self.cpu.step();
+            // an instruction always reads its opcode, but don't spin if it somehow didn't
+            self.balance -= (self.cpu.bus.cycles - before).max(1) as i64;
+        }
+    }
+
+    pub fn read(&mut self, addr: u32) -> Option<u8> {
+        self.cpu.bus.main_read(addr)
+    }
+
+    pub fn write(&mut self, addr: u32, val: u8) -> bool {
+        self.cpu.bus.main_write(addr, val)
+    }
+
+    pub fn peek(&self, addr: u32) -> Option<u8> {
+        self.cpu.bus.peek(addr)
+    }
+
+    pub fn poke(&mut self, addr: u32, val: u8) -> bool {
+        self.cpu.bus.poke(addr, val)
+    }
+
+    // the SA-1's IRQ to the S-CPU
+    pub fn irq(&self) -> bool {
+        self.cpu.bus.snes_irq()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn runs_from_the_reset_vector() {
+        // LDA #$FF, STA $222A, LDA #$42, STA $3000, BRA *
+        let program = [
+            0xA9, 0xFF, 0x8D, 0x2A, 0x22, 0xA9, 0x42, 0x8D, 0x00, 0x30, 0x80, 0xFE,
+        ];
+        let mut rom = vec![0u8; 0x8000];
+        rom[0x100..0x100 + program.len()].copy_from_slice(&program);
+        let mut sa1 = Sa1::new(rom, 0);
+
+        // held in reset at power-on
+        sa1.advance(1000);
+        assert_eq!(sa1.read(0x003000), Some(0x00));
+
+        sa1.write(0x002203, 0x00);
+        sa1.write(0x002204, 0x81);
+        sa1.write(0x002200, 0x00);
+        sa1.advance(1000);
+        assert_eq!(sa1.read(0x003000), Some(0x42));
+        assert_eq!(sa1.cpu.pc, 0x810A);
+    }
+}
@@ -0,0 +1,118 @@
+// SA-1 DMA: plain copies between ROM, BW-RAM and I-RAM, and character conversion, which turns a
+// packed bitmap into SNES bitplane tiles. Type 1 converts BW-RAM as the S-CPU reads it, type 2
+// converts the pixels the SA-1 writes to the bitmap register file.
+
+pub const DCNT_ENABLE: u8 = 0x1 << 7;
+pub const DCNT_CHAR_CONVERSION: u8 = 0x1 << 5;
+// 1 for type 1, 0 for type 2
+pub const DCNT_CONVERSION_TYPE_1: u8 = 0x1 << 4;
+pub const DCNT_DEST_BWRAM: u8 = 0x1 << 2;
+pub const CDMA_END: u8 = 0x1 << 7;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Source {
+    Rom,
+    Bwram,
+    Iram,
+}
+
+#[derive(Default)]
+pub struct Dma {
+    pub control: u8,
+    pub cdma: u8,
+    pub source: u32,
+    pub dest: u32,
+    pub count: u16,
+    // bitmap register file, two buffers of 8 pixels
+    pub brf: [u8; 16],
+    // rows converted since the destination was set (type 2)
+    pub row: u32,
+    // a type 1 conversion is waiting for the S-CPU to read it
+    pub converting: bool,
+}
+
+impl Dma {
+    pub fn source_kind(&self) -> Source {
+        match self.control & 0x3 {
+            0 => Source::Rom,
+            1 => Source::Bwram,
+            _ => Source::Iram,
+        }
+    }
+
+    pub fn char_conversion(&self) -> bool {
+        self.control & (DCNT_ENABLE | DCNT_CHAR_CONVERSION) == DCNT_ENABLE | DCNT_CHAR_CONVERSION
+    }
+
+    // 8, 4 or 2 bits per pixel
+    pub fn bits_per_pixel(&self) -> usize {
+        match self.cdma & 0x3 {
+            0 => 8,
+            1 => 4,
+            _ => 2,
+        }
+    }
+
+    // characters in a row of the type 1 bitmap
+    pub fn chars_per_line(&self) -> usize {
+        1 << ((self.cdma >> 2) & 0x7).min(5)
+    }
+
+    pub fn bytes_per_char(&self) -> usize {
+        self.bits_per_pixel() * 8
+    }
+}
+
+// where in a tile byte `plane` of pixel row `row` goes, planes come in pairs of 16 bytes
+pub fn planar_offset(row: usize, plane: usize) -> usize {
+    (plane / 2) * 16 + row * 2 + (plane & 1)
+}
+
+// one bitplane of 8 pixels, leftmost pixel in bit 7
+pub fn plane_bits(pixels: &[u8], plane: usize) -> u8 {
+    pixels
+        .iter()
+        .take(8)
+        .fold(0, |bits, pixel| (bits << 1) | ((pixel >> plane) & 0x1))
+}
+
+// pixel `x` of a packed bitmap row, lower bits hold the leftmost pixel of each byte
+pub fn packed_pixel(row: &[u8], x: usize, bits_per_pixel: usize) -> u8 {
+    let per_byte = 8 / bits_per_pixel;
+    let byte = row[x / per_byte];
+    let shift = (x % per_byte) * bits_per_pixel;
+    ((byte as u16 >> shift) & ((1 << bits_per_pixel) - 1)) as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn conversion_helpers() {
+        assert_eq!(planar_offset(3, 0), 6);
+        assert_eq!(planar_offset(3, 3), 23);
+        let pixels = [3, 0, 1, 2, 3, 0, 0, 1];
+        assert_eq!(plane_bits(&pixels, 0), 0b1010_1001);
+        assert_eq!(plane_bits(&pixels, 1), 0b1001_1000);
+
+        // 0x1B holds pixels 3, 2, 1, 0 at 2bpp
+        assert_eq!(packed_pixel(&[0x1B], 0, 2), 3);
+        assert_eq!(packed_pixel(&[0x1B], 3, 2), 0);
+        assert_eq!(packed_pixel(&[0x00, 0xA5], 3, 4), 0xA);
+        assert_eq!(packed_pixel(&[0x12, 0x34], 1, 8), 0x34);
+
+        let dma = Dma {
+            cdma: 0x1 << 2 | 0x1,
+            ..Default::default()
+        };
+        assert_eq!(
+            (
+                dma.bits_per_pixel(),
+                dma.chars_per_line(),
+                dma.bytes_per_char()
+            ),
+            (4, 2, 32)
+        );
+    }
+}
@@ -0,0 +1,117 @@
+// The SA-1 arithmetic unit: signed 16x16 multiply, signed by unsigned divide and a 40-bit
+// multiply-accumulate, all finished by the time the CPU can read the result.
+
+pub const MCNT_DIVIDE: u8 = 0x1;
+pub const MCNT_CUMULATIVE: u8 = 0x1 << 1;
+
+const RESULT_MASK: i64 = 0xFF_FFFF_FFFF;
+
+#[derive(Default)]
+pub struct Arithmetic {
+    control: u8,
+    multiplicand: u16,
+    multiplier: u16,
+    // 40 bits, read back from MR at $2306-$230A
+    result: u64,
+    overflow: bool,
+}
+
+impl Arithmetic {
+    // $2250-$2254, writing the high byte of MB runs the operation
+    pub fn write_register(&mut self, offset: u16, val: u8) {
+        match offset {
+            0x2250 => {
+                self.control = val & 0x3;
+                // choosing the cumulative sum starts it from 0
+                if val & MCNT_CUMULATIVE > 0 {
+                    self.result = 0;
+                    self.overflow = false;
+                }
+            }
+            0x2251 => self.multiplicand = (self.multiplicand & 0xFF00) | val as u16,
+            0x2252 => self.multiplicand = (self.multiplicand & 0x00FF) | (val as u16) << 8,
+            0x2253 => self.multiplier = (self.multiplier & 0xFF00) | val as u16,
+            0x2254 => {
+                self.multiplier = (self.multiplier & 0x00FF) | (val as u16) << 8;
+                self.run();
+            }
+            _ => {}
+        }
+    }
+
+    // $2306-$230B
+    pub fn read_register(&self, offset: u16) -> u8 {
+        match offset {
+            0x2306..=0x230A => (self.result >> ((offset - 0x2306) * 8)) as u8,
+            _ => match self.overflow {
+                true => 0x80,
+                false => 0x00,
+            },
+        }
+    }
+
+    fn run(&mut self) {
+        let a = self.multiplicand as i16 as i64;
+        let product = a * self.multiplier as i16 as i64;
+        if self.control & MCNT_CUMULATIVE > 0 {
+            // the 40-bit total is signed
+            let sum = (((self.result << 24) as i64) >> 24) + product;
+            self.overflow |= !(-(1 << 39)..1 << 39).contains(&sum);
+            self.result = (sum & RESULT_MASK) as u64;
+        } else if self.control & MCNT_DIVIDE > 0 {
+            // the remainder is never negative, a division by 0 gives 0 and 0
+            let (quotient, remainder) = match self.multiplier {
+                0 => (0, 0),
+                divisor => (a.div_euclid(divisor as i64), a.rem_euclid(divisor as i64)),
+            };
+            self.result = (remainder as u16 as u64) << 16 | quotient as u16 as u64;
+        } else {
+            self.result = (product & RESULT_MASK) as u64;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn operate(math: &mut Arithmetic, a: i16, b: u16) -> u64 {
+        let [a_lo, a_hi] = a.to_le_bytes();
+        let [b_lo, b_hi] = b.to_le_bytes();
+        for (offset, val) in [
+            (0x2251, a_lo),
+            (0x2252, a_hi),
+            (0x2253, b_lo),
+            (0x2254, b_hi),
+        ] {
+            math.write_register(offset, val);
+        }
+        (0..5).fold(0, |result, i| {
+            result | (math.read_register(0x2306 + i) as u64) << (i * 8)
+        })
+    }
+
+    #[test]
+    fn operations() {
+        let mut math = Arithmetic::default();
+        math.write_register(0x2250, 0);
+        assert_eq!(
+            operate(&mut math, -3, 1000),
+            (-3000i64 & RESULT_MASK) as u64
+        );
+
+        math.write_register(0x2250, MCNT_DIVIDE);
+        // -7 / 2 is -4 remainder 1
+        assert_eq!(operate(&mut math, -7, 2), 0x0001_FFFC);
+        assert_eq!(operate(&mut math, 7, 0), 0);
+
+        math.write_register(0x2250, MCNT_CUMULATIVE);
+        operate(&mut math, 0x4000, 0x4000);
+        assert_eq!(operate(&mut math, 0x4000, 0x4000), 0x2000_0000);
+        assert_eq!(math.read_register(0x230B), 0x00);
+        for _ in 0..0x800 {
+            operate(&mut math, 0x7FFF, 0x7FFF);
+        }
+        assert_eq!(math.read_register(0x230B), 0x80);
+    }
+}
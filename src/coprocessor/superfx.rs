@@ -0,0 +1,3 @@
+pub mod chip;
+pub mod ops;
+pub mod plot;
@@ -0,0 +1,454 @@
+use crate::coprocessor::superfx::plot::{self, PixelCache, SCMR_RAM, SCMR_ROM, Screen};
+
+// The Super FX (GSU-1/GSU-2): a 16-bit RISC chip with its own view of the cartridge ROM and the
+// game RAM it draws into, started by the S-CPU writing R15 and stopped by its own STOP. The
+// instruction set is in `ops`.
+
+// SFR flags
+pub const SFR_ZERO: u16 = 0x1 << 1;
+pub const SFR_CARRY: u16 = 0x1 << 2;
+pub const SFR_SIGN: u16 = 0x1 << 3;
+pub const SFR_OVERFLOW: u16 = 0x1 << 4;
+pub const SFR_GO: u16 = 0x1 << 5;
+pub const SFR_ALT1: u16 = 0x1 << 8;
+pub const SFR_ALT2: u16 = 0x1 << 9;
+// WITH was the last instruction, TO and FROM become MOVE and MOVES
+pub const SFR_PREFIX: u16 = 0x1 << 12;
+pub const SFR_IRQ: u16 = 0x1 << 15;
+
+// CFGR
+pub const CFGR_IRQ_DISABLE: u8 = 0x1 << 7;
+pub const CFGR_FAST_MULTIPLY: u8 = 0x1 << 5;
+
+pub const CACHE_SIZE: usize = 0x200;
+const CACHE_LINE: usize = 0x10;
+// games that declare less still have at least this much
+const MIN_RAM_SIZE: usize = 0x10000;
+const VERSION: u8 = 0x04;
+
+// NOP, what the pipeline holds after STOP
+pub const OPCODE_NOP: u8 = 0x01;
+
+// what the S-CPU reads from ROM while the GSU owns it: the vectors point into WRAM
+#[rustfmt::skip]
+const ROM_BUSY_VECTORS: [u8; 16] = [
+    0x00, 0x01, 0x00, 0x01, 0x04, 0x01, 0x00, 0x01,
+    0x00, 0x01, 0x08, 0x01, 0x00, 0x01, 0x0C, 0x01,
+];
+
+pub struct SuperFx {
+    rom: Vec<u8>,
+    pub ram: Vec<u8>,
+    pub regs: [u16; 16],
+    pub sfr: u16,
+    pub pbr: u8,
+    pub rombr: u8,
+    pub rambr: u8,
+    pub cbr: u16,
+    pub scbr: u8,
+    pub scmr: u8,
+    pub cfgr: u8,
+    pub clsr: u8,
+    bramr: u8,
+    pub por: u8,
+    pub colr: u8,
+    // registers picked by FROM, TO and WITH
+    pub sreg: usize,
+    pub dreg: usize,
+    // the byte after the current opcode, fetched while it runs
+    pub pipeline: u8,
+    pub r15_modified: bool,
+    // ROM byte at ROMBR:R14, read when R14 changes
+    pub rom_buffer: u8,
+    // last RAM address used, for SBK
+    pub ram_address: u16,
+    cache: [u8; CACHE_SIZE],
+    cache_valid: [bool; CACHE_SIZE / CACHE_LINE],
+    pub pixels: [PixelCache; 2],
+    // master cycles run ahead of or behind the S-CPU
+    balance: i64,
+    pub cycles: u64,
+}
+
+impl SuperFx {
+    pub fn new(rom: Vec<u8>, ram_size: usize) -> Self {
+        Self {
+            rom,
+            ram: vec![0; ram_size.max(MIN_RAM_SIZE)],
+            regs: [0; 16],
+            sfr: 0,
+            pbr: 0,
+            rombr: 0,
+            rambr: 0,
+            cbr: 0,
+            scbr: 0,
+            scmr: 0,
+            cfgr: 0,
+            clsr: 0,
+            bramr: 0,
+            por: 0,
+            colr: 0,
+            sreg: 0,
+            dreg: 0,
+            pipeline: OPCODE_NOP,
+            r15_modified: false,
+            rom_buffer: 0,
+            ram_address: 0,
+            cache: [0; CACHE_SIZE],
+            cache_valid: [false; CACHE_SIZE / CACHE_LINE],
+            pixels: [PixelCache::default(); 2],
+            balance: 0,
+            cycles: 0,
+        }
+    }
+
+    pub fn running(&self) -> bool {
+        self.sfr & SFR_GO > 0
+    }
+
+    // the IRQ to the S-CPU after STOP, unless CFGR masks it
+    pub fn irq(&self) -> bool {
+        self.sfr & SFR_IRQ > 0 && self.cfgr & CFGR_IRQ_DISABLE == 0
+    }
+
+    // master cycles per cache and per ROM/RAM access, CLSR picks 21MHz or 10.7MHz
+    pub fn cache_speed(&self) -> u64 {
+        match self.clsr & 0x1 {
+            0 => 2,
+            _ => 1,
+        }
+    }
+
+    pub fn memory_speed(&self) -> u64 {
+        match self.clsr & 0x1 {
+            0 => 6,
+            _ => 5,
+        }
+    }
+
+    // run for as many master cycles as the S-CPU just spent
+    pub fn advance(&mut self, cycles: u64) {
+        if !self.running() {
+            self.balance = 0;
+            return;
+        }
+        self.balance += cycles as i64;
+        while self.balance > 0 && self.running() {
+            let before = self.cycles;
+            self.step();
+            self.balance -= (self.cycles - before).max(1) as i64;
+        }
+    }
+
+    // one instruction, with the pipeline fetching the next byte meanwhile
+    pub fn step(&mut self) {
+        let opcode = self.pipeline;
+        self.pipeline = self.read_opcode(self.regs[15]);
+        self.r15_modified = false;
+        self.execute(opcode);
+        match self.r15_modified {
+            true => self.r15_modified = false,
+            false => self.regs[15] = self.regs[15].wrapping_add(1),
+        }
+    }
+
+    // the immediate byte after an opcode
+    pub fn pipe(&mut self) -> u8 {
+        let value = self.pipeline;
+        self.regs[15] = self.regs[15].wrapping_add(1);
+        self.pipeline = self.read_opcode(self.regs[15]);
+        self.r15_modified = false;
+        value
+    }
+
+    // code within 512 bytes of CBR runs from the cache, loaded a 16 byte line at a time
+    fn read_opcode(&mut self, pc: u16) -> u8 {
+        if (pc.wrapping_sub(self.cbr) as usize) < CACHE_SIZE {
+            let index = pc as usize % CACHE_SIZE;
+            let line = index / CACHE_LINE;
+            if !self.cache_valid[line] {
+                let start = pc & !(CACHE_LINE as u16 - 1);
+                for i in 0..CACHE_LINE {
+                    let addr = ((self.pbr as u32) << 16) | start.wrapping_add(i as u16) as u32;
+                    self.cache[line * CACHE_LINE + i] = self.read_bus(addr);
+                }
+                self.cache_valid[line] = true;
+            } else {
+                self.cycles += self.cache_speed();
+            }
+            return self.cache[index];
+        }
+        self.read_bus(((self.pbr as u32) << 16) | pc as u32)
+    }
+
+    // CACHE and LJMP move the cache, which then has to be loaded again
+    pub fn set_cache_base(&mut self, cbr: u16) {
+        self.cbr = cbr & 0xFFF0;
+        self.cache_valid = [false; CACHE_SIZE / CACHE_LINE];
+    }
+
+    fn rom_offset(&self, bank: u32, offset: u32) -> usize {
+        let offset = match bank {
+            0x00..=0x3F => (bank << 15) | (offset & 0x7FFF),
+            _ => ((bank & 0x1F) << 16) | offset,
+        };
+        offset as usize % self.rom.len().max(1)
+    }
+
+    fn rom_byte(&self, bank: u32, offset: u32) -> u8 {
+        self.rom
+            .get(self.rom_offset(bank, offset))
+            .copied()
+            .unwrap_or(0)
+    }
+
+    fn ram_offset(&self, bank: u32, offset: u32) -> usize {
+        (((bank & 0x1) << 16) | offset) as usize % self.ram.len()
+    }
+
+    // the GSU's side: ROM at $00-$5F, game RAM at $70-$71
+    pub fn read_bus(&mut self, addr: u32) -> u8 {
+        self.cycles += self.memory_speed();
+        let bank = (addr >> 16) & 0x7F;
+        let offset = addr & 0xFFFF;
+        match bank {
+            0x00..=0x5F => self.rom_byte(bank, offset),
+            _ => self.ram[self.ram_offset(bank, offset)],
+        }
+    }
+
+    pub fn read_ram(&mut self, addr: u16) -> u8 {
+        self.ram_address = addr;
+        self.read_bus(((0x70 + self.rambr as u32) << 16) | addr as u32)
+    }
+
+    pub fn write_ram(&mut self, addr: u16, val: u8) {
+        self.ram_address = addr;
+        self.cycles += self.memory_speed();
+        let index = self.ram_offset(self.rambr as u32, addr as u32);
+        self.ram[index] = val;
+    }
+
+    // R14 changed: fetch the byte GETB and friends will see
+    pub fn update_rom_buffer(&mut self) {
+        let addr = ((self.rombr as u32) << 16) | self.regs[14] as u32;
+        self.rom_buffer = self.read_bus(addr);
+    }
+
+    pub fn screen(&self) -> Screen {
+        Screen {
+            base: self.scbr,
+            scmr: self.scmr,
+            por: self.por,
+        }
+    }
+
+    pub fn plot(&mut self, x: u8, y: u8) {
+        let screen = self.screen();
+        if let Some(color) = screen.plot_color(self.colr, x, y) {
+            let accesses = plot::plot(&mut self.pixels, &screen, &mut self.ram, x, y, color);
+            self.cycles += accesses * self.memory_speed();
+        }
+    }
+
+    // RPIX reads RAM, so anything still in the pixel cache goes out first
+    pub fn read_pixel(&mut self, x: u8, y: u8) -> u8 {
+        self.flush_pixels();
+        let screen = self.screen();
+        self.cycles += screen.bits_per_pixel() as u64 * self.memory_speed();
+        plot::read_pixel(&screen, &self.ram, x, y)
+    }
+
+    pub fn flush_pixels(&mut self) {
+        let screen = self.screen();
+        let accesses = self.pixels[1].flush(&screen, &mut self.ram)
+            + self.pixels[0].flush(&screen, &mut self.ram);
+        self.cycles += accesses * self.memory_speed();
+    }
+
+    fn read_register(&mut self, offset: u16, mdr: u8) -> u8 {
+        match offset {
+            0x3000..=0x301F => {
+                let reg = self.regs[(offset as usize & 0x1F) >> 1];
+                reg.to_le_bytes()[offset as usize & 0x1]
+            }
+            0x3030 => self.sfr as u8,
+            0x3031 => {
+                // reading the high byte acknowledges the IRQ
+                let value = (self.sfr >> 8) as u8;
+                self.sfr &= !SFR_IRQ;
+                value
+            }
+            0x3034 => self.pbr,
+            0x3036 => self.rombr,
+            0x303B => VERSION,
+            0x303C => self.rambr,
+            0x303E => self.cbr as u8,
+            0x303F => (self.cbr >> 8) as u8,
+            0x3100..=0x32FF => self.cache[offset as usize - 0x3100],
+            _ => mdr,
+        }
+    }
+
+    fn write_register(&mut self, offset: u16, val: u8) {
+        match offset {
+            0x3000..=0x301F => {
+                let n = (offset as usize & 0x1F) >> 1;
+                let mut bytes = self.regs[n].to_le_bytes();
+                bytes[offset as usize & 0x1] = val;
+                self.regs[n] = u16::from_le_bytes(bytes);
+                if n == 14 {
+                    self.update_rom_buffer();
+                }
+                // writing the high byte of R15 starts the GSU
+                if offset == 0x301F {
+                    self.sfr |= SFR_GO;
+                }
+            }
+            0x3030 => {
+                let go = self.running();
+                self.sfr = (self.sfr & 0xFF00) | val as u16;
+                // stopping the GSU by hand also resets the cache
+                if go && !self.running() {
+                    self.set_cache_base(0);
+                }
+            }
+            0x3031 => self.sfr = (self.sfr & 0x00FF) | (val as u16) << 8,
+            0x3033 => self.bramr = val & 0x1,
+            0x3034 => self.pbr = val & 0x7F,
+            0x3037 => self.cfgr = val,
+            0x3038 => self.scbr = val,
+            0x3039 => self.clsr = val & 0x1,
+            0x303A => self.scmr = val,
+            0x3100..=0x32FF => {
+                let index = offset as usize - 0x3100;
+                self.cache[index] = val;
+                if index % CACHE_LINE == CACHE_LINE - 1 {
+                    self.cache_valid[index / CACHE_LINE] = true;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // the S-CPU side: registers and cache at $3000-$34FF, ROM and game RAM. While the GSU runs
+    // with SCMR giving it the ROM or RAM, the S-CPU can't have them. None is for addresses that
+    // aren't the cartridge's.
+    pub fn read(&mut self, addr: u32, mdr: u8) -> Option<u8> {
+        let bank = (addr >> 16) & 0xFF;
+        let offset = addr & 0xFFFF;
+        let rom_busy = self.running() && self.scmr & SCMR_ROM > 0;
+        let ram_busy = self.running() && self.scmr & SCMR_RAM > 0;
+        match bank & 0x7F {
+            0x00..=0x3F => match offset {
+                0x3000..=0x34FF => Some(self.read_register(offset as u16, mdr)),
+                0x6000..=0x7FFF if ram_busy => Some(mdr),
+                0x6000..=0x7FFF => Some(self.ram[self.ram_offset(0, offset & 0x1FFF)]),
+                0x8000..=0xFFFF if rom_busy => Some(ROM_BUSY_VECTORS[offset as usize & 0xF]),
+                0x8000..=0xFFFF => Some(self.rom_byte(bank & 0x3F, offset)),
+                _ => None,
+            },
+            0x40..=0x5F if rom_busy => Some(ROM_BUSY_VECTORS[offset as usize & 0xF]),
+            0x40..=0x5F => Some(self.rom_byte(bank & 0x5F, offset)),
+            0x70..=0x71 if ram_busy => Some(mdr),
+            0x70..=0x71 => Some(self.ram[self.ram_offset(bank, offset)]),
+            _ => None,
+        }
+    }
+
+    // game RAM, even while the GSU has it, for RAM search, watches and cheats
+    fn ram_location(&self, addr: u32) -> Option<usize> {
+        let bank = (addr >> 16) & 0x7F;
+        let offset = addr & 0xFFFF;
+        match (bank, offset) {
+            (0x00..=0x3F, 0x6000..=0x7FFF) => Some(self.ram_offset(0, offset & 0x1FFF)),
+            (0x70..=0x71, _) => Some(self.ram_offset(bank, offset)),
+            _ => None,
+        }
+    }
+
+    pub fn peek(&self, addr: u32) -> Option<u8> {
+        self.ram_location(addr).map(|index| self.ram[index])
+    }
+
+    pub fn poke(&mut self, addr: u32, val: u8) -> bool {
+        match self.ram_location(addr) {
+            Some(index) => {
+                self.ram[index] = val;
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn write(&mut self, addr: u32, val: u8) -> bool {
+        let bank = (addr >> 16) & 0xFF;
+        let offset = addr & 0xFFFF;
+        let ram_busy = self.running() && self.scmr & SCMR_RAM > 0;
+        match bank & 0x7F {
+            0x00..=0x3F => match offset {
+                0x3000..=0x34FF => self.write_register(offset as u16, val),
+                0x6000..=0x7FFF if !ram_busy => {
+                    let index = self.ram_offset(0, offset & 0x1FFF);
+                    self.ram[index] = val;
+                }
+                0x6000..=0xFFFF => {}
+                _ => return false,
+            },
+            0x70..=0x71 if !ram_busy => {
+                let index = self.ram_offset(bank, offset);
+                self.ram[index] = val;
+            }
+            0x40..=0x5F | 0x70..=0x71 => {}
+            _ => return false,
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn memory_map_and_registers() {
+        let mut rom = vec![0u8; 0x100000];
+        rom[0x8001] = 0x11;
+        rom[0x10001] = 0x22;
+        let mut gsu = SuperFx::new(rom, 0);
+        assert_eq!(gsu.ram.len(), MIN_RAM_SIZE);
+
+        // LoROM at $00-$3F, the whole ROM again at $40-$5F
+        assert_eq!(gsu.read(0x018001, 0), Some(0x11));
+        assert_eq!(gsu.read(0x410001, 0), Some(0x22));
+        assert_eq!(gsu.read_bus(0x018001), 0x11);
+        assert_eq!(gsu.read(0x7E0000, 0), None);
+
+        gsu.write(0x700010, 0x33);
+        assert_eq!(gsu.read(0x006010, 0), Some(0x33));
+        assert_eq!(gsu.read_bus(0x700010), 0x33);
+
+        // cache RAM lines become valid once their last byte is written
+        gsu.write(0x00310F, 0x44);
+        assert!(gsu.cache_valid[0]);
+        assert_eq!(gsu.read(0x00310F, 0), Some(0x44));
+
+        // starting it hands the ROM to the GSU
+        gsu.write(0x00303A, SCMR_ROM | SCMR_RAM);
+        gsu.write(0x00301E, 0x00);
+        gsu.write(0x00301F, 0x80);
+        assert!(gsu.running());
+        assert_eq!(gsu.regs[15], 0x8000);
+        assert_eq!(gsu.read(0x00FFEA, 0), Some(0x08));
+        // the tools still see game RAM the S-CPU can't have
+        assert_eq!(gsu.read(0x700010, 0xEE), Some(0xEE));
+        assert_eq!(gsu.peek(0x700010), Some(0x33));
+        assert!(gsu.poke(0x006011, 0x55));
+        assert_eq!(gsu.ram[0x11], 0x55);
+        assert_eq!(gsu.peek(0x7E0000), None);
+        gsu.write(0x003030, 0);
+        assert!(!gsu.running());
+        assert_eq!(gsu.read(0x018001, 0), Some(0x11));
+        assert_eq!(gsu.read(0x00303B, 0), Some(VERSION));
+    }
+}
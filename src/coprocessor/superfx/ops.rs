@@ -0,0 +1,553 @@
+use crate::coprocessor::superfx::chip::{
+    CFGR_FAST_MULTIPLY, CFGR_IRQ_DISABLE, OPCODE_NOP, SFR_ALT1, SFR_ALT2, SFR_CARRY, SFR_GO,
+    SFR_IRQ, SFR_OVERFLOW, SFR_PREFIX, SFR_SIGN, SFR_ZERO, SuperFx,
+};
+use crate::coprocessor::superfx::plot;
+
+// The GSU instruction set. Most opcodes take their source from the register FROM or WITH
+// picked and store into the one TO or WITH picked (R0 by default); ALT1, ALT2 and ALT3 select
+// the variant of the next opcode. Both wear off after every instruction that isn't a prefix.
+
+impl SuperFx {
+    fn sr(&self) -> u16 {
+        self.regs[self.sreg]
+    }
+
+    pub fn set_reg(&mut self, n: usize, value: u16) {
+        self.regs[n] = value;
+        match n {
+            14 => self.update_rom_buffer(),
+            15 => self.r15_modified = true,
+            _ => {}
+        }
+    }
+
+    fn set_dr(&mut self, value: u16) {
+        self.set_reg(self.dreg, value);
+    }
+
+    fn set_flag(&mut self, flag: u16, set: bool) {
+        match set {
+            true => self.sfr |= flag,
+            false => self.sfr &= !flag,
+        }
+    }
+
+    fn flag_set(&self, flag: u16) -> bool {
+        self.sfr & flag > 0
+    }
+
+    fn set_sz(&mut self, value: u16) {
+        self.set_flag(SFR_SIGN, value & 0x8000 > 0);
+        self.set_flag(SFR_ZERO, value == 0);
+    }
+
+    // 0 to 3 for no prefix, ALT1, ALT2 and ALT3
+    fn alt(&self) -> u8 {
+        ((self.sfr >> 8) & 0x3) as u8
+    }
+
+    fn reset_prefix(&mut self) {
+        self.sfr &= !(SFR_ALT1 | SFR_ALT2 | SFR_PREFIX);
+        self.sreg = 0;
+        self.dreg = 0;
+    }
+
+    fn read_ram_word(&mut self, addr: u16) -> u16 {
+        let lo = self.read_ram(addr);
+        let hi = self.read_ram(addr ^ 0x1);
+        u16::from_le_bytes([lo, hi])
+    }
+
+    fn write_ram_word(&mut self, addr: u16, value: u16) {
+        let [lo, hi] = value.to_le_bytes();
+        self.write_ram(addr, lo);
+        self.write_ram(addr ^ 0x1, hi);
+    }
+
+    fn branch(&mut self, condition: bool) {
+        let displacement = self.pipe() as i8;
+        if condition {
+            self.set_reg(15, self.regs[15].wrapping_add(displacement as u16));
+        }
+    }
+
+    pub fn execute(&mut self, opcode: u8) {
+        let n = (opcode & 0xF) as usize;
+        match opcode {
+            // STOP
+            0x00 => {
+                if self.cfgr & CFGR_IRQ_DISABLE == 0 {
+                    self.sfr |= SFR_IRQ;
+                }
+                // the last pixels plotted still reach RAM
+                self.flush_pixels();
+                self.sfr &= !SFR_GO;
+                self.pipeline = OPCODE_NOP;
+                self.reset_prefix();
+            }
+            // NOP
+            0x01 => self.reset_prefix(),
+            // CACHE
+            0x02 => {
+                if self.cbr != self.regs[15] & 0xFFF0 {
+                    self.set_cache_base(self.regs[15]);
+                }
+                self.reset_prefix();
+            }
+            // LSR
+            0x03 => {
+                let sr = self.sr();
+                let result = sr >> 1;
+                self.set_flag(SFR_CARRY, sr & 0x1 > 0);
+                self.set_sz(result);
+                self.set_dr(result);
+                self.reset_prefix();
+            }
+            // ROL
+            0x04 => {
+                let sr = self.sr();
+                let result = (sr << 1) | self.flag_set(SFR_CARRY) as u16;
+                self.set_flag(SFR_CARRY, sr & 0x8000 > 0);
+                self.set_sz(result);
+                self.set_dr(result);
+                self.reset_prefix();
+            }
+            // BRA, BGE, BLT, BNE, BEQ, BPL, BMI, BCC, BCS, BVC, BVS
+            0x05 => self.branch(true),
+            0x06 => self.branch(self.flag_set(SFR_SIGN) == self.flag_set(SFR_OVERFLOW)),
+            0x07 => self.branch(self.flag_set(SFR_SIGN) != self.flag_set(SFR_OVERFLOW)),
+            0x08 => self.branch(!self.flag_set(SFR_ZERO)),
+            0x09 => self.branch(self.flag_set(SFR_ZERO)),
+            0x0A => self.branch(!self.flag_set(SFR_SIGN)),
+            0x0B => self.branch(self.flag_set(SFR_SIGN)),
+            0x0C => self.branch(!self.flag_set(SFR_CARRY)),
+            0x0D => self.branch(self.flag_set(SFR_CARRY)),
+            0x0E => self.branch(!self.flag_set(SFR_OVERFLOW)),
+            0x0F => self.branch(self.flag_set(SFR_OVERFLOW)),
+            // TO, or MOVE after WITH
+            0x10..=0x1F => match self.flag_set(SFR_PREFIX) {
+                true => {
+                    self.set_reg(n, self.sr());
+                    self.reset_prefix();
+                }
+                false => self.dreg = n,
+            },
+            // WITH
+            0x20..=0x2F => {
+                self.sreg = n;
+                self.dreg = n;
+                self.sfr |= SFR_PREFIX;
+            }
+            // STW (Rn), STB (Rn)
+            0x30..=0x3B => {
+                let addr = self.regs[n];
+                match self.alt() {
+                    0 => self.write_ram_word(addr, self.sr()),
+                    _ => self.write_ram(addr, self.sr() as u8),
+                }
+                self.reset_prefix();
+            }
+            // LOOP
+            0x3C => {
+                let counter = self.regs[12].wrapping_sub(1);
+                self.regs[12] = counter;
+                self.set_sz(counter);
+                if counter != 0 {
+                    self.set_reg(15, self.regs[13]);
+                }
+                self.reset_prefix();
+            }
+            // ALT1, ALT2, ALT3
+            0x3D..=0x3F => {
+                self.sfr &= !(SFR_PREFIX | SFR_ALT1 | SFR_ALT2);
+                self.sfr |= ((opcode as u16 - 0x3C) & 0x3) << 8;
+            }
+            // LDW (Rn), LDB (Rn)
+            0x40..=0x4B => {
+                let addr = self.regs[n];
+                let value = match self.alt() {
+                    0 => self.read_ram_word(addr),
+                    _ => self.read_ram(addr) as u16,
+                };
+                self.set_dr(value);
+                self.reset_prefix();
+            }
+            // PLOT, RPIX
+            0x4C => {
+                let (x, y) = (self.regs[1] as u8, self.regs[2] as u8);
+                match self.alt() {
+                    0 => {
+                        self.plot(x, y);
+                        self.regs[1] = self.regs[1].wrapping_add(1);
+                    }
+                    _ => {
+                        let color = self.read_pixel(x, y) as u16;
+                        self.set_sz(color);
+                        self.set_dr(color);
+                    }
+                }
+                self.reset_prefix();
+            }
+            // SWAP
+            0x4D => {
+                let result = self.sr().swap_bytes();
+                self.set_sz(result);
+                self.set_dr(result);
+                self.reset_prefix();
+            }
+            // COLOR, CMODE
+            0x4E => {
+                match self.alt() {
+                    0 => self.colr = plot::color(self.colr, self.por, self.sr() as u8),
+                    _ => self.por = self.sr() as u8,
+                }
+                self.reset_prefix();
+            }
+            // NOT
+            0x4F => {
+                let result = !self.sr();
+                self.set_sz(result);
+                self.set_dr(result);
+                self.reset_prefix();
+            }
+            // ADD, ADC, ADD #, ADC #
+            0x50..=0x5F => {
+                let operand = match self.alt() & 0x2 {
+                    0 => self.regs[n],
+                    _ => n as u16,
+                };
+                let carry = self.alt() & 0x1 > 0 && self.flag_set(SFR_CARRY);
+                let sr = self.sr();
+                let sum = sr as u32 + operand as u32 + carry as u32;
+                let result = sum as u16;
+                self.set_flag(SFR_OVERFLOW, !(sr ^ operand) & (sr ^ result) & 0x8000 > 0);
+                self.set_flag(SFR_CARRY, sum > 0xFFFF);
+                self.set_sz(result);
+                self.set_dr(result);
+                self.reset_prefix();
+            }
+            // SUB, SBC, SUB #, CMP
+            0x60..=0x6F => {
+                let operand = match self.alt() {
+                    2 => n as u16,
+                    _ => self.regs[n],
+                };
+                let borrow = self.alt() == 1 && !self.flag_set(SFR_CARRY);
+                let sr = self.sr();
+                let difference = sr as i32 - operand as i32 - borrow as i32;
+                let result = difference as u16;
+                self.set_flag(SFR_OVERFLOW, (sr ^ operand) & (sr ^ result) & 0x8000 > 0);
+                self.set_flag(SFR_CARRY, difference >= 0);
+                self.set_sz(result);
+                if self.alt() != 3 {
+                    self.set_dr(result);
+                }
+                self.reset_prefix();
+            }
+            // MERGE, with flags from the high bits of both bytes
+            0x70 => {
+                let result = (self.regs[7] & 0xFF00) | (self.regs[8] >> 8);
+                self.set_flag(SFR_OVERFLOW, result & 0xC0C0 > 0);
+                self.set_flag(SFR_SIGN, result & 0x8080 > 0);
+                self.set_flag(SFR_CARRY, result & 0xE0E0 > 0);
+                self.set_flag(SFR_ZERO, result & 0xF0F0 > 0);
+                self.set_dr(result);
+                self.reset_prefix();
+            }
+            // AND, BIC, AND #, BIC #
+            0x71..=0x7F => {
+                let operand = match self.alt() & 0x2 {
+                    0 => self.regs[n],
+                    _ => n as u16,
+                };
+                let result = match self.alt() & 0x1 {
+                    0 => self.sr() & operand,
+                    _ => self.sr() & !operand,
+                };
+                self.set_sz(result);
+                self.set_dr(result);
+                self.reset_prefix();
+            }
+            // MULT, UMULT, MULT #, UMULT #: 8x8 bits
+            0x80..=0x8F => {
+                let operand = match self.alt() & 0x2 {
+                    0 => self.regs[n],
+                    _ => n as u16,
+                };
+                let result = match self.alt() & 0x1 {
+                    0 => (self.sr() as i8 as i16).wrapping_mul(operand as i8 as i16) as u16,
+                    _ => (self.sr() & 0xFF) * (operand & 0xFF),
+                };
+                if self.cfgr & CFGR_FAST_MULTIPLY == 0 {
+                    self.cycles += self.cache_speed();
+                }
+                self.set_sz(result);
+                self.set_dr(result);
+                self.reset_prefix();
+            }
+            // SBK: store back to the last RAM address used
+            0x90 => {
+                self.write_ram_word(self.ram_address, self.sr());
+                self.reset_prefix();
+            }
+            // LINK #n
+            0x91..=0x94 => {
+                self.regs[11] = self.regs[15].wrapping_add(n as u16);
+                self.reset_prefix();
+            }
+            // SEX
+            0x95 => {
+                let result = self.sr() as u8 as i8 as u16;
+                self.set_sz(result);
+                self.set_dr(result);
+                self.reset_prefix();
+            }
+            // ASR, DIV2 (which rounds -1 to 0)
+            0x96 => {
+                let sr = self.sr();
+                let result = match self.alt() == 1 && sr == 0xFFFF {
+                    true => 0,
+                    false => ((sr as i16) >> 1) as u16,
+                };
+                self.set_flag(SFR_CARRY, sr & 0x1 > 0);
+                self.set_sz(result);
+                self.set_dr(result);
+                self.reset_prefix();
+            }
+            // ROR
+            0x97 => {
+                let sr = self.sr();
+                let result = (sr >> 1) | (self.flag_set(SFR_CARRY) as u16) << 15;
+                self.set_flag(SFR_CARRY, sr & 0x1 > 0);
+                self.set_sz(result);
+                self.set_dr(result);
+                self.reset_prefix();
+            }
+            // JMP Rn, LJMP Rn (bank from Rn, address from the source register)
+            0x98..=0x9D => {
+                match self.alt() {
+                    0 => self.set_reg(15, self.regs[n]),
+                    _ => {
+                        self.pbr = self.regs[n] as u8 & 0x7F;
+                        self.set_reg(15, self.sr());
+                        self.set_cache_base(self.regs[15]);
+                    }
+                }
+                self.reset_prefix();
+            }
+            // LOB
+            0x9E => {
+                let result = self.sr() & 0xFF;
+                self.set_flag(SFR_SIGN, result & 0x80 > 0);
+                self.set_flag(SFR_ZERO, result == 0);
+                self.set_dr(result);
+                self.reset_prefix();
+            }
+            // FMULT, LMULT: 16x16 bits by R6, LMULT keeps the low half in R4
+            0x9F => {
+                let product = (self.sr() as i16 as i32) * (self.regs[6] as i16 as i32);
+                let result = (product >> 16) as u16;
+                if self.alt() == 1 {
+                    self.regs[4] = product as u16;
+                }
+                let speed = match self.cfgr & CFGR_FAST_MULTIPLY {
+                    0 => 7,
+                    _ => 3,
+                };
+                self.cycles += speed * self.cache_speed();
+                self.set_flag(SFR_CARRY, product & 0x8000 > 0);
+                self.set_sz(result);
+                self.set_dr(result);
+                self.reset_prefix();
+            }
+            // IBT Rn, #pp, LMS Rn, (yy), SMS (yy), Rn
+            0xA0..=0xAF => {
+                let byte = self.pipe();
+                match self.alt() {
+                    1 => {
+                        let value = self.read_ram_word((byte as u16) << 1);
+                        self.set_reg(n, value);
+                    }
+                    2 => self.write_ram_word((byte as u16) << 1, self.regs[n]),
+                    _ => self.set_reg(n, byte as i8 as u16),
+                }
+                self.reset_prefix();
+            }
+            // FROM, or MOVES after WITH
+            0xB0..=0xBF => match self.flag_set(SFR_PREFIX) {
+                true => {
+                    let value = self.regs[n];
+                    self.set_flag(SFR_OVERFLOW, value & 0x80 > 0);
+                    self.set_sz(value);
+                    self.set_dr(value);
+                    self.reset_prefix();
+                }
+                false => self.sreg = n,
+            },
+            // HIB
+            0xC0 => {
+                let result = self.sr() >> 8;
+                self.set_flag(SFR_SIGN, result & 0x80 > 0);
+                self.set_flag(SFR_ZERO, result == 0);
+                self.set_dr(result);
+                self.reset_prefix();
+            }
+            // OR, XOR, OR #, XOR #
+            0xC1..=0xCF => {
+                let operand = match self.alt() & 0x2 {
+                    0 => self.regs[n],
+                    _ => n as u16,
+                };
+                let result = match self.alt() & 0x1 {
+                    0 => self.sr() | operand,
+                    _ => self.sr() ^ operand,
+                };
+                self.set_sz(result);
+                self.set_dr(result);
+                self.reset_prefix();
+            }
+            // INC Rn
+            0xD0..=0xDE => {
+                let result = self.regs[n].wrapping_add(1);
+                self.set_sz(result);
+                self.set_reg(n, result);
+                self.reset_prefix();
+            }
+            // GETC, RAMB, ROMB
+            0xDF => {
+                match self.alt() {
+                    0 | 1 => self.colr = plot::color(self.colr, self.por, self.rom_buffer),
+                    2 => self.rambr = self.sr() as u8 & 0x1,
+                    _ => self.rombr = self.sr() as u8 & 0x7F,
+                }
+                self.reset_prefix();
+            }
+            // DEC Rn
+            0xE0..=0xEE => {
+                let result = self.regs[n].wrapping_sub(1);
+                self.set_sz(result);
+                self.set_reg(n, result);
+                self.reset_prefix();
+            }
+            // GETB, GETBH, GETBL, GETBS: the ROM buffer into the destination
+            0xEF => {
+                let byte = self.rom_buffer as u16;
+                let result = match self.alt() {
+                    0 => byte,
+                    1 => (byte << 8) | (self.sr() & 0xFF),
+                    2 => (self.sr() & 0xFF00) | byte,
+                    _ => byte as u8 as i8 as u16,
+                };
+                self.set_dr(result);
+                self.reset_prefix();
+            }
+            // IWT Rn, #xxxx, LM Rn, (xxxx), SM (xxxx), Rn
+            0xF0..=0xFF => {
+                let lo = self.pipe();
+                let hi = self.pipe();
+                let word = u16::from_le_bytes([lo, hi]);
+                match self.alt() {
+                    1 => {
+                        let value = self.read_ram_word(word);
+                        self.set_reg(n, value);
+                    }
+                    2 => self.write_ram_word(word, self.regs[n]),
+                    _ => self.set_reg(n, word),
+                }
+                self.reset_prefix();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // run a program from ROM until STOP
+    fn run(program: &[u8]) -> SuperFx {
+        let mut rom = vec![0u8; 0x10000];
+        rom[..program.len()].copy_from_slice(program);
+        let mut gsu = SuperFx::new(rom, 0);
+        gsu.write(0x00301E, 0x00);
+        gsu.write(0x00301F, 0x80);
+        for _ in 0..1000 {
+            if !gsu.running() {
+                break;
+            }
+            gsu.step();
+        }
+        assert!(!gsu.running());
+        gsu
+    }
+
+    #[test]
+    fn arithmetic_and_prefixes() {
+        let gsu = run(&[
+            0xF1, 0x34, 0x12, // IWT R1, #$1234
+            0xA2, 0xFE, // IBT R2, #-2
+            0x21, 0x52, // WITH R1, ADD R2: R1 = $1232
+            0x13, 0xB1, 0x3E, 0x53, // TO R3, FROM R1, ALT2, ADD #3: R3 = $1235
+            0x21, 0x14, // WITH R1, TO R4: MOVE R4, R1
+            0xB3, 0x3F, 0x65, // FROM R3, ALT3, CMP R5: flags only
+            0x3D, 0x96, // ALT1, DIV2 R0
+            0x00, 0x01,
+        ]);
+        assert_eq!(gsu.regs[1], 0x1232);
+        assert_eq!(gsu.regs[2], 0xFFFE);
+        assert_eq!(gsu.regs[3], 0x1235);
+        assert_eq!(gsu.regs[4], 0x1232);
+        assert_eq!(gsu.regs[5], 0);
+        // CMP left nothing in R0, DIV2 of 0 sets zero
+        assert_eq!(gsu.regs[0], 0);
+        assert!(gsu.flag_set(SFR_ZERO));
+        assert!(gsu.irq());
+        assert_eq!(gsu.sfr & (SFR_ALT1 | SFR_ALT2), 0);
+    }
+
+    #[test]
+    fn branches_loops_and_delay_slots() {
+        let gsu = run(&[
+            0xAC, 0x05, // IBT R12, #5
+            0x2F, 0x1D, // MOVE R13, R15: the address of the next opcode
+            0xD0, // INC R0
+            0x3C, // LOOP
+            0xD1, // INC R1, in the delay slot of every LOOP
+            0x05, 0x03, // BRA +3, from the delay slot
+            0xD2, // INC R2, the delay slot
+            0xD3, // INC R3, skipped
+            0xD3, // INC R3, skipped
+            0x00, 0x01,
+        ]);
+        assert_eq!(gsu.regs[0], 5);
+        assert_eq!(gsu.regs[1], 5);
+        assert_eq!(gsu.regs[2], 1);
+        assert_eq!(gsu.regs[3], 0);
+        assert_eq!(gsu.regs[12], 0);
+    }
+
+    #[test]
+    fn memory_and_plot() {
+        let gsu = run(&[
+            0xF3, 0x00, 0x01, // IWT R3, #$0100
+            0xF0, 0xCD, 0xAB, // IWT R0, #$ABCD
+            0x33, // STW (R3)
+            0x3D, 0x43, // ALT1, LDB (R3) into R0
+            0x14, 0x3D, 0xF0, 0x00, 0x01, // TO R4... LM R0, ($0100)
+            0xFE, 0x02, 0x00, // IWT R14, #$0002: the ROM buffer
+            0x15, 0xEF, // TO R5, GETB
+            0xA0, 0x03, 0x4E, // IBT R0, #3, COLOR
+            0x4C, 0x4C, // PLOT, PLOT at 0,0 and 1,0
+            0x00, 0x01,
+        ]);
+        assert_eq!(gsu.ram[0x100..0x102], [0xCD, 0xAB]);
+        assert_eq!(gsu.regs[0], 3);
+        // LM loads into the register in its opcode, TO doesn't matter
+        assert_eq!(gsu.regs[4], 0);
+        assert_eq!(gsu.regs[5], 0x01);
+        assert_eq!(gsu.regs[1], 2);
+        // STOP wrote the plotted pixels out, 2bpp color 3
+        assert_eq!(gsu.ram[0..2], [0b1100_0000, 0b1100_0000]);
+    }
+}
@@ -0,0 +1,217 @@
+// PLOT and RPIX: the GSU draws into a screen of 8x8 tiles in game RAM. Plotted pixels gather in
+// a two entry cache, one row of a tile each, and are written out as bitplanes when the row is
+// full or PLOT moves on to another row.
+
+// POR, set by CMODE
+pub const POR_TRANSPARENT: u8 = 0x1;
+pub const POR_DITHER: u8 = 0x1 << 1;
+pub const POR_HIGH_NIBBLE: u8 = 0x1 << 2;
+pub const POR_FREEZE_HIGH: u8 = 0x1 << 3;
+pub const POR_OBJ: u8 = 0x1 << 4;
+
+// SCMR
+pub const SCMR_HEIGHT_LOW: u8 = 0x1 << 2;
+pub const SCMR_RAM: u8 = 0x1 << 3;
+pub const SCMR_ROM: u8 = 0x1 << 4;
+pub const SCMR_HEIGHT_HIGH: u8 = 0x1 << 5;
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct PixelCache {
+    // (y << 5) + (x >> 3), the tile row the pixels belong to
+    pub offset: u16,
+    // a bit per pixel plotted, bit 7 is the leftmost
+    pub pending: u8,
+    pub colors: [u8; 8],
+}
+
+// the screen as SCBR, SCMR and POR lay it out
+#[derive(Debug, Clone, Copy)]
+pub struct Screen {
+    pub base: u8,
+    pub scmr: u8,
+    pub por: u8,
+}
+
+impl Screen {
+    // color depth in the low bits of SCMR, 1 and 2 are both 4bpp
+    pub fn bits_per_pixel(&self) -> usize {
+        match self.scmr & 0x3 {
+            0 => 2,
+            3 => 8,
+            _ => 4,
+        }
+    }
+
+    // game RAM offset of the tile row that holds pixel x, y
+    pub fn row_address(&self, x: u8, y: u8) -> usize {
+        let (x, y) = (x as usize, y as usize);
+        let height = match self.por & POR_OBJ > 0 {
+            true => 3,
+            false => {
+                (self.scmr & SCMR_HEIGHT_LOW > 0) as u8
+                    | ((self.scmr & SCMR_HEIGHT_HIGH > 0) as u8) << 1
+            }
+        };
+        // tiles run down the columns of a 128, 160 or 192 line screen, or as 4 16x16 OBJ blocks
+        let tile = match height {
+            0 => ((x & 0xF8) << 1) + ((y & 0xF8) >> 3),
+            1 => ((x & 0xF8) << 1) + ((x & 0xF8) >> 1) + ((y & 0xF8) >> 3),
+            2 => ((x & 0xF8) << 1) + (x & 0xF8) + ((y & 0xF8) >> 3),
+            _ => ((y & 0x80) << 2) + ((x & 0x80) << 1) + ((y & 0x78) << 1) + ((x & 0x78) >> 3),
+        };
+        ((self.base as usize) << 10) + tile * self.bits_per_pixel() * 8 + (y & 0x7) * 2
+    }
+
+    // the color PLOT writes at x, y, or None when it is transparent
+    pub fn plot_color(&self, colr: u8, x: u8, y: u8) -> Option<u8> {
+        let eight_bits = self.scmr & 0x3 == 3;
+        let mut color = colr;
+        if self.por & POR_DITHER > 0 && !eight_bits {
+            if (x ^ y) & 0x1 > 0 {
+                color >>= 4;
+            }
+            color &= 0xF;
+        }
+        let visible = match eight_bits && self.por & POR_FREEZE_HIGH == 0 {
+            true => color,
+            false => color & 0xF,
+        };
+        match self.por & POR_TRANSPARENT == 0 && visible == 0 {
+            true => None,
+            false => Some(color),
+        }
+    }
+}
+
+// COLOR and GETC: the color register, with its high nibble taken from the source or kept
+pub fn color(colr: u8, por: u8, source: u8) -> u8 {
+    if por & POR_HIGH_NIBBLE > 0 {
+        (colr & 0xF0) | (source >> 4)
+    } else if por & POR_FREEZE_HIGH > 0 {
+        (colr & 0xF0) | (source & 0xF)
+    } else {
+        source
+    }
+}
+
+// where in a tile row byte pair `plane` goes, planes come in pairs of 16 bytes
+fn plane_offset(plane: usize) -> usize {
+    (plane >> 1) * 16 + (plane & 0x1)
+}
+
+impl PixelCache {
+    // write the pending pixels to RAM, returns the number of RAM accesses for timing
+    pub fn flush(&mut self, screen: &Screen, ram: &mut [u8]) -> u64 {
+        if self.pending == 0 {
+            return 0;
+        }
+        let x = (self.offset << 3) as u8;
+        let y = (self.offset >> 5) as u8;
+        let address = screen.row_address(x, y);
+        let mut accesses = 0;
+        for plane in 0..screen.bits_per_pixel() {
+            let index = (address + plane_offset(plane)) % ram.len();
+            let mut data = self
+                .colors
+                .iter()
+                .enumerate()
+                .fold(0, |data, (bit, color)| {
+                    data | ((color >> plane) & 0x1) << bit
+                });
+            // a partial row keeps the pixels already in RAM
+            if self.pending != 0xFF {
+                data = (data & self.pending) | (ram[index] & !self.pending);
+                accesses += 1;
+            }
+            ram[index] = data;
+            accesses += 1;
+        }
+        self.pending = 0;
+        accesses
+    }
+}
+
+// RPIX: the color of pixel x, y as it is in RAM
+pub fn read_pixel(screen: &Screen, ram: &[u8], x: u8, y: u8) -> u8 {
+    let address = screen.row_address(x, y);
+    let bit = (x & 0x7) ^ 0x7;
+    (0..screen.bits_per_pixel()).fold(0, |color, plane| {
+        let byte = ram[(address + plane_offset(plane)) % ram.len()];
+        color | ((byte >> bit) & 0x1) << plane
+    })
+}
+
+// PLOT: add a pixel to the cache, pushing the older row out when a new one starts or the
+// current one fills up. Returns the RAM accesses the push took.
+pub fn plot(
+    cache: &mut [PixelCache; 2],
+    screen: &Screen,
+    ram: &mut [u8],
+    x: u8,
+    y: u8,
+    color: u8,
+) -> u64 {
+    let mut accesses = 0;
+    let offset = ((y as u16) << 5) + (x as u16 >> 3);
+    if offset != cache[0].offset {
+        accesses += cache[1].flush(screen, ram);
+        cache[1] = cache[0];
+        cache[0].pending = 0;
+        cache[0].offset = offset;
+    }
+
+    let bit = (x & 0x7) ^ 0x7;
+    cache[0].colors[bit as usize] = color;
+    cache[0].pending |= 0x1 << bit;
+    if cache[0].pending == 0xFF {
+        accesses += cache[1].flush(screen, ram);
+        cache[1] = cache[0];
+        cache[0].pending = 0;
+    }
+    accesses
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plot_and_read_back() {
+        let screen = Screen {
+            base: 0,
+            scmr: 0x1,
+            por: 0,
+        };
+        assert_eq!(screen.row_address(0, 0), 0);
+        // the next tile down, then the next column of 16 tiles
+        assert_eq!(screen.row_address(0, 9), 32 + 2);
+        assert_eq!(screen.row_address(8, 0), 16 * 32);
+
+        let mut ram = vec![0u8; 0x10000];
+        let mut cache = [PixelCache::default(); 2];
+        plot(&mut cache, &screen, &mut ram, 1, 0, 0x5);
+        // nothing reaches RAM until the row is pushed out of the cache
+        assert_eq!(ram[0], 0);
+        plot(&mut cache, &screen, &mut ram, 0, 1, 0xA);
+        plot(&mut cache, &screen, &mut ram, 0, 2, 0xA);
+        // 5 is planes 0 and 2, pixel 1 is bit 6
+        assert_eq!(ram[..4], [0b0100_0000, 0, 0, 0]);
+        assert_eq!(ram[16..18], [0b0100_0000, 0]);
+        cache[1].flush(&screen, &mut ram);
+        cache[0].flush(&screen, &mut ram);
+        assert_eq!(read_pixel(&screen, &ram, 1, 0), 0x5);
+        assert_eq!(read_pixel(&screen, &ram, 0, 1), 0xA);
+        assert_eq!(ram[3], 0b1000_0000);
+
+        // color 0 is transparent unless POR says otherwise, dithering picks a nibble
+        assert_eq!(screen.plot_color(0xF0, 0, 0), None);
+        let dither = Screen {
+            por: POR_DITHER | POR_TRANSPARENT,
+            ..screen
+        };
+        assert_eq!(dither.plot_color(0x3C, 0, 0), Some(0xC));
+        assert_eq!(dither.plot_color(0x3C, 1, 0), Some(0x3));
+        assert_eq!(color(0x30, POR_HIGH_NIBBLE, 0xAB), 0x3A);
+        assert_eq!(color(0x30, POR_FREEZE_HIGH, 0xAB), 0x3B);
+    }
+}
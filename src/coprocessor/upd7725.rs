@@ -0,0 +1,3 @@
+pub mod alu;
+pub mod chip;
+pub mod firmware;
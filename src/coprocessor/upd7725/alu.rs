@@ -0,0 +1,501 @@
+use crate::coprocessor::upd7725::firmware::{Firmware, Revision};
+
+// The NEC uPD7725 (and its bigger uPD96050): a 16-bit fixed point DSP running the program in
+// its own ROM, one 24-bit instruction per cycle. The S-CPU only sees the data register DR and
+// the high byte of the status register SR, and hands over each word when SR.RQM asks for it.
+
+// SR
+pub const SR_RQM: u16 = 0x1 << 15;
+pub const SR_DRS: u16 = 0x1 << 12;
+pub const SR_DRC: u16 = 0x1 << 10;
+// bits the program can't change with a load to SR
+const SR_FIXED: u16 = 0x907C;
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Flags {
+    pub ov0: bool,
+    pub ov1: bool,
+    pub z: bool,
+    pub c: bool,
+    pub s0: bool,
+    pub s1: bool,
+}
+
+pub struct Upd7725 {
+    revision: Revision,
+    program: Vec<u32>,
+    data_rom: Vec<u16>,
+    pub data_ram: Vec<u16>,
+    pub pc: u16,
+    pub rp: u16,
+    pub dp: u16,
+    stack: [u16; 8],
+    sp: usize,
+    pub k: u16,
+    pub l: u16,
+    pub m: u16,
+    pub n: u16,
+    pub a: u16,
+    pub b: u16,
+    pub flags_a: Flags,
+    pub flags_b: Flags,
+    pub tr: u16,
+    pub trb: u16,
+    pub sr: u16,
+    pub dr: u16,
+    // serial ports, which no cartridge wires up
+    si: u16,
+    so: u16,
+}
+
+impl Upd7725 {
+    pub fn new(revision: Revision, firmware: Firmware) -> Self {
+        let ram_words = match revision {
+            Revision::Upd7725 => 256,
+            Revision::Upd96050 => 2048,
+        };
+        Self {
+            revision,
+            program: firmware.program,
+            data_rom: firmware.data,
+            data_ram: vec![0; ram_words],
+            pc: 0,
+            rp: 0x3FF,
+            dp: 0,
+            stack: [0; 8],
+            sp: 0,
+            k: 0,
+            l: 0,
+            m: 0,
+            n: 0,
+            a: 0,
+            b: 0,
+            flags_a: Flags::default(),
+            flags_b: Flags::default(),
+            tr: 0,
+            trb: 0,
+            sr: 0,
+            dr: 0,
+            si: 0,
+            so: 0,
+        }
+    }
+
+    fn pc_mask(&self) -> u16 {
+        match self.revision {
+            Revision::Upd7725 => 0x7FF,
+            Revision::Upd96050 => 0x3FFF,
+        }
+    }
+
+    fn rp_mask(&self) -> u16 {
+        match self.revision {
+            Revision::Upd7725 => 0x3FF,
+            Revision::Upd96050 => 0x7FF,
+        }
+    }
+
+    fn dp_mask(&self) -> u16 {
+        match self.revision {
+            Revision::Upd7725 => 0xFF,
+            Revision::Upd96050 => 0x7FF,
+        }
+    }
+
+    fn stack_mask(&self) -> usize {
+        match self.revision {
+            Revision::Upd7725 => 0x3,
+            Revision::Upd96050 => 0x7,
+        }
+    }
+
+    fn ram(&self, addr: u16) -> u16 {
+        self.data_ram[(addr & self.dp_mask()) as usize % self.data_ram.len()]
+    }
+
+    fn set_ram(&mut self, addr: u16, value: u16) {
+        let index = (addr & self.dp_mask()) as usize % self.data_ram.len();
+        self.data_ram[index] = value;
+    }
+
+    fn rom(&self, addr: u16) -> u16 {
+        self.data_rom[(addr & self.rp_mask()) as usize % self.data_rom.len()]
+    }
+
+    // the S-CPU side of DR, a byte at a time: low then high, unless SR.DRC makes it 8 bits
+    pub fn read_dr(&mut self) -> u8 {
+        if self.sr & SR_DRC > 0 {
+            self.sr &= !SR_RQM;
+            return self.dr as u8;
+        }
+        match self.sr & SR_DRS > 0 {
+            false => {
+                self.sr |= SR_DRS;
+                self.dr as u8
+            }
+            true => {
+                self.sr &= !(SR_RQM | SR_DRS);
+                (self.dr >> 8) as u8
+            }
+        }
+    }
+
+    pub fn write_dr(&mut self, val: u8) {
+        if self.sr & SR_DRC > 0 {
+            self.sr &= !SR_RQM;
+            self.dr = (self.dr & 0xFF00) | val as u16;
+            return;
+        }
+        match self.sr & SR_DRS > 0 {
+            false => {
+                self.sr |= SR_DRS;
+                self.dr = (self.dr & 0xFF00) | val as u16;
+            }
+            true => {
+                self.sr &= !(SR_RQM | SR_DRS);
+                self.dr = (self.dr & 0x00FF) | (val as u16) << 8;
+            }
+        }
+    }
+
+    pub fn read_sr(&self) -> u8 {
+        (self.sr >> 8) as u8
+    }
+
+    pub fn step(&mut self) {
+        let opcode = self.program[self.pc as usize % self.program.len()];
+        self.pc = (self.pc + 1) & self.pc_mask();
+        match opcode >> 22 {
+            0 => self.op(opcode),
+            // RT: an OP, then return
+            1 => {
+                self.op(opcode);
+                self.sp = self.sp.wrapping_sub(1) & self.stack_mask();
+                self.pc = self.stack[self.sp];
+            }
+            2 => self.jump(opcode),
+            _ => self.load((opcode >> 6) as u16, opcode & 0xF),
+        }
+
+        // the multiplier works on K and L all the time
+        let product = (self.k as i16 as i32) * (self.l as i16 as i32);
+        self.m = (product >> 15) as u16;
+        self.n = (product << 1) as u16;
+    }
+
+    // OP: an ALU operation, a move between registers and DP/RP updates, all at once
+    fn op(&mut self, opcode: u32) {
+        let pselect = (opcode >> 20) & 0x3;
+        let alu = (opcode >> 16) & 0xF;
+        let accumulator_b = (opcode >> 15) & 0x1 > 0;
+        let dpl = (opcode >> 13) & 0x3;
+        let dphm = ((opcode >> 9) & 0xF) as u16;
+        let rp_decrement = (opcode >> 8) & 0x1 > 0;
+        let src = (opcode >> 4) & 0xF;
+        let dst = opcode & 0xF;
+
+        let idb = match src {
+            0 => self.trb,
+            1 => self.a,
+            2 => self.b,
+            3 => self.tr,
+            4 => self.dp,
+            5 => self.rp,
+            6 => self.rom(self.rp),
+            // SGN
+            7 => 0x8000 - self.flags_a.s1 as u16,
+            8 => {
+                self.sr |= SR_RQM;
+                self.dr
+            }
+            9 => self.dr,
+            10 => self.sr,
+            11 | 12 => self.si,
+            13 => self.k,
+            14 => self.l,
+            _ => self.ram(self.dp),
+        };
+
+        if alu > 0 {
+            let p = match pselect {
+                0 => self.ram(self.dp),
+                1 => idb,
+                2 => self.m,
+                _ => self.n,
+            };
+            // carries come from the other accumulator
+            let (q, mut flags, carry) = match accumulator_b {
+                false => (self.a, self.flags_a, self.flags_b.c),
+                true => (self.b, self.flags_b, self.flags_a.c),
+            };
+            let (result, p) = alu_result(alu, q, p, carry);
+            flags.s0 = result & 0x8000 > 0;
+            flags.z = result == 0;
+            match alu {
+                4..=9 => {
+                    // odd operations add, even ones subtract
+                    let overflow = match alu & 0x1 {
+                        1 => (q ^ result) & !(q ^ p) & 0x8000 > 0,
+                        _ => (q ^ result) & (q ^ p) & 0x8000 > 0,
+                    };
+                    flags.c = match alu & 0x1 {
+                        1 => result < q,
+                        _ => result > q,
+                    };
+                    flags.ov0 = overflow;
+                    if overflow {
+                        flags.s1 = flags.ov1 ^ (result & 0x8000 == 0);
+                        flags.ov1 = !flags.ov1;
+                    }
+                }
+                11 => {
+                    flags.c = q & 0x1 > 0;
+                    flags.ov0 = false;
+                    flags.ov1 = false;
+                }
+                12 => {
+                    flags.c = q & 0x8000 > 0;
+                    flags.ov0 = false;
+                    flags.ov1 = false;
+                }
+                _ => {
+                    flags.c = false;
+                    flags.ov0 = false;
+                    flags.ov1 = false;
+                }
+            }
+            match accumulator_b {
+                false => {
+                    self.a = result;
+                    self.flags_a = flags;
+                }
+                true => {
+                    self.b = result;
+                    self.flags_b = flags;
+                }
+            }
+        }
+
+        self.load(idb, dst);
+
+        let dp = self.dp;
+        self.dp = match dpl {
+            1 => (dp & !0xF) | (dp.wrapping_add(1) & 0xF),
+            2 => (dp & !0xF) | (dp.wrapping_sub(1) & 0xF),
+            3 => dp & !0xF,
+            _ => dp,
+        };
+        self.dp = (self.dp ^ (dphm << 4)) & self.dp_mask();
+        if rp_decrement {
+            self.rp = self.rp.wrapping_sub(1) & self.rp_mask();
+        }
+    }
+
+    fn jump(&mut self, opcode: u32) {
+        let branch = (opcode >> 13) & 0x1FF;
+        let next = ((opcode >> 2) & 0x7FF) as u16;
+        let bank = (opcode & 0x3) as u16;
+        let target = ((self.pc & 0x2000) | bank << 11 | next) & self.pc_mask();
+
+        let (a, b) = (self.flags_a, self.flags_b);
+        let dpl = self.dp & 0xF;
+        let taken = match branch {
+            // JMPSO
+            0x000 => {
+                self.pc = self.so & self.pc_mask();
+                return;
+            }
+            0x080 => !a.c,
+            0x082 => a.c,
+            0x084 => !b.c,
+            0x086 => b.c,
+            0x088 => !a.z,
+            0x08A => a.z,
+            0x08C => !b.z,
+            0x08E => b.z,
+            0x090 => !a.ov0,
+            0x092 => a.ov0,
+            0x094 => !b.ov0,
+            0x096 => b.ov0,
+            0x098 => !a.ov1,
+            0x09A => a.ov1,
+            0x09C => !b.ov1,
+            0x09E => b.ov1,
+            0x0A0 => !a.s0,
+            0x0A2 => a.s0,
+            0x0A4 => !b.s0,
+            0x0A6 => b.s0,
+            0x0A8 => !a.s1,
+            0x0AA => a.s1,
+            0x0AC => !b.s1,
+            0x0AE => b.s1,
+            0x0B0 => dpl == 0x0,
+            0x0B1 => dpl != 0x0,
+            0x0B2 => dpl == 0xF,
+            0x0B3 => dpl != 0xF,
+            // the serial ports never acknowledge
+            0x0B4 | 0x0B8 => true,
+            0x0B6 | 0x0BA => false,
+            0x0BC => self.sr & SR_RQM == 0,
+            0x0BE => self.sr & SR_RQM > 0,
+            // LJMP, HJMP
+            0x100 => {
+                self.pc = target & !0x2000;
+                return;
+            }
+            0x101 => {
+                self.pc = (target | 0x2000) & self.pc_mask();
+                return;
+            }
+            // LCALL, HCALL
+            0x140 | 0x141 => {
+                self.stack[self.sp] = self.pc;
+                self.sp = (self.sp + 1) & self.stack_mask();
+                self.pc = match branch {
+                    0x140 => target & !0x2000,
+                    _ => (target | 0x2000) & self.pc_mask(),
+                };
+                return;
+            }
+            _ => false,
+        };
+        if taken {
+            self.pc = target;
+        }
+    }
+
+    // LD: immediate data, or the bus value of an OP, into a register
+    fn load(&mut self, value: u16, dst: u32) {
+        match dst {
+            1 => self.a = value,
+            2 => self.b = value,
+            3 => self.tr = value,
+            4 => self.dp = value & self.dp_mask(),
+            5 => self.rp = value & self.rp_mask(),
+            6 => {
+                self.dr = value;
+                self.sr |= SR_RQM;
+            }
+            7 => self.sr = (self.sr & SR_FIXED) | (value & !SR_FIXED),
+            8 | 9 => self.so = value,
+            10 => self.k = value,
+            11 => {
+                self.k = value;
+                self.l = self.rom(self.rp);
+            }
+            12 => {
+                self.l = value;
+                self.k = self.ram(self.dp | 0x40);
+            }
+            13 => self.l = value,
+            14 => self.trb = value,
+            15 => self.set_ram(self.dp, value),
+            _ => {}
+        }
+    }
+}
+
+// the ALU result and the P operand the flags see (1 for INC and DEC)
+fn alu_result(alu: u32, q: u16, p: u16, carry: bool) -> (u16, u16) {
+    let carry = carry as u16;
+    match alu {
+        1 => (q | p, p),
+        2 => (q & p, p),
+        3 => (q ^ p, p),
+        4 => (q.wrapping_sub(p), p),
+        5 => (q.wrapping_add(p), p),
+        6 => (q.wrapping_sub(p).wrapping_sub(carry), p),
+        7 => (q.wrapping_add(p).wrapping_add(carry), p),
+        8 => (q.wrapping_sub(1), 1),
+        9 => (q.wrapping_add(1), 1),
+        10 => (!q, p),
+        11 => ((q >> 1) | (q & 0x8000), p),
+        12 => ((q << 1) | carry, p),
+        13 => ((q << 2) | 0x3, p),
+        14 => ((q << 4) | 0xF, p),
+        _ => (q.rotate_left(8), p),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const fn ld(value: u16, dst: u32) -> u32 {
+        0x3 << 22 | (value as u32) << 6 | dst
+    }
+
+    const fn op(alu: u32, src: u32, dst: u32) -> u32 {
+        // P is the bus value, accumulator A
+        0x1 << 20 | alu << 16 | src << 4 | dst
+    }
+
+    fn dsp(program: &[u32]) -> Upd7725 {
+        let mut words = vec![0; 2048];
+        words[..program.len()].copy_from_slice(program);
+        let mut data = vec![0; 1024];
+        data[0x3FF] = 0x1234;
+        Upd7725::new(
+            Revision::Upd7725,
+            Firmware {
+                program: words,
+                data,
+            },
+        )
+    }
+
+    #[test]
+    fn handshake_and_alu() {
+        // wait for a word from the S-CPU, add 0x100 to it and hand it back
+        let mut dsp = dsp(&[
+            ld(0x0000, 7),                    // SR: 16-bit DR
+            ld(0x0000, 6),                    // DR = 0, RQM set
+            0x2 << 22 | 0x0BE << 13 | 2 << 2, // 2: JRQM 2
+            op(0, 9, 1),                      // A = DR
+            ld(0x0100, 3),                    // TR = $100
+            op(5, 3, 0),                      // A += TR
+            op(0, 1, 6),                      // DR = A, RQM set
+            0x2 << 22 | 0x100 << 13 | 7 << 2, // 7: JMP 7
+        ]);
+        for _ in 0..3 {
+            dsp.step();
+        }
+        assert_eq!(dsp.read_sr() & 0x80, 0x80);
+        dsp.write_dr(0x34);
+        dsp.write_dr(0x12);
+        assert_eq!(dsp.read_sr() & 0x80, 0);
+        for _ in 0..10 {
+            dsp.step();
+        }
+        assert_eq!(dsp.a, 0x1334);
+        assert!(!dsp.flags_a.c && !dsp.flags_a.z);
+        assert_eq!(dsp.read_sr() & 0x80, 0x80);
+        assert_eq!(dsp.read_dr(), 0x34);
+        assert_eq!(dsp.read_dr(), 0x13);
+        assert_eq!(dsp.read_sr() & 0x80, 0);
+    }
+
+    #[test]
+    fn multiplier_and_memory() {
+        let mut dsp = dsp(&[
+            ld(0x4000, 11),            // K = 0.5, L = data ROM at RP ($3FF)
+            ld(0x0010, 4),             // DP = $10
+            op(0, 13, 15) | 0x1 << 13, // RAM[DP] = K, DP low + 1
+            op(9, 0, 0),               // A = A + 1 (P unused)
+            op(4, 15, 0),              // A -= RAM[$11] (0)
+            op(8, 0, 0),               // A -= 1
+            op(8, 0, 0),               // A -= 1, borrow
+        ]);
+        dsp.step();
+        // 0.5 * $1234 in 1.15 fixed point
+        assert_eq!((dsp.k, dsp.l), (0x4000, 0x1234));
+        assert_eq!(dsp.m, 0x091A);
+        for _ in 0..6 {
+            dsp.step();
+        }
+        assert_eq!(dsp.data_ram[0x10], 0x4000);
+        assert_eq!(dsp.dp, 0x11);
+        assert_eq!(dsp.a, 0xFFFF);
+        assert!(dsp.flags_a.c && dsp.flags_a.s0);
+    }
+}
@@ -0,0 +1,160 @@
+use crate::apu::scheduler::MASTER_CLOCK_HZ;
+use crate::coprocessor::upd7725::alu::Upd7725;
+use crate::coprocessor::upd7725::firmware::{Firmware, Model};
+use std::error::Error;
+use std::path::Path;
+
+// A DSP cartridge: the uPD7725 or uPD96050 behind DR and SR, wherever the board puts them.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mapping {
+    // DR at $8000-$BFFF and SR at $C000-$FFFF of banks $20-$3F, LoROM games up to 1M
+    LoRom,
+    // DR at $0000-$3FFF and SR at $4000-$7FFF of banks $60-$6F, bigger LoROM games
+    LoRomLarge,
+    // DR at $6000-$6FFF and SR at $7000-$7FFF of banks $00-$1F
+    HiRom,
+    // ST010/ST011: DR and SR at $60:0000 and $60:0001, the data RAM at $68-$6F:0000-$0FFF
+    St01x,
+}
+
+enum Port {
+    Data,
+    Status,
+    Ram(usize),
+}
+
+pub struct Dsp {
+    pub model: Model,
+    pub core: Upd7725,
+    mapping: Mapping,
+    // master cycles times the DSP clock, spent one DSP instruction at a time
+    balance: u64,
+}
+
+impl Dsp {
+    pub fn new(model: Model, firmware: Firmware, mapping: Mapping) -> Self {
+        Self {
+            model,
+            core: Upd7725::new(model.revision(), firmware),
+            mapping,
+            balance: 0,
+        }
+    }
+
+    pub fn open(
+        model: Model,
+        firmware_dir: &Path,
+        mapping: Mapping,
+    ) -> Result<Self, Box<dyn Error>> {
+        Ok(Self::new(
+            model,
+            Firmware::load(model, firmware_dir)?,
+            mapping,
+        ))
+    }
+
+    // run for as many master cycles as the S-CPU just spent
+    pub fn advance(&mut self, cycles: u64) {
+        self.balance += cycles * self.model.clock_hz();
+        while self.balance >= MASTER_CLOCK_HZ {
+            self.balance -= MASTER_CLOCK_HZ;
+            self.core.step();
+        }
+    }
+
+    fn port(&self, addr: u32) -> Option<Port> {
+        let bank = (addr >> 16) & 0x7F;
+        let offset = addr & 0xFFFF;
+        match (self.mapping, bank, offset) {
+            (Mapping::LoRom, 0x20..=0x3F, 0x8000..=0xBFFF) => Some(Port::Data),
+            (Mapping::LoRom, 0x20..=0x3F, 0xC000..=0xFFFF) => Some(Port::Status),
+            (Mapping::LoRomLarge, 0x60..=0x6F, 0x0000..=0x3FFF) => Some(Port::Data),
+            (Mapping::LoRomLarge, 0x60..=0x6F, 0x4000..=0x7FFF) => Some(Port::Status),
+            (Mapping::HiRom, 0x00..=0x1F, 0x6000..=0x6FFF) => Some(Port::Data),
+            (Mapping::HiRom, 0x00..=0x1F, 0x7000..=0x7FFF) => Some(Port::Status),
+            (Mapping::St01x, 0x60..=0x67, _) => match offset & 0x1 {
+                0 => Some(Port::Data),
+                _ => Some(Port::Status),
+            },
+            (Mapping::St01x, 0x68..=0x6F, 0x0000..=0x0FFF) => Some(Port::Ram(offset as usize)),
+            _ => None,
+        }
+    }
+
+    // None is for addresses the DSP doesn't answer
+    pub fn read(&mut self, addr: u32) -> Option<u8> {
+        match self.port(addr)? {
+            Port::Data => Some(self.core.read_dr()),
+            Port::Status => Some(self.core.read_sr()),
+            Port::Ram(offset) => {
+                let word = self.core.data_ram[(offset >> 1) % self.core.data_ram.len()];
+                Some(word.to_le_bytes()[offset & 0x1])
+            }
+        }
+    }
+
+    pub fn write(&mut self, addr: u32, val: u8) -> bool {
+        match self.port(addr) {
+            Some(Port::Data) => self.core.write_dr(val),
+            // SR is read-only
+            Some(Port::Status) => {}
+            Some(Port::Ram(offset)) => {
+                let index = (offset >> 1) % self.core.data_ram.len();
+                let mut bytes = self.core.data_ram[index].to_le_bytes();
+                bytes[offset & 0x1] = val;
+                self.core.data_ram[index] = u16::from_le_bytes(bytes);
+            }
+            None => return false,
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::coprocessor::upd7725::firmware::Revision;
+
+    fn dsp(model: Model, mapping: Mapping) -> Dsp {
+        let revision = model.revision();
+        // LD $0000, SR, then LD $ABCD, DR and spin
+        let mut program = vec![0; revision.program_words()];
+        program[0] = 0x3 << 22 | 7;
+        program[1] = 0x3 << 22 | 0xABCD << 6 | 6;
+        program[2] = 0x2 << 22 | 0x100 << 13 | 2 << 2;
+        let firmware = Firmware {
+            program,
+            data: vec![0; revision.data_words()],
+        };
+        Dsp::new(model, firmware, mapping)
+    }
+
+    #[test]
+    fn register_mapping() {
+        let mut lorom = dsp(Model::Dsp1, Mapping::LoRom);
+        lorom.advance(20);
+        assert_eq!(lorom.read(0x30C000), Some(0x80));
+        assert_eq!(lorom.read(0xB08000), Some(0xCD));
+        assert_eq!(lorom.read(0x308001), Some(0xAB));
+        assert_eq!(lorom.read(0x3FC000), Some(0x00));
+        assert_eq!(lorom.read(0x408000), None);
+        assert!(!lorom.write(0x008000, 0));
+
+        let mut hirom = dsp(Model::Dsp1, Mapping::HiRom);
+        assert_eq!(hirom.read(0x007000), Some(0x00));
+        assert_eq!(hirom.read(0x206000), None);
+        hirom.advance(20);
+        assert_eq!(hirom.read(0x807000), Some(0x80));
+
+        let mut st010 = dsp(Model::St010, Mapping::St01x);
+        assert!(st010.write(0x680002, 0x34));
+        assert!(st010.write(0x680003, 0x12));
+        assert_eq!(st010.core.data_ram[1], 0x1234);
+        assert_eq!(st010.read(0x680003), Some(0x12));
+        st010.advance(20);
+        assert_eq!(st010.read(0x600001), Some(0x80));
+        assert_eq!(st010.read(0x600000), Some(0xCD));
+        assert_eq!(Revision::Upd96050, st010.model.revision());
+    }
+}
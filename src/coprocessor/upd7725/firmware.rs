@@ -0,0 +1,199 @@
+use std::error::Error;
+use std::fmt;
+use std::fs::read;
+use std::path::Path;
+
+// The program and data ROMs inside the DSP chips can't be shipped with the emulator, they are
+// dumps the user supplies: either one file with the program ROM followed by the data ROM
+// (`dsp1b.rom`) or the two halves (`dsp1b.program.rom` and `dsp1b.data.rom`). Program words are
+// 24 bits and data words 16 bits, both little endian.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Revision {
+    // DSP-1 to DSP-4
+    Upd7725,
+    // ST010 and ST011
+    Upd96050,
+}
+
+impl Revision {
+    pub fn program_words(&self) -> usize {
+        match self {
+            Revision::Upd7725 => 2048,
+            Revision::Upd96050 => 16384,
+        }
+    }
+
+    pub fn data_words(&self) -> usize {
+        match self {
+            Revision::Upd7725 => 1024,
+            Revision::Upd96050 => 2048,
+        }
+    }
+
+    pub fn program_bytes(&self) -> usize {
+        self.program_words() * 3
+    }
+
+    pub fn data_bytes(&self) -> usize {
+        self.data_words() * 2
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Model {
+    Dsp1,
+    Dsp2,
+    Dsp3,
+    Dsp4,
+    St010,
+    St011,
+}
+
+impl Model {
+    pub fn revision(&self) -> Revision {
+        match self {
+            Model::St010 | Model::St011 => Revision::Upd96050,
+            _ => Revision::Upd7725,
+        }
+    }
+
+    // the dump's file name without extension, DSP-1 games run on the DSP-1B program
+    pub fn file_name(&self) -> &'static str {
+        match self {
+            Model::Dsp1 => "dsp1b",
+            Model::Dsp2 => "dsp2",
+            Model::Dsp3 => "dsp3",
+            Model::Dsp4 => "dsp4",
+            Model::St010 => "st010",
+            Model::St011 => "st011",
+        }
+    }
+
+    // as the game database writes it
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "dsp1" => Some(Model::Dsp1),
+            "dsp2" => Some(Model::Dsp2),
+            "dsp3" => Some(Model::Dsp3),
+            "dsp4" => Some(Model::Dsp4),
+            "st010" => Some(Model::St010),
+            "st011" => Some(Model::St011),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Model::Dsp1 => "DSP-1",
+            Model::Dsp2 => "DSP-2",
+            Model::Dsp3 => "DSP-3",
+            Model::Dsp4 => "DSP-4",
+            Model::St010 => "ST010",
+            Model::St011 => "ST011",
+        }
+    }
+
+    // instructions per second
+    pub fn clock_hz(&self) -> u64 {
+        match self {
+            Model::St010 => 11_000_000,
+            Model::St011 => 15_000_000,
+            _ => 7_600_000,
+        }
+    }
+}
+
+impl fmt::Display for Model {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+pub struct Firmware {
+    pub program: Vec<u32>,
+    pub data: Vec<u16>,
+}
+
+impl Firmware {
+    // the combined image, program ROM first
+    pub fn parse(revision: Revision, bytes: &[u8]) -> Result<Self, Box<dyn Error>> {
+        let expected = revision.program_bytes() + revision.data_bytes();
+        if bytes.len() != expected {
+            return Err(format!("{} bytes, expected {}", bytes.len(), expected).into());
+        }
+        let (program, data) = bytes.split_at(revision.program_bytes());
+        Ok(Self {
+            program: program
+                .chunks(3)
+                .map(|word| u32::from_le_bytes([word[0], word[1], word[2], 0]))
+                .collect(),
+            data: data
+                .chunks(2)
+                .map(|word| u16::from_le_bytes([word[0], word[1]]))
+                .collect(),
+        })
+    }
+
+    // NAME.rom, or NAME.program.rom and NAME.data.rom, from `dir`
+    pub fn load(model: Model, dir: &Path) -> Result<Self, Box<dyn Error>> {
+        let revision = model.revision();
+        let name = model.file_name();
+        let combined = dir.join(format!("{}.rom", name));
+        let program = dir.join(format!("{}.program.rom", name));
+        let data = dir.join(format!("{}.data.rom", name));
+
+        let bytes = if combined.is_file() {
+            read(&combined)?
+        } else if program.is_file() && data.is_file() {
+            let mut bytes = read(&program)?;
+            bytes.extend(read(&data)?);
+            bytes
+        } else {
+            return Err(format!(
+                "the {} firmware is missing: put {}.rom ({} bytes), or {}.program.rom and \
+                 {}.data.rom, in {} or give its directory with --firmware",
+                model,
+                name,
+                revision.program_bytes() + revision.data_bytes(),
+                name,
+                name,
+                dir.display()
+            )
+            .into());
+        };
+        Self::parse(revision, &bytes).map_err(|e| format!("{} firmware: {}", model, e).into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn load_firmware() {
+        let dir = std::env::temp_dir().join(format!("ddss-firmware-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        let error = Firmware::load(Model::Dsp2, &dir).err().unwrap().to_string();
+        assert!(error.contains("DSP-2 firmware is missing"));
+        assert!(error.contains("dsp2.rom (8192 bytes)"));
+
+        let mut program = vec![0u8; Revision::Upd7725.program_bytes()];
+        program[..3].copy_from_slice(&[0x56, 0x34, 0x12]);
+        let mut data = vec![0u8; Revision::Upd7725.data_bytes()];
+        data[2..4].copy_from_slice(&[0xCD, 0xAB]);
+        fs::write(dir.join("dsp2.program.rom"), &program).unwrap();
+        fs::write(dir.join("dsp2.data.rom"), &data).unwrap();
+        let firmware = Firmware::load(Model::Dsp2, &dir).unwrap();
+        assert_eq!(firmware.program.len(), 2048);
+        assert_eq!(firmware.program[0], 0x123456);
+        assert_eq!(firmware.data[1], 0xABCD);
+
+        fs::write(dir.join("dsp2.rom"), &program).unwrap();
+        let error = Firmware::load(Model::Dsp2, &dir).err().unwrap().to_string();
+        assert_eq!(error, "DSP-2 firmware: 6144 bytes, expected 8192");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
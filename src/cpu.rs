@@ -1,6 +1,8 @@
 pub mod alu;
 mod bits;
 pub mod bus;
+pub mod dma;
+pub mod io;
 mod op_adc;
 mod op_and;
 mod op_asl;
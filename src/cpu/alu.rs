@@ -1,4 +1,7 @@
-use crate::cpu::{bits::Word, bus::Bus};
+use crate::cpu::{
+    bits::Word,
+    bus::{Bus, CpuBus},
+};
 use log::debug;
 
 pub const S_CARRY: u8 = 0x1;
@@ -13,8 +16,14 @@ pub const S_NEGATIVE: u8 = 0x1 << 7;
 
 pub const STACK_POINTER_START: u32 = 0x1FF;
 
-pub struct Cpu {
-    pub bus: Box<Bus>,
+pub const VECTOR_NMI_NATIVE: u32 = 0xFFEA;
+pub const VECTOR_IRQ_NATIVE: u32 = 0xFFEE;
+pub const VECTOR_NMI_EMULATION: u32 = 0xFFFA;
+pub const VECTOR_IRQ_EMULATION: u32 = 0xFFFE;
+
+// the 65816 core, on the main bus or (for the SA-1) on a bus of its own
+pub struct Cpu<B = Bus> {
+    pub bus: Box<B>,
     pub reg_a: Word,
     pub reg_x: u16,
     pub reg_y: u16,
@@ -47,8 +56,8 @@ pub enum AddressMode {
     StackRelativeIndirectIndexedY,
 }
 
-impl Cpu {
-    pub fn new(bus: Box<Bus>) -> Self {
+impl<B: CpuBus> Cpu<B> {
+    pub fn new(bus: Box<B>) -> Self {
         Self {
             bus: bus,
             sp: STACK_POINTER_START,
@@ -66,10 +75,61 @@ impl Cpu {
 
     pub fn start(&mut self) {
         loop {
-            let opcode = self.bus.read_byte(self.pc as u32);
-            self.decode_and_execute(opcode);
-            self.incr_pc();
+            self.step();
+        }
+    }
+
+    // run one instruction, servicing a pending NMI or IRQ first
+    pub fn step(&mut self) {
+        if self.bus.take_nmi() {
+            self.interrupt(VECTOR_NMI_NATIVE, VECTOR_NMI_EMULATION);
+        } else if self.reg_p & S_IRQ_DISABLE == 0 && self.bus.irq_pending() {
+            self.interrupt(VECTOR_IRQ_NATIVE, VECTOR_IRQ_EMULATION);
         }
+
+        let opcode = self.bus.read_byte(self.pc as u32);
+        self.decode_and_execute(opcode);
+        self.incr_pc();
+    }
+
+    pub fn interrupt(&mut self, native_vector: u32, emulation_vector: u32) {
+        // push PBR (native mode only)
+        if !self.emulation {
+            self.bus.write_byte(self.sp, self.reg_pb);
+            self.sp -= 1;
+        }
+
+        // push PC High
+        self.bus.write_byte(self.sp, (self.pc >> 8) as u8);
+        self.sp -= 1;
+
+        // push PC Low
+        self.bus.write_byte(self.sp, (self.pc & 0xFF) as u8);
+        self.sp -= 1;
+
+        // push P
+        self.bus.write_byte(self.sp, self.reg_p);
+        self.sp -= 1;
+
+        self.flag(S_IRQ_DISABLE, true);
+        self.flag(S_DECIMAL_MODE, false);
+
+        let vector = match self.emulation {
+            true => emulation_vector,
+            false => native_vector,
+        };
+        let pcl = self.bus.read_byte(vector);
+        let pch = self.bus.read_byte(vector + 1);
+
+        debug!(
+            "[0x{:X}] INTERRUPT : VECTOR=0x{:X} NEW_PC=0x{:X}",
+            self.pc,
+            vector,
+            Self::make_word(pcl, pch)
+        );
+
+        self.reg_pb = 0x0;
+        self.pc = Self::make_word(pcl, pch);
     }
 
     pub fn incr_pc(&mut self) {
@@ -966,6 +1026,16 @@ impl Cpu {
     }
 }
 
+impl Cpu {
+    // run until the PPU wraps around to the next frame
+    pub fn run_frame(&mut self) {
+        let frame = self.bus.ppu.frame;
+        while self.bus.ppu.frame == frame {
+            self.step();
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -983,4 +1053,30 @@ mod tests {
         let result = c.fetch(AddressMode::Absolute, true);
         assert_eq!(result, 0x201);
     }
+
+    #[test]
+    fn nmi_at_vblank() {
+        let mut b = Bus::new();
+        // NOP-free loop: BRA -2 at 0x8000
+        b.write_byte(0x8000, 0x80);
+        b.write_byte(0x8001, 0xFE);
+        // emulation NMI vector -> 0x9000
+        b.write_byte(0xFFFA, 0x00);
+        b.write_byte(0xFFFB, 0x90);
+        b.write_byte(0x9000, 0x80);
+        b.write_byte(0x9001, 0xFE);
+        // NMITIMEN - enable NMI
+        b.write_byte(0x4200, 0x80);
+        let mut c = Cpu::new(Box::new(b));
+        while !c.bus.ppu.in_vblank() {
+            c.step();
+        }
+        c.step();
+        assert_eq!(c.pc, 0x9000);
+        assert_eq!(c.sp, STACK_POINTER_START - 3);
+        assert_eq!(c.reg_p & S_IRQ_DISABLE, S_IRQ_DISABLE);
+        // RDNMI reports and acknowledges the flag
+        assert_eq!(c.bus.read_byte(0x4210) & 0x80, 0x80);
+        assert_eq!(c.bus.read_byte(0x4210) & 0x80, 0x0);
+    }
 }
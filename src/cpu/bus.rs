@@ -1,30 +1,325 @@
+use crate::apu::scheduler::Scheduler;
+use crate::cheats::Cheats;
+use crate::coprocessor::sa1::chip::Sa1;
+use crate::coprocessor::superfx::chip::SuperFx;
+use crate::coprocessor::upd7725::chip::Dsp;
+use crate::cpu::dma::Dma;
+use crate::cpu::io::{Io, NMITIMEN_AUTO_JOYPAD};
+use crate::input::ports::{ControllerPorts, WRIO_PORT2_IOBIT};
+use crate::movie::state::{SaveState, StateReader, StateWriter};
+use crate::ppu::regs::Ppu;
+use crate::ppu::timing::{MASTER_CYCLES_PER_DOT, PpuEvent, VideoStandard};
+use std::error::Error;
 use std::ops::Range;
 
+// memory access speeds, in master cycles
+pub const FAST_ACCESS: u64 = 6;
+pub const SLOW_ACCESS: u64 = 8;
+pub const XSLOW_ACCESS: u64 = 12;
+
+// save states keep the flat memory in pages, only the ones that changed since power-on
+const STATE_PAGE_SIZE: usize = 0x1000;
+
+// what the 65816 core needs from the bus it sits on
+pub trait CpuBus {
+    fn read_byte(&mut self, addr: u32) -> u8;
+    fn write_byte(&mut self, addr: u32, val: u8);
+    // true once per NMI edge
+    fn take_nmi(&mut self) -> bool;
+    fn irq_pending(&self) -> bool;
+
+    fn read_dword(&mut self, addr: u32) -> u32 {
+        self.read_byte(addr) as u32
+    }
+}
+
 pub struct Bus {
     work_ram: Box<[u8]>,
+    pub ppu: Ppu,
+    pub io: Io,
+    pub dma: Dma,
+    pub apu: Scheduler,
+    pub input: ControllerPorts,
+    pub cheats: Cheats,
+    pub sa1: Option<Box<Sa1>>,
+    pub superfx: Option<Box<SuperFx>>,
+    pub dsp: Option<Box<Dsp>>,
+    pub master_cycles: u64,
+    dot_cycles: u64,
+    // H counter at which a light gun fires on the current line
+    light_h: Option<u16>,
+    pub(crate) mdr: u8,
 }
 
 impl Bus {
     pub fn new() -> Self {
         Self {
-            work_ram: vec![0u8; 0xFFFFFF as usize].into_boxed_slice(),
+            work_ram: vec![0u8; 0x1000000].into_boxed_slice(),
+            ppu: Ppu::new(VideoStandard::Ntsc),
+            io: Io::new(),
+            dma: Dma::new(),
+            apu: Scheduler::new(),
+            input: ControllerPorts::new(),
+            cheats: Cheats::new(),
+            sa1: None,
+            superfx: None,
+            dsp: None,
+            master_cycles: 0,
+            dot_cycles: 0,
+            light_h: None,
+            mdr: 0,
+        }
+    }
+
+    // PAL consoles have more lines per frame and a slower master clock, which the SPC700
+    // (with its own crystal) has to be paced against
+    pub fn set_video_standard(&mut self, standard: VideoStandard) {
+        self.ppu.standard = standard;
+        self.apu.master_clock_hz = standard.master_clock_hz();
+    }
+
+    // $2000-$5FFF in banks $00-$3F and $80-$BF hold the memory mapped registers
+    fn register_offset(addr: u32) -> Option<u16> {
+        let bank = (addr >> 16) & 0xFF;
+        let offset = (addr & 0xFFFF) as u16;
+        match bank & 0x40 == 0 && (0x2000..0x6000).contains(&offset) {
+            true => Some(offset),
+            false => None,
+        }
+    }
+
+    fn access_cycles(&self, addr: u32) -> u64 {
+        let bank = (addr >> 16) & 0xFF;
+        let offset = addr & 0xFFFF;
+        if bank & 0x40 == 0 {
+            match offset {
+                0x0000..=0x1FFF => SLOW_ACCESS,
+                0x2000..=0x3FFF => FAST_ACCESS,
+                0x4000..=0x41FF => XSLOW_ACCESS,
+                0x4200..=0x5FFF => FAST_ACCESS,
+                0x6000..=0x7FFF => SLOW_ACCESS,
+                _ => self.rom_access_cycles(bank),
+            }
+        } else if bank >= 0xC0 {
+            self.rom_access_cycles(bank)
+        } else {
+            SLOW_ACCESS
         }
     }
 
+    // banks $80-$FF run at 3.58MHz when MEMSEL selects FastROM
+    fn rom_access_cycles(&self, bank: u32) -> u64 {
+        match bank >= 0x80 && self.io.fast_rom() {
+            true => FAST_ACCESS,
+            false => SLOW_ACCESS,
+        }
+    }
+
+    // the first 8K of WRAM is mirrored at $0000-$1FFF in banks $00-$3F and $80-$BF
+    pub(crate) fn mirror(addr: u32) -> u32 {
+        let bank = (addr >> 16) & 0xFF;
+        let offset = addr & 0xFFFF;
+        match bank & 0x40 == 0 && offset < 0x2000 {
+            true => 0x7E0000 | offset,
+            false => addr,
+        }
+    }
+
+    // buttons held by a player (0 is player 1) on a standard pad, see ControllerPorts
+    pub fn set_joypad(&mut self, player: usize, buttons: u16) {
+        self.input.set_buttons(player, buttons);
+    }
+
+    // advance the rest of the system by some master cycles
+    pub fn tick(&mut self, cycles: u64) {
+        self.master_cycles += cycles;
+        self.dot_cycles += cycles;
+        self.io.clock(cycles);
+        self.apu.advance(cycles);
+        if let Some(sa1) = &mut self.sa1 {
+            sa1.advance(cycles);
+        }
+        if let Some(superfx) = &mut self.superfx {
+            superfx.advance(cycles);
+        }
+        if let Some(dsp) = &mut self.dsp {
+            dsp.advance(cycles);
+        }
+
+        while self.dot_cycles >= MASTER_CYCLES_PER_DOT {
+            self.dot_cycles -= MASTER_CYCLES_PER_DOT;
+            match self.ppu.step_dot() {
+                Some(PpuEvent::VBlankStart) => {
+                    if self.io.nmitimen & NMITIMEN_AUTO_JOYPAD > 0 {
+                        self.io.joypads = self.input.auto_read();
+                    }
+                    self.io.vblank_start();
+                    for (address, value) in self.cheats.ram_writes(|address| self.peek(address)) {
+                        self.poke(address, value);
+                    }
+                }
+                Some(PpuEvent::FrameStart) => {
+                    self.io.frame_start();
+                    self.init_hdma();
+                }
+                Some(PpuEvent::HBlankStart) => {
+                    self.ppu.render_line();
+                    if !self.ppu.in_vblank() {
+                        self.run_hdma();
+                    }
+                }
+                // keep the SPC700 close behind, even when the CPU never polls the ports
+                Some(PpuEvent::LineStart) => {
+                    self.apu.catch_up();
+                    self.light_h = self.input.light_target(self.ppu.v_counter);
+                }
+                _ => {}
+            }
+            // the gun pulls IOBit low, which only latches while WRIO keeps the line high
+            if self.light_h == Some(self.ppu.h_counter) {
+                self.light_h = None;
+                if self.io.wrio & WRIO_PORT2_IOBIT > 0 {
+                    self.ppu.latch_counters();
+                }
+            }
+            self.io.poll_irq(self.ppu.h_counter, self.ppu.v_counter);
+        }
+    }
+
+    pub fn take_nmi(&mut self) -> bool {
+        self.io.take_nmi()
+    }
+
+    pub fn irq_pending(&self) -> bool {
+        self.io.irq_line()
+            || self.sa1.as_ref().is_some_and(|sa1| sa1.irq())
+            || self.superfx.as_ref().is_some_and(|superfx| superfx.irq())
+    }
+
     pub fn write_byte(&mut self, addr: u32, val: u8) {
-        match addr {
-            _ => self.work_ram[addr as usize] = val,
+        self.tick(self.access_cycles(addr));
+        self.mdr = val;
+        self.write_register_or_memory(addr, val);
+    }
+
+    // the actual write, without spending cycles (shared with the DMA unit)
+    pub(crate) fn write_register_or_memory(&mut self, addr: u32, val: u8) {
+        if let Some(sa1) = &mut self.sa1
+            && sa1.write(addr, val)
+        {
+            return;
+        }
+        if let Some(superfx) = &mut self.superfx
+            && superfx.write(addr, val)
+        {
+            return;
+        }
+        if let Some(dsp) = &mut self.dsp
+            && dsp.write(addr, val)
+        {
+            return;
+        }
+        match Self::register_offset(addr) {
+            Some(offset @ 0x2100..=0x213F) => self.ppu.write_register(offset, val),
+            Some(offset @ 0x2140..=0x217F) => self.apu.write_port((offset & 0x3) as usize, val),
+            Some(0x2180) => {
+                let wram_addr = self.io.next_wram_address();
+                self.work_ram[wram_addr as usize] = val;
+            }
+            Some(offset @ 0x2181..=0x2183) => self.io.write_wram_address(offset, val),
+            Some(0x4016) => self.input.write_latch(val),
+            Some(0x4201) => {
+                // IOBit on port 2 going from 1 to 0 latches the H/V counters
+                if self.io.wrio & WRIO_PORT2_IOBIT > 0 && val & WRIO_PORT2_IOBIT == 0 {
+                    self.ppu.latch_counters();
+                }
+                self.io.write_register(0x4201, val);
+                self.input.write_iobits(val);
+            }
+            Some(0x420B) => self.start_dma(val),
+            Some(0x420C) => self.dma.hdmaen = val,
+            Some(offset @ 0x4200..=0x421F) => self.io.write_register(offset, val),
+            Some(offset @ 0x4300..=0x437F) => self.dma.write_register(offset, val),
+            _ => {
+                let addr = Self::mirror(addr);
+                self.work_ram[addr as usize] = self.cheats.write(addr, val);
+            }
         }
     }
 
-    pub fn read_dword(&self, addr: u32) -> u32 {
-        self.read_byte(addr) as u32
+    pub fn read_byte(&mut self, addr: u32) -> u8 {
+        self.tick(self.access_cycles(addr));
+        let value = self.read_register_or_memory(addr);
+
+        // write-only registers return whatever was last on the data bus
+        self.mdr = value.unwrap_or(self.mdr);
+        self.mdr
     }
 
-    pub fn read_byte(&self, addr: u32) -> u8 {
-        match addr {
-            _ => self.work_ram[addr as usize],
+    // the actual read, without spending cycles (shared with the DMA unit)
+    pub(crate) fn read_register_or_memory(&mut self, addr: u32) -> Option<u8> {
+        if let Some(value) = self.sa1.as_mut().and_then(|sa1| sa1.read(addr)) {
+            return Some(self.cheats.read(addr, value));
+        }
+        let mdr = self.mdr;
+        if let Some(value) = self
+            .superfx
+            .as_mut()
+            .and_then(|superfx| superfx.read(addr, mdr))
+        {
+            return Some(self.cheats.read(addr, value));
+        }
+        if let Some(value) = self.dsp.as_mut().and_then(|dsp| dsp.read(addr)) {
+            return Some(value);
+        }
+        match Self::register_offset(addr) {
+            Some(offset @ 0x2100..=0x213F) => self.ppu.read_register(offset),
+            Some(offset @ 0x2140..=0x217F) => Some(self.apu.read_port((offset & 0x3) as usize)),
+            Some(0x2180) => {
+                let wram_addr = self.io.next_wram_address();
+                Some(self.work_ram[wram_addr as usize])
+            }
+            // bits 2-4 of JOYB are always set, the rest is open bus
+            Some(0x4016) => Some(self.input.read_port(0) | (self.mdr & 0xFC)),
+            Some(0x4017) => Some(self.input.read_port(1) | 0x1C | (self.mdr & 0xE0)),
+            Some(offset @ 0x4200..=0x421F) => self.io.read_register(offset, &self.ppu, self.mdr),
+            Some(offset @ 0x4300..=0x437F) => self.dma.read_register(offset),
+            _ => {
+                let addr = Self::mirror(addr);
+                Some(self.cheats.read(addr, self.work_ram[addr as usize]))
+            }
+        }
+    }
+
+    // raw access used while mapping the cartridge, no side effects and no cycles spent
+    pub fn load_byte(&mut self, addr: u32, val: u8) {
+        self.work_ram[addr as usize] = val;
+    }
+
+    // a byte of memory without cycles, side effects or cheats, with the cartridge RAM behind an
+    // SA-1 or Super FX in place of the flat memory: what RAM search, watches and cheats see
+    pub fn peek(&self, addr: u32) -> u8 {
+        if let Some(value) = self.sa1.as_ref().and_then(|sa1| sa1.peek(addr)) {
+            return value;
+        }
+        if let Some(value) = self.superfx.as_ref().and_then(|superfx| superfx.peek(addr)) {
+            return value;
+        }
+        self.work_ram[Self::mirror(addr) as usize]
+    }
+
+    pub fn poke(&mut self, addr: u32, val: u8) {
+        if let Some(sa1) = &mut self.sa1
+            && sa1.poke(addr, val)
+        {
+            return;
+        }
+        if let Some(superfx) = &mut self.superfx
+            && superfx.poke(addr, val)
+        {
+            return;
         }
+        self.work_ram[Self::mirror(addr) as usize] = val;
     }
 
     pub fn read_bytes(&self, addr: Range<usize>) -> &[u8] {
@@ -33,3 +328,81 @@ impl Bus {
         }
     }
 }
+
+impl CpuBus for Bus {
+    fn read_byte(&mut self, addr: u32) -> u8 {
+        Bus::read_byte(self, addr)
+    }
+
+    fn write_byte(&mut self, addr: u32, val: u8) {
+        Bus::write_byte(self, addr, val)
+    }
+
+    fn take_nmi(&mut self) -> bool {
+        Bus::take_nmi(self)
+    }
+
+    fn irq_pending(&self) -> bool {
+        Bus::irq_pending(self)
+    }
+}
+
+impl Bus {
+    // the pages of memory that differ from the freshly mapped cartridge
+    pub fn save_memory(&self, w: &mut StateWriter, power_on: &Bus) {
+        let pages = self
+            .work_ram
+            .chunks(STATE_PAGE_SIZE)
+            .zip(power_on.work_ram.chunks(STATE_PAGE_SIZE))
+            .enumerate()
+            .filter(|(_, (page, original))| page != original)
+            .map(|(index, (page, _))| (index, page))
+            .collect::<Vec<_>>();
+        w.u32(pages.len() as u32);
+        for (index, page) in pages {
+            w.u32(index as u32);
+            w.bytes(page);
+        }
+    }
+
+    pub fn load_memory(&mut self, r: &mut StateReader) -> Result<(), Box<dyn Error>> {
+        for _ in 0..r.u32()? {
+            let start = r.u32()? as usize * STATE_PAGE_SIZE;
+            let page = self
+                .work_ram
+                .get_mut(start..start + STATE_PAGE_SIZE)
+                .ok_or("invalid memory page in save state")?;
+            r.bytes(page)?;
+        }
+        Ok(())
+    }
+}
+
+impl SaveState for Bus {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.u8(self.mdr);
+        w.u64(self.master_cycles);
+        w.u64(self.dot_cycles);
+        w.bool(self.light_h.is_some());
+        w.u16(self.light_h.unwrap_or(0));
+        self.io.save_state(w);
+        self.dma.save_state(w);
+        self.ppu.save_state(w);
+        self.apu.save_state(w);
+        self.input.save_state(w);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), Box<dyn Error>> {
+        self.mdr = r.u8()?;
+        self.master_cycles = r.u64()?;
+        self.dot_cycles = r.u64()?;
+        let light = r.bool()?;
+        let light_h = r.u16()?;
+        self.light_h = light.then_some(light_h);
+        self.io.load_state(r)?;
+        self.dma.load_state(r)?;
+        self.ppu.load_state(r)?;
+        self.apu.load_state(r)?;
+        self.input.load_state(r)
+    }
+}
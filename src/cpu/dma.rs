@@ -0,0 +1,558 @@
+use crate::cpu::bus::Bus;
+use crate::movie::state::{SaveState, StateReader, StateWriter};
+use log::debug;
+use std::error::Error;
+
+pub const CHANNELS: usize = 8;
+
+pub const DMAP_B_TO_A: u8 = 0x1 << 7;
+pub const DMAP_HDMA_INDIRECT: u8 = 0x1 << 6;
+pub const DMAP_A_DECREMENT: u8 = 0x1 << 4;
+pub const DMAP_A_FIXED: u8 = 0x1 << 3;
+pub const DMAP_MODE: u8 = 0x7;
+
+pub const DMA_CYCLES_PER_BYTE: u64 = 8;
+pub const DMA_CYCLES_PER_CHANNEL: u64 = 8;
+pub const HDMA_SETUP_CYCLES: u64 = 18;
+pub const HDMA_INDIRECT_CYCLES: u64 = 16;
+
+// NTRLx bit 7 repeats the transfer on every line instead of only the first one
+pub const NTRL_REPEAT: u8 = 0x1 << 7;
+
+// B-bus address offsets written in one unit, indexed by the DMAP transfer mode
+pub const TRANSFER_PATTERNS: [&[u8]; 8] = [
+    &[0],
+    &[0, 1],
+    &[0, 0],
+    &[0, 0, 1, 1],
+    &[0, 1, 2, 3],
+    &[0, 1, 0, 1],
+    &[0, 0],
+    &[0, 0, 1, 1],
+];
+
+#[derive(Debug, Clone, Copy)]
+pub struct DmaChannel {
+    pub dmap: u8,
+    pub bbad: u8,
+    pub a1t: u16,
+    pub a1b: u8,
+    pub das: u16,
+    pub dasb: u8,
+    pub a2a: u16,
+    pub ntrl: u8,
+    pub unused: u8,
+    pub hdma_do_transfer: bool,
+    pub hdma_completed: bool,
+}
+
+impl DmaChannel {
+    pub fn new() -> Self {
+        Self {
+            dmap: 0xFF,
+            bbad: 0xFF,
+            a1t: 0xFFFF,
+            a1b: 0xFF,
+            das: 0xFFFF,
+            dasb: 0xFF,
+            a2a: 0xFFFF,
+            ntrl: 0xFF,
+            unused: 0xFF,
+            hdma_do_transfer: false,
+            hdma_completed: true,
+        }
+    }
+
+    pub fn pattern(&self) -> &'static [u8] {
+        TRANSFER_PATTERNS[(self.dmap & DMAP_MODE) as usize]
+    }
+
+    pub fn b_to_a(&self) -> bool {
+        self.dmap & DMAP_B_TO_A > 0
+    }
+
+    pub fn hdma_indirect(&self) -> bool {
+        self.dmap & DMAP_HDMA_INDIRECT > 0
+    }
+
+    // next HDMA data byte, from the indirect address or straight from the table
+    fn next_hdma_address(&mut self) -> u32 {
+        match self.hdma_indirect() {
+            true => {
+                let addr = ((self.dasb as u32) << 16) | self.das as u32;
+                self.das = self.das.wrapping_add(1);
+                addr
+            }
+            false => self.next_table_address(),
+        }
+    }
+
+    fn next_table_address(&mut self) -> u32 {
+        let addr = ((self.a1b as u32) << 16) | self.a2a as u32;
+        self.a2a = self.a2a.wrapping_add(1);
+        addr
+    }
+
+    // A-bus address for the next byte, stepping A1T according to DMAP bits 3-4
+    fn next_a_address(&mut self) -> u32 {
+        let addr = ((self.a1b as u32) << 16) | self.a1t as u32;
+        if self.dmap & DMAP_A_FIXED == 0 {
+            self.a1t = match self.dmap & DMAP_A_DECREMENT > 0 {
+                true => self.a1t.wrapping_sub(1),
+                false => self.a1t.wrapping_add(1),
+            };
+        }
+        addr
+    }
+}
+
+impl Default for DmaChannel {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// DMA/HDMA channel registers at $4300-$437F
+pub struct Dma {
+    pub channels: [DmaChannel; CHANNELS],
+    pub hdmaen: u8,
+    gdma_active: u8,
+    gdma_terminated: bool,
+}
+
+impl Dma {
+    pub fn new() -> Self {
+        Self {
+            channels: [DmaChannel::new(); CHANNELS],
+            hdmaen: 0,
+            gdma_active: 0,
+            gdma_terminated: false,
+        }
+    }
+
+    pub fn read_register(&self, addr: u16) -> Option<u8> {
+        let c = &self.channels[((addr >> 4) & 0x7) as usize];
+        match addr & 0xF {
+            0x0 => Some(c.dmap),
+            0x1 => Some(c.bbad),
+            0x2 => Some((c.a1t & 0xFF) as u8),
+            0x3 => Some((c.a1t >> 8) as u8),
+            0x4 => Some(c.a1b),
+            0x5 => Some((c.das & 0xFF) as u8),
+            0x6 => Some((c.das >> 8) as u8),
+            0x7 => Some(c.dasb),
+            0x8 => Some((c.a2a & 0xFF) as u8),
+            0x9 => Some((c.a2a >> 8) as u8),
+            0xA => Some(c.ntrl),
+            0xB | 0xF => Some(c.unused),
+            _ => None,
+        }
+    }
+
+    pub fn write_register(&mut self, addr: u16, val: u8) {
+        let c = &mut self.channels[((addr >> 4) & 0x7) as usize];
+        match addr & 0xF {
+            // DMAPx - DMA/HDMA Parameters
+            0x0 => c.dmap = val,
+            // BBADx - DMA/HDMA I/O-Bus Address (PPU-Bus aka B-Bus)
+            0x1 => c.bbad = val,
+            // A1TxL/A1TxH/A1Bx - HDMA Table Start Address / DMA Current Addr
+            0x2 => c.a1t = (c.a1t & 0xFF00) | val as u16,
+            0x3 => c.a1t = (c.a1t & 0xFF) | ((val as u16) << 8),
+            0x4 => c.a1b = val,
+            // DASxL/DASxH/DASBx - Indirect HDMA Address / DMA Byte-Counter
+            0x5 => c.das = (c.das & 0xFF00) | val as u16,
+            0x6 => c.das = (c.das & 0xFF) | ((val as u16) << 8),
+            0x7 => c.dasb = val,
+            // A2AxL/A2AxH - HDMA Table Current Address
+            0x8 => c.a2a = (c.a2a & 0xFF00) | val as u16,
+            0x9 => c.a2a = (c.a2a & 0xFF) | ((val as u16) << 8),
+            // NTRLx - HDMA Line-Counter
+            0xA => c.ntrl = val,
+            // UNUSEDx - Unused Byte (R/W)
+            0xB | 0xF => c.unused = val,
+            _ => {}
+        }
+    }
+}
+
+impl Default for Dma {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Bus {
+    // MDMAEN - Select General Purpose DMA Channel(s) and Start Transfer
+    pub fn start_dma(&mut self, mask: u8) {
+        if mask == 0 {
+            return;
+        }
+
+        // the CPU is halted on the next 8-cycle boundary, plus some setup time
+        self.tick(DMA_CYCLES_PER_BYTE - self.master_cycles % DMA_CYCLES_PER_BYTE);
+        self.tick(DMA_CYCLES_PER_CHANNEL);
+
+        for ch in 0..CHANNELS {
+            if mask & (0x1 << ch) == 0 {
+                continue;
+            }
+
+            debug!(
+                "DMA channel {} : {:?} (from 0x{:X})",
+                ch, self.dma.channels[ch], self.master_cycles
+            );
+            self.tick(DMA_CYCLES_PER_CHANNEL);
+
+            let pattern = self.dma.channels[ch].pattern();
+            let mut index = 0;
+            self.dma.gdma_active = 0x1 << ch;
+            self.dma.gdma_terminated = false;
+            loop {
+                let c = &mut self.dma.channels[ch];
+                let a_addr = c.next_a_address();
+                let b_addr = c.bbad.wrapping_add(pattern[index % pattern.len()]);
+                let b_to_a = c.b_to_a();
+                self.dma_transfer(a_addr, b_addr, b_to_a);
+                self.tick(DMA_CYCLES_PER_BYTE);
+                index += 1;
+
+                // a byte count of zero means 65536 bytes
+                let c = &mut self.dma.channels[ch];
+                c.das = c.das.wrapping_sub(1);
+                if c.das == 0 || self.dma.gdma_terminated {
+                    break;
+                }
+            }
+            self.dma.gdma_active = 0;
+        }
+    }
+
+    // reload the HDMA tables at the start of the frame
+    pub fn init_hdma(&mut self) {
+        if self.dma.hdmaen == 0 {
+            return;
+        }
+
+        self.tick(HDMA_SETUP_CYCLES);
+        for ch in 0..CHANNELS {
+            let c = &mut self.dma.channels[ch];
+            c.hdma_completed = true;
+            c.hdma_do_transfer = false;
+            if self.dma.hdmaen & (0x1 << ch) == 0 {
+                continue;
+            }
+
+            c.a2a = c.a1t;
+            c.hdma_completed = false;
+            self.terminate_gdma(ch);
+            self.load_hdma_entry(ch);
+        }
+    }
+
+    // transfer one unit per active channel, called once per scanline in H-Blank
+    pub fn run_hdma(&mut self) {
+        let active = (0..CHANNELS)
+            .filter(|ch| {
+                self.dma.hdmaen & (0x1 << ch) > 0 && !self.dma.channels[*ch].hdma_completed
+            })
+            .collect::<Vec<usize>>();
+        if active.is_empty() {
+            return;
+        }
+
+        self.tick(HDMA_SETUP_CYCLES);
+        for ch in active {
+            self.terminate_gdma(ch);
+            self.tick(DMA_CYCLES_PER_CHANNEL);
+
+            if self.dma.channels[ch].hdma_do_transfer {
+                let pattern = self.dma.channels[ch].pattern();
+                for offset in pattern {
+                    let c = &mut self.dma.channels[ch];
+                    let a_addr = c.next_hdma_address();
+                    let b_addr = c.bbad.wrapping_add(*offset);
+                    let b_to_a = c.b_to_a();
+                    self.dma_transfer(a_addr, b_addr, b_to_a);
+                    self.tick(DMA_CYCLES_PER_BYTE);
+                }
+            }
+
+            let c = &mut self.dma.channels[ch];
+            c.ntrl = c.ntrl.wrapping_sub(1);
+            c.hdma_do_transfer = c.ntrl & NTRL_REPEAT > 0;
+            if c.ntrl & !NTRL_REPEAT == 0 {
+                self.load_hdma_entry(ch);
+            }
+        }
+    }
+
+    // read the line counter (and the indirect address) of the next table entry
+    fn load_hdma_entry(&mut self, ch: usize) {
+        let addr = self.dma.channels[ch].next_table_address();
+        let ntrl = self.read_register_or_memory(addr).unwrap_or(self.mdr);
+        self.tick(DMA_CYCLES_PER_BYTE);
+
+        let c = &mut self.dma.channels[ch];
+        c.ntrl = ntrl;
+        c.hdma_do_transfer = true;
+        if c.hdma_indirect() {
+            let lo_addr = c.next_table_address();
+            let hi_addr = c.next_table_address();
+            let lo = self.read_register_or_memory(lo_addr).unwrap_or(self.mdr);
+            let hi = self.read_register_or_memory(hi_addr).unwrap_or(self.mdr);
+            self.dma.channels[ch].das = ((hi as u16) << 8) | lo as u16;
+            self.tick(HDMA_INDIRECT_CYCLES);
+        }
+
+        if ntrl == 0 {
+            self.dma.channels[ch].hdma_completed = true;
+        }
+    }
+
+    // HDMA has priority, a general DMA running on the same channel is cut short
+    fn terminate_gdma(&mut self, ch: usize) {
+        if self.dma.gdma_active & (0x1 << ch) > 0 {
+            debug!("HDMA channel {} terminates the running DMA", ch);
+            self.dma.gdma_terminated = true;
+        }
+    }
+
+    // move one byte between the A-bus and the B-bus ($2100-$21FF)
+    pub(crate) fn dma_transfer(&mut self, a_addr: u32, b_addr: u8, b_to_a: bool) {
+        let b_addr = 0x2100 | b_addr as u32;
+
+        // the A-bus side cannot reach the B-bus or the DMA registers themselves
+        let offset = a_addr & 0xFFFF;
+        let a_valid = (a_addr >> 16) & 0x40 > 0
+            || !((0x2100..0x2200).contains(&offset)
+                || (0x4300..0x4380).contains(&offset)
+                || offset == 0x420B
+                || offset == 0x420C);
+
+        if b_to_a {
+            let value = self.read_register_or_memory(b_addr).unwrap_or(self.mdr);
+            if a_valid {
+                self.write_register_or_memory(a_addr, value);
+            }
+            self.mdr = value;
+        } else {
+            let value = match a_valid {
+                true => self.read_register_or_memory(a_addr).unwrap_or(self.mdr),
+                false => self.mdr,
+            };
+            self.write_register_or_memory(b_addr, value);
+            self.mdr = value;
+        }
+    }
+}
+
+impl SaveState for Dma {
+    fn save_state(&self, w: &mut StateWriter) {
+        for c in &self.channels {
+            w.u8(c.dmap);
+            w.u8(c.bbad);
+            w.u16(c.a1t);
+            w.u8(c.a1b);
+            w.u16(c.das);
+            w.u8(c.dasb);
+            w.u16(c.a2a);
+            w.u8(c.ntrl);
+            w.u8(c.unused);
+            w.bool(c.hdma_do_transfer);
+            w.bool(c.hdma_completed);
+        }
+        w.u8(self.hdmaen);
+        w.u8(self.gdma_active);
+        w.bool(self.gdma_terminated);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), Box<dyn Error>> {
+        for c in &mut self.channels {
+            c.dmap = r.u8()?;
+            c.bbad = r.u8()?;
+            c.a1t = r.u16()?;
+            c.a1b = r.u8()?;
+            c.das = r.u16()?;
+            c.dasb = r.u8()?;
+            c.a2a = r.u16()?;
+            c.ntrl = r.u8()?;
+            c.unused = r.u8()?;
+            c.hdma_do_transfer = r.bool()?;
+            c.hdma_completed = r.bool()?;
+        }
+        self.hdmaen = r.u8()?;
+        self.gdma_active = r.u8()?;
+        self.gdma_terminated = r.bool()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn setup(b: &mut Bus, ch: u16, dmap: u8, bbad: u8, src: u32, count: u16) {
+        let base = 0x4300 | (ch << 4);
+        b.write_byte(base as u32, dmap);
+        b.write_byte(base as u32 + 1, bbad);
+        b.write_byte(base as u32 + 2, (src & 0xFF) as u8);
+        b.write_byte(base as u32 + 3, ((src >> 8) & 0xFF) as u8);
+        b.write_byte(base as u32 + 4, (src >> 16) as u8);
+        b.write_byte(base as u32 + 5, (count & 0xFF) as u8);
+        b.write_byte(base as u32 + 6, (count >> 8) as u8);
+    }
+
+    #[test]
+    fn vram_upload() {
+        let mut b = Bus::new();
+        for i in 0..8 {
+            b.write_byte(0x7F0000 + i, 0x10 + i as u8);
+        }
+        // VMAIN increment after high byte, VMADD = 0x1000
+        b.write_byte(0x2115, 0x80);
+        b.write_byte(0x2116, 0x00);
+        b.write_byte(0x2117, 0x10);
+        // mode 1 (VMDATAL/VMDATAH), A to B, increment
+        setup(&mut b, 3, 0x01, 0x18, 0x7F0000, 8);
+        let before = b.master_cycles;
+        b.write_byte(0x420B, 0x1 << 3);
+
+        assert_eq!(
+            &b.ppu.vram[0x2000..0x2008],
+            &[0x10, 0x11, 0x12, 0x13, 0x14, 0x15, 0x16, 0x17]
+        );
+        assert_eq!(b.ppu.vmadd, 0x1004);
+        assert_eq!(b.dma.channels[3].das, 0);
+        assert_eq!(b.dma.channels[3].a1t, 0x0008);
+        assert!(b.master_cycles - before >= 8 * DMA_CYCLES_PER_BYTE + DMA_CYCLES_PER_CHANNEL);
+    }
+
+    #[test]
+    fn cgram_fixed_source() {
+        let mut b = Bus::new();
+        b.write_byte(0x7E0100, 0x1F);
+        b.write_byte(0x2121, 0x0);
+        // mode 2 (CGDATA twice), fixed A-bus address
+        setup(&mut b, 0, 0x02 | DMAP_A_FIXED, 0x22, 0x7E0100, 4);
+        b.write_byte(0x420B, 0x1);
+
+        assert_eq!(b.ppu.color(0), 0x1F1F);
+        assert_eq!(b.ppu.color(1), 0x1F1F);
+        assert_eq!(b.dma.channels[0].a1t, 0x0100);
+    }
+
+    #[test]
+    fn b_to_a_decrement() {
+        let mut b = Bus::new();
+        // WRAM port as the B-bus source
+        b.write_byte(0x7E2000, 0xAA);
+        b.write_byte(0x7E2001, 0xBB);
+        b.write_byte(0x2181, 0x00);
+        b.write_byte(0x2182, 0x20);
+        b.write_byte(0x2183, 0x00);
+        setup(&mut b, 7, DMAP_B_TO_A | DMAP_A_DECREMENT, 0x80, 0x7F0010, 2);
+        b.write_byte(0x420B, 0x1 << 7);
+
+        assert_eq!(b.read_byte(0x7F0010), 0xAA);
+        assert_eq!(b.read_byte(0x7F000F), 0xBB);
+    }
+
+    fn setup_hdma(b: &mut Bus, ch: u16, dmap: u8, bbad: u8, table: u32) {
+        setup(b, ch, dmap, bbad, table, 0);
+    }
+
+    fn run_lines(b: &mut Bus, lines: u16) {
+        while b.ppu.v_counter != lines {
+            b.tick(4);
+        }
+    }
+
+    #[test]
+    fn hdma_direct() {
+        let mut b = Bus::new();
+        // 3 lines with a single transfer, then 2 lines repeating, then end of table
+        let table = [0x03, 0x0A, 0x82, 0x0B, 0x0C, 0x00];
+        for (i, v) in table.iter().enumerate() {
+            b.write_byte(0x7F1000 + i as u32, *v);
+        }
+        // write through the WRAM port so each transfer lands in memory
+        b.write_byte(0x2181, 0x00);
+        b.write_byte(0x2182, 0x30);
+        setup_hdma(&mut b, 2, 0x00, 0x80, 0x7F1000);
+        b.write_byte(0x420C, 0x1 << 2);
+
+        // the tables are loaded at the start of the next frame
+        while b.ppu.frame == 0 {
+            b.tick(4);
+        }
+        run_lines(&mut b, 10);
+
+        assert_eq!(b.read_bytes(0x7E3000..0x7E3004), &[0x0A, 0x0B, 0x0C, 0x00]);
+        assert!(b.dma.channels[2].hdma_completed);
+    }
+
+    #[test]
+    fn hdma_indirect() {
+        let mut b = Bus::new();
+        // 2 lines repeating, data at $7E:2000
+        let table = [0x82, 0x00, 0x20, 0x00];
+        for (i, v) in table.iter().enumerate() {
+            b.write_byte(0x7F1000 + i as u32, *v);
+        }
+        let data = [0x11, 0x22, 0x33, 0x44];
+        for (i, v) in data.iter().enumerate() {
+            b.write_byte(0x7E2000 + i as u32, *v);
+        }
+        b.write_byte(0x2181, 0x00);
+        b.write_byte(0x2182, 0x30);
+        // mode 2 writes the B-bus address twice per line
+        setup_hdma(&mut b, 5, DMAP_HDMA_INDIRECT | 0x02, 0x80, 0x7F1000);
+        b.write_byte(0x4357, 0x7E);
+        b.write_byte(0x420C, 0x1 << 5);
+
+        while b.ppu.frame == 0 {
+            b.tick(4);
+        }
+        run_lines(&mut b, 10);
+
+        assert_eq!(
+            b.read_bytes(0x7E3000..0x7E3005),
+            &[0x11, 0x22, 0x33, 0x44, 0x00]
+        );
+    }
+
+    #[test]
+    fn hdma_cuts_general_dma() {
+        let mut b = Bus::new();
+        for i in 0..0x200 {
+            b.write_byte(0x7F0000 + i, (i as u8) ^ 0x5A);
+        }
+        // a single entry covering 127 lines
+        b.write_byte(0x7F1000, 0x7F);
+        b.write_byte(0x7F1001, 0x00);
+        b.write_byte(0x7F1002, 0x00);
+        setup_hdma(&mut b, 0, 0x00, 0x80, 0x7F1000);
+        b.write_byte(0x420C, 0x1);
+
+        while b.ppu.frame == 0 {
+            b.tick(4);
+        }
+        run_lines(&mut b, 100);
+
+        // a long DMA on the same channel into the WRAM port, crossing the next H-Blank
+        b.write_byte(0x2181, 0x00);
+        b.write_byte(0x2182, 0x20);
+        b.write_byte(0x2183, 0x00);
+        setup(&mut b, 0, 0x00, 0x80, 0x7F0000, 0x8000);
+        b.write_byte(0x420B, 0x1);
+
+        // 126 bytes, 2 dots each, go out before HDMA runs at dot 274 of line 100
+        assert_eq!(b.ppu.v_counter, 100);
+        assert_eq!(b.dma.channels[0].das, 0x8000 - 126);
+        assert_eq!(b.dma.channels[0].a1t, 126);
+        let expected = (0..126).map(|i| (i as u8) ^ 0x5A).collect::<Vec<u8>>();
+        assert_eq!(b.read_bytes(0x7E2000..0x7E2000 + 126), expected);
+        assert_eq!(b.read_byte(0x7E2000 + 126), 0x00);
+    }
+}
@@ -0,0 +1,451 @@
+use crate::movie::state::{SaveState, StateReader, StateWriter};
+use crate::ppu::regs::Ppu;
+use log::debug;
+use std::error::Error;
+
+pub const CPU_VERSION: u8 = 0x02;
+
+pub const NMITIMEN_NMI_ENABLE: u8 = 0x1 << 7;
+pub const NMITIMEN_V_IRQ: u8 = 0x1 << 5;
+pub const NMITIMEN_H_IRQ: u8 = 0x1 << 4;
+pub const NMITIMEN_AUTO_JOYPAD: u8 = 0x1;
+
+pub const RDNMI_NMI_FLAG: u8 = 0x1 << 7;
+pub const TIMEUP_IRQ_FLAG: u8 = 0x1 << 7;
+
+pub const HVBJOY_VBLANK: u8 = 0x1 << 7;
+pub const HVBJOY_HBLANK: u8 = 0x1 << 6;
+pub const HVBJOY_AUTO_JOYPAD_BUSY: u8 = 0x1;
+
+pub const MEMSEL_FAST_ROM: u8 = 0x1;
+
+// the automatic joypad read takes about three scanlines
+pub const AUTO_JOYPAD_CYCLES: u64 = 4224;
+
+// number of ALU steps (one per CPU cycle) before the results are final
+pub const MULTIPLY_STEPS: u8 = 8;
+pub const DIVIDE_STEPS: u8 = 16;
+
+// CPU-side I/O registers at $4200-$421F
+pub struct Io {
+    pub nmitimen: u8,
+    pub htime: u16,
+    pub vtime: u16,
+    pub memsel: u8,
+    pub wmadd: u32,
+    pub wrio: u8,
+    // what the automatic read shifted in from the controller ports
+    pub joypads: [u16; 4],
+    nmi_flag: bool,
+    irq_flag: bool,
+    nmi_pending: bool,
+    wrmpya: u8,
+    wrmpyb: u8,
+    wrdiva: u16,
+    wrdivb: u8,
+    rddiv: u16,
+    rdmpy: u16,
+    alu_shift: u32,
+    multiply_steps: u8,
+    divide_steps: u8,
+    joy: [u16; 4],
+    auto_joypad_cycles: u64,
+}
+
+impl Io {
+    pub fn new() -> Self {
+        Self {
+            nmitimen: 0,
+            htime: 0x1FF,
+            vtime: 0x1FF,
+            memsel: 0,
+            wmadd: 0,
+            wrio: 0xFF,
+            joypads: [0; 4],
+            nmi_flag: false,
+            irq_flag: false,
+            nmi_pending: false,
+            wrmpya: 0xFF,
+            wrmpyb: 0xFF,
+            wrdiva: 0xFFFF,
+            wrdivb: 0xFF,
+            rddiv: 0,
+            rdmpy: 0,
+            alu_shift: 0,
+            multiply_steps: 0,
+            divide_steps: 0,
+            joy: [0; 4],
+            auto_joypad_cycles: 0,
+        }
+    }
+
+    // None means the register is write-only (open bus)
+    pub fn read_register(&mut self, addr: u16, ppu: &Ppu, open_bus: u8) -> Option<u8> {
+        match addr {
+            // RDNMI - V-Blank NMI Flag and CPU Version Number (read/ack)
+            0x4210 => {
+                let mut value = (open_bus & 0x70) | CPU_VERSION;
+                if self.nmi_flag {
+                    value |= RDNMI_NMI_FLAG;
+                }
+                self.nmi_flag = false;
+                Some(value)
+            }
+
+            // TIMEUP - H/V-Timer IRQ Flag (read/ack)
+            0x4211 => {
+                let mut value = open_bus & 0x7F;
+                if self.irq_flag {
+                    value |= TIMEUP_IRQ_FLAG;
+                }
+                self.irq_flag = false;
+                Some(value)
+            }
+
+            // HVBJOY - H/V-Blank flag and Joypad Busy flag
+            0x4212 => {
+                let mut value = open_bus & 0x3E;
+                if ppu.in_vblank() {
+                    value |= HVBJOY_VBLANK;
+                }
+                if ppu.in_hblank() {
+                    value |= HVBJOY_HBLANK;
+                }
+                if self.auto_joypad_cycles > 0 {
+                    value |= HVBJOY_AUTO_JOYPAD_BUSY;
+                }
+                Some(value)
+            }
+
+            // RDIO - Joypad Programmable I/O Port (Input)
+            0x4213 => Some(self.wrio),
+
+            // RDDIVL/RDDIVH - Unsigned Division Result (Quotient)
+            0x4214 => Some((self.rddiv & 0xFF) as u8),
+            0x4215 => Some((self.rddiv >> 8) as u8),
+
+            // RDMPYL/RDMPYH - Unsigned Division Remainder / Multiply Product
+            0x4216 => Some((self.rdmpy & 0xFF) as u8),
+            0x4217 => Some((self.rdmpy >> 8) as u8),
+
+            // JOY1L..JOY4H - Joypad Input Registers
+            0x4218..=0x421F => {
+                let pad = self.joy[((addr - 0x4218) >> 1) as usize];
+                match addr & 0x1 {
+                    0 => Some((pad & 0xFF) as u8),
+                    _ => Some((pad >> 8) as u8),
+                }
+            }
+
+            _ => None,
+        }
+    }
+
+    pub fn write_register(&mut self, addr: u16, val: u8) {
+        match addr {
+            // NMITIMEN - Interrupt Enable and Joypad Request
+            0x4200 => {
+                // enabling NMI in the middle of V-Blank (flag still set) fires it right away
+                if val & NMITIMEN_NMI_ENABLE > 0
+                    && self.nmitimen & NMITIMEN_NMI_ENABLE == 0
+                    && self.nmi_flag
+                {
+                    self.nmi_pending = true;
+                }
+
+                // disabling the H/V timer acknowledges a pending IRQ
+                if val & (NMITIMEN_H_IRQ | NMITIMEN_V_IRQ) == 0 {
+                    self.irq_flag = false;
+                }
+
+                self.nmitimen = val;
+            }
+
+            // WRIO - Joypad Programmable I/O Port (Output)
+            0x4201 => self.wrio = val,
+
+            // HTIMEL/HTIMEH - H-Count Timer Setting
+            0x4207 => self.htime = (self.htime & 0x100) | val as u16,
+            0x4208 => self.htime = (self.htime & 0xFF) | ((val as u16 & 0x1) << 8),
+
+            // WRMPYA - Set unsigned 8bit Multiplicand
+            0x4202 => self.wrmpya = val,
+
+            // WRMPYB - Set unsigned 8bit Multiplier and Start Multiplication
+            0x4203 => {
+                if self.alu_busy() {
+                    return;
+                }
+                self.rdmpy = 0;
+                self.wrmpyb = val;
+                self.rddiv = ((val as u16) << 8) | self.wrmpya as u16;
+                self.alu_shift = val as u32;
+                self.multiply_steps = MULTIPLY_STEPS;
+            }
+
+            // WRDIVL/WRDIVH - Set unsigned 16bit Dividend
+            0x4204 => self.wrdiva = (self.wrdiva & 0xFF00) | val as u16,
+            0x4205 => self.wrdiva = (self.wrdiva & 0xFF) | ((val as u16) << 8),
+
+            // WRDIVB - Set unsigned 8bit Divisor and Start Division
+            0x4206 => {
+                if self.alu_busy() {
+                    return;
+                }
+                self.rdmpy = self.wrdiva;
+                self.wrdivb = val;
+                self.rddiv = self.wrdiva;
+                self.alu_shift = (val as u32) << 16;
+                self.divide_steps = DIVIDE_STEPS;
+            }
+
+            // VTIMEL/VTIMEH - V-Count Timer Setting
+            0x4209 => self.vtime = (self.vtime & 0x100) | val as u16,
+            0x420A => self.vtime = (self.vtime & 0xFF) | ((val as u16 & 0x1) << 8),
+
+            // MEMSEL - Memory-2 Waitstate Control
+            0x420D => self.memsel = val & MEMSEL_FAST_ROM,
+
+            _ => debug!("I/O write 0x{:X} = 0x{:X} (ignored)", addr, val),
+        }
+    }
+
+    pub fn vblank_start(&mut self) {
+        self.nmi_flag = true;
+        if self.nmitimen & NMITIMEN_NMI_ENABLE > 0 {
+            self.nmi_pending = true;
+        }
+
+        if self.nmitimen & NMITIMEN_AUTO_JOYPAD > 0 {
+            self.joy = self.joypads;
+            self.auto_joypad_cycles = AUTO_JOYPAD_CYCLES;
+        }
+    }
+
+    pub fn fast_rom(&self) -> bool {
+        self.memsel & MEMSEL_FAST_ROM > 0
+    }
+
+    // runs alongside the CPU, once per bus cycle
+    pub fn clock(&mut self, cycles: u64) {
+        self.auto_joypad_cycles = self.auto_joypad_cycles.saturating_sub(cycles);
+
+        if self.multiply_steps > 0 {
+            // shift-and-add, one multiplier bit per step
+            self.multiply_steps -= 1;
+            if self.rddiv & 0x1 > 0 {
+                self.rdmpy = self.rdmpy.wrapping_add(self.alu_shift as u16);
+            }
+            self.rddiv >>= 1;
+            self.alu_shift <<= 1;
+        }
+
+        if self.divide_steps > 0 {
+            // restoring division, one quotient bit per step
+            self.divide_steps -= 1;
+            self.rddiv <<= 1;
+            self.alu_shift >>= 1;
+            if self.rdmpy as u32 >= self.alu_shift {
+                self.rdmpy -= self.alu_shift as u16;
+                self.rddiv |= 0x1;
+            }
+        }
+    }
+
+    fn alu_busy(&self) -> bool {
+        self.multiply_steps > 0 || self.divide_steps > 0
+    }
+
+    // WMADDL/WMADDM/WMADDH - WRAM Address (17 bits)
+    pub fn write_wram_address(&mut self, addr: u16, val: u8) {
+        self.wmadd = match addr {
+            0x2181 => (self.wmadd & 0x1FF00) | val as u32,
+            0x2182 => (self.wmadd & 0x100FF) | ((val as u32) << 8),
+            _ => (self.wmadd & 0xFFFF) | ((val as u32 & 0x1) << 16),
+        };
+    }
+
+    // WMDATA accesses go to $7E0000 + WMADD, post-incrementing the address
+    pub fn next_wram_address(&mut self) -> u32 {
+        let addr = 0x7E0000 + self.wmadd;
+        self.wmadd = (self.wmadd + 1) & 0x1FFFF;
+        addr
+    }
+
+    pub fn frame_start(&mut self) {
+        self.nmi_flag = false;
+    }
+
+    // compare the beam position against HTIME/VTIME, called once per dot
+    pub fn poll_irq(&mut self, h: u16, v: u16) {
+        let h_enabled = self.nmitimen & NMITIMEN_H_IRQ > 0;
+        let v_enabled = self.nmitimen & NMITIMEN_V_IRQ > 0;
+        let hit = match (h_enabled, v_enabled) {
+            (true, false) => h == self.htime,
+            (false, true) => v == self.vtime && h == 0,
+            (true, true) => v == self.vtime && h == self.htime,
+            (false, false) => false,
+        };
+
+        if hit {
+            self.irq_flag = true;
+        }
+    }
+
+    // NMI is edge triggered, the CPU consumes it once
+    pub fn take_nmi(&mut self) -> bool {
+        let pending = self.nmi_pending;
+        self.nmi_pending = false;
+        pending
+    }
+
+    // IRQ is level triggered, it stays asserted until TIMEUP is read
+    pub fn irq_line(&self) -> bool {
+        self.irq_flag
+    }
+}
+
+impl Default for Io {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SaveState for Io {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.u8(self.nmitimen);
+        w.u16(self.htime);
+        w.u16(self.vtime);
+        w.u8(self.memsel);
+        w.u32(self.wmadd);
+        w.u8(self.wrio);
+        for pad in self.joypads.iter().chain(&self.joy) {
+            w.u16(*pad);
+        }
+        w.bool(self.nmi_flag);
+        w.bool(self.irq_flag);
+        w.bool(self.nmi_pending);
+        w.u8(self.wrmpya);
+        w.u8(self.wrmpyb);
+        w.u16(self.wrdiva);
+        w.u8(self.wrdivb);
+        w.u16(self.rddiv);
+        w.u16(self.rdmpy);
+        w.u32(self.alu_shift);
+        w.u8(self.multiply_steps);
+        w.u8(self.divide_steps);
+        w.u64(self.auto_joypad_cycles);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), Box<dyn Error>> {
+        self.nmitimen = r.u8()?;
+        self.htime = r.u16()?;
+        self.vtime = r.u16()?;
+        self.memsel = r.u8()?;
+        self.wmadd = r.u32()?;
+        self.wrio = r.u8()?;
+        for pad in self.joypads.iter_mut().chain(&mut self.joy) {
+            *pad = r.u16()?;
+        }
+        self.nmi_flag = r.bool()?;
+        self.irq_flag = r.bool()?;
+        self.nmi_pending = r.bool()?;
+        self.wrmpya = r.u8()?;
+        self.wrmpyb = r.u8()?;
+        self.wrdiva = r.u16()?;
+        self.wrdivb = r.u8()?;
+        self.rddiv = r.u16()?;
+        self.rdmpy = r.u16()?;
+        self.alu_shift = r.u32()?;
+        self.multiply_steps = r.u8()?;
+        self.divide_steps = r.u8()?;
+        self.auto_joypad_cycles = r.u64()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run_alu(io: &mut Io) {
+        for _ in 0..DIVIDE_STEPS {
+            io.clock(6);
+        }
+    }
+
+    #[test]
+    fn multiply() {
+        let mut io = Io::new();
+        io.write_register(0x4202, 0xC8);
+        io.write_register(0x4203, 0x2D);
+        run_alu(&mut io);
+        let ppu = Ppu::new(crate::ppu::timing::VideoStandard::Ntsc);
+        let lo = io.read_register(0x4216, &ppu, 0).unwrap() as u16;
+        let hi = io.read_register(0x4217, &ppu, 0).unwrap() as u16;
+        assert_eq!((hi << 8) | lo, 0xC8 * 0x2D);
+    }
+
+    #[test]
+    fn divide() {
+        let mut io = Io::new();
+        io.write_register(0x4204, 0x39);
+        io.write_register(0x4205, 0x30);
+        io.write_register(0x4206, 0x7B);
+        run_alu(&mut io);
+        assert_eq!(io.rddiv, 0x3039 / 0x7B);
+        assert_eq!(io.rdmpy, 0x3039 % 0x7B);
+
+        // division by zero gives $FFFF and leaves the dividend as remainder
+        io.write_register(0x4206, 0x0);
+        run_alu(&mut io);
+        assert_eq!(io.rddiv, 0xFFFF);
+        assert_eq!(io.rdmpy, 0x3039);
+    }
+
+    #[test]
+    fn result_is_delayed() {
+        let mut io = Io::new();
+        io.write_register(0x4202, 0xFF);
+        io.write_register(0x4203, 0xFF);
+        io.clock(6);
+        assert_ne!(io.rdmpy, 0xFE01);
+        run_alu(&mut io);
+        assert_eq!(io.rdmpy, 0xFE01);
+    }
+
+    #[test]
+    fn busy_unit_ignores_writes() {
+        let mut io = Io::new();
+        io.write_register(0x4202, 0x10);
+        io.write_register(0x4203, 0x11);
+        io.clock(6);
+        let partial = io.rdmpy;
+
+        // a second start while the multiplier runs leaves its product alone
+        io.write_register(0x4203, 0x20);
+        assert_eq!(io.rdmpy, partial);
+        run_alu(&mut io);
+        assert_eq!(io.rdmpy, 0x110);
+    }
+
+    #[test]
+    fn auto_joypad() {
+        let mut io = Io::new();
+        io.joypads[0] = 0x8080;
+        io.write_register(0x4200, NMITIMEN_AUTO_JOYPAD);
+        io.vblank_start();
+        let ppu = Ppu::new(crate::ppu::timing::VideoStandard::Ntsc);
+        assert_eq!(
+            io.read_register(0x4212, &ppu, 0).unwrap() & HVBJOY_AUTO_JOYPAD_BUSY,
+            0x1
+        );
+        io.clock(AUTO_JOYPAD_CYCLES);
+        assert_eq!(
+            io.read_register(0x4212, &ppu, 0).unwrap() & HVBJOY_AUTO_JOYPAD_BUSY,
+            0x0
+        );
+        assert_eq!(io.read_register(0x4218, &ppu, 0), Some(0x80));
+        assert_eq!(io.read_register(0x4219, &ppu, 0), Some(0x80));
+    }
+}
@@ -1,8 +1,9 @@
 use log::debug;
 
 use crate::cpu::alu::Cpu;
+use crate::cpu::bus::CpuBus;
 
-impl Cpu {
+impl<B: CpuBus> Cpu<B> {
     pub fn op_asl(&mut self, opcode: u8) {
         let mut value = match opcode {
             0x0A => self.reg_a.data,
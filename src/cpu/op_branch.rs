@@ -2,8 +2,9 @@ use log::debug;
 
 use crate::cpu::alu::Cpu;
 use crate::cpu::alu::*;
+use crate::cpu::bus::CpuBus;
 
-impl Cpu {
+impl<B: CpuBus> Cpu<B> {
     pub fn op_branch(&mut self, opcode: u8) {
         let oldpc = self.pc;
         let taken: Result<(bool, String), String> = match opcode {
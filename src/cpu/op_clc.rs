@@ -1,7 +1,8 @@
 use crate::cpu::alu::Cpu;
+use crate::cpu::bus::CpuBus;
 use log::debug;
 
-impl Cpu {
+impl<B: CpuBus> Cpu<B> {
     pub fn op_clc(&mut self, opcode: u8) {
         self.flag_c(false);
         debug!(
@@ -1,8 +1,9 @@
 use log::debug;
 
 use crate::cpu::alu::{Cpu, S_IRQ_DISABLE};
+use crate::cpu::bus::CpuBus;
 
-impl Cpu {
+impl<B: CpuBus> Cpu<B> {
     pub fn op_cli(&mut self, opcode: u8) {
         self.flag(S_IRQ_DISABLE, false);
         debug!("[0x{:X}] CLI : FLAGS={:b}", opcode, self.reg_p);
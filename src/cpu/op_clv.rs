@@ -1,8 +1,9 @@
 use log::debug;
 
 use crate::cpu::alu::{Cpu, S_OVERFLOW};
+use crate::cpu::bus::CpuBus;
 
-impl Cpu {
+impl<B: CpuBus> Cpu<B> {
     pub fn op_clv(&mut self, opcode: u8) {
         self.flag(S_OVERFLOW, false);
         debug!("[0x{:X}] CLV : FLAGS={:b}", opcode, self.reg_p);
@@ -1,8 +1,9 @@
 use log::debug;
 
 use crate::cpu::alu::{AddressMode, Cpu};
+use crate::cpu::bus::CpuBus;
 
-impl Cpu {
+impl<B: CpuBus> Cpu<B> {
     pub fn op_cpy(&mut self, opcode: u8) {
         let operand = match opcode {
             0xC0 => self.fetch(AddressMode::Immediate, true),
@@ -1,11 +1,12 @@
 use log::debug;
 
+use crate::cpu::bus::CpuBus;
 use crate::cpu::{
     alu::{AddressMode, Cpu},
     bits::Word,
 };
 
-impl Cpu {
+impl<B: CpuBus> Cpu<B> {
     pub fn op_inc(&mut self, opcode: u8) {
         let mut value = match opcode {
             0xE6 => self.fetch(AddressMode::ZeroPage, true),
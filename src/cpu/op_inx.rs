@@ -1,8 +1,9 @@
 use log::debug;
 
 use crate::cpu::alu::Cpu;
+use crate::cpu::bus::CpuBus;
 
-impl Cpu {
+impl<B: CpuBus> Cpu<B> {
     pub fn op_inx(&mut self, opcode: u8) {
         self.reg_x += 1;
         self.flag_nz(self.reg_x);
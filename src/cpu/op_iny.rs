@@ -1,8 +1,9 @@
 use log::debug;
 
 use crate::cpu::alu::Cpu;
+use crate::cpu::bus::CpuBus;
 
-impl Cpu {
+impl<B: CpuBus> Cpu<B> {
     pub fn op_iny(&mut self, opcode: u8) {
         self.reg_y += 1;
         self.flag_nz(self.reg_y);
@@ -1,8 +1,9 @@
 use log::debug;
 
 use crate::cpu::alu::Cpu;
+use crate::cpu::bus::CpuBus;
 
-impl Cpu {
+impl<B: CpuBus> Cpu<B> {
     pub fn op_jml(&mut self, opcode: u8) {
         // self.incr_pc();
         let addr_lo = self.bus.read_byte(self.pbr_pc());
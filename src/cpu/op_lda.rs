@@ -2,8 +2,9 @@ use log::debug;
 
 use crate::cpu::alu::Cpu;
 use crate::cpu::alu::*;
+use crate::cpu::bus::CpuBus;
 
-impl Cpu {
+impl<B: CpuBus> Cpu<B> {
     pub fn op_lda(&mut self, opcode: u8) {
         let sixteen_bits_mode = (self.reg_p & S_ACCUMULATOR_MEMORY) == 0;
         let oldpc = self.pc;
@@ -1,7 +1,8 @@
 use crate::cpu::alu::{AddressMode, Cpu, S_INDEX_REGISTERS};
+use crate::cpu::bus::CpuBus;
 use log::debug;
 
-impl Cpu {
+impl<B: CpuBus> Cpu<B> {
     pub fn op_ldx(&mut self, opcode: u8) {
         let sixteen_bits_mode = (self.reg_p & S_INDEX_REGISTERS) == 0;
         self.reg_x = match opcode {
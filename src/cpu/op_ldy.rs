@@ -1,8 +1,9 @@
 use log::debug;
 
 use crate::cpu::alu::{AddressMode, Cpu, S_INDEX_REGISTERS};
+use crate::cpu::bus::CpuBus;
 
-impl Cpu {
+impl<B: CpuBus> Cpu<B> {
     pub fn op_ldy(&mut self, opcode: u8) {
         let sixteen_bits_mode = (self.reg_p & S_INDEX_REGISTERS) == 0;
         let oldpc = self.pc;
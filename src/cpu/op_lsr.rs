@@ -1,8 +1,9 @@
 use log::debug;
 
 use crate::cpu::alu::{Cpu, S_NEGATIVE, S_RESULT_ZERO};
+use crate::cpu::bus::CpuBus;
 
-impl Cpu {
+impl<B: CpuBus> Cpu<B> {
     pub fn op_lsr(&mut self, opcode: u8) {
         let mut value = match opcode {
             0x4A => self.reg_a.data,
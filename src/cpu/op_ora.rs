@@ -1,8 +1,9 @@
 use log::debug;
 
 use crate::cpu::alu::{AddressMode, Cpu};
+use crate::cpu::bus::CpuBus;
 
-impl Cpu {
+impl<B: CpuBus> Cpu<B> {
     pub fn op_ora(&mut self, opcode: u8) {
         let value = match opcode {
             0x09 => self.fetch(AddressMode::Immediate, true),
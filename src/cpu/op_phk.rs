@@ -1,8 +1,9 @@
 use log::debug;
 
 use crate::cpu::alu::Cpu;
+use crate::cpu::bus::CpuBus;
 
-impl Cpu {
+impl<B: CpuBus> Cpu<B> {
     pub fn op_phk(&mut self, opcode: u8) {
         let oldsp = self.sp;
         self.bus.write_byte(self.sp, self.reg_pb);
@@ -2,8 +2,9 @@ use log::debug;
 
 use crate::cpu::alu::Cpu;
 use crate::cpu::bits::Word;
+use crate::cpu::bus::CpuBus;
 
-impl Cpu {
+impl<B: CpuBus> Cpu<B> {
     pub fn op_phy(&mut self, opcode: u8) {
         let value = Word { data: self.reg_y };
         let oldsp = self.sp;
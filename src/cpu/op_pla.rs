@@ -2,8 +2,9 @@ use log::debug;
 
 use crate::cpu::alu::{Cpu, S_ACCUMULATOR_MEMORY};
 use crate::cpu::bits::Word;
+use crate::cpu::bus::CpuBus;
 
-impl Cpu {
+impl<B: CpuBus> Cpu<B> {
     pub fn op_pla(&mut self, opcode: u8) {
         let sixteen_bits_mode = (self.reg_p & S_ACCUMULATOR_MEMORY) == 0;
 
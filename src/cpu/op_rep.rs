@@ -1,7 +1,8 @@
 use crate::cpu::alu::Cpu;
+use crate::cpu::bus::CpuBus;
 use log::debug;
 
-impl Cpu {
+impl<B: CpuBus> Cpu<B> {
     pub fn op_rep(&mut self, opcode: u8) {
         let oldpc = self.pc;
         self.incr_pc();
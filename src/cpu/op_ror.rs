@@ -1,8 +1,9 @@
 use crate::cpu::alu::{AddressMode, Cpu, S_ACCUMULATOR_MEMORY, S_CARRY};
 use crate::cpu::bits::Word;
+use crate::cpu::bus::CpuBus;
 use log::debug;
 
-impl Cpu {
+impl<B: CpuBus> Cpu<B> {
     pub fn op_ror(&mut self, opcode: u8) {
         let sixteen_bits_mode = (self.reg_p & S_ACCUMULATOR_MEMORY) == 0;
         let oldpc = self.pc;
@@ -1,8 +1,9 @@
 use log::debug;
 
 use crate::cpu::alu::Cpu;
+use crate::cpu::bus::CpuBus;
 
-impl Cpu {
+impl<B: CpuBus> Cpu<B> {
     pub fn op_rti(&mut self, opcode: u8) {
         let oldpc = self.pc;
         let oldp = self.reg_p;
@@ -20,9 +21,12 @@ impl Cpu {
         self.sp += 1;
         let pch = self.bus.read_byte(self.sp);
 
-        // pop PBR
-        self.sp += 1;
-        let pbr = self.bus.read_byte(self.sp);
+        // pop PBR (native mode only)
+        let mut pbr = self.reg_pb;
+        if !self.emulation {
+            self.sp += 1;
+            pbr = self.bus.read_byte(self.sp);
+        }
 
         // Save new PC
         let newpc = Self::make_word(pcl, pch);
@@ -30,7 +34,9 @@ impl Cpu {
             "[0x{:X}:0x{:X}] RTI : OLD_PC=0x{:X} NEW_PC=0x{:X} OLD_P=0x{:X} NEW_P=0x{:X} OLD_PBR=0x{:X} NEW_PBR=0x{:X}",
             oldpc, opcode, self.pc, newpc, oldp, p, oldpb, pbr,
         );
-        self.pc = newpc;
+        // unlike RTS/RTL the stacked PC is the next instruction itself,
+        // step back one byte since the main loop increments PC afterwards
+        self.pc = newpc.wrapping_sub(1);
         self.reg_p = p;
         self.reg_pb = pbr;
     }
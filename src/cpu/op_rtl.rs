@@ -1,8 +1,9 @@
 use log::debug;
 
 use crate::cpu::alu::Cpu;
+use crate::cpu::bus::CpuBus;
 
-impl Cpu {
+impl<B: CpuBus> Cpu<B> {
     pub fn op_rtl(&mut self, opcode: u8) {
         let oldpc = self.pc;
         let oldpb = self.reg_pb;
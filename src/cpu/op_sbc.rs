@@ -3,8 +3,9 @@ use log::debug;
 use crate::cpu::alu::AddressMode;
 use crate::cpu::alu::Cpu;
 use crate::cpu::alu::*;
+use crate::cpu::bus::CpuBus;
 
-impl Cpu {
+impl<B: CpuBus> Cpu<B> {
     pub fn op_sbc(&mut self, opcode: u8) {
         let mut value = match opcode {
             0xE9 => self.fetch(AddressMode::Immediate, true),
@@ -1,8 +1,9 @@
 use log::debug;
 
 use crate::cpu::alu::Cpu;
+use crate::cpu::bus::CpuBus;
 
-impl Cpu {
+impl<B: CpuBus> Cpu<B> {
     pub fn op_sec(&mut self, opcode: u8) {
         self.flag_c(true);
         debug!("[0x{:X}] SEC : FLAGS={:b}", opcode, self.reg_p);
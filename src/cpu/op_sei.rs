@@ -1,7 +1,8 @@
 use crate::cpu::alu::{Cpu, S_IRQ_DISABLE};
+use crate::cpu::bus::CpuBus;
 use log::debug;
 
-impl Cpu {
+impl<B: CpuBus> Cpu<B> {
     pub fn op_sei(&mut self, opcode: u8) {
         self.flag(S_IRQ_DISABLE, true);
         debug!(
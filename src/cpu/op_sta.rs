@@ -1,7 +1,8 @@
 use crate::cpu::alu::{AddressMode, Cpu, S_ACCUMULATOR_MEMORY};
+use crate::cpu::bus::CpuBus;
 use log::debug;
 
-impl Cpu {
+impl<B: CpuBus> Cpu<B> {
     pub fn op_sta(&mut self, opcode: u8) {
         let sixteen_bits_mode = (self.reg_p & S_ACCUMULATOR_MEMORY) == 0;
         let value = &self.reg_a.clone();
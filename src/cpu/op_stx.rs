@@ -1,10 +1,11 @@
+use crate::cpu::bus::CpuBus;
 use crate::cpu::{
     alu::{AddressMode, Cpu, S_ACCUMULATOR_MEMORY},
     bits::Word,
 };
 use log::debug;
 
-impl Cpu {
+impl<B: CpuBus> Cpu<B> {
     pub fn op_stx(&mut self, opcode: u8) {
         let sixteen_bits_mode = (self.reg_p & S_ACCUMULATOR_MEMORY) == 0;
         let value = &Word { data: self.reg_x };
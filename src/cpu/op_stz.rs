@@ -1,11 +1,12 @@
 use log::debug;
 
+use crate::cpu::bus::CpuBus;
 use crate::cpu::{
     alu::{AddressMode, Cpu, S_ACCUMULATOR_MEMORY},
     bits::Word,
 };
 
-impl Cpu {
+impl<B: CpuBus> Cpu<B> {
     pub fn op_stz(&mut self, opcode: u8) {
         let sixteen_bits_mode = (self.reg_p & S_ACCUMULATOR_MEMORY) == 0;
         let zero_value = &Word { data: 0x0 };
@@ -1,7 +1,8 @@
 use crate::cpu::alu::Cpu;
+use crate::cpu::bus::CpuBus;
 use log::debug;
 
-impl Cpu {
+impl<B: CpuBus> Cpu<B> {
     pub fn op_tax(&mut self, opcode: u8) {
         let oldx = self.reg_x;
         self.reg_x = self.reg_a.data;
@@ -1,7 +1,8 @@
 use crate::cpu::alu::Cpu;
+use crate::cpu::bus::CpuBus;
 use log::debug;
 
-impl Cpu {
+impl<B: CpuBus> Cpu<B> {
     pub fn op_tcd(&mut self, opcode: u8) {
         let oldd = self.reg_d;
         self.reg_d = self.reg_a.data;
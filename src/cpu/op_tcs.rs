@@ -1,7 +1,8 @@
 use crate::cpu::alu::Cpu;
+use crate::cpu::bus::CpuBus;
 use log::debug;
 
-impl Cpu {
+impl<B: CpuBus> Cpu<B> {
     pub fn op_tcs(&mut self, opcode: u8) {
         let oldsp = self.sp;
         self.sp = self.reg_a.data as u32;
@@ -1,8 +1,9 @@
 use log::debug;
 
 use crate::cpu::alu::Cpu;
+use crate::cpu::bus::CpuBus;
 
-impl Cpu {
+impl<B: CpuBus> Cpu<B> {
     pub fn op_txs(&mut self, opcode: u8) {
         self.sp = self.reg_x as u32;
         debug!("[0x{:X}:0x{:X}] TXS : SP=0x{:X}", self.pc, opcode, self.sp);
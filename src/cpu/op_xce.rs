@@ -1,7 +1,8 @@
 use crate::cpu::alu::{Cpu, S_ACCUMULATOR_MEMORY, S_BREAK_INSTRUCTION, S_CARRY};
+use crate::cpu::bus::CpuBus;
 use log::debug;
 
-impl Cpu {
+impl<B: CpuBus> Cpu<B> {
     pub fn op_xce(&mut self, opcode: u8) {
         // save carry
         let carry = self.reg_p & S_CARRY > 0;
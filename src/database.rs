@@ -0,0 +1,239 @@
+use crate::checksum::{crc32, sha256};
+use crate::coprocessor::upd7725::firmware::Model;
+use std::error::Error;
+use std::fs;
+use std::sync::OnceLock;
+
+const BUILTIN_DATABASE: &str = include_str!("database.txt");
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Peripheral {
+    Mouse,
+    SuperScope,
+    Justifier,
+    Multitap,
+}
+
+impl Peripheral {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "mouse" => Some(Peripheral::Mouse),
+            "superscope" => Some(Peripheral::SuperScope),
+            "justifier" => Some(Peripheral::Justifier),
+            "multitap" => Some(Peripheral::Multitap),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Key {
+    Crc32(u32),
+    Sha256([u8; 32]),
+    // the header title, with `_` for spaces and a `*` standing for anything
+    Title(String),
+}
+
+impl Key {
+    fn parse(text: &str) -> Option<Self> {
+        match text.split_once(':')? {
+            ("crc32", hash) => u32::from_str_radix(hash, 16).ok().map(Key::Crc32),
+            ("sha256", hash) => hex::decode(hash).ok()?.try_into().ok().map(Key::Sha256),
+            ("title", title) if !title.is_empty() => Some(Key::Title(title.replace('_', " "))),
+            _ => None,
+        }
+    }
+}
+
+fn title_matches(pattern: &str, title: &str) -> bool {
+    match pattern.split_once('*') {
+        Some((start, end)) => {
+            title.len() >= start.len() + end.len()
+                && title.starts_with(start)
+                && title.ends_with(end)
+        }
+        None => pattern == title,
+    }
+}
+
+// the header fields a database entry replaces, as their header byte codes
+#[derive(Debug, Clone, Default)]
+pub struct Overrides {
+    pub map_mode: Option<u8>,
+    pub chipset: Option<u8>,
+    pub ram_size: Option<u32>,
+    pub region: Option<u8>,
+    pub peripherals: Vec<Peripheral>,
+    // which DSP program a DSP cartridge runs, the header only says it has one
+    pub dsp: Option<Model>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Entry {
+    pub key: Key,
+    pub overrides: Overrides,
+}
+
+pub struct Database {
+    pub entries: Vec<Entry>,
+}
+
+fn parse_byte(value: &str) -> Option<u8> {
+    match value.strip_prefix("0x") {
+        Some(hex) => u8::from_str_radix(hex, 16).ok(),
+        None => value.parse().ok(),
+    }
+}
+
+impl Database {
+    pub fn parse(text: &str) -> Result<Self, Box<dyn Error>> {
+        let mut entries = Vec::new();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let invalid = |what: &str| format!("line {}: invalid {}", number + 1, what);
+
+            let mut fields = line.split_whitespace();
+            let key = fields
+                .next()
+                .and_then(Key::parse)
+                .ok_or_else(|| invalid("key"))?;
+            let mut overrides = Overrides::default();
+            for field in fields {
+                let (name, value) = field.split_once('=').ok_or_else(|| invalid(field))?;
+                match name {
+                    "map" => {
+                        overrides.map_mode = Some(parse_byte(value).ok_or_else(|| invalid(field))?)
+                    }
+                    "chipset" => {
+                        overrides.chipset = Some(parse_byte(value).ok_or_else(|| invalid(field))?)
+                    }
+                    "ram" => overrides.ram_size = Some(value.parse().map_err(|_| invalid(field))?),
+                    "region" => {
+                        overrides.region = Some(parse_byte(value).ok_or_else(|| invalid(field))?)
+                    }
+                    "peripherals" => {
+                        for name in value.split(',') {
+                            let peripheral =
+                                Peripheral::from_name(name).ok_or_else(|| invalid(name))?;
+                            overrides.peripherals.push(peripheral);
+                        }
+                    }
+                    "dsp" => {
+                        overrides.dsp = Some(Model::from_name(value).ok_or_else(|| invalid(field))?)
+                    }
+                    _ => return Err(invalid(name).into()),
+                }
+            }
+            entries.push(Entry { key, overrides });
+        }
+        Ok(Self { entries })
+    }
+
+    // parsed the first time a ROM is looked up
+    pub fn builtin() -> &'static Self {
+        static BUILTIN: OnceLock<Database> = OnceLock::new();
+        BUILTIN.get_or_init(|| Self::parse(BUILTIN_DATABASE).expect("built-in database is valid"))
+    }
+
+    pub fn open(path: &str) -> Result<Self, Box<dyn Error>> {
+        Self::parse(&fs::read_to_string(path)?).map_err(|e| format!("{}: {}", path, e).into())
+    }
+
+    pub fn lookup(&self, crc32: u32, sha256: &[u8; 32]) -> Option<&Entry> {
+        self.entries.iter().find(|entry| match &entry.key {
+            Key::Crc32(hash) => *hash == crc32,
+            Key::Sha256(hash) => hash == sha256,
+            Key::Title(_) => false,
+        })
+    }
+
+    pub fn lookup_title(&self, title: &str) -> Option<&Entry> {
+        self.entries.iter().find(|entry| match &entry.key {
+            Key::Title(pattern) => title_matches(pattern, title),
+            _ => false,
+        })
+    }
+}
+
+// the entry for a ROM image, from the user's database first and then the built-in one
+pub fn find(data: &[u8], user: Option<&Database>) -> Option<Entry> {
+    let crc32 = crc32(data);
+    let sha256 = sha256(data);
+    user.into_iter()
+        .chain([Database::builtin()])
+        .find_map(|database| database.lookup(crc32, &sha256).cloned())
+}
+
+// the entry for a header title, only for ROMs that no entry in either database has the hash of
+pub fn find_title(title: &str, user: Option<&Database>) -> Option<Entry> {
+    user.into_iter()
+        .chain([Database::builtin()])
+        .find_map(|database| database.lookup_title(title).cloned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_and_lookup() {
+        let database = Database::parse(
+            "# comment\n\
+             crc32:CBF43926 map=0x21 ram=8192 # trailing\n\
+             sha256:ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad region=2 peripherals=mouse,multitap\n",
+        )
+        .unwrap();
+        assert_eq!(database.entries.len(), 2);
+
+        let entry = find(b"123456789", Some(&database)).unwrap();
+        assert_eq!(entry.overrides.map_mode, Some(0x21));
+        assert_eq!(entry.overrides.ram_size, Some(8192));
+        assert_eq!(entry.overrides.chipset, None);
+
+        let entry = find(b"abc", Some(&database)).unwrap();
+        assert_eq!(entry.overrides.region, Some(0x02));
+        assert_eq!(
+            entry.overrides.peripherals,
+            [Peripheral::Mouse, Peripheral::Multitap]
+        );
+
+        assert!(find(b"nothing", Some(&database)).is_none());
+    }
+
+    #[test]
+    fn title_keys() {
+        let database = Database::parse(
+            "title:MY_GAME ram=2048
+             title:SD*GX region=0
+",
+        )
+        .unwrap();
+        let entry = find_title("MY GAME", Some(&database)).unwrap();
+        assert_eq!(entry.overrides.ram_size, Some(2048));
+        assert!(find_title("MY GAME 2", Some(&database)).is_none());
+        assert!(find_title("SD\u{FFFD}\u{FFFD}GX", Some(&database)).is_some());
+        assert!(find_title("SDGX", Some(&database)).is_some());
+        assert!(find_title("SGX", Some(&database)).is_none());
+        // title entries never match by hash
+        assert!(find(b"MY GAME", Some(&database)).is_none());
+
+        // the built-in entries are there for anyone
+        let entry = find_title("MARIO PAINT", None).unwrap();
+        assert_eq!(entry.overrides.peripherals, [Peripheral::Mouse]);
+        let entry = find_title("TOP GEAR 3000", None).unwrap();
+        assert_eq!(entry.overrides.dsp, Some(Model::Dsp4));
+    }
+
+    #[test]
+    fn invalid_lines() {
+        assert!(Database::parse("md5:00 map=0x20\n").is_err());
+        assert!(Database::parse("crc32:00000000 speed=fast\n").is_err());
+        assert!(Database::parse("crc32:00000000 peripherals=lightpen\n").is_err());
+        assert!(Database::parse("crc32:00000000 dsp=dsp5\n").is_err());
+        // parsed once and shared
+        assert!(std::ptr::eq(Database::builtin(), Database::builtin()));
+    }
+}
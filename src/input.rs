@@ -0,0 +1,8 @@
+pub mod controller;
+pub mod joypad;
+pub mod justifier;
+pub mod mouse;
+pub mod multitap;
+pub mod ports;
+pub mod script;
+pub mod super_scope;
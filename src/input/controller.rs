@@ -0,0 +1,45 @@
+use crate::movie::state::{SaveState, StateReader, StateWriter};
+use std::any::Any;
+use std::error::Error;
+
+// What a device sees of its controller port: the latch line (OUT0, bit 0 of $4016), a clock
+// pulse on every read of $4016/$4017, and IOBit (WRIO bit 6 for port 1, bit 7 for port 2).
+pub trait Controller: SaveState {
+    fn latch(&mut self, high: bool);
+
+    // clock the device once, data1 is returned in bit 0 and data2 in bit 1
+    fn clock(&mut self) -> u8;
+
+    fn set_iobit(&mut self, _high: bool) {}
+
+    // for light guns, the pixel (x, y) whose beam the sensor sees this frame
+    fn light_position(&self) -> Option<(u16, u16)> {
+        None
+    }
+
+    // lets the host get at the concrete device to drive it
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+// an empty port, both data lines read as 0
+pub struct Unplugged;
+
+impl Controller for Unplugged {
+    fn latch(&mut self, _high: bool) {}
+
+    fn clock(&mut self) -> u8 {
+        0x0
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+impl SaveState for Unplugged {
+    fn save_state(&self, _w: &mut StateWriter) {}
+
+    fn load_state(&mut self, _r: &mut StateReader) -> Result<(), Box<dyn Error>> {
+        Ok(())
+    }
+}
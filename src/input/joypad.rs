@@ -0,0 +1,115 @@
+use crate::input::controller::Controller;
+use crate::movie::state::{SaveState, StateReader, StateWriter};
+use std::any::Any;
+use std::error::Error;
+
+// buttons in the order they are shifted out, the same layout as JOY1H/JOY1L
+pub const BUTTON_B: u16 = 0x1 << 15;
+pub const BUTTON_Y: u16 = 0x1 << 14;
+pub const BUTTON_SELECT: u16 = 0x1 << 13;
+pub const BUTTON_START: u16 = 0x1 << 12;
+pub const BUTTON_UP: u16 = 0x1 << 11;
+pub const BUTTON_DOWN: u16 = 0x1 << 10;
+pub const BUTTON_LEFT: u16 = 0x1 << 9;
+pub const BUTTON_RIGHT: u16 = 0x1 << 8;
+pub const BUTTON_A: u16 = 0x1 << 7;
+pub const BUTTON_X: u16 = 0x1 << 6;
+pub const BUTTON_L: u16 = 0x1 << 5;
+pub const BUTTON_R: u16 = 0x1 << 4;
+
+// the low 4 bits are the device ID, all zero for a standard pad
+pub const BUTTONS_MASK: u16 = 0xFFF0;
+
+// standard 12 button pad, a 16-bit parallel-in serial-out shift register
+pub struct Joypad {
+    pub buttons: u16,
+    shift: u16,
+    latched: bool,
+}
+
+impl Joypad {
+    pub fn new() -> Self {
+        Self {
+            buttons: 0,
+            shift: 0,
+            latched: false,
+        }
+    }
+
+    pub fn set_buttons(&mut self, buttons: u16) {
+        self.buttons = buttons & BUTTONS_MASK;
+    }
+}
+
+impl Default for Joypad {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Controller for Joypad {
+    fn latch(&mut self, high: bool) {
+        self.latched = high;
+        if high {
+            self.shift = self.buttons;
+        }
+    }
+
+    fn clock(&mut self) -> u8 {
+        // while latched the register keeps reloading, so B comes out every time
+        if self.latched {
+            return (self.buttons >> 15) as u8;
+        }
+
+        // the serial input is tied high, after 16 bits every read returns 1
+        let bit = (self.shift >> 15) as u8;
+        self.shift = (self.shift << 1) | 0x1;
+        bit
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+impl SaveState for Joypad {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.u16(self.buttons);
+        w.u16(self.shift);
+        w.bool(self.latched);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), Box<dyn Error>> {
+        self.buttons = r.u16()?;
+        self.shift = r.u16()?;
+        self.latched = r.bool()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shifts_out_buttons() {
+        let mut pad = Joypad::new();
+        pad.set_buttons(BUTTON_B | BUTTON_START | BUTTON_R);
+        pad.latch(true);
+        assert_eq!(pad.clock(), 1);
+        assert_eq!(pad.clock(), 1);
+        pad.latch(false);
+
+        let bits: Vec<u8> = (0..18).map(|_| pad.clock()).collect();
+        assert_eq!(bits, [1, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 1, 1]);
+    }
+
+    #[test]
+    fn buttons_change_after_latch() {
+        let mut pad = Joypad::new();
+        pad.latch(true);
+        pad.latch(false);
+        pad.set_buttons(BUTTON_B);
+        assert_eq!(pad.clock(), 0);
+    }
+}
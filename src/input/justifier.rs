@@ -0,0 +1,170 @@
+use crate::input::controller::Controller;
+use crate::input::ports::{LIGHT_GUN_HEIGHT, LIGHT_GUN_WIDTH};
+use crate::movie::state::{SaveState, StateReader, StateWriter};
+use std::any::Any;
+use std::error::Error;
+
+// the 16 bits after 12 zeros: the 1110 ID, then a fixed 01010101
+const JUSTIFIER_ID: u32 = 0xE55;
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Gun {
+    pub x: i32,
+    pub y: i32,
+    pub trigger: bool,
+    pub start: bool,
+}
+
+impl Gun {
+    fn offscreen(&self) -> bool {
+        !(0..LIGHT_GUN_WIDTH).contains(&self.x) || !(0..LIGHT_GUN_HEIGHT).contains(&self.y)
+    }
+}
+
+// Konami Justifier for port 2, optionally with a second gun chained to the first. With two
+// guns only one is sensed per frame, alternating on every latch.
+pub struct Justifier {
+    pub guns: [Gun; 2],
+    pub chained: bool,
+    active: usize,
+    report: u32,
+    bits: u8,
+    latched: bool,
+}
+
+impl Justifier {
+    pub fn new(chained: bool) -> Self {
+        Self {
+            guns: [Gun::default(); 2],
+            chained,
+            active: 0,
+            report: 0,
+            bits: 0,
+            latched: false,
+        }
+    }
+
+    pub fn active_gun(&self) -> usize {
+        self.active
+    }
+
+    // 32 bits: 12 zeros, the ID, then triggers, starts and the active gun
+    fn build_report(&self) -> u32 {
+        let [gun1, gun2] = self.guns;
+        let gun2_connected = self.chained;
+        let buttons = ((gun1.trigger as u32) << 7)
+            | (((gun2.trigger && gun2_connected) as u32) << 6)
+            | ((gun1.start as u32) << 5)
+            | (((gun2.start && gun2_connected) as u32) << 4)
+            | ((self.active as u32) << 3);
+        (JUSTIFIER_ID << 8) | buttons
+    }
+}
+
+impl Controller for Justifier {
+    fn latch(&mut self, high: bool) {
+        if high && !self.latched {
+            if self.chained {
+                self.active ^= 1;
+            }
+            self.report = self.build_report();
+            self.bits = 0;
+        }
+        self.latched = high;
+    }
+
+    fn clock(&mut self) -> u8 {
+        if self.bits >= 32 {
+            return 0x1;
+        }
+        let bit = ((self.report >> (31 - self.bits)) & 0x1) as u8;
+        if !self.latched {
+            self.bits += 1;
+        }
+        bit
+    }
+
+    fn light_position(&self) -> Option<(u16, u16)> {
+        let gun = self.guns[self.active];
+        match gun.offscreen() {
+            true => None,
+            false => Some((gun.x as u16, gun.y as u16)),
+        }
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+impl SaveState for Justifier {
+    fn save_state(&self, w: &mut StateWriter) {
+        for gun in &self.guns {
+            w.i32(gun.x);
+            w.i32(gun.y);
+            w.bool(gun.trigger);
+            w.bool(gun.start);
+        }
+        w.bool(self.chained);
+        w.u8(self.active as u8);
+        w.u32(self.report);
+        w.u8(self.bits);
+        w.bool(self.latched);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), Box<dyn Error>> {
+        for gun in &mut self.guns {
+            gun.x = r.i32()?;
+            gun.y = r.i32()?;
+            gun.trigger = r.bool()?;
+            gun.start = r.bool()?;
+        }
+        self.chained = r.bool()?;
+        self.active = r.u8()? as usize % 2;
+        self.report = r.u32()?;
+        self.bits = r.u8()?;
+        self.latched = r.bool()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read_32(justifier: &mut Justifier) -> u32 {
+        justifier.latch(true);
+        justifier.latch(false);
+        (0..32).fold(0, |report, _| (report << 1) | justifier.clock() as u32)
+    }
+
+    #[test]
+    fn report_and_alternating_guns() {
+        let mut justifier = Justifier::new(true);
+        justifier.guns[0] = Gun {
+            x: 10,
+            y: 20,
+            trigger: true,
+            start: false,
+        };
+        justifier.guns[1] = Gun {
+            x: 200,
+            y: 100,
+            trigger: false,
+            start: true,
+        };
+
+        assert_eq!(read_32(&mut justifier), 0x000E_5598);
+        assert_eq!(justifier.light_position(), Some((200, 100)));
+        assert_eq!(read_32(&mut justifier), 0x000E_5590);
+        assert_eq!(justifier.light_position(), Some((10, 20)));
+    }
+
+    #[test]
+    fn single_gun() {
+        let mut justifier = Justifier::new(false);
+        justifier.guns[1].trigger = true;
+        assert_eq!(read_32(&mut justifier), 0x000E_5500);
+        assert_eq!(read_32(&mut justifier), 0x000E_5500);
+    }
+}
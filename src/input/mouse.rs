@@ -0,0 +1,157 @@
+use crate::input::controller::Controller;
+use crate::movie::state::{SaveState, StateReader, StateWriter};
+use std::any::Any;
+use std::error::Error;
+
+pub const SPEED_SLOW: u8 = 0;
+pub const SPEED_NORMAL: u8 = 1;
+pub const SPEED_FAST: u8 = 2;
+
+// SNES Mouse. The report is 32 bits: 8 zero bits, then right/left buttons, the speed and the
+// 0001 signature, then Y and X as sign and magnitude (up and left are negative).
+pub struct Mouse {
+    pub left: bool,
+    pub right: bool,
+    pub speed: u8,
+    // motion since the last latch
+    dx: i32,
+    dy: i32,
+    report: u32,
+    latched: bool,
+}
+
+impl Mouse {
+    pub fn new() -> Self {
+        Self {
+            left: false,
+            right: false,
+            speed: SPEED_SLOW,
+            dx: 0,
+            dy: 0,
+            report: 0,
+            latched: false,
+        }
+    }
+
+    pub fn move_by(&mut self, dx: i32, dy: i32) {
+        self.dx += dx;
+        self.dy += dy;
+    }
+
+    pub fn set_buttons(&mut self, left: bool, right: bool) {
+        self.left = left;
+        self.right = right;
+    }
+
+    // the higher speeds scale the motion by 1.5 and 2
+    fn axis(&self, delta: i32) -> u32 {
+        let magnitude = (delta.unsigned_abs() * (self.speed as u32 + 2) / 2).min(0x7F);
+        match delta < 0 {
+            true => 0x80 | magnitude,
+            false => magnitude,
+        }
+    }
+
+    fn build_report(&self) -> u32 {
+        let buttons = ((self.right as u32) << 7)
+            | ((self.left as u32) << 6)
+            | ((self.speed as u32) << 4)
+            | 0x1;
+        (buttons << 16) | (self.axis(self.dy) << 8) | self.axis(self.dx)
+    }
+}
+
+impl Default for Mouse {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Controller for Mouse {
+    fn latch(&mut self, high: bool) {
+        if high && !self.latched {
+            self.report = self.build_report();
+            self.dx = 0;
+            self.dy = 0;
+        }
+        self.latched = high;
+    }
+
+    fn clock(&mut self) -> u8 {
+        // clocking while latched cycles through the three speeds
+        if self.latched {
+            self.speed = (self.speed + 1) % 3;
+            return 0x0;
+        }
+
+        let bit = (self.report >> 31) as u8;
+        self.report = (self.report << 1) | 0x1;
+        bit
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+impl SaveState for Mouse {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.bool(self.left);
+        w.bool(self.right);
+        w.u8(self.speed);
+        w.i32(self.dx);
+        w.i32(self.dy);
+        w.u32(self.report);
+        w.bool(self.latched);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), Box<dyn Error>> {
+        self.left = r.bool()?;
+        self.right = r.bool()?;
+        self.speed = r.u8()?;
+        self.dx = r.i32()?;
+        self.dy = r.i32()?;
+        self.report = r.u32()?;
+        self.latched = r.bool()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read_32(mouse: &mut Mouse) -> u32 {
+        mouse.latch(true);
+        mouse.latch(false);
+        (0..32).fold(0, |report, _| (report << 1) | mouse.clock() as u32)
+    }
+
+    #[test]
+    fn report_layout() {
+        let mut mouse = Mouse::new();
+        mouse.set_buttons(true, false);
+        mouse.move_by(-5, 3);
+        assert_eq!(read_32(&mut mouse), 0x0041_0385);
+        // motion is cleared by the latch
+        assert_eq!(read_32(&mut mouse), 0x0041_0000);
+        assert_eq!(mouse.clock(), 1);
+    }
+
+    #[test]
+    fn speed_cycles_while_latched() {
+        let mut mouse = Mouse::new();
+        mouse.latch(true);
+        mouse.clock();
+        assert_eq!(mouse.speed, SPEED_NORMAL);
+        mouse.clock();
+        assert_eq!(mouse.speed, SPEED_FAST);
+        mouse.clock();
+        assert_eq!(mouse.speed, SPEED_SLOW);
+        mouse.clock();
+        mouse.latch(false);
+
+        mouse.move_by(100, 10);
+        assert_eq!(read_32(&mut mouse), 0x0011_0F7F);
+    }
+}
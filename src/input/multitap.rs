@@ -0,0 +1,112 @@
+use crate::input::controller::Controller;
+use crate::input::joypad::Joypad;
+use crate::movie::state::{SaveState, StateReader, StateWriter};
+use std::any::Any;
+use std::error::Error;
+
+// Super Multitap, four pads on one port. IOBit selects which pair drives the data lines:
+// high for the first two pads (players 2 and 3), low for the others (players 4 and 5).
+pub struct Multitap {
+    pub pads: [Joypad; 4],
+    iobit: bool,
+    latched: bool,
+}
+
+impl Multitap {
+    pub fn new() -> Self {
+        Self {
+            pads: [Joypad::new(), Joypad::new(), Joypad::new(), Joypad::new()],
+            iobit: true,
+            latched: false,
+        }
+    }
+}
+
+impl Default for Multitap {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Controller for Multitap {
+    fn latch(&mut self, high: bool) {
+        self.latched = high;
+        for pad in self.pads.iter_mut() {
+            pad.latch(high);
+        }
+    }
+
+    fn clock(&mut self) -> u8 {
+        // data2 held high during the latch is how games detect the multitap
+        if self.latched {
+            return (self.pads[0].clock() & 0x1) | 0x2;
+        }
+
+        let (first, second) = match self.iobit {
+            true => (0, 1),
+            false => (2, 3),
+        };
+        (self.pads[first].clock() & 0x1) | ((self.pads[second].clock() & 0x1) << 1)
+    }
+
+    fn set_iobit(&mut self, high: bool) {
+        self.iobit = high;
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+impl SaveState for Multitap {
+    fn save_state(&self, w: &mut StateWriter) {
+        for pad in &self.pads {
+            pad.save_state(w);
+        }
+        w.bool(self.iobit);
+        w.bool(self.latched);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), Box<dyn Error>> {
+        for pad in &mut self.pads {
+            pad.load_state(r)?;
+        }
+        self.iobit = r.bool()?;
+        self.latched = r.bool()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::joypad::{BUTTON_A, BUTTON_B};
+
+    fn read_16(tap: &mut Multitap) -> (u16, u16) {
+        let mut data1 = 0;
+        let mut data2 = 0;
+        for _ in 0..16 {
+            let bits = tap.clock();
+            data1 = (data1 << 1) | (bits & 0x1) as u16;
+            data2 = (data2 << 1) | ((bits >> 1) & 0x1) as u16;
+        }
+        (data1, data2)
+    }
+
+    #[test]
+    fn pairs_selected_by_iobit() {
+        let mut tap = Multitap::new();
+        tap.pads[0].set_buttons(BUTTON_B);
+        tap.pads[1].set_buttons(BUTTON_A);
+        tap.pads[2].set_buttons(BUTTON_A | BUTTON_B);
+        tap.pads[3].set_buttons(0);
+
+        tap.latch(true);
+        assert_eq!(tap.clock() & 0x2, 0x2);
+        tap.latch(false);
+        assert_eq!(read_16(&mut tap), (BUTTON_B, BUTTON_A));
+
+        tap.set_iobit(false);
+        assert_eq!(read_16(&mut tap), (BUTTON_A | BUTTON_B, 0));
+    }
+}
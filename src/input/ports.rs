@@ -0,0 +1,251 @@
+use crate::input::controller::Controller;
+use crate::input::joypad::Joypad;
+use crate::input::multitap::Multitap;
+use crate::movie::state::{SaveState, StateReader, StateWriter};
+use log::warn;
+use std::error::Error;
+
+pub const PORTS: usize = 2;
+
+// WRIO bits wired to IOBit on each port
+pub const WRIO_PORT1_IOBIT: u8 = 0x1 << 6;
+pub const WRIO_PORT2_IOBIT: u8 = 0x1 << 7;
+
+// the area a light gun can aim at, in pixels
+pub const LIGHT_GUN_WIDTH: i32 = 256;
+pub const LIGHT_GUN_HEIGHT: i32 = 224;
+// H counter at which the beam reaches pixel 0, as seen by the sensor
+pub const LIGHT_GUN_H_OFFSET: u16 = 40;
+
+// the two controller ports, both start out with a standard pad
+pub struct ControllerPorts {
+    pub ports: [Box<dyn Controller>; PORTS],
+    latch: bool,
+}
+
+impl ControllerPorts {
+    pub fn new() -> Self {
+        Self {
+            ports: [Box::new(Joypad::new()), Box::new(Joypad::new())],
+            latch: false,
+        }
+    }
+
+    pub fn connect(&mut self, port: usize, device: Box<dyn Controller>) {
+        self.ports[port] = device;
+    }
+
+    // the device in a port, if it is a T
+    pub fn device_mut<T: 'static>(&mut self, port: usize) -> Option<&mut T> {
+        self.ports[port].as_any_mut().downcast_mut::<T>()
+    }
+
+    // players 1 and 2 are the pads in each port, or 2 to 5 go through a multitap in port 2
+    pub fn set_buttons(&mut self, player: usize, buttons: u16) {
+        if player == 0 {
+            if let Some(pad) = self.device_mut::<Joypad>(0) {
+                pad.set_buttons(buttons);
+            }
+            return;
+        }
+
+        if let Some(tap) = self.device_mut::<Multitap>(1) {
+            if let Some(pad) = tap.pads.get_mut(player - 1) {
+                pad.set_buttons(buttons);
+            }
+            return;
+        }
+
+        match (player, self.device_mut::<Joypad>(1)) {
+            (1, Some(pad)) => pad.set_buttons(buttons),
+            _ => warn!("no pad for player {}, input dropped", player + 1),
+        }
+    }
+
+    // make room for a movie's players: more than two need a multitap, which takes the place of
+    // the pad in port 2
+    pub fn connect_players(&mut self, players: usize) -> Result<(), Box<dyn Error>> {
+        if players <= PORTS || self.device_mut::<Multitap>(1).is_some() {
+            return Ok(());
+        }
+        match self.device_mut::<Joypad>(1) {
+            Some(_) => {
+                self.connect(1, Box::new(Multitap::new()));
+                Ok(())
+            }
+            None => Err(format!(
+                "{} players need a multitap in port 2, the cartridge uses another device there",
+                players
+            )
+            .into()),
+        }
+    }
+
+    // JOYWR ($4016) - bit 0 drives the latch line of both ports
+    pub fn write_latch(&mut self, val: u8) {
+        self.latch = val & 0x1 > 0;
+        for port in self.ports.iter_mut() {
+            port.latch(self.latch);
+        }
+    }
+
+    // JOYA/JOYB ($4016/$4017) - clock a port, data1 and data2 in bits 0 and 1
+    pub fn read_port(&mut self, port: usize) -> u8 {
+        self.ports[port].clock() & 0x3
+    }
+
+    // WRIO ($4201) - bits 6 and 7 go to IOBit on ports 1 and 2
+    pub fn write_iobits(&mut self, wrio: u8) {
+        self.ports[0].set_iobit(wrio & WRIO_PORT1_IOBIT > 0);
+        self.ports[1].set_iobit(wrio & WRIO_PORT2_IOBIT > 0);
+    }
+
+    // the H counter at which a light gun in port 2 pulls IOBit low on line `v`, only port 2
+    // IOBit is wired to the PPU counter latch
+    pub fn light_target(&self, v: u16) -> Option<u16> {
+        match self.ports[1].light_position() {
+            Some((x, y)) if y + 1 == v => Some(x + LIGHT_GUN_H_OFFSET),
+            _ => None,
+        }
+    }
+
+    // the automatic read at V-Blank: a latch pulse then 16 clocks on both ports, giving
+    // JOY1/JOY2 from data1 and JOY3/JOY4 from data2
+    pub fn auto_read(&mut self) -> [u16; 4] {
+        let latch = self.latch as u8;
+        self.write_latch(0x1);
+        self.write_latch(0x0);
+
+        let mut joy = [0u16; 4];
+        for _ in 0..16 {
+            let port1 = self.read_port(0);
+            let port2 = self.read_port(1);
+            joy[0] = (joy[0] << 1) | (port1 & 0x1) as u16;
+            joy[1] = (joy[1] << 1) | (port2 & 0x1) as u16;
+            joy[2] = (joy[2] << 1) | (port1 >> 1) as u16;
+            joy[3] = (joy[3] << 1) | (port2 >> 1) as u16;
+        }
+
+        self.write_latch(latch);
+        joy
+    }
+}
+
+impl Default for ControllerPorts {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// each device is stored with its length, so a state can't be loaded into other devices
+impl SaveState for ControllerPorts {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.bool(self.latch);
+        for port in &self.ports {
+            let mut device = StateWriter { data: Vec::new() };
+            port.save_state(&mut device);
+            w.u32(device.data.len() as u32);
+            w.bytes(&device.data);
+        }
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), Box<dyn Error>> {
+        self.latch = r.bool()?;
+        for (index, port) in self.ports.iter_mut().enumerate() {
+            let len = r.u32()? as usize;
+            let start = r.position();
+            port.load_state(r)?;
+            if r.position() - start != len {
+                return Err(format!(
+                    "save state has another device in controller port {}",
+                    index + 1
+                )
+                .into());
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu::bus::Bus;
+    use crate::input::controller::Unplugged;
+    use crate::input::joypad::{BUTTON_A, BUTTON_START, BUTTON_X};
+    use crate::input::super_scope::SuperScope;
+    use crate::ppu::regs::STAT78_LATCHED;
+    use crate::ppu::timing::MASTER_CYCLES_PER_DOT;
+
+    #[test]
+    fn auto_read_with_multitap() {
+        let mut ports = ControllerPorts::new();
+        ports.connect(1, Box::new(Multitap::new()));
+        for player in 0..5 {
+            ports.set_buttons(player, BUTTON_A >> player);
+        }
+
+        // WRIO is $FF after reset, so the first pair is on the data lines
+        ports.write_iobits(0xFF);
+        assert_eq!(ports.auto_read(), [BUTTON_A, BUTTON_X, 0, BUTTON_A >> 2]);
+    }
+
+    #[test]
+    fn manual_read_through_registers() {
+        let mut bus = Bus::new();
+        bus.set_joypad(0, BUTTON_A);
+        bus.write_byte(0x4016, 0x1);
+        bus.write_byte(0x4016, 0x0);
+
+        let bits: Vec<u8> = (0..16).map(|_| bus.read_byte(0x4016) & 0x1).collect();
+        assert_eq!(bits, [0, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(bus.read_byte(0x4016) & 0x1, 1);
+        assert_eq!(bus.read_byte(0x4017) & 0x1F, 0x1C);
+    }
+
+    #[test]
+    fn unplugged_port() {
+        let mut ports = ControllerPorts::new();
+        ports.connect(1, Box::new(Unplugged));
+        ports.set_buttons(0, BUTTON_START);
+        ports.set_buttons(1, BUTTON_START);
+        assert_eq!(ports.auto_read(), [BUTTON_START, 0, 0, 0]);
+    }
+
+    #[test]
+    fn super_scope_latches_counters() {
+        let mut bus = Bus::new();
+        bus.input.connect(1, Box::new(SuperScope::new()));
+        bus.input.device_mut::<SuperScope>(1).unwrap().aim(100, 50);
+
+        // with WRIO bit 7 low the gun cannot pull IOBit, nothing is latched
+        bus.write_byte(0x4201, 0x7F);
+        bus.read_byte(0x213F);
+        while bus.ppu.v_counter != 60 {
+            bus.tick(MASTER_CYCLES_PER_DOT);
+        }
+        assert_eq!(bus.read_byte(0x213F) & STAT78_LATCHED, 0);
+
+        bus.write_byte(0x4201, 0xFF);
+        while bus.ppu.v_counter != 50 {
+            bus.tick(MASTER_CYCLES_PER_DOT);
+        }
+        while bus.ppu.v_counter != 60 {
+            bus.tick(MASTER_CYCLES_PER_DOT);
+        }
+        assert_ne!(bus.read_byte(0x213F) & STAT78_LATCHED, 0);
+        let h = bus.read_byte(0x213C) as u16 | ((bus.read_byte(0x213C) as u16 & 0x1) << 8);
+        let v = bus.read_byte(0x213D) as u16 | ((bus.read_byte(0x213D) as u16 & 0x1) << 8);
+        assert_eq!((h, v), (100 + LIGHT_GUN_H_OFFSET, 51));
+    }
+
+    #[test]
+    fn wrio_falling_edge_latches_counters() {
+        let mut bus = Bus::new();
+        bus.read_byte(0x213F);
+        bus.write_byte(0x4201, 0x7F);
+        assert_ne!(bus.read_byte(0x213F) & STAT78_LATCHED, 0);
+        bus.write_byte(0x4201, 0x7F);
+        assert_eq!(bus.read_byte(0x213F) & STAT78_LATCHED, 0);
+    }
+}
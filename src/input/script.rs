@@ -0,0 +1,82 @@
+use crate::input::joypad::*;
+
+pub const BUTTON_NAMES: [(&str, u16); 12] = [
+    ("B", BUTTON_B),
+    ("Y", BUTTON_Y),
+    ("Select", BUTTON_SELECT),
+    ("Start", BUTTON_START),
+    ("Up", BUTTON_UP),
+    ("Down", BUTTON_DOWN),
+    ("Left", BUTTON_LEFT),
+    ("Right", BUTTON_RIGHT),
+    ("A", BUTTON_A),
+    ("X", BUTTON_X),
+    ("L", BUTTON_L),
+    ("R", BUTTON_R),
+];
+
+// "A+Start", or "-" for nothing
+pub fn parse_buttons(text: &str) -> Result<u16, String> {
+    if text == "-" {
+        return Ok(0);
+    }
+    text.split('+').try_fold(0, |buttons, name| {
+        match BUTTON_NAMES.iter().find(|(button, _)| *button == name) {
+            Some((_, bit)) => Ok(buttons | bit),
+            None => Err(format!("unknown button {}", name)),
+        }
+    })
+}
+
+// buttons held on one pad from a frame on, until the next entry
+pub struct InputScript {
+    pub entries: Vec<(u64, u16)>,
+}
+
+impl InputScript {
+    // "0:A,30:-" holds A for the first 30 frames, "-" is no input at all
+    pub fn parse(text: &str) -> Result<Self, String> {
+        if text == "-" {
+            return Ok(Self {
+                entries: Vec::new(),
+            });
+        }
+        let mut entries = text
+            .split(',')
+            .map(|entry| {
+                let (frame, buttons) = entry
+                    .split_once(':')
+                    .ok_or(format!("input entry {} is not frame:buttons", entry))?;
+                let frame = frame
+                    .parse()
+                    .map_err(|_| format!("invalid frame {}", frame))?;
+                Ok((frame, parse_buttons(buttons)?))
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+        entries.sort_by_key(|(frame, _)| *frame);
+        Ok(Self { entries })
+    }
+
+    pub fn buttons_at(&self, frame: u64) -> u16 {
+        self.entries
+            .iter()
+            .rev()
+            .find(|(at, _)| *at <= frame)
+            .map_or(0, |(_, buttons)| *buttons)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_script() {
+        let script = InputScript::parse("30:-,0:A+Start,10:Up").unwrap();
+        assert_eq!(script.buttons_at(0), BUTTON_A | BUTTON_START);
+        assert_eq!(script.buttons_at(15), BUTTON_UP);
+        assert_eq!(script.buttons_at(30), 0);
+        assert!(InputScript::parse("0:Turbo").is_err());
+        assert!(InputScript::parse("-").unwrap().entries.is_empty());
+    }
+}
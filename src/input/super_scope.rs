@@ -0,0 +1,174 @@
+use crate::input::controller::Controller;
+use crate::input::ports::{LIGHT_GUN_HEIGHT, LIGHT_GUN_WIDTH};
+use crate::movie::state::{SaveState, StateReader, StateWriter};
+use std::any::Any;
+use std::error::Error;
+
+// report bits, shifted out first to last, followed by 1s
+const SCOPE_FIRE: u8 = 0x1 << 7;
+const SCOPE_CURSOR: u8 = 0x1 << 6;
+const SCOPE_TURBO: u8 = 0x1 << 5;
+const SCOPE_PAUSE: u8 = 0x1 << 4;
+const SCOPE_OFFSCREEN: u8 = 0x1 << 1;
+
+// Nintendo Super Scope, a light gun for port 2
+pub struct SuperScope {
+    pub x: i32,
+    pub y: i32,
+    pub fire: bool,
+    pub cursor: bool,
+    pub pause: bool,
+    // the turbo switch, without it each trigger pull is only reported once
+    pub turbo: bool,
+    fire_reported: bool,
+    report: u8,
+    bits: u8,
+    latched: bool,
+}
+
+impl SuperScope {
+    pub fn new() -> Self {
+        Self {
+            x: 0,
+            y: 0,
+            fire: false,
+            cursor: false,
+            pause: false,
+            turbo: false,
+            fire_reported: false,
+            report: 0,
+            bits: 0,
+            latched: false,
+        }
+    }
+
+    pub fn aim(&mut self, x: i32, y: i32) {
+        self.x = x;
+        self.y = y;
+    }
+
+    fn offscreen(&self) -> bool {
+        !(0..LIGHT_GUN_WIDTH).contains(&self.x) || !(0..LIGHT_GUN_HEIGHT).contains(&self.y)
+    }
+
+    fn build_report(&mut self) -> u8 {
+        let mut report = 0;
+        if self.fire && (self.turbo || !self.fire_reported) {
+            report |= SCOPE_FIRE;
+        }
+        self.fire_reported = self.fire;
+        if self.cursor {
+            report |= SCOPE_CURSOR;
+        }
+        if self.turbo {
+            report |= SCOPE_TURBO;
+        }
+        if self.pause {
+            report |= SCOPE_PAUSE;
+        }
+        if self.offscreen() {
+            report |= SCOPE_OFFSCREEN;
+        }
+        report
+    }
+}
+
+impl Default for SuperScope {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Controller for SuperScope {
+    fn latch(&mut self, high: bool) {
+        if high && !self.latched {
+            self.report = self.build_report();
+            self.bits = 0;
+        }
+        self.latched = high;
+    }
+
+    fn clock(&mut self) -> u8 {
+        if self.bits >= 8 {
+            return 0x1;
+        }
+        let bit = (self.report >> (7 - self.bits)) & 0x1;
+        if !self.latched {
+            self.bits += 1;
+        }
+        bit
+    }
+
+    fn light_position(&self) -> Option<(u16, u16)> {
+        match self.offscreen() {
+            true => None,
+            false => Some((self.x as u16, self.y as u16)),
+        }
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+impl SaveState for SuperScope {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.i32(self.x);
+        w.i32(self.y);
+        w.bool(self.fire);
+        w.bool(self.cursor);
+        w.bool(self.pause);
+        w.bool(self.turbo);
+        w.bool(self.fire_reported);
+        w.u8(self.report);
+        w.u8(self.bits);
+        w.bool(self.latched);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), Box<dyn Error>> {
+        self.x = r.i32()?;
+        self.y = r.i32()?;
+        self.fire = r.bool()?;
+        self.cursor = r.bool()?;
+        self.pause = r.bool()?;
+        self.turbo = r.bool()?;
+        self.fire_reported = r.bool()?;
+        self.report = r.u8()?;
+        self.bits = r.u8()?;
+        self.latched = r.bool()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read_report(scope: &mut SuperScope) -> Vec<u8> {
+        scope.latch(true);
+        scope.latch(false);
+        (0..10).map(|_| scope.clock()).collect()
+    }
+
+    #[test]
+    fn fire_is_edge_triggered_without_turbo() {
+        let mut scope = SuperScope::new();
+        scope.aim(128, 100);
+        scope.fire = true;
+        assert_eq!(read_report(&mut scope), [1, 0, 0, 0, 0, 0, 0, 0, 1, 1]);
+        assert_eq!(read_report(&mut scope), [0, 0, 0, 0, 0, 0, 0, 0, 1, 1]);
+
+        scope.turbo = true;
+        assert_eq!(read_report(&mut scope), [1, 0, 1, 0, 0, 0, 0, 0, 1, 1]);
+        assert_eq!(read_report(&mut scope), [1, 0, 1, 0, 0, 0, 0, 0, 1, 1]);
+    }
+
+    #[test]
+    fn offscreen() {
+        let mut scope = SuperScope::new();
+        scope.aim(-1, 10);
+        scope.cursor = true;
+        assert_eq!(read_report(&mut scope), [0, 1, 0, 0, 0, 0, 1, 0, 1, 1]);
+        assert_eq!(scope.light_position(), None);
+    }
+}
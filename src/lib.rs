@@ -1,2 +1,13 @@
+pub mod apu;
+pub mod archive;
+pub mod cheats;
+pub mod checksum;
+pub mod coprocessor;
 pub mod cpu;
-pub mod rom;
\ No newline at end of file
+pub mod database;
+pub mod input;
+pub mod movie;
+pub mod patch;
+pub mod ppu;
+pub mod ram_search;
+pub mod rom;
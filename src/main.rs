@@ -1,23 +1,560 @@
+use ddss_snes::apu::dsp::regs::SAMPLE_RATE;
+use ddss_snes::apu::scheduler::SPC_CLOCK_HZ;
+use ddss_snes::apu::spc_file;
+use ddss_snes::apu::spc700::alu::Spc700;
+use ddss_snes::apu::spc700::bus::SpcBus;
+use ddss_snes::apu::wav::AudioRecorder;
+use ddss_snes::cheats::Cheats;
+use ddss_snes::checksum::{crc32, sha1};
 use ddss_snes::cpu::alu::Cpu;
 use ddss_snes::cpu::bus::Bus;
-use ddss_snes::rom::open;
+use ddss_snes::database::Peripheral;
+use ddss_snes::input::justifier::Justifier;
+use ddss_snes::input::mouse::Mouse;
+use ddss_snes::input::multitap::Multitap;
+use ddss_snes::input::script::InputScript;
+use ddss_snes::input::super_scope::SuperScope;
+use ddss_snes::movie::file::{DEFAULT_HASH_INTERVAL, MAX_PLAYERS, Movie};
+use ddss_snes::movie::import;
+use ddss_snes::movie::state;
+use ddss_snes::ppu::screenshot;
+use ddss_snes::ppu::timing::VideoStandard;
+use ddss_snes::ram_search::{Filter, Memory, RamSearch, View, Watch, watch_line};
+use ddss_snes::rom::{LoadOptions, ROM, open_with};
 use std::env;
 use std::error::Error;
+use std::fs;
+
+const USAGE: &str = "usage: ddss-snes [options] rom-file.sfc
+       ddss-snes spc [options] file.spc
+       ddss-snes info [--json] [--database FILE] rom-file.sfc
+
+options:
+  --patch FILE          apply an .ips, .bps or .ups patch, can be given more than once;
+                        a patch named like the ROM (.bps, .ups, then .ips) is applied
+  --database FILE       header overrides to search before the built-in game database
+  --firmware DIR        where the DSP firmware dumps (dsp1b.rom, dsp2.rom ..., st010.rom) are,
+                        the ROM's directory by default
+  --region ntsc|pal     force NTSC (60Hz, 262 lines) or PAL (50Hz, 312 lines) timing instead
+                        of the one the cartridge region calls for
+  --cheat CODE          a Game Genie (DDDD-DDDD), Pro Action Replay (AAAAAAVV) or raw
+                        (AAAAAA:VV, AAAAAA:VV:CC to patch only when the byte is CC) code,
+                        can be given more than once
+  --cheats FILE         load cheat codes from a file, one per line with an optional
+                        description; lines starting with - are loaded switched off
+  --watch [NAME=]ADDRESS[:VIEW]
+                        print a variable after every frame, VIEW is u8 (default), s8, u16,
+                        s16, u24 or s24; can be given more than once
+  --search MEMORY:VIEW  search wram or sram for a variable, e.g. wram:u16, starting from
+                        power-on; the addresses left are printed at exit
+  --search-filter N FILTER
+                        after frame N keep the addresses that are equal, greater, less
+                        (than the last snapshot, or =VALUE), changed or unchanged
+  --record-audio FILE   write the sound output to a WAV file
+  --sample-rate HZ      sample rate of the recording: 32000 (native), 44100 or 48000
+  --frames N            run N frames and exit
+  --screenshot-at-frame N FILE
+                        save the picture after frame N (1 or more) as .png or .ppm, the
+                        emulator exits after the last screenshot unless --frames says otherwise
+  --input SCRIPT        buttons for joypad 1, e.g. 0:A+Start,30:- holds A and Start for
+                        30 frames
+  --save-state-at-frame N FILE
+                        save the whole machine after frame N, can be given more than once
+                        (not for SA-1, Super FX or DSP games yet)
+  --load-state FILE     start from a save state instead of power-on
+  --record-movie FILE   save the input of every frame to a movie file, a movie that doesn't
+                        start at power-on gets its save state in FILE.state
+  --movie-hash-interval N
+                        store a state hash in the movie every N frames (default: 60, 0 = off)
+  --play-movie FILE     replay a movie, checking the ROM and the state hashes; runs until
+                        the end of the movie unless --frames says otherwise. BizHawk .bk2
+                        and lsnes .lsmv input logs are imported, use --record-movie to
+                        convert them
+
+spc options:
+  --out FILE            WAV file to render to (default: file.wav)
+  --seconds N           length to render (default: from the ID666 tag, or 60)
+  --sample-rate HZ      as above
+
+info options:
+  --json                print the ROM information as JSON
+  --database FILE       as above";
+
+// length of a rendered .spc without a tag
+const DEFAULT_SPC_SECONDS: u64 = 60;
+
+struct Options {
+    rom_path: String,
+    record_audio: Option<String>,
+    sample_rate: u32,
+    frames: Option<u64>,
+    screenshots: Vec<(u64, String)>,
+    save_states: Vec<(u64, String)>,
+    load_state: Option<String>,
+    input: InputScript,
+    record_movie: Option<String>,
+    movie_hash_interval: u64,
+    play_movie: Option<String>,
+    load: LoadOptions,
+    cheats: Cheats,
+    watches: Vec<Watch>,
+    search: Option<(String, View)>,
+    search_filters: Vec<(u64, Filter)>,
+    region: Option<VideoStandard>,
+}
+
+struct SpcOptions {
+    spc_path: String,
+    out: Option<String>,
+    seconds: Option<u64>,
+    sample_rate: u32,
+}
+
+fn parse_sample_rate(value: &str) -> Result<u32, Box<dyn Error>> {
+    match value.parse()? {
+        rate @ (32000 | 44100 | 48000) => Ok(rate),
+        rate => Err(format!("unsupported sample rate {}", rate).into()),
+    }
+}
+
+fn parse_args(args: &[String]) -> Result<Options, Box<dyn Error>> {
+    let mut rom_path = None;
+    let mut record_audio = None;
+    let mut sample_rate = SAMPLE_RATE;
+    let mut frames = None;
+    let mut screenshots = Vec::new();
+    let mut save_states = Vec::new();
+    let mut load_state = None;
+    let mut input = InputScript {
+        entries: Vec::new(),
+    };
+    let mut record_movie = None;
+    let mut movie_hash_interval = DEFAULT_HASH_INTERVAL;
+    let mut play_movie = None;
+    let mut load = LoadOptions::default();
+    let mut cheats = Cheats::new();
+    let mut watches = Vec::new();
+    let mut search = None;
+    let mut search_filters = Vec::new();
+    let mut region = None;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or(format!("missing value for {}", arg));
+        match arg.as_str() {
+            "--record-audio" => record_audio = Some(value()?.clone()),
+            "--screenshot-at-frame" => {
+                let frame = value()?.parse()?;
+                // frame N is the picture after N frames, there is nothing to show before the first
+                if frame == 0 {
+                    return Err("--screenshot-at-frame counts from frame 1".into());
+                }
+                screenshots.push((frame, value()?.clone()));
+            }
+            "--save-state-at-frame" => {
+                let frame = value()?.parse()?;
+                save_states.push((frame, value()?.clone()));
+            }
+            "--load-state" => load_state = Some(value()?.clone()),
+            "--sample-rate" => sample_rate = parse_sample_rate(value()?)?,
+            "--frames" => frames = Some(value()?.parse()?),
+            "--input" => input = InputScript::parse(value()?)?,
+            "--record-movie" => record_movie = Some(value()?.clone()),
+            "--movie-hash-interval" => movie_hash_interval = value()?.parse()?,
+            "--play-movie" => play_movie = Some(value()?.clone()),
+            "--patch" => load.patches.push(value()?.clone()),
+            "--database" => load.database = Some(value()?.clone()),
+            "--firmware" => load.firmware = Some(value()?.clone()),
+            "--region" => region = Some(VideoStandard::parse(value()?)?),
+            "--cheat" => {
+                cheats.add(value()?)?;
+            }
+            "--cheats" => cheats.open(value()?)?,
+            "--watch" => watches.push(Watch::parse(value()?)?),
+            "--search" => {
+                let spec = value()?;
+                let (memory, view) = spec.split_once(':').unwrap_or((spec, "u8"));
+                search = Some((memory.to_string(), View::parse(view)?));
+            }
+            "--search-filter" => {
+                let frame = value()?.parse()?;
+                search_filters.push((frame, Filter::parse(value()?)?));
+            }
+            _ if arg.starts_with("--") => return Err(format!("unknown option {}", arg).into()),
+            _ => rom_path = Some(arg.clone()),
+        }
+    }
+
+    Ok(Options {
+        rom_path: rom_path.ok_or(USAGE)?,
+        record_audio,
+        sample_rate,
+        frames,
+        screenshots,
+        save_states,
+        load_state,
+        input,
+        record_movie,
+        movie_hash_interval,
+        play_movie,
+        load,
+        cheats,
+        watches,
+        search,
+        search_filters,
+        region,
+    })
+}
+
+fn parse_spc_args(args: &[String]) -> Result<SpcOptions, Box<dyn Error>> {
+    let mut spc_path = None;
+    let mut out = None;
+    let mut seconds = None;
+    let mut sample_rate = SAMPLE_RATE;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or(format!("missing value for {}", arg));
+        match arg.as_str() {
+            "--out" => out = Some(value()?.clone()),
+            "--seconds" => seconds = Some(value()?.parse()?),
+            "--sample-rate" => sample_rate = parse_sample_rate(value()?)?,
+            _ if arg.starts_with("--") => return Err(format!("unknown option {}", arg).into()),
+            _ => spc_path = Some(arg.clone()),
+        }
+    }
+
+    Ok(SpcOptions {
+        spc_path: spc_path.ok_or(USAGE)?,
+        out,
+        seconds,
+        sample_rate,
+    })
+}
+
+// play a .spc snapshot on its own and render it to a WAV file
+fn render_spc(options: SpcOptions) -> Result<(), Box<dyn Error>> {
+    let spc_file = spc_file::open(&options.spc_path)?;
+    let mut spc = Spc700::new(Box::<SpcBus>::default());
+    spc_file.load_into(&mut spc);
+
+    let seconds = options
+        .seconds
+        .or(spc_file
+            .tag
+            .as_ref()
+            .and_then(|tag| tag.play_seconds)
+            .map(u64::from))
+        .unwrap_or(DEFAULT_SPC_SECONDS);
+    let out = match options.out {
+        Some(out) => out,
+        None => format!("{}.wav", options.spc_path.trim_end_matches(".spc")),
+    };
+
+    let mut recorder = AudioRecorder::create(&out, options.sample_rate)?;
+    let end = seconds * SPC_CLOCK_HZ;
+    while spc.cycles < end {
+        // a second at a time, so the sample buffer never overflows
+        let second = (spc.cycles + SPC_CLOCK_HZ).min(end);
+        while spc.cycles < second {
+            spc.step();
+        }
+        recorder.record(&mut spc.bus.dsp.output)?;
+    }
+    recorder.finish()?;
+
+    Ok(())
+}
+
+fn json_string(text: &str) -> String {
+    let mut out = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' => out += "\\\"",
+            '\\' => out += "\\\\",
+            c if (c as u32) < 0x20 => out += &format!("\\u{:04x}", c as u32),
+            c => out.push(c),
+        }
+    }
+    out + "\""
+}
+
+enum InfoValue {
+    Text(String),
+    Number(u64),
+    Flag(bool),
+    Missing,
+    List(Vec<String>),
+}
+
+impl InfoValue {
+    fn json(&self) -> String {
+        match self {
+            InfoValue::Text(text) => json_string(text),
+            InfoValue::Number(number) => number.to_string(),
+            InfoValue::Flag(flag) => flag.to_string(),
+            InfoValue::Missing => "null".to_string(),
+            InfoValue::List(items) => {
+                let items: Vec<String> = items.iter().map(|item| json_string(item)).collect();
+                format!("[{}]", items.join(", "))
+            }
+        }
+    }
+
+    fn text(&self) -> String {
+        match self {
+            InfoValue::Text(text) => text.clone(),
+            InfoValue::Number(number) => number.to_string(),
+            InfoValue::Flag(true) => "yes".to_string(),
+            InfoValue::Flag(false) => "no".to_string(),
+            InfoValue::Missing => "none".to_string(),
+            InfoValue::List(items) if items.is_empty() => "none".to_string(),
+            InfoValue::List(items) => items.join(", "),
+        }
+    }
+}
+
+// everything rom::open learns about a ROM
+fn rom_info(rom: &ROM) -> Vec<(&'static str, InfoValue)> {
+    use InfoValue::*;
+    vec![
+        ("title", Text(rom.game_title.clone())),
+        ("map_mode", Text(format!("{:?}", rom.rom_mode))),
+        ("chipset", Text(format!("{:?}", rom.chipset))),
+        (
+            "coprocessor",
+            rom.coprocessor()
+                .map_or(Missing, |chip| Text(chip.to_string())),
+        ),
+        ("fast_rom", Flag(rom.fast_rom)),
+        ("rom_size", Number(rom.rom_size as u64)),
+        ("file_size", Number(rom.data.len() as u64)),
+        ("ram_size", Number(rom.ram_size as u64)),
+        ("region", Text(format!("{:?}", rom.region))),
+        (
+            "video",
+            Text(format!("{:?}", VideoStandard::from_region(&rom.region))),
+        ),
+        ("version", Number(rom.version as u64)),
+        ("copier_header", Flag(rom.headered)),
+        ("checksum", Text(format!("{:04X}", rom.checksum))),
+        (
+            "checksum_complement",
+            Text(format!("{:04X}", rom.checksum_complement)),
+        ),
+        (
+            "computed_checksum",
+            Text(format!("{:04X}", rom.computed_checksum())),
+        ),
+        ("checksum_valid", Flag(rom.checksum_valid())),
+        ("crc32", Text(format!("{:08X}", crc32(&rom.data)))),
+        ("sha1", Text(hex::encode(sha1(&rom.data)))),
+        ("patches", List(rom.patches.clone())),
+        ("database_match", Flag(rom.database_match)),
+        (
+            "peripherals",
+            List(rom.peripherals.iter().map(|p| format!("{:?}", p)).collect()),
+        ),
+    ]
+}
+
+fn print_info(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let mut json = false;
+    let mut load = LoadOptions::default();
+    let mut path = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--json" => json = true,
+            "--database" => {
+                load.database = Some(args.next().ok_or("missing value for --database")?.clone())
+            }
+            _ if arg.starts_with("--") => return Err(format!("unknown option {}", arg).into()),
+            _ => path = Some(arg),
+        }
+    }
+
+    let rom = open_with(path.ok_or(USAGE)?, &load)?;
+    let info = rom_info(&rom);
+    if json {
+        let fields: Vec<String> = info
+            .iter()
+            .map(|(key, value)| format!("  \"{}\": {}", key, value.json()))
+            .collect();
+        println!("{{\n{}\n}}", fields.join(",\n"));
+    } else {
+        for (key, value) in info {
+            println!("{:20} {}", format!("{}:", key), value.text());
+        }
+    }
+    Ok(())
+}
+
+// most addresses worth printing after a search, the count is always shown
+const MAX_SEARCH_RESULTS: usize = 100;
+
+fn print_search_results(search: &RamSearch) {
+    let results = search.results();
+    println!("{} addresses left ({})", results.len(), search.view);
+    for (address, value) in results.iter().take(MAX_SEARCH_RESULTS) {
+        println!("{:06X} {}", address, value);
+    }
+}
+
+// plug in what the game database says the game needs, the mouse goes in port 1 and the rest
+// in port 2
+fn connect_peripherals(bus: &mut Bus, peripherals: &[Peripheral]) {
+    for peripheral in peripherals {
+        match peripheral {
+            Peripheral::Mouse => bus.input.connect(0, Box::new(Mouse::new())),
+            Peripheral::SuperScope => bus.input.connect(1, Box::new(SuperScope::new())),
+            Peripheral::Justifier => bus.input.connect(1, Box::new(Justifier::new(false))),
+            Peripheral::Multitap => bus.input.connect(1, Box::new(Multitap::new())),
+        }
+    }
+}
 
 fn main() -> Result<(), Box<dyn Error>> {
 
     env_logger::init();
     
     let args: Vec<String> = env::args().collect();
-    if args.len() == 1 {
-        panic!("usage: ddss-snes rom-file.sfc")
+    if args.get(1).is_some_and(|arg| arg == "spc") {
+        return render_spc(parse_spc_args(&args[2..])?);
     }
+    if args.get(1).is_some_and(|arg| arg == "info") {
+        return print_info(&args[2..]);
+    }
+    let options = parse_args(&args[1..])?;
+
+    let rom = open_with(&options.rom_path, &options.load)?;
+    let playback = match &options.play_movie {
+        Some(path) if import::is_importable(path) => Some(import::open(path, &rom)?),
+        Some(path) => {
+            let movie = Movie::open(path)?;
+            movie.check_rom(&rom)?;
+            Some(movie)
+        }
+        None => None,
+    };
+    let players = playback.as_ref().map_or(1, |movie| movie.players);
+    let mut recording = options
+        .record_movie
+        .as_ref()
+        .map(|_| Movie::new(&rom, players, options.movie_hash_interval));
+    let mut bus = rom.map_to(Box::new(Bus::new()))?;
+    connect_peripherals(&mut bus, &rom.peripherals);
+    bus.input.connect_players(players)?;
+    if let Some(standard) = options.region {
+        bus.set_video_standard(standard);
+    }
+    bus.cheats = options.cheats;
 
-    let rom = open(&args[1])?;
-    let bus = rom.map_to(Box::new(Bus::new()))?;
+    let mut recorder = match &options.record_audio {
+        Some(path) => Some(AudioRecorder::create(path, options.sample_rate)?),
+        None => None,
+    };
+
+    // without an explicit frame count, stop at the end of the movie or once the last
+    // screenshot is taken
+    let last_frame = options
+        .frames
+        .or(playback.as_ref().map(|movie| movie.frames.len() as u64))
+        .or(options
+            .screenshots
+            .iter()
+            .chain(&options.save_states)
+            .map(|(frame, _)| *frame)
+            .max())
+        .or(options.search_filters.iter().map(|(frame, _)| *frame).max());
 
     let cpu = &mut Cpu::new(bus);
-    cpu.start();
+
+    // a save state given here or the one a movie starts from, recorded movies start there too
+    let start = match (&options.load_state, &playback, &options.play_movie) {
+        (Some(_), Some(_), _) => {
+            return Err("--load-state and --play-movie both say where to start".into());
+        }
+        (Some(path), None, _) => {
+            let data = fs::read(path)?;
+            state::load(cpu, &data)?;
+            Some(data)
+        }
+        (None, Some(movie), Some(path)) => movie.restore_start(cpu, path)?,
+        _ => None,
+    };
+    if let (Some(movie), Some(data), Some(path)) = (&mut recording, &start, &options.record_movie) {
+        movie.save_start(data, path)?;
+    }
+
+    let mut search = match &options.search {
+        Some((memory, view)) => Some(RamSearch::new(
+            Memory::parse(memory, &rom, &cpu.bus)?,
+            *view,
+            &cpu.bus,
+        )),
+        None => None,
+    };
+
+    let mut frame = 0;
+    while last_frame.is_none_or(|last| frame < last) {
+        let buttons = match &playback {
+            Some(movie) => movie.apply(cpu, frame),
+            None => {
+                let mut buttons = [0u16; MAX_PLAYERS];
+                buttons[0] = options.input.buttons_at(frame);
+                cpu.bus.set_joypad(0, buttons[0]);
+                buttons
+            }
+        };
+
+        cpu.run_frame();
+        frame += 1;
+
+        if let Some(movie) = &playback {
+            movie.verify(cpu, frame)?;
+        }
+        if let Some(movie) = &mut recording {
+            movie.record(cpu, buttons);
+        }
+
+        if !options.watches.is_empty() {
+            println!(
+                "frame {}: {}",
+                frame,
+                watch_line(&options.watches, &cpu.bus)
+            );
+        }
+        if let Some(search) = &mut search {
+            for (_, filter) in options.search_filters.iter().filter(|(at, _)| *at == frame) {
+                search.filter(&cpu.bus, *filter);
+            }
+        }
+
+        for (_, path) in options.screenshots.iter().filter(|(at, _)| *at == frame) {
+            screenshot::save(&cpu.bus.ppu.framebuffer, path)?;
+        }
+        for (_, path) in options.save_states.iter().filter(|(at, _)| *at == frame) {
+            let power_on = rom.map_to(Box::new(Bus::new()))?;
+            fs::write(path, state::save(cpu, &power_on)?)?;
+        }
+
+        match &mut recorder {
+            Some(recorder) => recorder.record(cpu.bus.apu.samples())?,
+            // nobody is listening, don't let the output pile up
+            None => cpu.bus.apu.samples().drain().for_each(drop),
+        }
+    }
+
+    if let Some(recorder) = recorder {
+        recorder.finish()?;
+    }
+    if let (Some(movie), Some(path)) = (recording, &options.record_movie) {
+        movie.save(path)?;
+    }
+    if let Some(search) = search {
+        print_search_results(&search);
+    }
 
     Ok(())
 }
@@ -0,0 +1,3 @@
+pub mod file;
+pub mod import;
+pub mod state;
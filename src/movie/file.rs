@@ -0,0 +1,438 @@
+use crate::checksum::{Crc32, crc32};
+use crate::cpu::alu::Cpu;
+use crate::movie::state;
+use crate::rom::ROM;
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+// Input movies: the buttons held on every pad for each frame since power-on or a save state,
+// plus a hash of the emulator state every few frames so a replay can tell where it stopped
+// matching.
+//
+//   ddss-movie 1
+//   rom-crc32 1A2B3C4D
+//   start power-on     <- or "start state 76543210 bug.movie.state", a save state next to the
+//                         movie with its CRC32
+//   players 2
+//   hash-interval 60
+//   8000 0000          <- one line per frame, buttons of each player in hex
+//   hash 60 89ABCDEF   <- state hash after 60 frames
+pub const MOVIE_MAGIC: &str = "ddss-movie 1";
+
+// pads 1 and 2, or 1 to 5 with a multitap
+pub const MAX_PLAYERS: usize = 5;
+
+pub const DEFAULT_HASH_INTERVAL: u64 = 60;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Start {
+    PowerOn,
+    // a save state file, relative to the movie, and its CRC32
+    State { file: String, crc32: u32 },
+}
+
+pub struct Movie {
+    pub rom_crc32: u32,
+    pub start: Start,
+    pub players: usize,
+    // 0 records no hashes
+    pub hash_interval: u64,
+    pub frames: Vec<[u16; MAX_PLAYERS]>,
+    // (frame, hash of the state after that many frames)
+    pub hashes: Vec<(u64, u32)>,
+}
+
+// CRC32 over the CPU registers, WRAM and coprocessor RAM, the PPU memories and counters, and the
+// SPC700 side
+pub fn state_hash(cpu: &Cpu) -> u32 {
+    let mut crc = Crc32::new();
+    crc.update(&cpu.reg_a.data.to_le_bytes());
+    crc.update(&cpu.reg_x.to_le_bytes());
+    crc.update(&cpu.reg_y.to_le_bytes());
+    crc.update(&cpu.reg_d.to_le_bytes());
+    crc.update(&cpu.sp.to_le_bytes());
+    crc.update(&cpu.pc.to_le_bytes());
+    crc.update(&[cpu.reg_p, cpu.reg_pb, cpu.reg_db, cpu.emulation as u8]);
+    crc.update(cpu.bus.read_bytes(0x7E0000..0x800000));
+
+    // cartridge RAM a coprocessor keeps to itself
+    if let Some(sa1) = &cpu.bus.sa1 {
+        crc.update(&sa1.cpu.bus.iram);
+        crc.update(&sa1.cpu.bus.bwram);
+        crc.update(&sa1.cpu.pc.to_le_bytes());
+    }
+    if let Some(superfx) = &cpu.bus.superfx {
+        crc.update(&superfx.ram);
+        for reg in superfx.regs {
+            crc.update(&reg.to_le_bytes());
+        }
+    }
+
+    let ppu = &cpu.bus.ppu;
+    crc.update(&ppu.vram);
+    crc.update(&ppu.cgram);
+    crc.update(&ppu.oam);
+    crc.update(&ppu.h_counter.to_le_bytes());
+    crc.update(&ppu.v_counter.to_le_bytes());
+    crc.update(&ppu.frame.to_le_bytes());
+
+    let spc = &cpu.bus.apu.spc;
+    crc.update(&spc.bus.aram);
+    crc.update(&spc.bus.dsp.regs);
+    crc.update(&spc.pc.to_le_bytes());
+    crc.update(&[spc.reg_a, spc.reg_x, spc.reg_y, spc.sp, spc.psw]);
+    crc.finish()
+}
+
+impl Movie {
+    pub fn new(rom: &ROM, players: usize, hash_interval: u64) -> Self {
+        Self {
+            rom_crc32: crc32(&rom.data),
+            start: Start::PowerOn,
+            players: players.clamp(1, MAX_PLAYERS),
+            hash_interval,
+            frames: Vec::new(),
+            hashes: Vec::new(),
+        }
+    }
+
+    pub fn parse(text: &str) -> Result<Self, Box<dyn Error>> {
+        let mut lines = text
+            .lines()
+            .enumerate()
+            .map(|(number, line)| (number + 1, line.trim()))
+            .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'));
+        if lines.next().map(|(_, line)| line) != Some(MOVIE_MAGIC) {
+            return Err("not a movie file".into());
+        }
+
+        let mut movie = Self {
+            rom_crc32: 0,
+            start: Start::PowerOn,
+            players: 1,
+            hash_interval: 0,
+            frames: Vec::new(),
+            hashes: Vec::new(),
+        };
+        let mut rom_crc32 = None;
+        for (number, line) in lines {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let invalid = || format!("line {}: invalid {}", number, line);
+            match fields[..] {
+                ["rom-crc32", hash] => {
+                    rom_crc32 = Some(u32::from_str_radix(hash, 16).map_err(|_| invalid())?)
+                }
+                ["start", "power-on"] => movie.start = Start::PowerOn,
+                ["start", "state", hash, file] => {
+                    movie.start = Start::State {
+                        file: file.to_string(),
+                        crc32: u32::from_str_radix(hash, 16).map_err(|_| invalid())?,
+                    }
+                }
+                ["players", players] => match players.parse() {
+                    Ok(players @ 1..=MAX_PLAYERS) => movie.players = players,
+                    _ => return Err(invalid().into()),
+                },
+                ["hash-interval", interval] => {
+                    movie.hash_interval = interval.parse().map_err(|_| invalid())?
+                }
+                ["hash", frame, hash] => movie.hashes.push((
+                    frame.parse().map_err(|_| invalid())?,
+                    u32::from_str_radix(hash, 16).map_err(|_| invalid())?,
+                )),
+                _ if fields.len() == movie.players => {
+                    let mut buttons = [0u16; MAX_PLAYERS];
+                    for (player, field) in fields.iter().enumerate() {
+                        buttons[player] = u16::from_str_radix(field, 16).map_err(|_| invalid())?;
+                    }
+                    movie.frames.push(buttons);
+                }
+                _ => return Err(invalid().into()),
+            }
+        }
+
+        movie.rom_crc32 = rom_crc32.ok_or("movie has no rom-crc32")?;
+        Ok(movie)
+    }
+
+    pub fn to_text(&self) -> String {
+        let start = match &self.start {
+            Start::PowerOn => "power-on".to_string(),
+            Start::State { file, crc32 } => format!("state {:08X} {}", crc32, file),
+        };
+        let mut text = format!(
+            "{}\nrom-crc32 {:08X}\nstart {}\nplayers {}\nhash-interval {}\n",
+            MOVIE_MAGIC, self.rom_crc32, start, self.players, self.hash_interval
+        );
+        let mut hashes = self.hashes.iter().peekable();
+        for (frame, buttons) in self.frames.iter().enumerate() {
+            let fields: Vec<String> = buttons[..self.players]
+                .iter()
+                .map(|buttons| format!("{:04X}", buttons))
+                .collect();
+            text += &fields.join(" ");
+            text += "\n";
+            while let Some((_, hash)) = hashes.next_if(|(at, _)| *at == frame as u64 + 1) {
+                text += &format!("hash {} {:08X}\n", frame + 1, hash);
+            }
+        }
+        text
+    }
+
+    pub fn open(path: &str) -> Result<Self, Box<dyn Error>> {
+        Self::parse(&fs::read_to_string(path)?)
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), Box<dyn Error>> {
+        fs::write(path, self.to_text())?;
+        Ok(())
+    }
+
+    // refuse to play a movie recorded on a different ROM
+    pub fn check_rom(&self, rom: &ROM) -> Result<(), Box<dyn Error>> {
+        let rom_crc32 = crc32(&rom.data);
+        match rom_crc32 == self.rom_crc32 {
+            true => Ok(()),
+            false => Err(format!(
+                "movie was recorded on ROM crc32 {:08X}, this one is {:08X}",
+                self.rom_crc32, rom_crc32
+            )
+            .into()),
+        }
+    }
+
+    // put a machine just powered on where the movie starts, `path` is the movie's own path the
+    // state file is looked up next to. Gives back the state loaded, if any.
+    pub fn restore_start(
+        &self,
+        cpu: &mut Cpu,
+        path: &str,
+    ) -> Result<Option<Vec<u8>>, Box<dyn Error>> {
+        let Start::State {
+            file,
+            crc32: expected,
+        } = &self.start
+        else {
+            return Ok(None);
+        };
+        let state_path = Path::new(path).with_file_name(file);
+        let data = fs::read(&state_path)
+            .map_err(|e| format!("can't read save state {}: {}", state_path.display(), e))?;
+        let actual = crc32(&data);
+        if actual != *expected {
+            return Err(format!(
+                "save state {} has crc32 {:08X}, the movie was recorded from {:08X}",
+                state_path.display(),
+                actual,
+                expected
+            )
+            .into());
+        }
+        state::load(cpu, &data)?;
+        Ok(Some(data))
+    }
+
+    // start from a save state stored next to the movie at `path`
+    pub fn save_start(&mut self, state: &[u8], path: &str) -> Result<(), Box<dyn Error>> {
+        let state_path = format!("{}.state", path);
+        fs::write(&state_path, state)?;
+        let file = Path::new(&state_path)
+            .file_name()
+            .ok_or("invalid movie path")?
+            .to_string_lossy()
+            .to_string();
+        self.start = Start::State {
+            file,
+            crc32: crc32(state),
+        };
+        Ok(())
+    }
+
+    // set the pads for a frame, past the end of the movie nothing is held
+    pub fn apply(&self, cpu: &mut Cpu, frame: u64) -> [u16; MAX_PLAYERS] {
+        let buttons = self.frames.get(frame as usize).copied().unwrap_or_default();
+        for (player, buttons) in buttons[..self.players].iter().enumerate() {
+            cpu.bus.set_joypad(player, *buttons);
+        }
+        buttons
+    }
+
+    // store the input of the frame just run, and a hash when it is due
+    pub fn record(&mut self, cpu: &Cpu, buttons: [u16; MAX_PLAYERS]) {
+        self.frames.push(buttons);
+        let frame = self.frames.len() as u64;
+        if self.hash_interval > 0 && frame.is_multiple_of(self.hash_interval) {
+            self.hashes.push((frame, state_hash(cpu)));
+        }
+    }
+
+    // after `frame` frames of playback, compare the state with the recorded hash, if any
+    pub fn verify(&self, cpu: &Cpu, frame: u64) -> Result<(), Box<dyn Error>> {
+        let Some((_, expected)) = self.hashes.iter().find(|(at, _)| *at == frame) else {
+            return Ok(());
+        };
+        let actual = state_hash(cpu);
+        match actual == *expected {
+            true => Ok(()),
+            false => Err(format!(
+                "playback desynced at frame {}: state hash {:08X}, recorded {:08X}",
+                frame, actual, expected
+            )
+            .into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::coprocessor::sa1::chip::Sa1;
+    use crate::cpu::bus::Bus;
+    use crate::input::joypad::{BUTTON_A, BUTTON_START};
+
+    // copies JOY1 to $0010 every frame
+    #[rustfmt::skip]
+    const PROGRAM: [u8; 14] = [
+        0xA9, 0x01, 0x00, // LDA #$0001
+        0x8D, 0x00, 0x42, // STA $4200   ; auto joypad read
+        0xAD, 0x18, 0x42, // LDA $4218
+        0x8D, 0x10, 0x00, // STA $0010
+        0x80, 0xF8,       // BRA LDA $4218
+    ];
+
+    fn power_on() -> Cpu {
+        let mut bus = Box::new(Bus::new());
+        for (offset, byte) in PROGRAM.iter().enumerate() {
+            bus.load_byte(0x8000 + offset as u32, *byte);
+        }
+        Cpu::new(bus)
+    }
+
+    fn movie() -> Movie {
+        Movie {
+            rom_crc32: 0x12345678,
+            start: Start::PowerOn,
+            players: 2,
+            hash_interval: 4,
+            frames: Vec::new(),
+            hashes: Vec::new(),
+        }
+    }
+
+    fn record(input: impl Fn(u64) -> u16) -> Movie {
+        let mut movie = movie();
+        let mut cpu = power_on();
+        for frame in 0..10 {
+            let buttons = [input(frame), 0, 0, 0, 0];
+            cpu.bus.set_joypad(0, buttons[0]);
+            cpu.run_frame();
+            movie.record(&cpu, buttons);
+        }
+        movie
+    }
+
+    fn play(movie: &Movie) -> Result<(), Box<dyn Error>> {
+        let mut cpu = power_on();
+        for frame in 0..movie.frames.len() as u64 {
+            movie.apply(&mut cpu, frame);
+            cpu.run_frame();
+            movie.verify(&cpu, frame + 1)?;
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn text_round_trip() {
+        let movie = record(|frame| match frame {
+            0..5 => BUTTON_A,
+            _ => BUTTON_START,
+        });
+        assert_eq!(movie.hashes.len(), 2);
+
+        let text = movie.to_text();
+        assert!(text.contains("0080 0000\nhash 4 "));
+        let parsed = Movie::parse(&text).unwrap();
+        assert_eq!(parsed.frames, movie.frames);
+        assert_eq!(parsed.hashes, movie.hashes);
+        assert_eq!(parsed.to_text(), text);
+
+        let from_state = text.replace("start power-on", "start state 0000ABCD start.state");
+        let parsed = Movie::parse(&from_state).unwrap();
+        assert_eq!(
+            parsed.start,
+            Start::State {
+                file: "start.state".to_string(),
+                crc32: 0xABCD
+            }
+        );
+        assert_eq!(parsed.to_text(), from_state);
+        assert!(Movie::parse(&text.replace("start power-on", "start savestate")).is_err());
+    }
+
+    #[test]
+    fn hash_covers_coprocessor_ram() {
+        let mut cpu = power_on();
+        cpu.bus.sa1 = Some(Box::new(Sa1::new(vec![0; 0x8000], 0x2000)));
+        let before = state_hash(&cpu);
+        cpu.bus.poke(0x400123, 0x01);
+        assert_ne!(state_hash(&cpu), before);
+    }
+
+    #[test]
+    fn playback_is_deterministic() {
+        let movie = record(|frame| (frame as u16 & 0x3) << 6);
+        play(&movie).unwrap();
+
+        // the same hashes with different input no longer match
+        let mut edited = record(|_| BUTTON_A);
+        edited.hashes = movie.hashes.clone();
+        let e = play(&edited).unwrap_err();
+        assert!(e.to_string().contains("desynced at frame 4"), "{}", e);
+    }
+
+    #[test]
+    fn starts_from_a_save_state() {
+        let dir = std::env::temp_dir().join(format!("ddss-movie-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("bug.movie");
+        let path = path.to_str().unwrap();
+
+        // 3 frames in, then record 8 more
+        let mut cpu = power_on();
+        for _ in 0..3 {
+            cpu.bus.set_joypad(0, BUTTON_START);
+            cpu.run_frame();
+        }
+        let start = state::save(&cpu, &power_on().bus).unwrap();
+        let mut recording = movie();
+        recording.save_start(&start, path).unwrap();
+        for frame in 0..8 {
+            let buttons = [frame << 4, 0, 0, 0, 0];
+            cpu.bus.set_joypad(0, buttons[0]);
+            cpu.run_frame();
+            recording.record(&cpu, buttons);
+        }
+        recording.save(path).unwrap();
+
+        let movie = Movie::open(path).unwrap();
+        assert!(movie.to_text().contains("start state "));
+        let mut cpu = power_on();
+        assert_eq!(movie.restore_start(&mut cpu, path).unwrap(), Some(start));
+        for frame in 0..movie.frames.len() as u64 {
+            movie.apply(&mut cpu, frame);
+            cpu.run_frame();
+            movie.verify(&cpu, frame + 1).unwrap();
+        }
+
+        // a different state file is refused
+        std::fs::write(dir.join("bug.movie.state"), b"DDSS-STATE\x01").unwrap();
+        let e = movie.restore_start(&mut power_on(), path).unwrap_err();
+        assert!(
+            e.to_string().contains("the movie was recorded from"),
+            "{}",
+            e
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,286 @@
+use crate::archive::zip::ZipArchive;
+use crate::input::joypad::*;
+use crate::input::script::BUTTON_NAMES;
+use crate::movie::file::{MAX_PLAYERS, Movie};
+use crate::rom::ROM;
+use std::error::Error;
+
+// Importers for the input logs of other emulators' movies, BizHawk .bk2 and lsnes .lsmv. Both
+// are zip archives, only the SNES joypad (and multitap) input is understood.
+
+// lsnes gamepad fields, one character per button in this order, '.' when released
+const LSNES_BUTTONS: [u16; 12] = [
+    BUTTON_B,
+    BUTTON_Y,
+    BUTTON_SELECT,
+    BUTTON_START,
+    BUTTON_UP,
+    BUTTON_DOWN,
+    BUTTON_LEFT,
+    BUTTON_RIGHT,
+    BUTTON_A,
+    BUTTON_X,
+    BUTTON_L,
+    BUTTON_R,
+];
+
+// what BizHawk's SNES core logs when a movie has no LogKey
+const BK2_DEFAULT_KEY: &str = "#Reset|Power|\
+    #P1 Up|P1 Down|P1 Left|P1 Right|P1 Select|P1 Start|P1 Y|P1 B|P1 X|P1 A|P1 L|P1 R|\
+    #P2 Up|P2 Down|P2 Left|P2 Right|P2 Select|P2 Start|P2 Y|P2 B|P2 X|P2 A|P2 L|P2 R|";
+
+pub struct ImportedInput {
+    pub players: usize,
+    pub frames: Vec<[u16; MAX_PLAYERS]>,
+}
+
+pub fn is_importable(path: &str) -> bool {
+    path.ends_with(".bk2") || path.ends_with(".lsmv")
+}
+
+// "P2 Start" -> (1, BUTTON_START)
+fn bk2_button(name: &str) -> Result<(usize, u16), Box<dyn Error>> {
+    let unsupported = || format!("unsupported input {} in movie", name);
+    let (player, button) = name.split_once(' ').ok_or_else(unsupported)?;
+    let player = player
+        .strip_prefix('P')
+        .and_then(|player| player.parse::<usize>().ok())
+        .filter(|player| (1..=MAX_PLAYERS).contains(player))
+        .ok_or_else(unsupported)?;
+    let (_, bit) = BUTTON_NAMES
+        .iter()
+        .find(|(known, _)| *known == button)
+        .ok_or_else(unsupported)?;
+    Ok((player - 1, *bit))
+}
+
+// the [Input] section of "Input Log.txt": a LogKey naming the buttons, then one line per frame
+// with a character per button, grouped by controller between '|'
+pub fn parse_bk2_log(text: &str) -> Result<ImportedInput, Box<dyn Error>> {
+    let key = text
+        .lines()
+        .find_map(|line| line.trim().strip_prefix("LogKey:"))
+        .unwrap_or(BK2_DEFAULT_KEY);
+    let groups: Vec<Vec<&str>> = key
+        .split('#')
+        .filter(|group| !group.is_empty())
+        .map(|group| group.split('|').filter(|name| !name.is_empty()).collect())
+        .collect();
+
+    let mut players = 1;
+    let mut frames = Vec::new();
+    for line in text
+        .lines()
+        .map(str::trim)
+        .filter(|line| line.starts_with('|'))
+    {
+        let number = frames.len();
+        let fields: Vec<&str> = line.trim_matches('|').split('|').collect();
+        if fields.len() != groups.len() {
+            return Err(format!("frame {}: expected {} controllers", number, groups.len()).into());
+        }
+
+        let mut buttons = [0u16; MAX_PLAYERS];
+        for (field, names) in fields.iter().zip(&groups) {
+            for (state, name) in field.chars().zip(names) {
+                if state == '.' {
+                    continue;
+                }
+                if *name == "Reset" || *name == "Power" {
+                    return Err(format!(
+                        "frame {}: {} is not supported",
+                        number,
+                        name.to_lowercase()
+                    )
+                    .into());
+                }
+                let (player, bit) = bk2_button(name)?;
+                buttons[player] |= bit;
+                players = players.max(player + 1);
+            }
+        }
+        frames.push(buttons);
+    }
+
+    // players who never press anything still count if the log has a group for them
+    for name in groups.iter().flatten() {
+        if let Ok((player, _)) = bk2_button(name) {
+            players = players.max(player + 1);
+        }
+    }
+    Ok(ImportedInput { players, frames })
+}
+
+// number of controllers lsnes logs for a port type
+fn lsnes_controllers(port: &str) -> Result<usize, Box<dyn Error>> {
+    match port {
+        "none" => Ok(0),
+        "gamepad" | "gamepad16" => Ok(1),
+        "multitap" | "multitap16" => Ok(4),
+        _ => Err(format!("unsupported lsnes controller type {}", port).into()),
+    }
+}
+
+// the "input" member: lines starting with 'F' begin a frame, others are subframes polled
+// within the same frame and are dropped. After the system field ("F." or "FR" on reset) come
+// the controllers of port 1, then those of port 2.
+pub fn parse_lsmv_input(text: &str, ports: [&str; 2]) -> Result<ImportedInput, Box<dyn Error>> {
+    let players = (lsnes_controllers(ports[0])? + lsnes_controllers(ports[1])?).min(MAX_PLAYERS);
+
+    let mut frames = Vec::new();
+    for line in text.lines().filter(|line| line.starts_with('F')) {
+        let number = frames.len();
+        let mut fields = line.split('|');
+        let system = fields.next().unwrap_or_default();
+        if system.chars().nth(1) == Some('R') {
+            return Err(format!("frame {}: reset is not supported", number).into());
+        }
+
+        let mut buttons = [0u16; MAX_PLAYERS];
+        for (player, field) in fields.take(players).enumerate() {
+            for (state, bit) in field.chars().zip(LSNES_BUTTONS) {
+                if state != '.' && state != ' ' {
+                    buttons[player] |= bit;
+                }
+            }
+        }
+        frames.push(buttons);
+    }
+    Ok(ImportedInput { players, frames })
+}
+
+fn read_bk2(zip: &ZipArchive) -> Result<ImportedInput, Box<dyn Error>> {
+    let header = String::from_utf8(zip.read_file("Header.txt")?)?;
+    let platform = header
+        .lines()
+        .find_map(|line| line.strip_prefix("Platform "))
+        .unwrap_or_default();
+    if platform.trim() != "SNES" {
+        return Err(format!("not a SNES movie (platform {})", platform.trim()).into());
+    }
+    parse_bk2_log(&String::from_utf8(zip.read_file("Input Log.txt")?)?)
+}
+
+fn read_lsmv(zip: &ZipArchive) -> Result<ImportedInput, Box<dyn Error>> {
+    // lsnes leaves the port files out when they hold the defaults
+    let port = |name: &str, default: &str| match zip.read_file(name) {
+        Ok(data) => String::from_utf8(data).map(|port| port.trim().to_string()),
+        Err(_) => Ok(default.to_string()),
+    };
+    let port1 = port("port1", "gamepad")?;
+    let port2 = port("port2", "none")?;
+    parse_lsmv_input(
+        &String::from_utf8(zip.read_file("input")?)?,
+        [&port1, &port2],
+    )
+}
+
+// read a .bk2 or .lsmv into a movie for `rom`, without state hashes
+pub fn open(path: &str, rom: &ROM) -> Result<Movie, Box<dyn Error>> {
+    let zip = ZipArchive::open(path)?;
+    let input = match path.ends_with(".lsmv") {
+        true => read_lsmv(&zip)?,
+        false => read_bk2(&zip)?,
+    };
+
+    let mut movie = Movie::new(rom, input.players, 0);
+    movie.frames = input.frames;
+    Ok(movie)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu::alu::Cpu;
+    use crate::cpu::bus::Bus;
+    use crate::movie::file::Start;
+
+    // copies JOY4 to $0010 every frame, with WRIO bit 7 kept high that is player 3 on a multitap
+    #[rustfmt::skip]
+    const PROGRAM: [u8; 14] = [
+        0xA9, 0x01, 0x80, // LDA #$8001
+        0x8D, 0x00, 0x42, // STA $4200   ; auto joypad read, and WRIO
+        0xAD, 0x1E, 0x42, // LDA $421E
+        0x8D, 0x10, 0x00, // STA $0010
+        0x80, 0xF8,       // BRA LDA $421E
+    ];
+
+    #[test]
+    fn bk2_log() {
+        let log = "[Input]\n\
+            LogKey:#Reset|Power|#P1 Up|P1 Down|P1 Left|P1 Right|P1 Select|P1 Start|P1 Y|P1 B|P1 X|P1 A|P1 L|P1 R|#P2 Up|P2 Down|P2 Left|P2 Right|P2 Select|P2 Start|P2 Y|P2 B|P2 X|P2 A|P2 L|P2 R|\n\
+            |..|............|............|\n\
+            |..|.....S......|............|\n\
+            |..|U......B...r|.........A..|\n\
+            [/Input]\n";
+        let input = parse_bk2_log(log).unwrap();
+        assert_eq!(input.players, 2);
+        assert_eq!(input.frames.len(), 3);
+        assert_eq!(input.frames[0], [0; MAX_PLAYERS]);
+        assert_eq!(input.frames[1][0], BUTTON_START);
+        assert_eq!(
+            input.frames[2][..2],
+            [BUTTON_UP | BUTTON_B | BUTTON_R, BUTTON_A]
+        );
+
+        let reset = log.replace("|..|.....S", "|r.|.....S");
+        assert!(parse_bk2_log(&reset).is_err());
+    }
+
+    #[test]
+    fn lsmv_input() {
+        let input = "F.|............|............\n\
+            F.|...S........|B...........\n\
+            .|...S........|B...........\n\
+            F.|....u...A..R|............\n";
+        let imported = parse_lsmv_input(input, ["gamepad", "gamepad"]).unwrap();
+        assert_eq!(imported.players, 2);
+        assert_eq!(imported.frames.len(), 3);
+        assert_eq!(imported.frames[1][..2], [BUTTON_START, BUTTON_B]);
+        assert_eq!(imported.frames[2][0], BUTTON_UP | BUTTON_A | BUTTON_R);
+
+        let tap = parse_lsmv_input(
+            "F.|............|B...........|.Y..........\n",
+            ["gamepad", "multitap"],
+        )
+        .unwrap();
+        assert_eq!(tap.players, MAX_PLAYERS);
+        assert_eq!(tap.frames[0][1..3], [BUTTON_B, BUTTON_Y]);
+
+        assert!(parse_lsmv_input("FR|............\n", ["gamepad", "none"]).is_err());
+        assert!(parse_lsmv_input("", ["mouse", "none"]).is_err());
+    }
+
+    #[test]
+    fn four_players_through_multitap() {
+        let input = parse_lsmv_input(
+            "F.|............|............|............|............\n\
+            F.|............|............|B...........|............\n",
+            ["gamepad", "multitap"],
+        )
+        .unwrap();
+        let movie = Movie {
+            rom_crc32: 0,
+            start: Start::PowerOn,
+            players: 4,
+            hash_interval: 0,
+            frames: input.frames,
+            hashes: Vec::new(),
+        };
+
+        let mut bus = Box::new(Bus::new());
+        for (offset, byte) in PROGRAM.iter().enumerate() {
+            bus.load_byte(0x8000 + offset as u32, *byte);
+        }
+        bus.input.connect_players(movie.players).unwrap();
+        let mut cpu = Cpu::new(bus);
+        for frame in 0..2 {
+            movie.apply(&mut cpu, frame);
+            cpu.run_frame();
+        }
+        assert_eq!(
+            cpu.bus.read_bytes(0x7E0010..0x7E0012),
+            BUTTON_B.to_le_bytes()
+        );
+    }
+}
@@ -0,0 +1,250 @@
+use crate::cpu::alu::Cpu;
+use crate::cpu::bus::Bus;
+use std::error::Error;
+
+// Save states: the whole machine between two frames, so a movie can start somewhere else than
+// power-on. Every part writes its fields in a fixed order after the magic, memory the cartridge
+// mapping put there is only stored where it changed since power-on.
+pub const STATE_MAGIC: &[u8] = b"DDSS-STATE\x01";
+
+pub struct StateWriter {
+    pub data: Vec<u8>,
+}
+
+impl StateWriter {
+    pub fn new() -> Self {
+        Self {
+            data: STATE_MAGIC.to_vec(),
+        }
+    }
+
+    pub fn u8(&mut self, value: u8) {
+        self.data.push(value);
+    }
+
+    pub fn bool(&mut self, value: bool) {
+        self.data.push(value as u8);
+    }
+
+    pub fn u16(&mut self, value: u16) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn u32(&mut self, value: u32) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn u64(&mut self, value: u64) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn i32(&mut self, value: i32) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn i64(&mut self, value: i64) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn bytes(&mut self, bytes: &[u8]) {
+        self.data.extend_from_slice(bytes);
+    }
+}
+
+impl Default for StateWriter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub struct StateReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> StateReader<'a> {
+    pub fn new(data: &'a [u8]) -> Result<Self, Box<dyn Error>> {
+        match data.starts_with(STATE_MAGIC) {
+            true => Ok(Self {
+                data,
+                pos: STATE_MAGIC.len(),
+            }),
+            false => Err("not a save state".into()),
+        }
+    }
+
+    fn take<const N: usize>(&mut self) -> Result<[u8; N], Box<dyn Error>> {
+        let mut bytes = [0u8; N];
+        self.bytes(&mut bytes)?;
+        Ok(bytes)
+    }
+
+    pub fn u8(&mut self) -> Result<u8, Box<dyn Error>> {
+        Ok(self.take::<1>()?[0])
+    }
+
+    pub fn bool(&mut self) -> Result<bool, Box<dyn Error>> {
+        Ok(self.u8()? > 0)
+    }
+
+    pub fn u16(&mut self) -> Result<u16, Box<dyn Error>> {
+        Ok(u16::from_le_bytes(self.take()?))
+    }
+
+    pub fn u32(&mut self) -> Result<u32, Box<dyn Error>> {
+        Ok(u32::from_le_bytes(self.take()?))
+    }
+
+    pub fn u64(&mut self) -> Result<u64, Box<dyn Error>> {
+        Ok(u64::from_le_bytes(self.take()?))
+    }
+
+    pub fn i32(&mut self) -> Result<i32, Box<dyn Error>> {
+        Ok(i32::from_le_bytes(self.take()?))
+    }
+
+    pub fn i64(&mut self) -> Result<i64, Box<dyn Error>> {
+        Ok(i64::from_le_bytes(self.take()?))
+    }
+
+    // fill a buffer of the size the emulator already has
+    pub fn bytes(&mut self, bytes: &mut [u8]) -> Result<(), Box<dyn Error>> {
+        let end = self.pos + bytes.len();
+        let data = self.data.get(self.pos..end).ok_or("truncated save state")?;
+        bytes.copy_from_slice(data);
+        self.pos = end;
+        Ok(())
+    }
+
+    pub fn position(&self) -> usize {
+        self.pos
+    }
+
+    pub fn at_end(&self) -> bool {
+        self.pos == self.data.len()
+    }
+}
+
+// a part of the machine that goes into a save state
+pub trait SaveState {
+    fn save_state(&self, w: &mut StateWriter);
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), Box<dyn Error>>;
+}
+
+// the machine after some frames. `power_on` is the same cartridge freshly mapped, memory that
+// still matches it is left out.
+pub fn save(cpu: &Cpu, power_on: &Bus) -> Result<Vec<u8>, Box<dyn Error>> {
+    if cpu.bus.sa1.is_some() || cpu.bus.superfx.is_some() || cpu.bus.dsp.is_some() {
+        return Err("save states don't cover SA-1, Super FX or DSP cartridges yet".into());
+    }
+    let mut w = StateWriter::new();
+    cpu.save_state(&mut w);
+    cpu.bus.save_memory(&mut w, power_on);
+    cpu.bus.save_state(&mut w);
+    Ok(w.data)
+}
+
+// restore a state into a machine just powered on with the same cartridge
+pub fn load(cpu: &mut Cpu, data: &[u8]) -> Result<(), Box<dyn Error>> {
+    if cpu.bus.sa1.is_some() || cpu.bus.superfx.is_some() || cpu.bus.dsp.is_some() {
+        return Err("save states don't cover SA-1, Super FX or DSP cartridges yet".into());
+    }
+    let mut r = StateReader::new(data)?;
+    cpu.load_state(&mut r)?;
+    cpu.bus.load_memory(&mut r)?;
+    cpu.bus.load_state(&mut r)?;
+    match r.at_end() {
+        true => Ok(()),
+        false => Err("save state has trailing data".into()),
+    }
+}
+
+impl SaveState for Cpu {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.u16(self.reg_a.data);
+        w.u16(self.reg_x);
+        w.u16(self.reg_y);
+        w.u8(self.reg_p);
+        w.u16(self.reg_d);
+        w.u8(self.reg_pb);
+        w.u8(self.reg_db);
+        w.u32(self.sp);
+        w.u16(self.pc);
+        w.bool(self.emulation);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), Box<dyn Error>> {
+        self.reg_a.data = r.u16()?;
+        self.reg_x = r.u16()?;
+        self.reg_y = r.u16()?;
+        self.reg_p = r.u8()?;
+        self.reg_d = r.u16()?;
+        self.reg_pb = r.u8()?;
+        self.reg_db = r.u8()?;
+        self.sp = r.u32()?;
+        self.pc = r.u16()?;
+        self.emulation = r.bool()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::movie::file::state_hash;
+
+    // copies JOY1 to $0010 and on to the SPC700 every frame
+    #[rustfmt::skip]
+    const PROGRAM: [u8; 17] = [
+        0xA9, 0x01, 0x00, // LDA #$0001
+        0x8D, 0x00, 0x42, // STA $4200   ; auto joypad read
+        0xAD, 0x18, 0x42, // LDA $4218
+        0x8D, 0x10, 0x00, // STA $0010
+        0x8D, 0x40, 0x21, // STA $2140
+        0x80, 0xF5,       // BRA LDA $4218
+    ];
+
+    fn power_on_bus() -> Box<Bus> {
+        let mut bus = Box::new(Bus::new());
+        for (offset, byte) in PROGRAM.iter().enumerate() {
+            bus.load_byte(0x8000 + offset as u32, *byte);
+        }
+        bus
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut cpu = Cpu::new(power_on_bus());
+        for frame in 0..5 {
+            cpu.bus.set_joypad(0, frame << 4);
+            cpu.run_frame();
+        }
+        let state = save(&cpu, &power_on_bus()).unwrap();
+        // the ROM and the untouched memory are left out
+        assert!(state.len() < 0x30000, "{} bytes", state.len());
+
+        let mut loaded = Cpu::new(power_on_bus());
+        load(&mut loaded, &state).unwrap();
+        assert_eq!(state_hash(&loaded), state_hash(&cpu));
+
+        // and both carry on the same way
+        for frame in 5..10 {
+            cpu.bus.set_joypad(0, frame << 4);
+            loaded.bus.set_joypad(0, frame << 4);
+            cpu.run_frame();
+            loaded.run_frame();
+        }
+        assert_eq!(state_hash(&loaded), state_hash(&cpu));
+        assert_eq!(loaded.bus.read_bytes(0x7E0010..0x7E0011), &[0x90]);
+    }
+
+    #[test]
+    fn rejects_damaged_states() {
+        let cpu = Cpu::new(power_on_bus());
+        let state = save(&cpu, &power_on_bus()).unwrap();
+        let mut loaded = Cpu::new(power_on_bus());
+        assert!(load(&mut loaded, &state[..state.len() - 1]).is_err());
+        assert!(load(&mut loaded, &state[1..]).is_err());
+    }
+}
@@ -0,0 +1,468 @@
+use crate::checksum::crc32;
+use std::error::Error;
+
+// ROM patches: IPS, UPS and BPS. The format is told apart by the magic at the start.
+
+const IPS_MAGIC: &[u8] = b"PATCH";
+const IPS_EOF: &[u8] = b"EOF";
+const UPS_MAGIC: &[u8] = b"UPS1";
+const BPS_MAGIC: &[u8] = b"BPS1";
+
+// UPS and BPS end with the source, target and patch CRC32s
+const FOOTER_SIZE: usize = 12;
+
+// BPS commands, in the low 2 bits of each command word
+const BPS_SOURCE_READ: u64 = 0;
+const BPS_TARGET_READ: u64 = 1;
+const BPS_SOURCE_COPY: u64 = 2;
+const BPS_TARGET_COPY: u64 = 3;
+
+// no cartridge is bigger than the 16 MiB the CPU can address, a patch asking for more is broken
+const MAX_TARGET_SIZE: usize = 0x1000000;
+
+// the checked formats first, for picking one of several patches named like the ROM
+pub const PATCH_EXTENSIONS: [&str; 3] = ["bps", "ups", "ips"];
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn byte(&mut self) -> Result<u8, Box<dyn Error>> {
+        let byte = *self.data.get(self.pos).ok_or("truncated patch")?;
+        self.pos += 1;
+        Ok(byte)
+    }
+
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], Box<dyn Error>> {
+        let end = self.pos.checked_add(len).ok_or("truncated patch")?;
+        let bytes = self.data.get(self.pos..end).ok_or("truncated patch")?;
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    fn be(&mut self, len: usize) -> Result<usize, Box<dyn Error>> {
+        Ok(self
+            .bytes(len)?
+            .iter()
+            .fold(0, |value, byte| (value << 8) | *byte as usize))
+    }
+
+    // the variable length numbers of UPS and BPS, 7 bits at a time with the end marked by bit 7
+    fn number(&mut self) -> Result<u64, Box<dyn Error>> {
+        let mut value = 0u64;
+        let mut shift = 1u64;
+        loop {
+            let byte = self.byte()?;
+            value = ((byte & 0x7F) as u64)
+                .checked_mul(shift)
+                .and_then(|bits| value.checked_add(bits))
+                .ok_or("invalid number in patch")?;
+            if byte & 0x80 > 0 {
+                return Ok(value);
+            }
+            shift = shift.checked_mul(0x80).ok_or("invalid number in patch")?;
+            value = value.checked_add(shift).ok_or("invalid number in patch")?;
+        }
+    }
+
+    // the target size of a UPS or BPS header, refused before anything is allocated for it
+    fn target_size(&mut self) -> Result<usize, Box<dyn Error>> {
+        let size = self.number()?;
+        match usize::try_from(size) {
+            Ok(size) if size <= MAX_TARGET_SIZE => Ok(size),
+            _ => Err(format!("patched ROM would be {} bytes, too large", size).into()),
+        }
+    }
+}
+
+fn crc_at(data: &[u8], pos: usize) -> u32 {
+    u32::from_le_bytes([data[pos], data[pos + 1], data[pos + 2], data[pos + 3]])
+}
+
+// the CRC32 of a UPS or BPS patch itself, checked before any of it is acted on
+fn check_patch(patch: &[u8], magic: &[u8]) -> Result<(), Box<dyn Error>> {
+    if patch.len() < magic.len() + FOOTER_SIZE {
+        return Err("truncated patch".into());
+    }
+    let footer = patch.len() - FOOTER_SIZE;
+    match crc32(&patch[..footer + 8]) == crc_at(patch, footer + 8) {
+        true => Ok(()),
+        false => Err("patch is corrupt, CRC32 mismatch".into()),
+    }
+}
+
+// check the source and target CRC32s at the end of a UPS or BPS patch
+fn check_footer(source: &[u8], target: &[u8], patch: &[u8]) -> Result<(), Box<dyn Error>> {
+    let footer = patch.len() - FOOTER_SIZE;
+    if crc32(source) != crc_at(patch, footer) {
+        return Err("patch is for a different ROM, source CRC32 mismatch".into());
+    }
+    if crc32(target) != crc_at(patch, footer + 4) {
+        return Err("patched ROM does not match, target CRC32 mismatch".into());
+    }
+    Ok(())
+}
+
+// records of a 24-bit offset and 16-bit size, a size of 0 is a run of one byte. An optional
+// 24-bit size after "EOF" truncates the file.
+pub fn apply_ips(source: &[u8], patch: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
+    let mut reader = Reader {
+        data: patch,
+        pos: IPS_MAGIC.len(),
+    };
+    let mut out = source.to_vec();
+    loop {
+        let offset = reader.bytes(3)?;
+        if offset == IPS_EOF {
+            if let Ok(size) = reader.be(3) {
+                out.truncate(size);
+            }
+            return Ok(out);
+        }
+        let offset = offset
+            .iter()
+            .fold(0, |value, byte| (value << 8) | *byte as usize);
+
+        let (len, data) = match reader.be(2)? {
+            0 => {
+                let len = reader.be(2)?;
+                (len, None)
+            }
+            len => (len, Some(reader.bytes(len)?)),
+        };
+        if out.len() < offset + len {
+            out.resize(offset + len, 0);
+        }
+        match data {
+            Some(data) => out[offset..offset + len].copy_from_slice(data),
+            None => {
+                let value = reader.byte()?;
+                out[offset..offset + len].fill(value);
+            }
+        }
+    }
+}
+
+// XOR hunks at relative offsets, each ended by a 0 byte
+pub fn apply_ups(source: &[u8], patch: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
+    check_patch(patch, UPS_MAGIC)?;
+    let end = patch.len() - FOOTER_SIZE;
+    let mut reader = Reader {
+        data: &patch[..end],
+        pos: UPS_MAGIC.len(),
+    };
+    let source_size = reader.number()? as usize;
+    let target_size = reader.target_size()?;
+    if source_size != source.len() {
+        return Err(format!(
+            "patch is for a {} byte ROM, this one is {}",
+            source_size,
+            source.len()
+        )
+        .into());
+    }
+
+    let mut out = source.to_vec();
+    out.resize(target_size, 0);
+    let mut pos = 0usize;
+    while reader.pos < end {
+        pos = usize::try_from(reader.number()?)
+            .ok()
+            .and_then(|skip| pos.checked_add(skip))
+            .ok_or("invalid offset in patch")?;
+        loop {
+            let byte = reader.byte()?;
+            if let Some(out) = out.get_mut(pos) {
+                *out ^= byte;
+            }
+            pos = pos.checked_add(1).ok_or("invalid offset in patch")?;
+            if byte == 0 {
+                break;
+            }
+        }
+    }
+
+    check_footer(source, &out, patch)?;
+    Ok(out)
+}
+
+// relative offsets of BPS copy commands, the sign is in bit 0
+fn bps_offset(reader: &mut Reader, offset: &mut i64) -> Result<usize, Box<dyn Error>> {
+    let data = reader.number()?;
+    let delta = (data >> 1) as i64;
+    *offset = match data & 0x1 {
+        0 => offset.checked_add(delta),
+        _ => offset.checked_sub(delta),
+    }
+    .ok_or("invalid copy offset in patch")?;
+    usize::try_from(*offset).map_err(|_| "invalid copy offset in patch".into())
+}
+
+pub fn apply_bps(source: &[u8], patch: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
+    check_patch(patch, BPS_MAGIC)?;
+    let end = patch.len() - FOOTER_SIZE;
+    let mut reader = Reader {
+        data: &patch[..end],
+        pos: BPS_MAGIC.len(),
+    };
+    let source_size = reader.number()? as usize;
+    let target_size = reader.target_size()?;
+    let metadata_size = reader.number()? as usize;
+    reader.bytes(metadata_size)?;
+    if source_size != source.len() {
+        return Err(format!(
+            "patch is for a {} byte ROM, this one is {}",
+            source_size,
+            source.len()
+        )
+        .into());
+    }
+
+    let mut out = Vec::with_capacity(target_size);
+    let mut source_offset = 0i64;
+    let mut target_offset = 0i64;
+    while reader.pos < end {
+        let data = reader.number()?;
+        let len = (data >> 2) as usize + 1;
+        // every command adds to the target, none may take it past the size in the header
+        if len > target_size - out.len() {
+            return Err("patch writes past the end of the patched ROM".into());
+        }
+        match data & 0x3 {
+            BPS_SOURCE_READ => {
+                let start = out.len();
+                out.extend_from_slice(
+                    source
+                        .get(start..start + len)
+                        .ok_or("source read out of range")?,
+                );
+            }
+            BPS_TARGET_READ => out.extend_from_slice(reader.bytes(len)?),
+            BPS_SOURCE_COPY => {
+                let start = bps_offset(&mut reader, &mut source_offset)?;
+                out.extend_from_slice(
+                    source
+                        .get(start..start + len)
+                        .ok_or("source copy out of range")?,
+                );
+                source_offset = source_offset
+                    .checked_add(len as i64)
+                    .ok_or("invalid copy offset in patch")?;
+            }
+            BPS_TARGET_COPY => {
+                let start = bps_offset(&mut reader, &mut target_offset)?;
+                if start >= out.len() {
+                    return Err("target copy out of range".into());
+                }
+                // byte by byte, the copy can run into what it writes
+                for i in start..start + len {
+                    out.push(out[i]);
+                }
+                target_offset = target_offset
+                    .checked_add(len as i64)
+                    .ok_or("invalid copy offset in patch")?;
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    if out.len() != target_size {
+        return Err("patched ROM has the wrong size".into());
+    }
+    check_footer(source, &out, patch)?;
+    Ok(out)
+}
+
+pub fn apply(source: &[u8], patch: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
+    if patch.starts_with(IPS_MAGIC) {
+        apply_ips(source, patch)
+    } else if patch.starts_with(UPS_MAGIC) {
+        apply_ups(source, patch)
+    } else if patch.starts_with(BPS_MAGIC) {
+        apply_bps(source, patch)
+    } else {
+        Err("unknown patch format".into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn number(mut value: u64, out: &mut Vec<u8>) {
+        loop {
+            let byte = (value & 0x7F) as u8;
+            value >>= 7;
+            if value == 0 {
+                out.push(byte | 0x80);
+                return;
+            }
+            out.push(byte);
+            value -= 1;
+        }
+    }
+
+    fn with_footer(mut patch: Vec<u8>, source: &[u8], target: &[u8]) -> Vec<u8> {
+        patch.extend_from_slice(&crc32(source).to_le_bytes());
+        patch.extend_from_slice(&crc32(target).to_le_bytes());
+        patch.extend_from_slice(&crc32(&patch).to_le_bytes());
+        patch
+    }
+
+    #[test]
+    fn numbers() {
+        for value in [0, 1, 127, 128, 300, 0x4000, 0x123456] {
+            let mut data = Vec::new();
+            number(value, &mut data);
+            let mut reader = Reader {
+                data: &data,
+                pos: 0,
+            };
+            assert_eq!(reader.number().unwrap(), value);
+        }
+
+        // a run of bytes without the end marker overflows instead of panicking
+        let data = [0x00; 16];
+        let mut reader = Reader {
+            data: &data,
+            pos: 0,
+        };
+        assert_eq!(
+            reader.number().unwrap_err().to_string(),
+            "invalid number in patch"
+        );
+    }
+
+    #[test]
+    fn ips() {
+        let mut patch = b"PATCH".to_vec();
+        patch.extend_from_slice(&[0x00, 0x00, 0x01, 0x00, 0x02, 0xAA, 0xBB]);
+        // a run of 3 bytes past the end grows the file
+        patch.extend_from_slice(&[0x00, 0x00, 0x05, 0x00, 0x00, 0x00, 0x03, 0xCC]);
+        patch.extend_from_slice(b"EOF");
+        assert_eq!(
+            apply(&[0; 4], &patch).unwrap(),
+            [0, 0xAA, 0xBB, 0, 0, 0xCC, 0xCC, 0xCC]
+        );
+
+        // truncation after EOF
+        patch.extend_from_slice(&[0x00, 0x00, 0x02]);
+        assert_eq!(apply(&[0; 4], &patch).unwrap(), [0, 0xAA]);
+    }
+
+    #[test]
+    fn ups() {
+        let source = b"hello world".to_vec();
+        let target = b"hello WORLD!".to_vec();
+        let mut patch = b"UPS1".to_vec();
+        number(source.len() as u64, &mut patch);
+        number(target.len() as u64, &mut patch);
+        number(6, &mut patch);
+        patch.extend(source[6..].iter().zip(&target[6..11]).map(|(a, b)| a ^ b));
+        // past the end of the source the target bytes are stored as they are
+        patch.extend_from_slice(&[b'!', 0]);
+        let patch = with_footer(patch, &source, &target);
+
+        assert_eq!(apply(&source, &patch).unwrap(), target);
+        assert!(apply(b"hello there", &patch).is_err());
+    }
+
+    #[test]
+    fn bps() {
+        let source = b"abcdefgh".to_vec();
+        let target = b"abcdXYXYXYefgh".to_vec();
+        let mut patch = b"BPS1".to_vec();
+        number(source.len() as u64, &mut patch);
+        number(target.len() as u64, &mut patch);
+        number(0, &mut patch);
+        // source read "abcd", target read "XY", target copy "XYXY" from 4, source copy "efgh"
+        number(3 << 2 | BPS_SOURCE_READ, &mut patch);
+        number(1 << 2 | BPS_TARGET_READ, &mut patch);
+        patch.extend_from_slice(b"XY");
+        number(3 << 2 | BPS_TARGET_COPY, &mut patch);
+        number(4 << 1, &mut patch);
+        number(3 << 2 | BPS_SOURCE_COPY, &mut patch);
+        number(4 << 1, &mut patch);
+        let patch = with_footer(patch, &source, &target);
+
+        assert_eq!(apply(&source, &patch).unwrap(), target);
+
+        let mut corrupt = patch.clone();
+        corrupt[8] ^= 0x1;
+        assert!(apply(&source, &corrupt).is_err());
+        assert!(apply(b"abcdefgX", &patch).is_err());
+    }
+
+    fn error(source: &[u8], patch: &[u8]) -> String {
+        apply(source, patch).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn crafted_ups() {
+        let source = [0u8; 4];
+        let header = |target_size: u64| {
+            let mut patch = b"UPS1".to_vec();
+            number(source.len() as u64, &mut patch);
+            number(target_size, &mut patch);
+            patch
+        };
+
+        // nothing is allocated for a target size out of range
+        let huge = with_footer(header(1 << 40), &source, &source);
+        assert_eq!(
+            error(&source, &huge),
+            "patched ROM would be 1099511627776 bytes, too large"
+        );
+
+        // and the patch CRC32 is checked before the header is read
+        let mut corrupt = huge.clone();
+        corrupt[5] ^= 0x1;
+        assert_eq!(error(&source, &corrupt), "patch is corrupt, CRC32 mismatch");
+
+        // relative offsets that add up past the end of the address space
+        let mut skips = header(source.len() as u64);
+        for _ in 0..2 {
+            number(1 << 63, &mut skips);
+            skips.push(0);
+        }
+        let skips = with_footer(skips, &source, &source);
+        assert_eq!(error(&source, &skips), "invalid offset in patch");
+    }
+
+    #[test]
+    fn crafted_bps() {
+        let source = b"abcd".to_vec();
+        let header = |target_size: u64| {
+            let mut patch = b"BPS1".to_vec();
+            number(source.len() as u64, &mut patch);
+            number(target_size, &mut patch);
+            number(0, &mut patch);
+            patch
+        };
+
+        let huge = with_footer(header(u64::MAX), &source, &source);
+        assert!(error(&source, &huge).ends_with("too large"));
+
+        // a target copy running far past the size in the header
+        let mut long_copy = header(4);
+        number(1 << 2 | BPS_SOURCE_READ, &mut long_copy);
+        number(0xFFFFFF << 2 | BPS_TARGET_COPY, &mut long_copy);
+        number(0, &mut long_copy);
+        let long_copy = with_footer(long_copy, &source, &source);
+        assert_eq!(
+            error(&source, &long_copy),
+            "patch writes past the end of the patched ROM"
+        );
+
+        // copy offsets at the ends of the i64 range
+        for data in [(i64::MAX as u64) << 1, (i64::MAX as u64) << 1 | 1] {
+            let mut far_copy = header(4);
+            number(BPS_SOURCE_COPY, &mut far_copy);
+            number(data, &mut far_copy);
+            let far_copy = with_footer(far_copy, &source, &source);
+            assert!(apply(&source, &far_copy).is_err());
+        }
+    }
+}
@@ -0,0 +1,5 @@
+pub mod framebuffer;
+pub mod memory;
+pub mod regs;
+pub mod screenshot;
+pub mod timing;
@@ -0,0 +1,212 @@
+use crate::ppu::regs::Ppu;
+
+// big enough for hires, interlace and overscan together
+pub const MAX_WIDTH: usize = 512;
+pub const MAX_HEIGHT: usize = 478;
+
+pub const LINES: u16 = 224;
+pub const OVERSCAN_LINES: u16 = 239;
+
+// INIDISP - Display Control 1
+pub const INIDISP_FORCE_BLANK: u8 = 0x1 << 7;
+
+// SETINI - Display Control 2
+pub const SETINI_INTERLACE: u8 = 0x1;
+pub const SETINI_OVERSCAN: u8 = 0x1 << 2;
+pub const SETINI_PSEUDO_HIRES: u8 = 0x1 << 3;
+
+// the last rendered frame, kept at the full 512x478 and scaled down on the way out when the
+// frame used neither hires nor interlace
+pub struct Framebuffer {
+    // 24-bit RGB
+    pixels: Box<[u8]>,
+    pub hires: bool,
+    pub interlace: bool,
+    pub lines: u16,
+}
+
+impl Framebuffer {
+    pub fn new() -> Self {
+        Self {
+            pixels: vec![0u8; MAX_WIDTH * MAX_HEIGHT * 3].into_boxed_slice(),
+            hires: false,
+            interlace: false,
+            lines: LINES,
+        }
+    }
+
+    // double resolution in both directions as soon as either is needed
+    fn doubled(&self) -> bool {
+        self.hires || self.interlace
+    }
+
+    pub fn width(&self) -> usize {
+        match self.doubled() {
+            true => MAX_WIDTH,
+            false => MAX_WIDTH / 2,
+        }
+    }
+
+    pub fn height(&self) -> usize {
+        match self.doubled() {
+            true => self.lines as usize * 2,
+            false => self.lines as usize,
+        }
+    }
+
+    pub fn pixel(&self, x: usize, y: usize) -> [u8; 3] {
+        let (x, y) = match self.doubled() {
+            true => (x, y),
+            false => (x * 2, y * 2),
+        };
+        let offset = (y * MAX_WIDTH + x) * 3;
+        [
+            self.pixels[offset],
+            self.pixels[offset + 1],
+            self.pixels[offset + 2],
+        ]
+    }
+
+    // width x height RGB triplets, row by row
+    pub fn to_rgb(&self) -> Vec<u8> {
+        let mut rgb = Vec::with_capacity(self.width() * self.height() * 3);
+        for y in 0..self.height() {
+            for x in 0..self.width() {
+                rgb.extend_from_slice(&self.pixel(x, y));
+            }
+        }
+        rgb
+    }
+
+    fn set_pixel(&mut self, x: usize, y: usize, rgb: [u8; 3]) {
+        let offset = (y * MAX_WIDTH + x) * 3;
+        self.pixels[offset..offset + 3].copy_from_slice(&rgb);
+    }
+}
+
+impl Default for Framebuffer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// 15-bit BGR to 24-bit RGB, scaled by the master brightness (0-15)
+pub fn to_rgb(color: u16, brightness: u8) -> [u8; 3] {
+    let channel = |shift: u16| {
+        let c = ((color >> shift) & 0x1F) as u32 * brightness as u32 / 15;
+        ((c << 3) | (c >> 2)) as u8
+    };
+    [channel(0), channel(5), channel(10)]
+}
+
+impl Ppu {
+    pub fn visible_lines(&self) -> u16 {
+        match self.setini & SETINI_OVERSCAN > 0 {
+            true => OVERSCAN_LINES,
+            false => LINES,
+        }
+    }
+
+    // BG modes 5 and 6 and pseudo-hires output 512 pixels per line
+    pub fn hires(&self) -> bool {
+        matches!(self.bgmode & 0x7, 5 | 6) || self.setini & SETINI_PSEUDO_HIRES > 0
+    }
+
+    // draw the line under the beam. There is no BG or sprite renderer yet (the PPU keeps VRAM,
+    // OAM and CGRAM but not the layer registers), so every pixel is the backdrop color at the
+    // current brightness and screenshots only show that, force blank and the picture size
+    pub fn render_line(&mut self) {
+        let line = self.v_counter;
+        if line == 0 || line > self.visible_lines() {
+            return;
+        }
+        let y = (line - 1) as usize;
+
+        let interlace = self.setini & SETINI_INTERLACE > 0;
+        let hires = self.hires();
+        let lines = self.visible_lines();
+        let fb = &mut self.framebuffer;
+        // a new frame starts over with whatever the first line uses
+        if y == 0 {
+            fb.hires = false;
+            fb.interlace = false;
+        }
+        fb.hires |= hires;
+        fb.interlace |= interlace;
+        fb.lines = lines;
+
+        let rgb = match self.inidisp & INIDISP_FORCE_BLANK > 0 {
+            true => [0, 0, 0],
+            false => to_rgb(self.color(0), self.inidisp & 0xF),
+        };
+
+        // interlaced frames only cover their own field's rows, others fill both
+        let rows = match interlace {
+            true => vec![y * 2 + self.interlace_field as usize],
+            false => vec![y * 2, y * 2 + 1],
+        };
+        let fb = &mut self.framebuffer;
+        for row in rows {
+            for x in 0..MAX_WIDTH {
+                fb.set_pixel(x, row, rgb);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ppu::timing::VideoStandard;
+
+    fn render_frame(ppu: &mut Ppu) {
+        for line in 1..=ppu.visible_lines() {
+            ppu.v_counter = line;
+            ppu.render_line();
+        }
+    }
+
+    #[test]
+    fn backdrop_and_brightness() {
+        let mut ppu = Ppu::new(VideoStandard::Ntsc);
+        // pure red
+        ppu.write_register(0x2122, 0x1F);
+        ppu.write_register(0x2122, 0x00);
+
+        render_frame(&mut ppu);
+        assert_eq!(ppu.framebuffer.pixel(10, 10), [0, 0, 0]);
+
+        ppu.write_register(0x2100, 0x0F);
+        render_frame(&mut ppu);
+        assert_eq!(
+            (ppu.framebuffer.width(), ppu.framebuffer.height()),
+            (256, 224)
+        );
+        assert_eq!(ppu.framebuffer.pixel(255, 223), [0xFF, 0, 0]);
+
+        ppu.write_register(0x2100, 0x07);
+        render_frame(&mut ppu);
+        assert_eq!(ppu.framebuffer.pixel(0, 0), [0x73, 0, 0]);
+    }
+
+    #[test]
+    fn hires_and_interlace_are_512x448() {
+        let mut ppu = Ppu::new(VideoStandard::Ntsc);
+        ppu.write_register(0x2100, 0x0F);
+        ppu.write_register(0x2105, 0x05);
+        render_frame(&mut ppu);
+        assert_eq!(
+            (ppu.framebuffer.width(), ppu.framebuffer.height()),
+            (512, 448)
+        );
+
+        ppu.write_register(0x2105, 0x01);
+        ppu.write_register(0x2133, SETINI_INTERLACE | SETINI_OVERSCAN);
+        render_frame(&mut ppu);
+        assert_eq!(
+            (ppu.framebuffer.width(), ppu.framebuffer.height()),
+            (512, 478)
+        );
+        assert_eq!(ppu.framebuffer.to_rgb().len(), 512 * 478 * 3);
+    }
+}
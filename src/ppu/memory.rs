@@ -0,0 +1,138 @@
+use crate::ppu::regs::Ppu;
+
+pub const VRAM_SIZE: usize = 0x10000;
+pub const CGRAM_SIZE: usize = 0x200;
+pub const OAM_SIZE: usize = 0x220;
+
+pub const VMAIN_INCREMENT_HIGH: u8 = 0x1 << 7;
+
+impl Ppu {
+    // VMADDL/VMADDH - VRAM Address (word address)
+    pub fn write_vram_address(&mut self, addr: u16, val: u8) {
+        self.vmadd = match addr {
+            0x2116 => (self.vmadd & 0xFF00) | val as u16,
+            _ => (self.vmadd & 0xFF) | ((val as u16) << 8),
+        };
+        self.prefetch_vram();
+    }
+
+    // VMDATAL/VMDATAH - VRAM Data Write
+    pub fn write_vram_data(&mut self, addr: u16, val: u8) {
+        let word = self.translated_vram_address() as usize;
+        let high = addr == 0x2119;
+        self.vram[(word << 1) | high as usize] = val;
+
+        if high == (self.vmain & VMAIN_INCREMENT_HIGH > 0) {
+            self.increment_vram_address();
+        }
+    }
+
+    // RDVRAML/RDVRAMH - VRAM Data Read, served from the prefetch latch
+    pub fn read_vram_data(&mut self, addr: u16) -> u8 {
+        let high = addr == 0x213A;
+        let value = match high {
+            true => (self.vram_prefetch >> 8) as u8,
+            false => (self.vram_prefetch & 0xFF) as u8,
+        };
+
+        if high == (self.vmain & VMAIN_INCREMENT_HIGH > 0) {
+            self.prefetch_vram();
+            self.increment_vram_address();
+        }
+        value
+    }
+
+    fn prefetch_vram(&mut self) {
+        let word = (self.translated_vram_address() as usize) << 1;
+        self.vram_prefetch = ((self.vram[word + 1] as u16) << 8) | self.vram[word] as u16;
+    }
+
+    fn increment_vram_address(&mut self) {
+        let step = match self.vmain & 0x3 {
+            0 => 1,
+            1 => 32,
+            _ => 128,
+        };
+        self.vmadd = self.vmadd.wrapping_add(step);
+    }
+
+    // VMAIN bits 2-3 rotate the low bits of the address (for bitmap-style uploads)
+    fn translated_vram_address(&self) -> u16 {
+        let addr = self.vmadd & 0x7FFF;
+        match (self.vmain >> 2) & 0x3 {
+            1 => (addr & 0x7F00) | ((addr & 0xE0) >> 5) | ((addr & 0x1F) << 3),
+            2 => (addr & 0x7E00) | ((addr & 0x1C0) >> 6) | ((addr & 0x3F) << 3),
+            3 => (addr & 0x7C00) | ((addr & 0x380) >> 7) | ((addr & 0x7F) << 3),
+            _ => addr,
+        }
+    }
+
+    // OAMADDL/OAMADDH - OAM Address and Priority Rotation
+    pub fn write_oam_address(&mut self, addr: u16, val: u8) {
+        match addr {
+            0x2102 => self.oamadd = (self.oamadd & 0x100) | val as u16,
+            _ => {
+                self.oamadd = (self.oamadd & 0xFF) | ((val as u16 & 0x1) << 8);
+                self.oam_priority = val & 0x80 > 0;
+            }
+        }
+        self.oam_addr = self.oamadd << 1;
+    }
+
+    // OAMDATA - OAM Data Write, the low table is written in word pairs
+    pub fn write_oam_data(&mut self, val: u8) {
+        let addr = self.oam_addr as usize;
+        if addr < 0x200 {
+            if addr & 0x1 == 0 {
+                self.oam_latch = val;
+            } else {
+                self.oam[addr - 1] = self.oam_latch;
+                self.oam[addr] = val;
+            }
+        } else {
+            self.oam[0x200 | (addr & 0x1F)] = val;
+        }
+        self.oam_addr = (self.oam_addr + 1) & 0x3FF;
+    }
+
+    // RDOAM - OAM Data Read
+    pub fn read_oam_data(&mut self) -> u8 {
+        let addr = self.oam_addr as usize;
+        let value = match addr < 0x200 {
+            true => self.oam[addr],
+            false => self.oam[0x200 | (addr & 0x1F)],
+        };
+        self.oam_addr = (self.oam_addr + 1) & 0x3FF;
+        value
+    }
+
+    // CGADD - Palette CGRAM Address (word address)
+    pub fn write_cgram_address(&mut self, val: u8) {
+        self.cgram_addr = (val as u16) << 1;
+    }
+
+    // CGDATA - Palette CGRAM Data Write, colors are written in word pairs
+    pub fn write_cgram_data(&mut self, val: u8) {
+        let addr = self.cgram_addr as usize;
+        if addr & 0x1 == 0 {
+            self.cgram_latch = val;
+        } else {
+            self.cgram[addr - 1] = self.cgram_latch;
+            self.cgram[addr] = val & 0x7F;
+        }
+        self.cgram_addr = (self.cgram_addr + 1) & 0x1FF;
+    }
+
+    // RDCGRAM - Palette CGRAM Data Read
+    pub fn read_cgram_data(&mut self) -> u8 {
+        let value = self.cgram[self.cgram_addr as usize];
+        self.cgram_addr = (self.cgram_addr + 1) & 0x1FF;
+        value
+    }
+
+    // 15-bit BGR color at a palette index
+    pub fn color(&self, index: u8) -> u16 {
+        let addr = (index as usize) << 1;
+        ((self.cgram[addr + 1] as u16) << 8) | self.cgram[addr] as u16
+    }
+}
@@ -0,0 +1,234 @@
+use crate::movie::state::{SaveState, StateReader, StateWriter};
+use crate::ppu::framebuffer::{Framebuffer, INIDISP_FORCE_BLANK};
+use crate::ppu::memory::{CGRAM_SIZE, OAM_SIZE, VRAM_SIZE};
+use crate::ppu::timing::VideoStandard;
+use log::debug;
+use std::error::Error;
+
+pub const PPU1_VERSION: u8 = 0x01;
+pub const PPU2_VERSION: u8 = 0x03;
+
+pub const STAT78_FIELD: u8 = 0x1 << 7;
+pub const STAT78_LATCHED: u8 = 0x1 << 6;
+pub const STAT78_PAL: u8 = 0x1 << 4;
+
+pub struct Ppu {
+    pub standard: VideoStandard,
+    pub h_counter: u16,
+    pub v_counter: u16,
+    pub frame: u64,
+    pub interlace_field: bool,
+    pub latched_h: u16,
+    pub latched_v: u16,
+    pub counters_latched: bool,
+    pub vram: Box<[u8]>,
+    pub cgram: Box<[u8]>,
+    pub oam: Box<[u8]>,
+    pub vmain: u8,
+    pub vmadd: u16,
+    pub oamadd: u16,
+    pub oam_priority: bool,
+    pub inidisp: u8,
+    pub bgmode: u8,
+    pub setini: u8,
+    pub framebuffer: Framebuffer,
+    pub(crate) vram_prefetch: u16,
+    pub(crate) oam_addr: u16,
+    pub(crate) oam_latch: u8,
+    pub(crate) cgram_addr: u16,
+    pub(crate) cgram_latch: u8,
+    ophct_hi: bool,
+    opvct_hi: bool,
+}
+
+impl Ppu {
+    pub fn new(standard: VideoStandard) -> Self {
+        Self {
+            standard,
+            h_counter: 0,
+            v_counter: 0,
+            frame: 0,
+            interlace_field: false,
+            latched_h: 0,
+            latched_v: 0,
+            counters_latched: false,
+            vram: vec![0u8; VRAM_SIZE].into_boxed_slice(),
+            cgram: vec![0u8; CGRAM_SIZE].into_boxed_slice(),
+            oam: vec![0u8; OAM_SIZE].into_boxed_slice(),
+            vmain: 0,
+            vmadd: 0,
+            oamadd: 0,
+            oam_priority: false,
+            inidisp: INIDISP_FORCE_BLANK,
+            bgmode: 0,
+            setini: 0,
+            framebuffer: Framebuffer::new(),
+            vram_prefetch: 0,
+            oam_addr: 0,
+            oam_latch: 0,
+            cgram_addr: 0,
+            cgram_latch: 0,
+            ophct_hi: false,
+            opvct_hi: false,
+        }
+    }
+
+    // $2100-$213F, None means the register is write-only (open bus)
+    pub fn read_register(&mut self, addr: u16) -> Option<u8> {
+        match addr {
+            // RDOAM - OAM Data Read
+            0x2138 => Some(self.read_oam_data()),
+
+            // RDVRAML/RDVRAMH - VRAM Data Read
+            0x2139 | 0x213A => Some(self.read_vram_data(addr)),
+
+            // RDCGRAM - Palette CGRAM Data Read
+            0x213B => Some(self.read_cgram_data()),
+
+            // SLHV - Latch H/V-Counter by Software
+            0x2137 => {
+                self.latch_counters();
+                None
+            }
+
+            // OPHCT - Horizontal Counter Latch (low byte, then bit 8)
+            0x213C => {
+                let value = match self.ophct_hi {
+                    true => ((self.latched_h >> 8) & 0x1) as u8,
+                    false => (self.latched_h & 0xFF) as u8,
+                };
+                self.ophct_hi = !self.ophct_hi;
+                Some(value)
+            }
+
+            // OPVCT - Vertical Counter Latch (low byte, then bit 8)
+            0x213D => {
+                let value = match self.opvct_hi {
+                    true => ((self.latched_v >> 8) & 0x1) as u8,
+                    false => (self.latched_v & 0xFF) as u8,
+                };
+                self.opvct_hi = !self.opvct_hi;
+                Some(value)
+            }
+
+            // STAT77 - PPU1 Status and Version Number
+            0x213E => Some(PPU1_VERSION),
+
+            // STAT78 - PPU2 Status and Version Number
+            0x213F => {
+                let mut value = PPU2_VERSION;
+                if self.interlace_field {
+                    value |= STAT78_FIELD;
+                }
+                if self.counters_latched {
+                    value |= STAT78_LATCHED;
+                }
+                if self.standard == VideoStandard::Pal {
+                    value |= STAT78_PAL;
+                }
+
+                // reading STAT78 resets the OPHCT/OPVCT flip-flops and the latch flag
+                self.ophct_hi = false;
+                self.opvct_hi = false;
+                self.counters_latched = false;
+                Some(value)
+            }
+
+            _ => None,
+        }
+    }
+
+    pub fn write_register(&mut self, addr: u16, val: u8) {
+        match addr {
+            // INIDISP - Display Control 1
+            0x2100 => self.inidisp = val,
+
+            // OAMADDL/OAMADDH - OAM Address and Priority Rotation
+            0x2102 | 0x2103 => self.write_oam_address(addr, val),
+
+            // OAMDATA - OAM Data Write
+            0x2104 => self.write_oam_data(val),
+
+            // BGMODE - BG Mode and BG Character Size
+            0x2105 => self.bgmode = val,
+
+            // VMAIN - VRAM Address Increment Mode
+            0x2115 => self.vmain = val,
+
+            // VMADDL/VMADDH - VRAM Address
+            0x2116 | 0x2117 => self.write_vram_address(addr, val),
+
+            // VMDATAL/VMDATAH - VRAM Data Write
+            0x2118 | 0x2119 => self.write_vram_data(addr, val),
+
+            // CGADD - Palette CGRAM Address
+            0x2121 => self.write_cgram_address(val),
+
+            // CGDATA - Palette CGRAM Data Write
+            0x2122 => self.write_cgram_data(val),
+
+            // SETINI - Display Control 2
+            0x2133 => self.setini = val,
+
+            _ => debug!("PPU write 0x{:X} = 0x{:X} (ignored)", addr, val),
+        }
+    }
+}
+
+// the picture isn't part of a save state, the next frame draws it again
+impl SaveState for Ppu {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.u16(self.h_counter);
+        w.u16(self.v_counter);
+        w.u64(self.frame);
+        w.bool(self.interlace_field);
+        w.u16(self.latched_h);
+        w.u16(self.latched_v);
+        w.bool(self.counters_latched);
+        w.bytes(&self.vram);
+        w.bytes(&self.cgram);
+        w.bytes(&self.oam);
+        w.u8(self.vmain);
+        w.u16(self.vmadd);
+        w.u16(self.oamadd);
+        w.bool(self.oam_priority);
+        w.u8(self.inidisp);
+        w.u8(self.bgmode);
+        w.u8(self.setini);
+        w.u16(self.vram_prefetch);
+        w.u16(self.oam_addr);
+        w.u8(self.oam_latch);
+        w.u16(self.cgram_addr);
+        w.u8(self.cgram_latch);
+        w.bool(self.ophct_hi);
+        w.bool(self.opvct_hi);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), Box<dyn Error>> {
+        self.h_counter = r.u16()?;
+        self.v_counter = r.u16()?;
+        self.frame = r.u64()?;
+        self.interlace_field = r.bool()?;
+        self.latched_h = r.u16()?;
+        self.latched_v = r.u16()?;
+        self.counters_latched = r.bool()?;
+        r.bytes(&mut self.vram)?;
+        r.bytes(&mut self.cgram)?;
+        r.bytes(&mut self.oam)?;
+        self.vmain = r.u8()?;
+        self.vmadd = r.u16()?;
+        self.oamadd = r.u16()?;
+        self.oam_priority = r.bool()?;
+        self.inidisp = r.u8()?;
+        self.bgmode = r.u8()?;
+        self.setini = r.u8()?;
+        self.vram_prefetch = r.u16()?;
+        self.oam_addr = r.u16()?;
+        self.oam_latch = r.u8()?;
+        self.cgram_addr = r.u16()?;
+        self.cgram_latch = r.u8()?;
+        self.ophct_hi = r.bool()?;
+        self.opvct_hi = r.bool()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,239 @@
+use crate::archive::inflate::unzlib;
+use crate::checksum::{Crc32, adler32};
+use crate::ppu::framebuffer::Framebuffer;
+use std::error::Error;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+
+// largest block a stored (uncompressed) deflate block can hold
+const STORED_BLOCK_SIZE: usize = 0xFFFF;
+
+// binary PPM (P6)
+pub fn write_ppm<W: Write>(fb: &Framebuffer, out: &mut W) -> io::Result<()> {
+    write!(out, "P6\n{} {}\n255\n", fb.width(), fb.height())?;
+    out.write_all(&fb.to_rgb())
+}
+
+fn write_chunk<W: Write>(out: &mut W, kind: &[u8; 4], data: &[u8]) -> io::Result<()> {
+    let mut crc = Crc32::new();
+    crc.update(kind);
+    crc.update(data);
+
+    out.write_all(&(data.len() as u32).to_be_bytes())?;
+    out.write_all(kind)?;
+    out.write_all(data)?;
+    out.write_all(&crc.finish().to_be_bytes())
+}
+
+// a zlib stream made of stored deflate blocks, bigger than need be but trivially lossless
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let blocks = data.len().div_ceil(STORED_BLOCK_SIZE).max(1);
+    let mut out = Vec::with_capacity(data.len() + blocks * 5 + 6);
+    // deflate with a 32K window, no preset dictionary, fastest level
+    out.extend_from_slice(&[0x78, 0x01]);
+
+    let mut chunks = data.chunks(STORED_BLOCK_SIZE).peekable();
+    if chunks.peek().is_none() {
+        out.extend_from_slice(&[0x01, 0x00, 0x00, 0xFF, 0xFF]);
+    }
+    while let Some(chunk) = chunks.next() {
+        let last = chunks.peek().is_none();
+        let len = chunk.len() as u16;
+        out.push(last as u8);
+        out.extend_from_slice(&len.to_le_bytes());
+        out.extend_from_slice(&(!len).to_le_bytes());
+        out.extend_from_slice(chunk);
+    }
+
+    out.extend_from_slice(&adler32(data).to_be_bytes());
+    out
+}
+
+// 8-bit RGB PNG without filtering or compression
+pub fn write_png<W: Write>(fb: &Framebuffer, out: &mut W) -> io::Result<()> {
+    write_png_rgb(fb.width(), fb.height(), &fb.to_rgb(), out)
+}
+
+// same, for any width x height RGB image
+pub fn write_png_rgb<W: Write>(
+    width: usize,
+    height: usize,
+    rgb: &[u8],
+    out: &mut W,
+) -> io::Result<()> {
+    let mut header = Vec::with_capacity(13);
+    header.extend_from_slice(&(width as u32).to_be_bytes());
+    header.extend_from_slice(&(height as u32).to_be_bytes());
+    // bit depth 8, truecolor, deflate, adaptive filtering, no interlace
+    header.extend_from_slice(&[8, 2, 0, 0, 0]);
+
+    // every scanline starts with its filter type, 0 is none
+    let mut scanlines = Vec::with_capacity((width * 3 + 1) * height);
+    for row in rgb.chunks(width * 3) {
+        scanlines.push(0);
+        scanlines.extend_from_slice(row);
+    }
+
+    out.write_all(&PNG_SIGNATURE)?;
+    write_chunk(out, b"IHDR", &header)?;
+    write_chunk(out, b"IDAT", &zlib_stored(&scanlines))?;
+    write_chunk(out, b"IEND", &[])
+}
+
+fn paeth(a: u8, b: u8, c: u8) -> u8 {
+    let p = a as i16 + b as i16 - c as i16;
+    let (pa, pb, pc) = (
+        (p - a as i16).abs(),
+        (p - b as i16).abs(),
+        (p - c as i16).abs(),
+    );
+    if pa <= pb && pa <= pc {
+        a
+    } else if pb <= pc {
+        b
+    } else {
+        c
+    }
+}
+
+// decode an 8-bit RGB PNG into (width, height, RGB triplets)
+pub fn read_png(data: &[u8]) -> Result<(usize, usize, Vec<u8>), Box<dyn Error>> {
+    if !data.starts_with(&PNG_SIGNATURE) {
+        return Err("not a PNG file".into());
+    }
+
+    let mut pos = PNG_SIGNATURE.len();
+    let mut size = None;
+    let mut idat = Vec::new();
+    while pos + 12 <= data.len() {
+        let len =
+            u32::from_be_bytes([data[pos], data[pos + 1], data[pos + 2], data[pos + 3]]) as usize;
+        let kind = &data[pos + 4..pos + 8];
+        let body = data
+            .get(pos + 8..pos + 8 + len)
+            .ok_or("truncated PNG chunk")?;
+        match kind {
+            b"IHDR" => {
+                if body.len() != 13 || body[8..] != [8, 2, 0, 0, 0] {
+                    return Err("only 8-bit RGB PNGs are supported".into());
+                }
+                let width = u32::from_be_bytes([body[0], body[1], body[2], body[3]]) as usize;
+                let height = u32::from_be_bytes([body[4], body[5], body[6], body[7]]) as usize;
+                size = Some((width, height));
+            }
+            b"IDAT" => idat.extend_from_slice(body),
+            b"IEND" => break,
+            _ => {}
+        }
+        pos += 12 + len;
+    }
+
+    let (width, height) = size.ok_or("missing PNG header")?;
+    let scanlines = unzlib(&idat)?;
+    let stride = width * 3;
+    if scanlines.len() != (stride + 1) * height {
+        return Err("PNG image data has the wrong size".into());
+    }
+
+    let mut rgb = vec![0u8; stride * height];
+    for y in 0..height {
+        let filter = scanlines[y * (stride + 1)];
+        let line = &scanlines[y * (stride + 1) + 1..(y + 1) * (stride + 1)];
+        for x in 0..stride {
+            let left = match x >= 3 {
+                true => rgb[y * stride + x - 3],
+                false => 0,
+            };
+            let up = match y > 0 {
+                true => rgb[(y - 1) * stride + x],
+                false => 0,
+            };
+            let up_left = match x >= 3 && y > 0 {
+                true => rgb[(y - 1) * stride + x - 3],
+                false => 0,
+            };
+            let predicted = match filter {
+                0 => 0,
+                1 => left,
+                2 => up,
+                3 => ((left as u16 + up as u16) / 2) as u8,
+                4 => paeth(left, up, up_left),
+                _ => return Err("invalid PNG filter".into()),
+            };
+            rgb[y * stride + x] = line[x].wrapping_add(predicted);
+        }
+    }
+    Ok((width, height, rgb))
+}
+
+// the format is picked from the extension, .png or .ppm
+pub fn save(fb: &Framebuffer, path: &str) -> Result<(), Box<dyn Error>> {
+    let extension = Path::new(path)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext.to_ascii_lowercase());
+
+    let mut out = BufWriter::new(File::create(path)?);
+    match extension.as_deref() {
+        Some("png") => write_png(fb, &mut out)?,
+        Some("ppm") => write_ppm(fb, &mut out)?,
+        _ => return Err(format!("unsupported screenshot format: {}", path).into()),
+    }
+    out.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::checksum::crc32;
+
+    #[test]
+    fn ppm_layout() {
+        let fb = Framebuffer::new();
+        let mut out = Vec::new();
+        write_ppm(&fb, &mut out).unwrap();
+        assert!(out.starts_with(b"P6\n256 224\n255\n"));
+        assert_eq!(out.len(), 15 + 256 * 224 * 3);
+    }
+
+    #[test]
+    fn png_layout() {
+        let fb = Framebuffer::new();
+        let mut out = Vec::new();
+        write_png(&fb, &mut out).unwrap();
+
+        assert_eq!(out[..8], PNG_SIGNATURE);
+        assert_eq!(&out[8..16], &[0, 0, 0, 13, b'I', b'H', b'D', b'R']);
+        assert_eq!(&out[16..20], &256u32.to_be_bytes());
+        assert_eq!(&out[20..24], &224u32.to_be_bytes());
+        assert_eq!(&out[29..33], &crc32(&out[12..29]).to_be_bytes());
+        assert!(out.ends_with(&[0, 0, 0, 0, b'I', b'E', b'N', b'D', 0xAE, 0x42, 0x60, 0x82]));
+    }
+
+    #[test]
+    fn png_round_trip() {
+        let rgb: Vec<u8> = (0..40 * 30 * 3).map(|i| (i * 7) as u8).collect();
+        let mut out = Vec::new();
+        write_png_rgb(40, 30, &rgb, &mut out).unwrap();
+        assert_eq!(read_png(&out).unwrap(), (40, 30, rgb));
+    }
+
+    #[test]
+    fn zlib_stored_blocks() {
+        let data = vec![0x5A; STORED_BLOCK_SIZE + 10];
+        let out = zlib_stored(&data);
+        assert_eq!(&out[..2], &[0x78, 0x01]);
+        // first block is not final and full
+        assert_eq!(&out[2..7], &[0x00, 0xFF, 0xFF, 0x00, 0x00]);
+        // second block is final with the remainder
+        let second = 7 + STORED_BLOCK_SIZE;
+        assert_eq!(&out[second..second + 5], &[0x01, 10, 0, !10, 0xFF]);
+        assert_eq!(out.len(), 2 + 5 * 2 + data.len() + 4);
+        // the header is a multiple of 31 as zlib requires
+        assert!(u16::from_be_bytes([out[0], out[1]]).is_multiple_of(31));
+    }
+}
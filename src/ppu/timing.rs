@@ -0,0 +1,219 @@
+use crate::apu::scheduler::MASTER_CLOCK_HZ;
+use crate::ppu::regs::Ppu;
+use crate::rom::Region;
+use std::error::Error;
+
+// one dot is four master cycles (ignoring the two long dots at H=323 and H=327)
+pub const MASTER_CYCLES_PER_DOT: u64 = 4;
+pub const DOTS_PER_LINE: u16 = 341;
+pub const HBLANK_START_DOT: u16 = 274;
+pub const HBLANK_END_DOT: u16 = 1;
+pub const VBLANK_START_LINE: u16 = 225;
+pub const PAL_MASTER_CLOCK_HZ: u64 = 21_281_370;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VideoStandard {
+    Ntsc,
+    Pal,
+}
+
+impl VideoStandard {
+    // Europe, Asia outside Japan and Korea, and Australia got PAL consoles. Brazil's PAL-M
+    // runs at 60Hz like NTSC.
+    pub fn from_region(region: &Region) -> Self {
+        match region {
+            Region::Europe
+            | Region::Sweden
+            | Region::Finland
+            | Region::Denmark
+            | Region::France
+            | Region::Netherlands
+            | Region::Spain
+            | Region::Germany
+            | Region::Italy
+            | Region::China
+            | Region::Indonesia
+            | Region::Australia => VideoStandard::Pal,
+            _ => VideoStandard::Ntsc,
+        }
+    }
+
+    pub fn parse(text: &str) -> Result<Self, Box<dyn Error>> {
+        match text {
+            "ntsc" => Ok(VideoStandard::Ntsc),
+            "pal" => Ok(VideoStandard::Pal),
+            _ => Err(format!("unknown region {}, expected ntsc or pal", text).into()),
+        }
+    }
+
+    // the master clock crystal, the CPU and PPU divide it down
+    pub fn master_clock_hz(&self) -> u64 {
+        match self {
+            VideoStandard::Ntsc => MASTER_CLOCK_HZ,
+            VideoStandard::Pal => PAL_MASTER_CLOCK_HZ,
+        }
+    }
+
+    pub fn lines_per_frame(&self) -> u16 {
+        match self {
+            VideoStandard::Ntsc => 262,
+            VideoStandard::Pal => 312,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PpuEvent {
+    // H=0 on a visible or blanked line
+    LineStart,
+    // H=274, the right border starts
+    HBlankStart,
+    // V=225, H=0
+    VBlankStart,
+    // V=0, H=0
+    FrameStart,
+}
+
+impl Ppu {
+    // advance the beam by one dot, reporting what happened at the new position
+    pub fn step_dot(&mut self) -> Option<PpuEvent> {
+        self.h_counter += 1;
+
+        if self.h_counter == HBLANK_START_DOT {
+            return Some(PpuEvent::HBlankStart);
+        }
+
+        if self.h_counter < DOTS_PER_LINE {
+            return None;
+        }
+
+        self.h_counter = 0;
+        self.v_counter += 1;
+
+        if self.v_counter == self.standard.lines_per_frame() {
+            self.v_counter = 0;
+            self.frame += 1;
+            self.interlace_field = !self.interlace_field;
+            return Some(PpuEvent::FrameStart);
+        }
+
+        if self.v_counter == VBLANK_START_LINE {
+            return Some(PpuEvent::VBlankStart);
+        }
+
+        Some(PpuEvent::LineStart)
+    }
+
+    pub fn in_vblank(&self) -> bool {
+        self.v_counter >= VBLANK_START_LINE
+    }
+
+    pub fn in_hblank(&self) -> bool {
+        self.h_counter < HBLANK_END_DOT || self.h_counter >= HBLANK_START_DOT
+    }
+
+    // copy the current beam position into OPHCT/OPVCT (reading SLHV at $2137)
+    pub fn latch_counters(&mut self) {
+        self.latched_h = self.h_counter;
+        self.latched_v = self.v_counter;
+        self.counters_latched = true;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu::bus::Bus;
+    use crate::ppu::regs::STAT78_PAL;
+
+    fn run_until(ppu: &mut Ppu, wanted: PpuEvent) -> u64 {
+        let mut dots = 0;
+        loop {
+            dots += 1;
+            if ppu.step_dot() == Some(wanted) {
+                return dots;
+            }
+            if dots > 1_000_000 {
+                panic!("event never happened");
+            }
+        }
+    }
+
+    #[test]
+    fn frame_length() {
+        let mut ppu = Ppu::new(VideoStandard::Ntsc);
+        let dots = run_until(&mut ppu, PpuEvent::FrameStart);
+        assert_eq!(dots, 262 * DOTS_PER_LINE as u64);
+        assert_eq!(ppu.frame, 1);
+
+        let mut ppu = Ppu::new(VideoStandard::Pal);
+        let dots = run_until(&mut ppu, PpuEvent::FrameStart);
+        assert_eq!(dots, 312 * DOTS_PER_LINE as u64);
+    }
+
+    #[test]
+    fn vblank_and_hblank() {
+        let mut ppu = Ppu::new(VideoStandard::Ntsc);
+        assert!(!ppu.in_vblank());
+        assert!(ppu.in_hblank());
+
+        run_until(&mut ppu, PpuEvent::HBlankStart);
+        assert_eq!(ppu.h_counter, HBLANK_START_DOT);
+        assert!(ppu.in_hblank());
+
+        run_until(&mut ppu, PpuEvent::VBlankStart);
+        assert_eq!(ppu.v_counter, VBLANK_START_LINE);
+        assert!(ppu.in_vblank());
+    }
+
+    #[test]
+    fn standards() {
+        assert_eq!(
+            VideoStandard::from_region(&Region::Germany),
+            VideoStandard::Pal
+        );
+        assert_eq!(
+            VideoStandard::from_region(&Region::Brazil),
+            VideoStandard::Ntsc
+        );
+        assert_eq!(VideoStandard::parse("pal").unwrap(), VideoStandard::Pal);
+        assert!(VideoStandard::parse("secam").is_err());
+
+        // about 60.1 and 50.0 frames a second
+        let rate = |standard: VideoStandard| {
+            standard.master_clock_hz() as f64
+                / (standard.lines_per_frame() as u64 * DOTS_PER_LINE as u64 * MASTER_CYCLES_PER_DOT)
+                    as f64
+        };
+        assert!((rate(VideoStandard::Ntsc) - 60.1).abs() < 0.1);
+        assert!((rate(VideoStandard::Pal) - 50.0).abs() < 0.1);
+
+        // region lock checks read the PAL bit of STAT78
+        let mut bus = Bus::new();
+        assert_eq!(bus.read_byte(0x213F) & STAT78_PAL, 0);
+        bus.set_video_standard(VideoStandard::Pal);
+        assert_ne!(bus.read_byte(0x213F) & STAT78_PAL, 0);
+        assert_eq!(bus.apu.master_clock_hz, PAL_MASTER_CLOCK_HZ);
+    }
+
+    #[test]
+    fn counter_latch() {
+        let mut ppu = Ppu::new(VideoStandard::Ntsc);
+        for _ in 0..(DOTS_PER_LINE as u32 * 3 + 0x123) {
+            ppu.step_dot();
+        }
+        ppu.read_register(0x2137);
+        ppu.step_dot();
+
+        // low byte first, then the 9th bit
+        assert_eq!(ppu.read_register(0x213C), Some(0x23));
+        assert_eq!(ppu.read_register(0x213C), Some(0x01));
+        assert_eq!(ppu.read_register(0x213D), Some(0x03));
+        assert_eq!(ppu.read_register(0x213D), Some(0x00));
+
+        // STAT78 reports the latch once, then resets the flip-flops
+        assert_eq!(ppu.read_register(0x213F).unwrap() & 0x40, 0x40);
+        assert_eq!(ppu.read_register(0x213F).unwrap() & 0x40, 0x0);
+        assert_eq!(ppu.read_register(0x213C), Some(0x23));
+    }
+}
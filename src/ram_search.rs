@@ -0,0 +1,369 @@
+use crate::cpu::bus::Bus;
+use crate::rom::ROM;
+use std::error::Error;
+use std::fmt;
+
+// RAM search and watch: narrow WRAM or SRAM down to the addresses that hold a game variable by
+// comparing snapshots, then print the variables every frame.
+
+const WRAM_START: u32 = 0x7E0000;
+const WRAM_SIZE: usize = 0x20000;
+
+// how the bytes at an address are read, little endian like the 65816
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct View {
+    // 1, 2 or 3 bytes
+    pub bytes: usize,
+    pub signed: bool,
+}
+
+impl View {
+    // "u8", "s16", "u24" ...
+    pub fn parse(text: &str) -> Result<Self, Box<dyn Error>> {
+        let signed = match text.get(..1) {
+            Some("u") => false,
+            Some("s") => true,
+            _ => return Err(format!("unknown view {}, expected u8, s16, u24 ...", text).into()),
+        };
+        let bytes = match &text[1..] {
+            "8" => 1,
+            "16" => 2,
+            "24" => 3,
+            _ => return Err(format!("unknown view {}, expected u8, s16, u24 ...", text).into()),
+        };
+        Ok(Self { bytes, signed })
+    }
+
+    pub fn value(&self, data: &[u8]) -> i64 {
+        let raw = data[..self.bytes]
+            .iter()
+            .rev()
+            .fold(0u32, |value, byte| (value << 8) | *byte as u32);
+        let bits = self.bytes as u32 * 8;
+        match self.signed {
+            true => ((raw << (32 - bits)) as i32 >> (32 - bits)) as i64,
+            false => raw as i64,
+        }
+    }
+}
+
+impl fmt::Display for View {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let sign = match self.signed {
+            true => 's',
+            false => 'u',
+        };
+        write!(f, "{}{}", sign, self.bytes * 8)
+    }
+}
+
+// where the S-CPU sees the cartridge RAM
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SramMap {
+    // the lower 32K of banks $70-$7D
+    LoRom,
+    // 8K at $6000 of banks $20-$3F
+    HiRom,
+    // one block from a bank on: SA-1 BW-RAM at $40, Super FX game RAM at $70
+    Linear(u32),
+}
+
+// the memory a search runs over, as one block of bytes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Memory {
+    Wram,
+    Sram { size: usize, map: SramMap },
+}
+
+impl Memory {
+    // the SA-1 and Super FX RAM is as big as the chip made it, which can be more than the header
+    // says or there even when it says nothing
+    pub fn parse(text: &str, rom: &ROM, bus: &Bus) -> Result<Self, Box<dyn Error>> {
+        let (size, map) = if let Some(sa1) = &bus.sa1 {
+            (sa1.cpu.bus.bwram.len(), SramMap::Linear(0x40))
+        } else if let Some(superfx) = &bus.superfx {
+            (superfx.ram.len(), SramMap::Linear(0x70))
+        } else if rom.is_hirom() {
+            (rom.ram_size as usize, SramMap::HiRom)
+        } else {
+            (rom.ram_size as usize, SramMap::LoRom)
+        };
+        match text {
+            "wram" => Ok(Memory::Wram),
+            "sram" if size == 0 => Err("the cartridge has no SRAM".into()),
+            "sram" => Ok(Memory::Sram { size, map }),
+            _ => Err(format!("unknown memory {}, expected wram or sram", text).into()),
+        }
+    }
+
+    pub fn size(&self) -> usize {
+        match self {
+            Memory::Wram => WRAM_SIZE,
+            Memory::Sram { size, .. } => *size,
+        }
+    }
+
+    // the bus address of a byte
+    pub fn address(&self, offset: usize) -> u32 {
+        let offset = offset as u32;
+        match self {
+            Memory::Wram => WRAM_START + offset,
+            Memory::Sram { map, .. } => match map {
+                SramMap::LoRom => ((0x70 + (offset >> 15)) << 16) | (offset & 0x7FFF),
+                SramMap::HiRom => ((0x20 + (offset >> 13)) << 16) | 0x6000 | (offset & 0x1FFF),
+                SramMap::Linear(bank) => (bank << 16) + offset,
+            },
+        }
+    }
+
+    // SRAM goes through Bus::peek, a coprocessor may have it
+    pub fn snapshot(&self, bus: &Bus) -> Vec<u8> {
+        match self {
+            Memory::Wram => bus
+                .read_bytes(WRAM_START as usize..WRAM_START as usize + WRAM_SIZE)
+                .to_vec(),
+            Memory::Sram { size, .. } => (0..*size)
+                .map(|offset| bus.peek(self.address(offset)))
+                .collect(),
+        }
+    }
+}
+
+// what to keep, against a value or (without one) the previous snapshot
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Filter {
+    Equal(Option<i64>),
+    Greater(Option<i64>),
+    Less(Option<i64>),
+    Changed,
+    Unchanged,
+}
+
+impl Filter {
+    // "changed", "equal", "less=10" ...
+    pub fn parse(text: &str) -> Result<Self, Box<dyn Error>> {
+        let (name, value) = match text.split_once('=') {
+            Some((name, value)) => (name, Some(value.parse::<i64>()?)),
+            None => (text, None),
+        };
+        match (name, value) {
+            ("equal", value) => Ok(Filter::Equal(value)),
+            ("greater", value) => Ok(Filter::Greater(value)),
+            ("less", value) => Ok(Filter::Less(value)),
+            ("changed", None) => Ok(Filter::Changed),
+            ("unchanged", None) => Ok(Filter::Unchanged),
+            _ => Err(format!("unknown filter {}", text).into()),
+        }
+    }
+
+    fn keeps(&self, current: i64, previous: i64) -> bool {
+        match *self {
+            Filter::Equal(value) => current == value.unwrap_or(previous),
+            Filter::Greater(value) => current > value.unwrap_or(previous),
+            Filter::Less(value) => current < value.unwrap_or(previous),
+            Filter::Changed => current != previous,
+            Filter::Unchanged => current == previous,
+        }
+    }
+}
+
+pub struct RamSearch {
+    pub memory: Memory,
+    pub view: View,
+    previous: Vec<u8>,
+    // offsets into the memory still in the running
+    pub candidates: Vec<usize>,
+}
+
+impl RamSearch {
+    // start with every address, taking the first snapshot
+    pub fn new(memory: Memory, view: View, bus: &Bus) -> Self {
+        let previous = memory.snapshot(bus);
+        let candidates = (0..=previous.len().saturating_sub(view.bytes)).collect();
+        Self {
+            memory,
+            view,
+            previous,
+            candidates,
+        }
+    }
+
+    // drop the addresses the filter doesn't keep and take a new snapshot
+    pub fn filter(&mut self, bus: &Bus, filter: Filter) {
+        let current = self.memory.snapshot(bus);
+        let view = self.view;
+        let previous = &self.previous;
+        self.candidates.retain(|offset| {
+            filter.keeps(
+                view.value(&current[*offset..]),
+                view.value(&previous[*offset..]),
+            )
+        });
+        self.previous = current;
+    }
+
+    // bus address and value of the addresses left, as of the last snapshot
+    pub fn results(&self) -> Vec<(u32, i64)> {
+        self.candidates
+            .iter()
+            .map(|offset| {
+                (
+                    self.memory.address(*offset),
+                    self.view.value(&self.previous[*offset..]),
+                )
+            })
+            .collect()
+    }
+}
+
+pub struct Watch {
+    pub name: String,
+    pub address: u32,
+    pub view: View,
+}
+
+impl Watch {
+    // [NAME=]ADDRESS[:VIEW], e.g. lives=7E0DBE:u8
+    pub fn parse(text: &str) -> Result<Self, Box<dyn Error>> {
+        let (name, spec) = match text.split_once('=') {
+            Some((name, spec)) => (Some(name), spec),
+            None => (None, text),
+        };
+        let (address, view) = match spec.split_once(':') {
+            Some((address, view)) => (address, View::parse(view)?),
+            None => (
+                spec,
+                View {
+                    bytes: 1,
+                    signed: false,
+                },
+            ),
+        };
+        let parsed = u32::from_str_radix(address, 16)
+            .ok()
+            .filter(|address| *address as usize + view.bytes <= 0x1000000)
+            .ok_or(format!("invalid watch address {}", address))?;
+        Ok(Self {
+            name: name.unwrap_or(address).to_string(),
+            address: parsed,
+            view,
+        })
+    }
+
+    pub fn value(&self, bus: &Bus) -> i64 {
+        let bytes: Vec<u8> = (0..self.view.bytes as u32)
+            .map(|i| bus.peek(self.address + i))
+            .collect();
+        self.view.value(&bytes)
+    }
+}
+
+// "lives=3 7E0100=-2", printed once a frame
+pub fn watch_line(watches: &[Watch], bus: &Bus) -> String {
+    watches
+        .iter()
+        .map(|watch| format!("{}={}", watch.name, watch.value(bus)))
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::coprocessor::sa1::chip::Sa1;
+    use crate::coprocessor::superfx::chip::SuperFx;
+    use crate::rom;
+
+    #[test]
+    fn views() {
+        let data = [0xFE, 0xFF, 0x80];
+        assert_eq!(View::parse("u8").unwrap().value(&data), 0xFE);
+        assert_eq!(View::parse("s8").unwrap().value(&data), -2);
+        assert_eq!(View::parse("u16").unwrap().value(&data), 0xFFFE);
+        assert_eq!(View::parse("s24").unwrap().value(&data), -0x7F0002);
+        assert!(View::parse("u32").is_err());
+        assert_eq!(View::parse("s16").unwrap().to_string(), "s16");
+    }
+
+    #[test]
+    fn search_and_watch() {
+        let mut bus = Bus::new();
+        bus.load_byte(0x7E0DBE, 3);
+        bus.load_byte(0x7E0100, 3);
+        let mut search = RamSearch::new(Memory::Wram, View::parse("u8").unwrap(), &bus);
+        assert_eq!(search.candidates.len(), WRAM_SIZE);
+
+        search.filter(&bus, Filter::parse("equal=3").unwrap());
+        assert_eq!(search.results(), [(0x7E0100, 3), (0x7E0DBE, 3)]);
+
+        // a life is lost
+        bus.load_byte(0x7E0DBE, 2);
+        search.filter(&bus, Filter::parse("less").unwrap());
+        assert_eq!(search.results(), [(0x7E0DBE, 2)]);
+        search.filter(&bus, Filter::Unchanged);
+        assert_eq!(search.candidates.len(), 1);
+
+        let watches = [
+            Watch::parse("lives=7E0DBE").unwrap(),
+            Watch::parse("000100:s16").unwrap(),
+        ];
+        assert_eq!(watch_line(&watches, &bus), "lives=2 000100=3");
+        assert!(Filter::parse("changed=3").is_err());
+    }
+
+    #[test]
+    fn sram_addresses() {
+        let lorom = Memory::Sram {
+            size: 0x10000,
+            map: SramMap::LoRom,
+        };
+        assert_eq!(lorom.address(0x8001), 0x710001);
+        let bwram = Memory::Sram {
+            size: 0x20000,
+            map: SramMap::Linear(0x40),
+        };
+        assert_eq!(bwram.address(0x10001), 0x410001);
+        let hirom = Memory::Sram {
+            size: 0x4000,
+            map: SramMap::HiRom,
+        };
+        assert_eq!(hirom.address(0x2001), 0x216001);
+
+        let mut bus = Bus::new();
+        bus.load_byte(0x216001, 0x42);
+        assert_eq!(hirom.snapshot(&bus)[0x2001], 0x42);
+
+        // BW-RAM lives in the SA-1, not in the flat memory
+        bus.sa1 = Some(Box::new(Sa1::new(vec![0; 0x8000], 0x20000)));
+        bus.poke(0x410001, 0x99);
+        assert_eq!(bus.read_bytes(0x410001..0x410002), [0x00]);
+        assert_eq!(bwram.snapshot(&bus)[0x10001], 0x99);
+        let watch = Watch::parse("bwram=410001").unwrap();
+        assert_eq!(watch_line(&[watch], &bus), "bwram=153");
+    }
+
+    #[test]
+    fn coprocessor_ram_sizes() {
+        // a LoROM without SRAM in its header
+        let rom = rom::open(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/tests/golden/roms/joypad_backdrop.sfc"
+        ))
+        .unwrap();
+        assert_eq!(rom.ram_size, 0);
+        let mut bus = Bus::new();
+        assert!(Memory::parse("sram", &rom, &bus).is_err());
+
+        // the chips have RAM the header doesn't mention
+        bus.superfx = Some(Box::new(SuperFx::new(vec![0; 0x8000], 0)));
+        let game_ram = Memory::parse("sram", &rom, &bus).unwrap();
+        assert_eq!(game_ram.size(), 0x10000);
+        assert_eq!(game_ram.address(0xFFFF), 0x70FFFF);
+
+        bus.superfx = None;
+        bus.sa1 = Some(Box::new(Sa1::new(vec![0; 0x8000], 0)));
+        let bwram = Memory::parse("sram", &rom, &bus).unwrap();
+        assert_eq!(bwram.size(), 0x800);
+        assert_eq!(bwram.address(0x7FF), 0x4007FF);
+    }
+}
@@ -1,8 +1,18 @@
+use crate::archive::gzip::{GZIP_MAGIC, gunzip};
+use crate::archive::zip::{ZIP_MAGIC, ZipArchive};
+use crate::coprocessor::sa1::chip::Sa1;
+use crate::coprocessor::superfx::chip::SuperFx;
+use crate::coprocessor::upd7725::chip::{Dsp, Mapping};
+use crate::coprocessor::upd7725::firmware::Model;
 use crate::cpu::bus::Bus;
-use log::{debug, info};
+use crate::database::{self, Database, Peripheral};
+use crate::patch::{self, PATCH_EXTENSIONS};
+use crate::ppu::timing::VideoStandard;
+use log::{debug, info, warn};
 use std::error::Error;
 use std::fmt;
 use std::fs::read;
+use std::path::{Path, PathBuf};
 
 #[derive(Debug)]
 pub enum MapMode {
@@ -15,36 +25,145 @@ pub enum MapMode {
     ExHiRom3_58MHz, // 0x35
 }
 
+impl MapMode {
+    fn from_byte(byte: u8) -> Result<Self, Box<dyn Error>> {
+        match byte {
+            0x20 => Ok(MapMode::LoRom2_68MHz),
+            0x21 => Ok(MapMode::HiRom2_68MHz),
+            0x23 => Ok(MapMode::SA1),
+            0x25 => Ok(MapMode::ExHiRom2_68MHz),
+            0x30 => Ok(MapMode::LoRom3_58MHz),
+            0x31 => Ok(MapMode::HiRom3_58MHz),
+            0x35 => Ok(MapMode::ExHiRom3_58MHz),
+            _ => Err("invalid map mode".into()),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub enum ChipsetType {
-    ROM,              // 0x0
-    ROMRAM,           // 0x1
-    ROMRAMBATTERY,    // 0x2,
-    ROMSA1,           // 0x33
-    ROMSA1RAM,        // 0x34
-    ROMSA1RAMBATTERY, // 0x35
-    ROMSA1BATTERY,    // 0x36
+    ROM,                  // 0x0
+    ROMRAM,               // 0x1
+    ROMRAMBATTERY,        // 0x2,
+    ROMDSP,               // 0x03
+    ROMDSPRAM,            // 0x04
+    ROMDSPRAMBATTERY,     // 0x05
+    ROMDSPBATTERY,        // 0x06
+    ROMSUPERFX,           // 0x13
+    ROMSUPERFXRAM,        // 0x14
+    ROMSUPERFXRAMBATTERY, // 0x15, 0x1A
+    ROMSA1,               // 0x33
+    ROMSA1RAM,            // 0x34
+    ROMSA1RAMBATTERY,     // 0x35
+    ROMSA1BATTERY,        // 0x36
+    Other(u8),            // OBC-1, S-DD1, S-RTC and custom chips
+}
+
+impl ChipsetType {
+    fn from_byte(byte: u8) -> Result<Self, Box<dyn Error>> {
+        match byte {
+            0x0 => Ok(ChipsetType::ROM),
+            0x01 => Ok(ChipsetType::ROMRAM),
+            0x02 => Ok(ChipsetType::ROMRAMBATTERY),
+            0x03 => Ok(ChipsetType::ROMDSP),
+            0x04 => Ok(ChipsetType::ROMDSPRAM),
+            0x05 => Ok(ChipsetType::ROMDSPRAMBATTERY),
+            0x06 => Ok(ChipsetType::ROMDSPBATTERY),
+            0x13 => Ok(ChipsetType::ROMSUPERFX),
+            0x14 => Ok(ChipsetType::ROMSUPERFXRAM),
+            0x15 | 0x1A => Ok(ChipsetType::ROMSUPERFXRAMBATTERY),
+            0x33 => Ok(ChipsetType::ROMSA1),
+            0x34 => Ok(ChipsetType::ROMSA1RAM),
+            0x35 => Ok(ChipsetType::ROMSA1RAMBATTERY),
+            0x36 => Ok(ChipsetType::ROMSA1BATTERY),
+            // the low nibble is the same ROM/RAM/battery layout, from 3 on with a coprocessor
+            _ if (0x3..=0x6).contains(&(byte & 0x0F))
+                && matches!(byte >> 4, 0x2 | 0x4 | 0x5 | 0xE | 0xF) =>
+            {
+                Ok(ChipsetType::Other(byte))
+            }
+            _ => Err("invalid chipset type".into()),
+        }
+    }
 }
 
 #[derive(Debug)]
 pub enum Region {
-    Japan,
-    USA,
-    Europe,
+    Japan,       // 0x0
+    USA,         // 0x1
+    Europe,      // 0x2
+    Sweden,      // 0x3
+    Finland,     // 0x4
+    Denmark,     // 0x5
+    France,      // 0x6
+    Netherlands, // 0x7
+    Spain,       // 0x8
+    Germany,     // 0x9
+    Italy,       // 0xA
+    China,       // 0xB
+    Indonesia,   // 0xC
+    Korea,       // 0xD
+    Common,      // 0xE
+    Canada,      // 0xF
+    Brazil,      // 0x10
+    Australia,   // 0x11
+}
+
+impl Region {
+    fn from_byte(byte: u8) -> Result<Self, Box<dyn Error>> {
+        match byte {
+            0x0 => Ok(Region::Japan),
+            0x1 => Ok(Region::USA),
+            0x2 => Ok(Region::Europe),
+            0x3 => Ok(Region::Sweden),
+            0x4 => Ok(Region::Finland),
+            0x5 => Ok(Region::Denmark),
+            0x6 => Ok(Region::France),
+            0x7 => Ok(Region::Netherlands),
+            0x8 => Ok(Region::Spain),
+            0x9 => Ok(Region::Germany),
+            0xA => Ok(Region::Italy),
+            0xB => Ok(Region::China),
+            0xC => Ok(Region::Indonesia),
+            0xD => Ok(Region::Korea),
+            0xE => Ok(Region::Common),
+            0xF => Ok(Region::Canada),
+            0x10 => Ok(Region::Brazil),
+            0x11 => Ok(Region::Australia),
+            _ => Err("invalid region".into()),
+        }
+    }
 }
 
 pub struct ROM {
+    // a 512 byte copier header was found and stripped
     pub headered: bool,
     pub game_title: String,
     pub fast_rom: bool,
     pub rom_mode: MapMode,
     pub chipset: ChipsetType,
+    // sizes in bytes, as given by the header
     pub rom_size: u32,
     pub real_rom_size: u8,
     pub ram_size: u32,
     pub real_ram_size: u8,
     pub region: Region,
+    pub version: u8,
+    pub checksum: u16,
+    pub checksum_complement: u16,
     pub data: Vec<u8>,
+    // paths of the patches applied, in order
+    pub patches: Vec<String>,
+    // offset of the cartridge header in `data`
+    pub header: usize,
+    // the game database had an entry for this ROM, its fields override the header
+    pub database_match: bool,
+    // controllers the game needs besides a joypad
+    pub peripherals: Vec<Peripheral>,
+    // the DSP program the game database names
+    pub dsp: Option<Model>,
+    // where the DSP firmware dumps are looked for
+    pub firmware_dir: PathBuf,
 }
 
 impl fmt::Display for ROM {
@@ -56,19 +175,165 @@ impl fmt::Display for ROM {
             self.rom_mode,
             self.chipset,
             self.fast_rom,
-            self.real_rom_size,
+            self.rom_size,
             self.ram_size,
             self.region
         )
     }
 }
 
+// sum of all bytes, with a size that isn't a power of two made up by mirroring the last part
+fn mirrored_sum(data: &[u8]) -> u32 {
+    if data.is_empty() {
+        return 0;
+    }
+    let size = 1 << data.len().ilog2();
+    let mut sum = data[..size]
+        .iter()
+        .fold(0u32, |sum, byte| sum.wrapping_add(*byte as u32));
+    let rest = &data[size..];
+    if !rest.is_empty() {
+        let repeat = size / rest.len().next_power_of_two();
+        sum = sum.wrapping_add(mirrored_sum(rest).wrapping_mul(repeat as u32));
+    }
+    sum
+}
+
 impl ROM {
+    // what the checksum in the header should be
+    pub fn computed_checksum(&self) -> u16 {
+        mirrored_sum(&self.data) as u16
+    }
+
+    pub fn checksum_valid(&self) -> bool {
+        self.checksum ^ self.checksum_complement == 0xFFFF
+            && self.checksum == self.computed_checksum()
+    }
+
+    // the extra chip the cartridge needs, if any
+    pub fn coprocessor(&self) -> Option<&'static str> {
+        match self.chipset {
+            ChipsetType::ROM | ChipsetType::ROMRAM | ChipsetType::ROMRAMBATTERY => None,
+            ChipsetType::ROMDSP
+            | ChipsetType::ROMDSPRAM
+            | ChipsetType::ROMDSPRAMBATTERY
+            | ChipsetType::ROMDSPBATTERY => self.dsp_model().map(|model| model.name()),
+            ChipsetType::ROMSUPERFX
+            | ChipsetType::ROMSUPERFXRAM
+            | ChipsetType::ROMSUPERFXRAMBATTERY => Some("Super FX"),
+            ChipsetType::ROMSA1
+            | ChipsetType::ROMSA1RAM
+            | ChipsetType::ROMSA1RAMBATTERY
+            | ChipsetType::ROMSA1BATTERY => Some("SA-1"),
+            ChipsetType::Other(byte) => Some(match byte >> 4 {
+                0x2 => "OBC-1",
+                0x4 => "S-DD1",
+                0x5 => "S-RTC",
+                0xE => "Super Game Boy or Satellaview",
+                // custom chips are told apart by the subtype byte before the header
+                _ => match self.data.get(self.header - 1) {
+                    Some(0x00) => "SPC7110",
+                    Some(0x01) => "ST010/ST011",
+                    Some(0x02) => "ST018",
+                    Some(0x10) => "CX4",
+                    _ => "unknown custom chip",
+                },
+            }),
+        }
+    }
+
+    // HiROM and ExHiROM, which also put SRAM at $20-$3F:$6000-$7FFF instead of $70-$7D
+    pub fn is_hirom(&self) -> bool {
+        matches!(
+            self.rom_mode,
+            MapMode::HiRom2_68MHz
+                | MapMode::HiRom3_58MHz
+                | MapMode::ExHiRom2_68MHz
+                | MapMode::ExHiRom3_58MHz
+        )
+    }
+
+    // the SA-1 map mode, or a LoROM header that names the chip
+    pub fn is_sa1(&self) -> bool {
+        matches!(self.rom_mode, MapMode::SA1)
+            || matches!(
+                self.chipset,
+                ChipsetType::ROMSA1
+                    | ChipsetType::ROMSA1RAM
+                    | ChipsetType::ROMSA1RAMBATTERY
+                    | ChipsetType::ROMSA1BATTERY
+            )
+    }
+
+    pub fn is_superfx(&self) -> bool {
+        matches!(
+            self.chipset,
+            ChipsetType::ROMSUPERFX
+                | ChipsetType::ROMSUPERFXRAM
+                | ChipsetType::ROMSUPERFXRAMBATTERY
+        )
+    }
+
+    // which DSP program the cartridge runs: the chipset says DSP, the game database which one
+    pub fn dsp_model(&self) -> Option<Model> {
+        match self.chipset {
+            ChipsetType::ROMDSP
+            | ChipsetType::ROMDSPRAM
+            | ChipsetType::ROMDSPRAMBATTERY
+            | ChipsetType::ROMDSPBATTERY => Some(self.dsp.unwrap_or(Model::Dsp1)),
+            ChipsetType::Other(byte) if byte >> 4 == 0xF => match self.data.get(self.header - 1) {
+                Some(0x01) => Some(self.dsp.unwrap_or(Model::St010)),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    // where DR and SR are on this board
+    fn dsp_mapping(&self, model: Model) -> Mapping {
+        match model {
+            Model::St010 | Model::St011 => Mapping::St01x,
+            _ if self.is_hirom() => Mapping::HiRom,
+            _ if self.data.len() > 0x100000 => Mapping::LoRomLarge,
+            _ => Mapping::LoRom,
+        }
+    }
+
     pub fn map_to(&self, mut bus: Box<Bus>) -> Result<Box<Bus>, Box<dyn Error>> {
+        bus.set_video_standard(VideoStandard::from_region(&self.region));
+        // the SA-1 answers for the whole cartridge, ROM included
+        if self.is_sa1() {
+            bus.sa1 = Some(Box::new(Sa1::new(
+                self.data.clone(),
+                self.ram_size as usize,
+            )));
+            return Ok(bus);
+        }
+        if self.is_superfx() {
+            bus.superfx = Some(Box::new(SuperFx::new(
+                self.data.clone(),
+                self.ram_size as usize,
+            )));
+            return Ok(bus);
+        }
+        if let Some(model) = self.dsp_model() {
+            let mapping = self.dsp_mapping(model);
+            bus.dsp = Some(Box::new(Dsp::open(model, &self.firmware_dir, mapping)?));
+        }
+        match self.is_hirom() {
+            true => self.map_hirom(&mut bus),
+            false => self.map_lorom(&mut bus),
+        }
+        Ok(bus)
+    }
+
+    // 32K of ROM in the upper half of each bank from $00 and $80
+    fn map_lorom(&self, bus: &mut Bus) {
         const BASE_ADDRESS: u32 = 0x8000;
         let mut counter: u32 = 0;
         let mut addr_counter: u32 = 0;
         let mut bank = 0x0;
+
         while counter < self.data.len() as u32 {
             let mut chunk_size: u32 = 0;
             debug!(
@@ -77,13 +342,13 @@ impl ROM {
                 (BASE_ADDRESS + addr_counter),
                 counter,
             );
-            while chunk_size < 32768 {
+            while chunk_size < 32768 && counter < self.data.len() as u32 {
                 if bank < 0x7E {
                     let addr = (bank << 16) | (BASE_ADDRESS + addr_counter);
-                    bus.write_byte(addr, self.data[counter as usize]);
+                    bus.load_byte(addr, self.data[counter as usize]);
                 }
                 let addr = ((bank + 0x80) << 16) | (BASE_ADDRESS + addr_counter);
-                bus.write_byte(addr, self.data[counter as usize]);
+                bus.load_byte(addr, self.data[counter as usize]);
                 chunk_size += 1;
                 counter += 1;
                 addr_counter += 1;
@@ -97,70 +362,396 @@ impl ROM {
             bank += 1;
             addr_counter = 0;
         }
+    }
 
-        Ok(bus)
+    // 64K of ROM in each bank from $C0, mirrored from $40 and with the upper halves from $00
+    // and $80. ExHiROM has the first 4M at $C0-$FF and $80-$BF, the rest at $40-$7D and $00-$3D.
+    fn map_hirom(&self, bus: &mut Bus) {
+        let extended = matches!(
+            self.rom_mode,
+            MapMode::ExHiRom2_68MHz | MapMode::ExHiRom3_58MHz
+        );
+        for (index, chunk) in self.data.chunks(0x10000).enumerate() {
+            let index = index as u32;
+            let (full, half) = match (extended, index) {
+                (false, 0x00..=0x3F) => {
+                    (vec![0xC0 + index, 0x40 + index], vec![0x80 + index, index])
+                }
+                (true, 0x00..=0x3F) => (vec![0xC0 + index], vec![0x80 + index]),
+                (true, 0x40..=0x7D) => (vec![index], vec![index - 0x40]),
+                _ => break,
+            };
+            debug!("bank {:?} offset 0x{:X}", full, index << 16);
+            for (offset, byte) in chunk.iter().enumerate() {
+                let offset = offset as u32;
+                // banks $7E and $7F are work RAM
+                for bank in full.iter().filter(|bank| !(0x7E..=0x7F).contains(*bank)) {
+                    bus.load_byte((bank << 16) | offset, *byte);
+                }
+                if offset >= 0x8000 {
+                    for bank in &half {
+                        bus.load_byte((bank << 16) | offset, *byte);
+                    }
+                }
+            }
+        }
     }
 }
 
+const ROM_EXTENSIONS: [&str; 2] = [".sfc", ".smc"];
+const ARCHIVE_EXTENSIONS: [&str; 2] = [".gz", ".zip"];
+
+// the ROM inside a .zip or .gz file, anything else is taken as it is
+fn unpack(path: &str, data: Vec<u8>) -> Result<Vec<u8>, Box<dyn Error>> {
+    if data.starts_with(&GZIP_MAGIC) {
+        return gunzip(&data).map_err(|e| format!("{}: {}", path, e).into());
+    }
+    if !data.starts_with(&ZIP_MAGIC) {
+        return Ok(data);
+    }
+
+    let zip = ZipArchive::parse(data)?;
+    let candidates: Vec<&str> = zip
+        .entries
+        .iter()
+        .map(|entry| entry.name.as_str())
+        .filter(|name| {
+            let name = name.to_lowercase();
+            ROM_EXTENSIONS.iter().any(|ext| name.ends_with(ext))
+        })
+        .collect();
+    match candidates[..] {
+        [name] => {
+            info!("Loading {} from {}", name, path);
+            zip.read_file(name)
+        }
+        [] => Err(format!("{} holds no .sfc or .smc file", path).into()),
+        _ => Err(format!("{} holds several ROMs: {}", path, candidates.join(", ")).into()),
+    }
+}
+
+// game.bps, game.ups or game.ips next to game.sfc or game.sfc.gz, only the first of them when
+// there are several
+fn patch_next_to(path: &str) -> Option<String> {
+    let lower = path.to_lowercase();
+    let rom = match ARCHIVE_EXTENSIONS.iter().find(|ext| lower.ends_with(*ext)) {
+        Some(ext) => &path[..path.len() - ext.len()],
+        None => path,
+    };
+    let found: Vec<PathBuf> = PATCH_EXTENSIONS
+        .iter()
+        .map(|ext| Path::new(rom).with_extension(ext))
+        .filter(|patch| patch.exists())
+        .collect();
+    if found.len() > 1 {
+        warn!(
+            "Several patches next to {}, only applying {}",
+            path,
+            found[0].display()
+        );
+    }
+    found
+        .first()
+        .and_then(|patch| patch.to_str().map(String::from))
+}
+
+// what a load takes besides the ROM file itself
+#[derive(Default)]
+pub struct LoadOptions {
+    // patches to apply after those found next to the ROM
+    pub patches: Vec<String>,
+    // a user database searched before the built-in one
+    pub database: Option<String>,
+    // directory of the DSP firmware dumps, the ROM's own directory by default
+    pub firmware: Option<String>,
+}
+
+// header locations of LoROM (and SA-1), HiROM and ExHiROM images
+const HEADER_OFFSETS: [usize; 3] = [0x7FC0, 0xFFC0, 0x40FFC0];
+const HEADER_SIZE: usize = 0x40;
+
+// where the header of a map mode byte sits
+fn header_offset(map_mode: u8) -> usize {
+    match map_mode & 0x0F {
+        0x1 => 0xFFC0,
+        0x5 => 0x40FFC0,
+        _ => 0x7FC0,
+    }
+}
+
+// the likeliest header location, scored by a checksum that matches its complement and a map
+// mode byte that agrees with the location. Ties go to LoROM.
+fn find_header(data: &[u8]) -> usize {
+    HEADER_OFFSETS
+        .iter()
+        .rev()
+        .filter(|offset| data.len() >= **offset + HEADER_SIZE)
+        .max_by_key(|offset| {
+            let header = &data[**offset..**offset + HEADER_SIZE];
+            let complement = u16::from_le_bytes([header[0x1C], header[0x1D]]);
+            let checksum = u16::from_le_bytes([header[0x1E], header[0x1F]]);
+            let map_mode = header[0x15];
+            let mut score = 0;
+            if checksum ^ complement == 0xFFFF {
+                score += 2;
+            }
+            if MapMode::from_byte(map_mode).is_ok() && header_offset(map_mode) == **offset {
+                score += 1;
+            }
+            score
+        })
+        .copied()
+        .unwrap_or(HEADER_OFFSETS[0])
+}
+
 pub fn open(path: &str) -> Result<Box<ROM>, Box<dyn Error>> {
-    let rom_file = read(path)?;
+    open_with(path, &LoadOptions::default())
+}
+
+// load a ROM, applying the patches found next to it and those in `options`, with the header
+// fields overridden by the game database when it knows the ROM
+pub fn open_with(path: &str, options: &LoadOptions) -> Result<Box<ROM>, Box<dyn Error>> {
+    let mut rom_file = unpack(path, read(path)?)?;
     let length = rom_file.len();
+    // patches are made against the ROM without the copier header
     let headered = rom_file.len() % 1024 == 512;
+    if headered {
+        rom_file.drain(..512);
+    }
+
+    let mut patches: Vec<String> = patch_next_to(path).into_iter().collect();
+    for patch in &options.patches {
+        if !patches.contains(patch) {
+            patches.push(patch.clone());
+        }
+    }
+    for patch in &patches {
+        rom_file =
+            patch::apply(&rom_file, &read(patch)?).map_err(|e| format!("{}: {}", patch, e))?;
+        info!("Applied patch {}", patch);
+    }
+
+    if rom_file.len() < 0x8000 {
+        return Err("file is too small to be a SNES ROM".into());
+    }
+
+    let user_database = match &options.database {
+        Some(path) => Some(Database::open(path)?),
+        None => None,
+    };
+    let entry = database::find(&rom_file, user_database.as_ref());
+
+    // the database's map mode says where the header is, as long as the file is big enough
+    let map_override = entry.as_ref().and_then(|entry| entry.overrides.map_mode);
+    let header = match map_override.map(header_offset) {
+        Some(offset) if rom_file.len() >= offset + HEADER_SIZE => offset,
+        _ => find_header(&rom_file),
+    };
+    let header_bytes = &rom_file[header..header + HEADER_SIZE];
+
+    let game_title = String::from_utf8_lossy(&header_bytes[..0x15])
+        .trim_end()
+        .to_string();
+
+    // without an entry for the dump itself, one for every dump of the game
+    let entry = entry.or_else(|| database::find_title(&game_title, user_database.as_ref()));
+    let database_match = entry.is_some();
+    let overrides = entry.map(|entry| entry.overrides).unwrap_or_default();
 
-    let game_title = match std::str::from_utf8(&rom_file[0x7FC0..0x7FD4]) {
-        Ok(decoded_string) => Ok(decoded_string.trim_end()),
-        Err(e) => Err(e),
-    }?;
-
-    let rom_mode = match rom_file[0x7FD5] {
-        0x20 => Ok(MapMode::LoRom2_68MHz),
-        0x21 => Ok(MapMode::HiRom2_68MHz),
-        0x23 => Ok(MapMode::SA1),
-        0x25 => Ok(MapMode::ExHiRom2_68MHz),
-        0x30 => Ok(MapMode::LoRom3_58MHz),
-        0x31 => Ok(MapMode::HiRom3_58MHz),
-        0x35 => Ok(MapMode::ExHiRom3_58MHz),
-        _ => Err("invalid map mode"),
-    }?;
-
-    let cartridge_type = match rom_file[0x7FD6] {
-        0x0 => Ok(ChipsetType::ROM),
-        0x01 => Ok(ChipsetType::ROMRAM),
-        0x02 => Ok(ChipsetType::ROMRAMBATTERY),
-        0x33 => Ok(ChipsetType::ROMSA1),
-        0x34 => Ok(ChipsetType::ROMSA1RAM),
-        0x35 => Ok(ChipsetType::ROMSA1RAMBATTERY),
-        0x36 => Ok(ChipsetType::ROMSA1BATTERY),
-        _ => Err("invalid chipset type"),
-    }?;
-
-    let real_rom_size = rom_file[0x7FD7];
-    let rom_size = 1 << real_rom_size;
-    let real_ram_size = rom_file[0x7FD8];
-    let ram_size = 1 << real_ram_size;
-
-    let region = match rom_file[0x7FD9] {
-        0x0 => Ok(Region::Japan),
-        0x1 => Ok(Region::USA),
-        0x2 => Ok(Region::Europe),
-        _ => Err("invalid region"),
-    }?;
+    let map_mode = overrides.map_mode.unwrap_or(header_bytes[0x15]);
+    let rom_mode = MapMode::from_byte(map_mode)?;
+
+    let cartridge_type = ChipsetType::from_byte(overrides.chipset.unwrap_or(header_bytes[0x16]))?;
+
+    // both sizes are 1K << n, a RAM size of 0 means there is none
+    let real_rom_size = header_bytes[0x17];
+    let rom_size = 0x400u32.checked_shl(real_rom_size as u32).unwrap_or(0);
+    let real_ram_size = header_bytes[0x18];
+    let ram_size = match real_ram_size {
+        0 => 0,
+        n => 0x400u32.checked_shl(n as u32).unwrap_or(0),
+    };
+
+    let region = Region::from_byte(overrides.region.unwrap_or(header_bytes[0x19]))?;
 
     let rom = ROM {
         region: region,
-        fast_rom: rom_file[0x7FD5] & 0x10 > 0,
-        game_title: String::from(game_title),
-        ram_size: ram_size,
+        fast_rom: map_mode & 0x10 > 0,
+        game_title,
+        ram_size: overrides.ram_size.unwrap_or(ram_size),
         real_ram_size: real_ram_size,
         rom_size: rom_size,
         real_rom_size: real_rom_size,
         chipset: cartridge_type,
         rom_mode: rom_mode,
         headered: headered,
+        version: header_bytes[0x1B],
+        checksum_complement: u16::from_le_bytes([header_bytes[0x1C], header_bytes[0x1D]]),
+        checksum: u16::from_le_bytes([header_bytes[0x1E], header_bytes[0x1F]]),
+        header,
+        database_match,
+        peripherals: overrides.peripherals,
+        dsp: overrides.dsp,
+        firmware_dir: match &options.firmware {
+            Some(dir) => PathBuf::from(dir),
+            None => Path::new(path)
+                .parent()
+                .map(Path::to_path_buf)
+                .unwrap_or_default(),
+        },
         data: rom_file,
+        patches,
     };
 
     info!("Loaded ROM: {} ({} bytes)", rom, length);
 
     Ok(Box::new(rom))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unpack_archives() {
+        // readme.txt and a deflated game.sfc
+        let zip = hex::decode(concat!(
+            "504b030414000000000000002150ac2a93d802000000020000000a000000726561646d652e747874",
+            "6869504b030414000000080000002150bdf7602608000000200000000800000067616d652e736663",
+            "0bf2f7550cc28301504b0102140314000000000000002150ac2a93d802000000020000000a000000",
+            "0000000000000000800100000000726561646d652e747874504b0102140314000000080000002150",
+            "bdf76026080000002000000008000000000000000000000080012a00000067616d652e736663504b",
+            "050600000000020002006e000000580000000000",
+        ))
+        .unwrap();
+        assert_eq!(unpack("game.zip", zip).unwrap(), b"ROM!".repeat(8));
+
+        let gz = hex::decode("1f8b08000000000002030bf2f7550cc28301bdf7602620000000").unwrap();
+        assert_eq!(unpack("game.sfc.gz", gz).unwrap(), b"ROM!".repeat(8));
+
+        assert_eq!(unpack("game.sfc", vec![1, 2, 3]).unwrap(), [1, 2, 3]);
+    }
+
+    #[test]
+    fn patch_named_like_the_rom() {
+        let dir = std::env::temp_dir().join(format!("ddss-patches-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let rom = dir.join("game.sfc.gz");
+        let rom = rom.to_str().unwrap();
+        assert_eq!(patch_next_to(rom), None);
+
+        std::fs::write(dir.join("game.ips"), b"PATCHEOF").unwrap();
+        let ips = dir.join("game.ips").to_str().map(String::from);
+        assert_eq!(patch_next_to(rom), ips);
+        // a BPS patch wins over the others
+        std::fs::write(dir.join("game.ups"), b"UPS1").unwrap();
+        std::fs::write(dir.join("game.bps"), b"BPS1").unwrap();
+        let bps = dir.join("game.bps").to_str().map(String::from);
+        assert_eq!(patch_next_to(rom), bps);
+        assert_eq!(patch_next_to(dir.join("game.zip").to_str().unwrap()), bps);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn patch_without_copier_header() {
+        let dir = std::env::temp_dir().join(format!("ddss-headered-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let rom = std::fs::read(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/tests/golden/roms/joypad_backdrop.sfc"
+        ))
+        .unwrap();
+        let mut headered = vec![0; 512];
+        headered.extend_from_slice(&rom);
+        std::fs::write(dir.join("game.smc"), &headered).unwrap();
+        // the offset is in the ROM as the patch author saw it, without the 512 bytes
+        std::fs::write(
+            dir.join("game.ips"),
+            b"PATCH\x00\x00\x10\x00\x02\xAB\xCDEOF",
+        )
+        .unwrap();
+
+        let loaded = open(dir.join("game.smc").to_str().unwrap()).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert!(loaded.headered);
+        assert_eq!(loaded.data.len(), rom.len());
+        assert_eq!(loaded.data[0x10..0x12], [0xAB, 0xCD]);
+        assert_eq!(loaded.data[0x12..], rom[0x12..]);
+    }
+
+    #[test]
+    fn checksum_mirrors_odd_sizes() {
+        assert_eq!(mirrored_sum(&[1, 2, 3, 4]), 10);
+        // 3 bytes sum like [1, 2, 3, 3], 6 like [1; 4] and [2, 2] mirrored to 4
+        assert_eq!(mirrored_sum(&[1, 2, 3]), 9);
+        assert_eq!(mirrored_sum(&[1, 1, 1, 1, 2, 2]), 12);
+    }
+
+    #[test]
+    fn chipset_coprocessors() {
+        assert!(matches!(
+            ChipsetType::from_byte(0x15),
+            Ok(ChipsetType::ROMSUPERFXRAMBATTERY)
+        ));
+        assert!(matches!(
+            ChipsetType::from_byte(0x43),
+            Ok(ChipsetType::Other(0x43))
+        ));
+        assert!(ChipsetType::from_byte(0x07).is_err());
+    }
+
+    #[test]
+    fn several_roms_in_zip() {
+        // a.sfc and b.SMC
+        let zip = hex::decode(concat!(
+            "504b0304140000000000000021508b9ed9d3010000000100000005000000612e73666341504b0304",
+            "1400000000000000215031cfd04a010000000100000005000000622e534d4342504b010214031400",
+            "00000000000021508b9ed9d30100000001000000050000000000000000000000800100000000612e",
+            "736663504b010214031400000000000000215031cfd04a0100000001000000050000000000000000",
+            "000000800124000000622e534d43504b0506000000000200020066000000480000000000",
+        ))
+        .unwrap();
+        let e = unpack("games.zip", zip).unwrap_err();
+        assert_eq!(e.to_string(), "games.zip holds several ROMs: a.sfc, b.SMC");
+    }
+
+    #[test]
+    fn database_overrides_the_header() {
+        let mut data = vec![0u8; 0x8000];
+        data[0x7FC0..0x7FD5].copy_from_slice(b"MARIO PAINT          ");
+        data[0x7FD5] = 0x20;
+        data[0x7FD7] = 0x0A;
+        let path = std::env::temp_dir().join(format!("ddss-database-{}.sfc", std::process::id()));
+        std::fs::write(&path, &data).unwrap();
+        let rom = open(path.to_str().unwrap()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(rom.game_title, "MARIO PAINT");
+        assert!(rom.database_match);
+        assert_eq!(rom.peripherals, [Peripheral::Mouse]);
+        assert_eq!(rom.dsp_model(), None);
+
+        // the header only says there is a DSP
+        data[0x7FC0..0x7FD5].copy_from_slice(b"DUNGEON MASTER       ");
+        data[0x7FD6] = 0x03;
+        std::fs::write(&path, &data).unwrap();
+        let rom = open(path.to_str().unwrap()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(rom.dsp_model(), Some(Model::Dsp2));
+    }
+
+    #[test]
+    fn header_location() {
+        let mut data = vec![0u8; 0x10000];
+        // a HiROM header with a matching complement beats a LoROM map mode byte alone
+        data[0x7FD5] = 0x20;
+        data[0xFFD5] = 0x21;
+        data[0xFFDC..0xFFE0].copy_from_slice(&[0xFF, 0xFF, 0x00, 0x00]);
+        assert_eq!(find_header(&data), 0xFFC0);
+
+        data[0xFFDC] = 0x00;
+        assert_eq!(find_header(&data), 0x7FC0);
+        assert_eq!(find_header(&data[..0x8000]), 0x7FC0);
+        assert_eq!(header_offset(0x35), 0x40FFC0);
+    }
+}
@@ -0,0 +1,236 @@
+// Screenshot comparison tests. Every case in tests/golden/manifest.txt boots a ROM, plays back
+// an input script on joypad 1 and compares the picture after the given frame with a PNG or a
+// CRC32. The PPU only draws the backdrop for now, so the cases check the backdrop color,
+// brightness and picture size a ROM sets up, not BG or sprite rendering.
+//
+// On a mismatch the actual picture and a diff (changed pixels in red) are written to
+// target/tmp/golden. To accept new output run with GOLDEN_BLESS=1, which rewrites the PNGs
+// and prints the new hashes for the manifest.
+
+use ddss_snes::checksum::Crc32;
+use ddss_snes::cpu::alu::Cpu;
+use ddss_snes::cpu::bus::Bus;
+use ddss_snes::input::script::InputScript;
+use ddss_snes::ppu::screenshot::{read_png, write_png_rgb};
+use ddss_snes::rom::open;
+use std::env;
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const GOLDEN_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/golden");
+const OUTPUT_DIR: &str = concat!(env!("CARGO_TARGET_TMPDIR"), "/golden");
+
+enum Expected {
+    Crc32(u32),
+    Png(PathBuf),
+}
+
+struct Case {
+    name: String,
+    rom: PathBuf,
+    frame: u64,
+    input: InputScript,
+    expected: Expected,
+}
+
+#[derive(PartialEq)]
+struct Image {
+    width: usize,
+    height: usize,
+    rgb: Vec<u8>,
+}
+
+impl Image {
+    fn hash(&self) -> u32 {
+        let mut crc = Crc32::new();
+        crc.update(format!("{}x{}", self.width, self.height).as_bytes());
+        crc.update(&self.rgb);
+        crc.finish()
+    }
+
+    fn save(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        let mut out = Vec::new();
+        write_png_rgb(self.width, self.height, &self.rgb, &mut out)?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, out)?;
+        Ok(())
+    }
+}
+
+// one case per line: name rom frame input expected, '#' starts a comment
+fn parse_manifest(text: &str) -> Result<Vec<Case>, String> {
+    let mut cases = Vec::new();
+    for (number, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap().trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let [name, rom, frame, input, expected] = fields[..] else {
+            return Err(format!("line {}: expected 5 fields", number + 1));
+        };
+
+        let expected = match expected.split_once(':') {
+            Some(("crc32", hash)) => u32::from_str_radix(hash, 16)
+                .map(Expected::Crc32)
+                .map_err(|_| format!("line {}: invalid hash {}", number + 1, hash))?,
+            Some(("png", path)) => Expected::Png(Path::new(GOLDEN_DIR).join(path)),
+            _ => {
+                return Err(format!(
+                    "line {}: expected crc32:HASH or png:PATH",
+                    number + 1
+                ));
+            }
+        };
+
+        cases.push(Case {
+            name: name.to_string(),
+            rom: Path::new(GOLDEN_DIR).join(rom),
+            frame: frame
+                .parse()
+                .map_err(|_| format!("line {}: invalid frame {}", number + 1, frame))?,
+            input: InputScript::parse(input).map_err(|e| format!("line {}: {}", number + 1, e))?,
+            expected,
+        });
+    }
+    Ok(cases)
+}
+
+fn run(case: &Case) -> Result<Image, Box<dyn Error>> {
+    let rom = open(case.rom.to_str().ok_or("invalid ROM path")?)?;
+    let mut cpu = Cpu::new(rom.map_to(Box::new(Bus::new()))?);
+
+    for frame in 0..case.frame {
+        cpu.bus.set_joypad(0, case.input.buttons_at(frame));
+        cpu.run_frame();
+    }
+
+    let fb = &cpu.bus.ppu.framebuffer;
+    Ok(Image {
+        width: fb.width(),
+        height: fb.height(),
+        rgb: fb.to_rgb(),
+    })
+}
+
+// changed pixels in red over a dimmed copy of the actual picture
+fn diff(expected: &Image, actual: &Image) -> (usize, Image) {
+    let mut changed = 0;
+    let mut rgb = Vec::with_capacity(actual.rgb.len());
+    for (want, got) in expected.rgb.chunks(3).zip(actual.rgb.chunks(3)) {
+        if want == got {
+            let gray = ((got[0] as u16 + got[1] as u16 + got[2] as u16) / 12) as u8;
+            rgb.extend_from_slice(&[gray, gray, gray]);
+        } else {
+            changed += 1;
+            rgb.extend_from_slice(&[0xFF, 0, 0]);
+        }
+    }
+    let image = Image {
+        width: actual.width,
+        height: actual.height,
+        rgb,
+    };
+    (changed, image)
+}
+
+fn check(case: &Case, actual: &Image, bless: bool) -> Result<(), Box<dyn Error>> {
+    let expected = match &case.expected {
+        Expected::Crc32(hash) if bless => {
+            if *hash != actual.hash() {
+                println!("{}: crc32:{:08X}", case.name, actual.hash());
+            }
+            return Ok(());
+        }
+        Expected::Crc32(hash) if *hash == actual.hash() => return Ok(()),
+        Expected::Crc32(hash) => {
+            let path = Path::new(OUTPUT_DIR).join(format!("{}.actual.png", case.name));
+            actual.save(&path)?;
+            return Err(format!(
+                "expected crc32:{:08X}, got crc32:{:08X} (see {})",
+                hash,
+                actual.hash(),
+                path.display()
+            )
+            .into());
+        }
+        Expected::Png(path) if bless => return actual.save(path),
+        Expected::Png(path) => {
+            let (width, height, rgb) = read_png(&fs::read(path)?)?;
+            Image { width, height, rgb }
+        }
+    };
+
+    if expected == *actual {
+        return Ok(());
+    }
+
+    let actual_path = Path::new(OUTPUT_DIR).join(format!("{}.actual.png", case.name));
+    actual.save(&actual_path)?;
+
+    if (expected.width, expected.height) != (actual.width, actual.height) {
+        return Err(format!(
+            "expected {}x{}, got {}x{} (see {})",
+            expected.width,
+            expected.height,
+            actual.width,
+            actual.height,
+            actual_path.display()
+        )
+        .into());
+    }
+
+    let (changed, diff_image) = diff(&expected, actual);
+    let diff_path = Path::new(OUTPUT_DIR).join(format!("{}.diff.png", case.name));
+    diff_image.save(&diff_path)?;
+    Err(format!("{} pixels differ (see {})", changed, diff_path.display()).into())
+}
+
+#[test]
+fn screenshots_match() {
+    let manifest = fs::read_to_string(Path::new(GOLDEN_DIR).join("manifest.txt")).unwrap();
+    let cases = parse_manifest(&manifest).unwrap();
+    let bless = env::var_os("GOLDEN_BLESS").is_some();
+
+    let mut failures = Vec::new();
+    for case in &cases {
+        // ROMs that can't be redistributed may be listed and dropped in locally
+        if !case.rom.exists() {
+            eprintln!("{}: skipped, {} not found", case.name, case.rom.display());
+            continue;
+        }
+
+        let result = run(case).and_then(|actual| check(case, &actual, bless));
+        if let Err(e) = result {
+            failures.push(format!("{}: {}", case.name, e));
+        }
+    }
+
+    assert!(
+        failures.is_empty(),
+        "screenshot mismatches:\n{}",
+        failures.join("\n")
+    );
+}
+
+#[test]
+fn manifest_syntax() {
+    let cases = parse_manifest(
+        "# comment\n\
+         idle roms/a.sfc 10 - crc32:DEADBEEF\n\
+         held roms/a.sfc 60 0:A+Start,30:- png:images/held.png # trailing\n",
+    )
+    .unwrap();
+
+    assert_eq!(cases.len(), 2);
+    assert!(matches!(cases[0].expected, Expected::Crc32(0xDEADBEEF)));
+    assert_eq!(cases[1].input.entries, [(0, 0x1080), (30, 0)]);
+    assert!(matches!(&cases[1].expected, Expected::Png(p) if p.ends_with("images/held.png")));
+
+    assert!(parse_manifest("short roms/a.sfc 10\n").is_err());
+    assert!(InputScript::parse("0:Turbo").is_err());
+}
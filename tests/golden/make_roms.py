@@ -0,0 +1,49 @@
+# Builds the homebrew ROMs used by the golden image tests.
+#
+# usage: python3 make_roms.py
+#
+# The ROMs are tiny LoROM images assembled by hand, keep them to the instructions the CPU core
+# handles reliably (no SEP/REP, no JMP) since they are only meant to exercise the PPU.
+
+import os
+
+ROM_SIZE = 0x8000
+HEADER = 0x7FC0
+
+
+def lorom(title, code):
+    rom = bytearray(ROM_SIZE)
+    rom[:len(code)] = code
+    rom[HEADER:HEADER + 21] = title.ljust(21).encode('ascii')
+    rom[0x7FD5] = 0x20  # LoROM, 2.68MHz
+    rom[0x7FD6] = 0x00  # ROM only
+    rom[0x7FD7] = 0x08  # 256KB
+    rom[0x7FD8] = 0x00  # no SRAM
+    rom[0x7FD9] = 0x01  # USA
+    # reset vector
+    rom[0x7FFC:0x7FFE] = bytes([0x00, 0x80])
+    return rom
+
+
+# Shows joypad 1 in the backdrop color: the low byte of JOY1 ORed with full red.
+# The accumulator is 16 bits wide, so every store also hits the next register.
+JOYPAD_BACKDROP = bytes([
+    0xA9, 0x01, 0x00,  # 8000 LDA #$0001
+    0x8D, 0x00, 0x42,  # 8003 STA $4200   ; auto joypad read
+    0xA9, 0x0F, 0x00,  # 8006 LDA #$000F
+    0x8D, 0x00, 0x21,  # 8009 STA $2100   ; full brightness
+    0xA9, 0x00, 0x00,  # 800C LDA #$0000
+    0x8D, 0x20, 0x21,  # 800F STA $2120   ; CGADD = 0
+    0xAD, 0x18, 0x42,  # 8012 LDA $4218   ; JOY1
+    0x09, 0x1F, 0x00,  # 8015 ORA #$001F
+    0x8D, 0x22, 0x21,  # 8018 STA $2122   ; color low byte
+    0xA9, 0x00, 0x00,  # 801B LDA #$0000
+    0x8D, 0x22, 0x21,  # 801E STA $2122   ; color high byte
+    0x80, 0xE9,        # 8021 BRA $800C
+])
+
+
+if __name__ == '__main__':
+    here = os.path.dirname(os.path.abspath(__file__))
+    with open(os.path.join(here, 'roms', 'joypad_backdrop.sfc'), 'wb') as f:
+        f.write(lorom('JOYPAD BACKDROP', JOYPAD_BACKDROP))